@@ -23,6 +23,8 @@ use std::{
     mem::ManuallyDrop,
     os::fd::FromRawFd,
     ptr::{self, null, null_mut},
+    sync::{atomic::AtomicU8, OnceLock},
+    time::Instant,
 };
 use std::{ops::Mul, vec::IntoIter};
 
@@ -35,10 +37,149 @@ lazy_static! {
         (DICT_SQUASH_UNIQUE_KEY_COST.cost() - DICT_SQUASH_REPEATED_ACCESS_COST.cost()) as u64;
 }
 
+/// The state of an execution that compiled programs read directly, passed to every function as its
+/// last argument.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ExecutionContext {
+    /// The cheatcode context of the execution's syscall handler, or null if it has none.
+    pub cheatcode_context: *mut c_void,
+    /// Flag set (to one of the `INTERRUPT_*` constants) to stop the execution, checked whenever
+    /// gas is withdrawn. Never null.
+    pub interrupt: *const AtomicU8,
+    /// Bitmap with a bit per Sierra statement (in 64-bit words), set by programs compiled with
+    /// coverage as they execute them. Null if coverage isn't collected.
+    pub coverage: *mut u64,
+    /// The word the statements are marked in when there's no coverage bitmap, so that the
+    /// compiled code doesn't need to branch.
+    pub coverage_discard: u64,
+}
+
+impl ExecutionContext {
+    /// Offset of the `cheatcode_context` field.
+    pub const CHEATCODE_CONTEXT: usize = std::mem::offset_of!(Self, cheatcode_context);
+    /// Offset of the `interrupt` field.
+    pub const INTERRUPT: usize = std::mem::offset_of!(Self, interrupt);
+    /// Offset of the `coverage` field.
+    pub const COVERAGE: usize = std::mem::offset_of!(Self, coverage);
+    /// Offset of the `coverage_discard` field.
+    pub const COVERAGE_DISCARD: usize = std::mem::offset_of!(Self, coverage_discard);
+}
+
+impl Default for ExecutionContext {
+    fn default() -> Self {
+        static NEVER_INTERRUPTED: AtomicU8 = AtomicU8::new(INTERRUPT_NONE);
+
+        Self {
+            cheatcode_context: null_mut(),
+            interrupt: &NEVER_INTERRUPTED,
+            coverage: null_mut(),
+            coverage_discard: 0,
+        }
+    }
+}
+
+/// No interruption was requested.
+pub const INTERRUPT_NONE: u8 = 0;
+/// The execution's cancellation token was cancelled.
+pub const INTERRUPT_CANCELLED: u8 = 1;
+/// The execution's deadline has passed.
+pub const INTERRUPT_TIMED_OUT: u8 = 2;
+
+/// Where `debug::print()` writes to instead of its target file descriptor.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PrintSink {
+    /// Opaque pointer passed to `write`.
+    pub data: *mut c_void,
+    /// Called with the formatted output, or `None` to write to the file descriptor.
+    pub write: Option<extern "C" fn(data: *mut c_void, bytes: *const u8, len: usize)>,
+}
+
+/// The state of an execution that the runtime functions report to: where its output, events,
+/// trace, function hooks, breakpoints and allocations go.
+///
+/// Unlike the [`ExecutionContext`], it's installed in the runtime (see
+/// [`cairo_native__set_runtime_state`]) since the runtime functions aren't given the context.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RuntimeState {
+    pub print_sink: PrintSink,
+    pub event_buffer: EventBuffer,
+    pub trace_sink: TraceSink,
+    pub function_hooks: FunctionHooks,
+    pub breakpoint_handler: BreakpointHandler,
+    pub memory_tracer: MemoryTracer,
+}
+
+impl RuntimeState {
+    /// A state that discards everything, used when none is installed.
+    pub const EMPTY: Self = Self {
+        print_sink: PrintSink {
+            data: null_mut(),
+            write: None,
+        },
+        event_buffer: EventBuffer {
+            events: null_mut(),
+            len: 0,
+            capacity: 0,
+            data: null_mut(),
+            flush: None,
+        },
+        trace_sink: TraceSink {
+            data: null_mut(),
+            append: None,
+        },
+        function_hooks: FunctionHooks {
+            data: null_mut(),
+            enter: None,
+            exit: None,
+        },
+        breakpoint_handler: BreakpointHandler {
+            data: null_mut(),
+            hit: None,
+        },
+        memory_tracer: MemoryTracer {
+            data: null_mut(),
+            trace: None,
+        },
+    };
+}
+
+impl Default for RuntimeState {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+thread_local! {
+    static RUNTIME_STATE: Cell<*mut RuntimeState> = const { Cell::new(null_mut()) };
+}
+
+/// Store the runtime state of an execution in the internal thread local, or none if null. Returns
+/// the old state, to restore it after execution.
+///
+/// The state is used in place (the event buffer's length is updated as events are recorded), so
+/// it must outlive the execution.
+/// Not a runtime metadata method, it should be called before the program is executed.
+#[no_mangle]
+pub extern "C" fn cairo_native__set_runtime_state(state: *mut RuntimeState) -> *mut RuntimeState {
+    RUNTIME_STATE.replace(state)
+}
+
+/// A copy of the runtime state of the current execution, or an empty one if none is installed.
+fn runtime_state() -> RuntimeState {
+    unsafe { RUNTIME_STATE.get().as_ref() }
+        .copied()
+        .unwrap_or(RuntimeState::EMPTY)
+}
+
 /// Based on `cairo-lang-runner`'s implementation.
 ///
 /// Source: <https://github.com/starkware-libs/cairo/blob/main/crates/cairo-lang-runner/src/casm_run/mod.rs#L1946-L1948>
 ///
+/// The output is sent to the current print sink instead of `target_fd` if there's one.
+///
 /// # Safety
 ///
 /// This function is intended to be called from MLIR, deals with pointers, and is therefore
@@ -64,6 +205,12 @@ pub unsafe extern "C" fn cairo_native__libfunc__debug__print(
 
     let value = format_for_debug(items.into_iter());
 
+    let sink = runtime_state().print_sink;
+    if let Some(write) = sink.write {
+        write(sink.data, value.as_ptr(), value.len());
+        return 0;
+    }
+
     if write!(target, "{}", value).is_err() {
         return 1;
     };
@@ -143,6 +290,66 @@ pub unsafe extern "C" fn cairo_native__libfunc__hades_permutation(
     *op2 = state[2].to_bytes_le();
 }
 
+/// Where programs compiled with memory tracing report their allocations.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryTracer {
+    /// Opaque pointer passed to `trace`.
+    pub data: *mut c_void,
+    /// Called with the freed (or reallocated) pointer, the allocated one and its size, so
+    /// allocations have a null `old_ptr` and frees a null `new_ptr`. `None` to ignore them.
+    pub trace: Option<
+        extern "C" fn(data: *mut c_void, old_ptr: *mut c_void, new_ptr: *mut c_void, len: u64),
+    >,
+}
+
+/// Report an allocation, reallocation or free to the current memory tracer, if any.
+fn trace_allocation(old_ptr: *mut c_void, new_ptr: *mut c_void, len: usize) {
+    let tracer = runtime_state().memory_tracer;
+    if let Some(trace) = tracer.trace {
+        trace(tracer.data, old_ptr, new_ptr, len as u64);
+    }
+}
+
+mod libc {
+    use std::ffi::c_void;
+
+    extern "C" {
+        pub fn realloc(ptr: *mut c_void, len: usize) -> *mut c_void;
+        pub fn free(ptr: *mut c_void);
+    }
+}
+
+/// Reallocate (or allocate, if `ptr` is null) memory with the C allocator, reporting it to the
+/// current memory tracer.
+///
+/// Called instead of `realloc` by programs compiled with memory tracing.
+///
+/// # Safety
+///
+/// Same as the C `realloc`.
+#[no_mangle]
+pub unsafe extern "C" fn cairo_native__realloc(ptr: *mut c_void, len: u64) -> *mut c_void {
+    let new_ptr = libc::realloc(ptr, len as usize);
+    trace_allocation(ptr, new_ptr, len as usize);
+    new_ptr
+}
+
+/// Free memory allocated with the C allocator, reporting it to the current memory tracer.
+///
+/// Called instead of `free` by programs compiled with memory tracing.
+///
+/// # Safety
+///
+/// Same as the C `free`.
+#[no_mangle]
+pub unsafe extern "C" fn cairo_native__free(ptr: *mut c_void) {
+    if !ptr.is_null() {
+        libc::free(ptr);
+        trace_allocation(ptr, null_mut(), 0);
+    }
+}
+
 /// Felt252 type used in cairo native runtime
 #[derive(Debug)]
 pub struct FeltDict {
@@ -162,14 +369,17 @@ pub struct FeltDict {
 /// definitely unsafe to use manually.
 #[no_mangle]
 pub unsafe extern "C" fn cairo_native__dict_new(size: u64, align: u64) -> *mut FeltDict {
-    Box::into_raw(Box::new(FeltDict {
+    let dict = Box::into_raw(Box::new(FeltDict {
         mappings: HashMap::default(),
 
         layout: Layout::from_size_align_unchecked(size as usize, align as usize),
         elements: null_mut(),
 
         count: 0,
-    }))
+    }));
+    trace_allocation(null_mut(), dict.cast(), size_of::<FeltDict>());
+
+    dict
 }
 
 /// Free a dictionary using an optional callback to drop each element.
@@ -189,6 +399,7 @@ pub unsafe extern "C" fn cairo_native__dict_drop(
     drop_fn: Option<extern "C" fn(*mut c_void)>,
 ) {
     let dict = Box::from_raw(ptr);
+    trace_allocation(ptr.cast(), null_mut(), 0);
 
     // Free the entries manually.
     if let Some(drop_fn) = drop_fn {
@@ -203,6 +414,7 @@ pub unsafe extern "C" fn cairo_native__dict_drop(
 
     // Free the value data.
     if !dict.elements.is_null() {
+        trace_allocation(dict.elements.cast(), null_mut(), 0);
         dealloc(
             dict.elements.cast(),
             Layout::from_size_align_unchecked(
@@ -244,6 +456,18 @@ pub unsafe extern "C" fn cairo_native__dict_dup(
         // TODO: Check if `0` is fine or otherwise we should copy the value from `old_dict` too.
         count: 0,
     });
+    trace_allocation(
+        null_mut(),
+        (&mut *new_dict as *mut FeltDict).cast(),
+        size_of::<FeltDict>(),
+    );
+    if !new_dict.elements.is_null() {
+        trace_allocation(
+            null_mut(),
+            new_dict.elements.cast(),
+            old_dict.layout.pad_to_align().size() * old_dict.mappings.len(),
+        );
+    }
 
     for (new_index, (&key, &old_index)) in old_dict.mappings.iter().enumerate() {
         let old_value_ptr = old_dict
@@ -296,6 +520,7 @@ pub unsafe extern "C" fn cairo_native__dict_get(
 
             // Reallocate `mem_data` to match the slab's capacity.
             if old_capacity != dict.mappings.capacity() {
+                let old_elements = dict.elements;
                 dict.elements = realloc(
                     dict.elements.cast(),
                     Layout::from_size_align_unchecked(
@@ -305,6 +530,11 @@ pub unsafe extern "C" fn cairo_native__dict_get(
                     dict.layout.pad_to_align().size() * dict.mappings.capacity(),
                 )
                 .cast();
+                trace_allocation(
+                    old_elements.cast(),
+                    dict.elements.cast(),
+                    dict.layout.pad_to_align().size() * dict.mappings.capacity(),
+                );
             }
 
             (index, 0)
@@ -583,6 +813,171 @@ pub extern "C" fn cairo_native__get_costs_builtin() -> *const u64 {
     BUILTIN_COSTS.get()
 }
 
+/// A Sierra function was entered, the value is its function id.
+pub const PROFILE_FUNCTION_ENTER: u8 = 0;
+/// A Sierra function returned, the value is its function id.
+pub const PROFILE_FUNCTION_EXIT: u8 = 1;
+/// A libfunc is about to be invoked, the value is its concrete libfunc id.
+pub const PROFILE_LIBFUNC: u8 = 2;
+/// Gas was withdrawn by the current function, the value is the amount.
+pub const PROFILE_GAS_WITHDRAWN: u8 = 3;
+/// A Sierra function was entered, the value is its function id.
+pub const CALL_STACK_PUSH: u8 = 4;
+/// The innermost Sierra function returned, the value is non-zero if it returned a panic.
+pub const CALL_STACK_POP: u8 = 5;
+
+/// An event recorded by programs compiled with profiling or backtraces.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Event {
+    /// One of the `PROFILE_*` or `CALL_STACK_*` constants.
+    pub kind: u8,
+    pub value: u64,
+    /// Nanoseconds since an arbitrary point in time, only meaningful relative to the other events
+    /// of the same execution.
+    pub timestamp: u64,
+}
+
+/// Where programs compiled with profiling or backtraces append their events.
+///
+/// The events are written straight to a buffer preallocated by the host, which is only called back
+/// when the buffer is full.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EventBuffer {
+    /// Room for `capacity` events, of which the first `len` have been recorded.
+    pub events: *mut Event,
+    pub len: u64,
+    /// How many events fit in the buffer, or zero to discard them.
+    pub capacity: u64,
+    /// Opaque pointer passed to `flush`.
+    pub data: *mut c_void,
+    /// Called with the recorded events when the buffer is full, after which they're overwritten.
+    pub flush: Option<extern "C" fn(data: *mut c_void, events: *const Event, len: u64)>,
+}
+
+/// Append an event to the event buffer of the current execution, flushing it first if it's full.
+///
+/// Called by programs compiled with profiling when entering and returning from every function,
+/// when withdrawing gas and optionally before every libfunc invocation, and by programs compiled
+/// with backtraces when entering and returning from every function.
+#[no_mangle]
+pub extern "C" fn cairo_native__record_event(kind: u8, value: u64) {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+    let Some(state) = (unsafe { RUNTIME_STATE.get().as_mut() }) else {
+        return;
+    };
+    let buffer = &mut state.event_buffer;
+    if buffer.capacity == 0 {
+        return;
+    }
+
+    if buffer.len == buffer.capacity {
+        if let Some(flush) = buffer.flush {
+            flush(buffer.data, buffer.events, buffer.len);
+        }
+        buffer.len = 0;
+    }
+
+    let timestamp = EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64;
+    unsafe {
+        buffer.events.add(buffer.len as usize).write(Event {
+            kind,
+            value,
+            timestamp,
+        });
+    }
+    buffer.len += 1;
+}
+
+/// Where programs compiled with tracing append the statements they execute.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TraceSink {
+    /// Opaque pointer passed to `append`.
+    pub data: *mut c_void,
+    /// Called with the index of every executed statement and the values it was traced with (as
+    /// little-endian two's complement 256-bit integers), or `None` to discard them.
+    pub append: Option<
+        extern "C" fn(data: *mut c_void, statement_idx: u64, values: *const [u8; 32], len: u64),
+    >,
+}
+
+/// Append a Sierra statement to the trace of the current trace sink, if any.
+///
+/// Called by programs compiled with tracing for every statement they execute, along with the felt
+/// and integer values the statement produced when values are traced too.
+#[no_mangle]
+pub extern "C" fn cairo_native__trace_statement(
+    statement_idx: u64,
+    values: *const [u8; 32],
+    len: u64,
+) {
+    let sink = runtime_state().trace_sink;
+    if let Some(append) = sink.append {
+        append(sink.data, statement_idx, values, len);
+    }
+}
+
+/// A Sierra function was entered, the values are its arguments.
+pub const FUNCTION_HOOK_ENTER: u8 = 0;
+/// A Sierra function returned, the values are its return values.
+pub const FUNCTION_HOOK_EXIT: u8 = 1;
+
+/// The host hooks called by programs compiled with function hooks when entering and returning from
+/// every function.
+///
+/// The hooks receive the function id and a pointer to its non-builtin arguments (or return values),
+/// laid out one after another with their natural alignment. The pointer is only valid during the
+/// call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FunctionHooks {
+    /// Opaque pointer passed to `enter` and `exit`.
+    pub data: *mut c_void,
+    pub enter: Option<extern "C" fn(data: *mut c_void, function_id: u64, values: *const c_void)>,
+    pub exit: Option<extern "C" fn(data: *mut c_void, function_id: u64, values: *const c_void)>,
+}
+
+/// Call the current function hook of the given kind, if any.
+///
+/// Called by programs compiled with function hooks when entering and returning from every
+/// function.
+#[no_mangle]
+pub extern "C" fn cairo_native__function_hook(kind: u8, function_id: u64, values: *const c_void) {
+    let hooks = runtime_state().function_hooks;
+    let hook = match kind {
+        FUNCTION_HOOK_ENTER => hooks.enter,
+        _ => hooks.exit,
+    };
+    if let Some(hook) = hook {
+        hook(hooks.data, function_id, values);
+    }
+}
+
+/// The host callback called by the breakpoints of programs compiled with debug utilities.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BreakpointHandler {
+    /// Opaque pointer passed to `hit`.
+    pub data: *mut c_void,
+    /// Called with the id of the function and the index of the statement where a breakpoint was
+    /// hit, or `None` to ignore them.
+    pub hit: Option<extern "C" fn(data: *mut c_void, function_id: u64, statement_idx: u64)>,
+}
+
+/// Call the current breakpoint handler, if any.
+///
+/// Called by the breakpoints of programs compiled with debug utilities whose action is a callback.
+#[no_mangle]
+pub extern "C" fn cairo_native__breakpoint_hit(function_id: u64, statement_idx: u64) {
+    let handler = runtime_state().breakpoint_handler;
+    if let Some(hit) = handler.hit {
+        hit(handler.data, function_id, statement_idx);
+    }
+}
+
 // Utility methods for the print runtime function
 
 /// Formats the given felts as a debug string.
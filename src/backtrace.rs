@@ -0,0 +1,175 @@
+//! # Panic backtraces
+//!
+//! Programs compiled with the `with-backtrace` feature record every Sierra function they enter and
+//! return from in the runtime's event buffer, from which the executors rebuild a shadow call stack.
+//! When a function returns a panic, the stack is snapshotted before unwinding, and the executors
+//! attach it to the results as a [`PanicBacktrace`].
+//!
+//! Programs compiled without the feature don't report anything, so their panics have no
+//! backtrace.
+
+use cairo_lang_sierra::{
+    extensions::core::{CoreLibfunc, CoreType},
+    ids::FunctionId,
+    program_registry::ProgramRegistry,
+};
+use cairo_native_runtime::{Event, CALL_STACK_POP, CALL_STACK_PUSH};
+use std::{collections::BTreeMap, fmt};
+
+/// The Sierra functions that were running when a program panicked.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct PanicBacktrace {
+    /// The functions of the call stack, innermost (the one that panicked) first.
+    pub frames: Vec<BacktraceFrame>,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct BacktraceFrame {
+    pub function_id: u64,
+    /// The debug name of the function, or its id if it has none.
+    pub name: String,
+}
+
+impl fmt::Display for PanicBacktrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, frame) in self.frames.iter().enumerate() {
+            writeln!(f, "{idx:>4}: {}", frame.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the call stack of an execution, keeping it when a panic starts unwinding.
+#[derive(Debug, Default)]
+pub(crate) struct CallStackRecorder {
+    stack: Vec<u64>,
+    panic_stack: Option<Vec<u64>>,
+}
+
+impl CallStackRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track an event of the runtime's event buffer, ignoring those unrelated to the call stack.
+    pub fn record(&mut self, event: &Event) {
+        match event.kind {
+            CALL_STACK_PUSH => self.push(event.value),
+            CALL_STACK_POP => self.pop(event.value != 0),
+            _ => {}
+        }
+    }
+
+    fn push(&mut self, function_id: u64) {
+        self.stack.push(function_id);
+    }
+
+    fn pop(&mut self, panicking: bool) {
+        if !panicking {
+            // The panic (if any) was caught.
+            self.panic_stack = None;
+        } else if self.panic_stack.is_none() {
+            self.panic_stack = Some(self.stack.clone());
+        }
+        self.stack.pop();
+    }
+
+    /// Build the backtrace of the panic that ended the execution (if any), naming the functions
+    /// after the program's registry.
+    pub fn finish(
+        self,
+        registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    ) -> Option<PanicBacktrace> {
+        self.build(|function_id| {
+            registry
+                .get_function(&FunctionId::new(function_id))
+                .ok()
+                .map(|x| x.id.to_string())
+        })
+    }
+
+    /// Build the backtrace of the panic that ended the execution (if any) when only the function
+    /// names are known.
+    pub fn finish_with_names(
+        self,
+        function_names: &BTreeMap<u64, String>,
+    ) -> Option<PanicBacktrace> {
+        self.build(|function_id| function_names.get(&function_id).cloned())
+    }
+
+    fn build(self, function_name: impl Fn(u64) -> Option<String>) -> Option<PanicBacktrace> {
+        let panic_stack = self.panic_stack?;
+        Some(PanicBacktrace {
+            frames: panic_stack
+                .into_iter()
+                .rev()
+                .map(|function_id| BacktraceFrame {
+                    function_id,
+                    name: function_name(function_id).unwrap_or_else(|| format!("[{function_id}]")),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_stack_recorder() {
+        let names = BTreeMap::from([(0, "main".to_string()), (1, "inner".to_string())]);
+
+        // A caught panic leaves no backtrace.
+        let mut recorder = CallStackRecorder::new();
+        recorder.push(0);
+        recorder.push(1);
+        recorder.pop(true);
+        recorder.pop(false);
+        assert_eq!(recorder.finish_with_names(&names), None);
+
+        // The stack is kept from where the panic started, not where it stopped unwinding.
+        let mut recorder = CallStackRecorder::new();
+        recorder.push(0);
+        recorder.push(1);
+        recorder.push(2);
+        recorder.pop(true);
+        recorder.pop(true);
+        recorder.pop(true);
+        let backtrace = recorder.finish_with_names(&names).unwrap();
+        assert_eq!(
+            backtrace
+                .frames
+                .iter()
+                .map(|x| x.name.as_str())
+                .collect::<Vec<_>>(),
+            ["[2]", "inner", "main"]
+        );
+        assert_eq!(
+            backtrace.to_string(),
+            "   0: [2]\n   1: inner\n   2: main\n"
+        );
+    }
+}
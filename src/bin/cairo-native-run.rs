@@ -3,9 +3,12 @@ use cairo_lang_compiler::{
     compile_prepared_db, db::RootDatabase, project::setup_project, CompilerConfig,
 };
 use cairo_lang_runner::short_string::as_cairo_short_string;
+use cairo_lang_sierra::ids::FunctionId;
 use cairo_native::{
     context::NativeContext,
-    executor::{AotNativeExecutor, JitNativeExecutor},
+    debug_info::SourceMap,
+    execution_result::ExecutionResult,
+    executor::{AotNativeExecutor, ExecutionOptions, JitNativeExecutor, NativeExecutor},
     metadata::gas::{GasMetadata, MetadataComputationConfig},
     starknet_stub::StubSyscallHandler,
 };
@@ -59,50 +62,32 @@ fn main() -> anyhow::Result<()> {
     let mut db = RootDatabase::builder().detect_corelib().build()?;
     let main_crate_ids = setup_project(&mut db, &args.path)?;
 
-    let sierra_program = compile_prepared_db(
+    let sierra_program_with_dbg = compile_prepared_db(
         &db,
         main_crate_ids,
         CompilerConfig {
             replace_ids: true,
             ..Default::default()
         },
-    )?
-    .program;
+    )?;
+    let sierra_program = sierra_program_with_dbg.program;
+    let source_map = SourceMap::from_statements_locations(
+        &db,
+        &sierra_program_with_dbg.debug_info.statements_locations,
+    );
 
     let native_context = NativeContext::new();
 
     // Compile the sierra program into a MLIR module.
     let native_module = native_context
-        .compile(&sierra_program, false, Some(Default::default()))
+        .compile_with_source_map(
+            &sierra_program,
+            false,
+            Some(Default::default()),
+            Some(&source_map),
+        )
         .unwrap();
 
-    let native_executor: Box<dyn Fn(_, _, _, &mut StubSyscallHandler) -> _> = match args.run_mode {
-        RunMode::Aot => {
-            let executor =
-                AotNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
-            Box::new(move |function_id, args, gas, syscall_handler| {
-                executor.invoke_dynamic_with_syscall_handler(
-                    function_id,
-                    args,
-                    gas,
-                    syscall_handler,
-                )
-            })
-        }
-        RunMode::Jit => {
-            let executor =
-                JitNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
-            Box::new(move |function_id, args, gas, syscall_handler| {
-                executor.invoke_dynamic_with_syscall_handler(
-                    function_id,
-                    args,
-                    gas,
-                    syscall_handler,
-                )
-            })
-        }
-    };
-
     let gas_metadata =
         GasMetadata::new(&sierra_program, Some(MetadataComputationConfig::default())).unwrap();
 
@@ -112,10 +97,19 @@ fn main() -> anyhow::Result<()> {
         .get_initial_available_gas(&func.id, args.available_gas)
         .with_context(|| "not enough gas to run")?;
 
-    let mut syscall_handler = StubSyscallHandler::default();
-
-    let result = native_executor(&func.id, &[], Some(initial_gas), &mut syscall_handler)
-        .with_context(|| "Failed to run the function.")?;
+    let result = match args.run_mode {
+        RunMode::Aot => {
+            let executor =
+                AotNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
+            run(&executor, &func.id, initial_gas)
+        }
+        RunMode::Jit => {
+            let executor =
+                JitNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
+            run(&executor, &func.id, initial_gas)
+        }
+    }
+    .with_context(|| "Failed to run the function.")?;
 
     let run_result = result_to_runresult(&result)?;
 
@@ -140,3 +134,21 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn run(
+    executor: &impl NativeExecutor,
+    function_id: &FunctionId,
+    initial_gas: u64,
+) -> cairo_native::error::Result<ExecutionResult> {
+    let mut syscall_handler = StubSyscallHandler::default();
+
+    executor.invoke_dynamic(
+        function_id,
+        &[],
+        ExecutionOptions {
+            gas: Some(initial_gas),
+            syscall_handler: Some(&mut syscall_handler),
+            ..Default::default()
+        },
+    )
+}
@@ -1,4 +1,4 @@
-use anyhow::bail;
+use anyhow::{bail, ensure};
 use cairo_lang_compiler::{
     db::RootDatabase,
     diagnostics::DiagnosticsReporter,
@@ -9,10 +9,10 @@ use cairo_lang_starknet::starknet_plugin_suite;
 use cairo_lang_test_plugin::{compile_test_prepared_db, test_plugin_suite, TestsCompilationConfig};
 use clap::Parser;
 use colored::Colorize;
-use std::path::PathBuf;
+use std::{fs, path::PathBuf};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 use utils::{
-    test::{display_tests_summary, filter_test_cases, run_tests},
+    test::{coverage_to_lcov, display_tests_summary, filter_test_cases, run_tests},
     RunArgs, RunMode,
 };
 
@@ -49,6 +49,10 @@ struct Args {
     /// Optimization level, Valid: 0, 1, 2, 3. Values higher than 3 are considered as 3.
     #[arg(short = 'O', long, default_value_t = 0)]
     opt_level: u8,
+    /// Write the Sierra statement coverage of the tests to this file, in the LCOV format.
+    /// Requires building with the `with-coverage` feature.
+    #[arg(long)]
+    coverage: Option<PathBuf>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -63,6 +67,10 @@ fn main() -> anyhow::Result<()> {
     )?;
 
     check_compiler_path(args.single_file, &args.path)?;
+    ensure!(
+        args.coverage.is_none() || cfg!(feature = "with-coverage"),
+        "coverage requires building with the `with-coverage` feature"
+    );
 
     let db = &mut {
         let mut b = RootDatabase::builder();
@@ -82,8 +90,8 @@ fn main() -> anyhow::Result<()> {
     let test_crate_ids = main_crate_ids.clone();
     let test_config = TestsCompilationConfig {
         starknet: args.starknet,
-        add_statements_functions: false,
-        add_statements_code_locations: false,
+        add_statements_functions: args.coverage.is_some(),
+        add_statements_code_locations: args.coverage.is_some(),
         contract_declarations: None,
         contract_crate_ids: None,
         executable_crate_ids: None,
@@ -106,15 +114,19 @@ fn main() -> anyhow::Result<()> {
 
     let summary = run_tests(
         compiled.metadata.named_tests,
-        compiled.sierra_program.program,
+        &compiled.sierra_program.program,
         compiled.metadata.function_set_costs,
         RunArgs {
             run_mode: args.run_mode.clone(),
             opt_level: args.opt_level,
+            coverage: args.coverage.is_some(),
         },
     )?;
 
     display_tests_summary(&summary, filtered_out);
+    if let Some(path) = &args.coverage {
+        fs::write(path, coverage_to_lcov(&summary, &compiled.sierra_program))?;
+    }
     if !summary.failed.is_empty() {
         bail!(
             "test result: {}. {} passed; {} failed; {} ignored",
@@ -0,0 +1,514 @@
+use anyhow::{anyhow, ensure, Context};
+use cairo_lang_casm::{
+    cell_expression::{CellExpression, CellOperator},
+    operand::{CellRef, DerefOrImmediate, Register},
+};
+use cairo_lang_compiler::{
+    compile_prepared_db, db::RootDatabase, diagnostics::DiagnosticsReporter,
+    project::setup_project, CompilerConfig,
+};
+use cairo_lang_runner::{
+    build_hints_dict, casm_run::run_function, initialize_vm, CairoHintProcessor, RunResultValue,
+    SierraCasmRunner, StarknetState,
+};
+use cairo_lang_sierra::{
+    extensions::core::{CoreConcreteLibfunc, CoreLibfunc, CoreType, CoreTypeConcrete},
+    ids::{ConcreteTypeId, FunctionId},
+    program::{BranchTarget, Function, GenericArg, Program, Statement, StatementIdx},
+    program_registry::ProgramRegistry,
+};
+use cairo_lang_sierra_to_casm::{
+    compiler::{compile, SierraStatementDebugInfo, SierraToCasmConfig, StatementKindDebugInfo},
+    metadata::calc_metadata,
+    references::ReferenceExpression,
+};
+use cairo_native::{
+    context::NativeContext,
+    debug_info::SourceMap,
+    execution_result::ExecutionResult,
+    executor::{AotNativeExecutor, ExecutionOptions, JitNativeExecutor, NativeExecutor},
+    starknet_stub::StubSyscallHandler,
+    trace::{StatementTrace, TraceEntry},
+};
+use clap::Parser;
+use itertools::Itertools;
+use starknet_types_core::felt::{Felt, NonZeroFelt};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+use tracing_subscriber::{EnvFilter, FmtSubscriber};
+use utils::{find_function, result_to_runresult, RunMode};
+
+mod utils;
+
+/// Runs a Cairo program both natively and in the Cairo VM, reporting the first Sierra statement
+/// where they diverge.
+/// Requires building with the `with-trace` feature.
+/// Exits with 1 if the compilation or run fails, otherwise 0.
+#[derive(Parser, Debug)]
+#[clap(version, verbatim_doc_comment)]
+struct Args {
+    /// The Cairo project path to compile and run.
+    path: PathBuf,
+    /// Allows the compilation to succeed with warnings.
+    #[arg(long)]
+    allow_warnings: bool,
+    /// The function to run, found by the suffix of its name.
+    #[arg(short, long, default_value = "::main")]
+    function: String,
+    /// In cases where gas is available, the amount of provided gas.
+    #[arg(long)]
+    available_gas: Option<usize>,
+    /// Run with JIT or AOT (compiled).
+    #[arg(long, value_enum, default_value_t = RunMode::Jit)]
+    run_mode: RunMode,
+    /// Optimization level, Valid: 0, 1, 2, 3. Values higher than 3 are considered as 3.
+    #[arg(short = 'O', long, default_value_t = 0)]
+    opt_level: u8,
+    /// Also trace and compare the felt and integer values produced by every statement, to find
+    /// where the values diverge even if the control flow doesn't.
+    #[arg(long)]
+    values: bool,
+    /// The number of statements to show before the divergence.
+    #[arg(long, default_value_t = 10)]
+    context: usize,
+}
+
+fn main() -> anyhow::Result<()> {
+    // Configure logging and error handling.
+    tracing::subscriber::set_global_default(
+        FmtSubscriber::builder()
+            .with_env_filter(EnvFilter::from_default_env())
+            .finish(),
+    )?;
+
+    let args = Args::parse();
+    ensure!(
+        cfg!(feature = "with-trace"),
+        "tracing requires building with the `with-trace` feature"
+    );
+    if args.values {
+        std::env::set_var("NATIVE_TRACE_VALUES", "1");
+    }
+
+    let mut db = RootDatabase::builder().detect_corelib().build()?;
+    let main_crate_ids = setup_project(&mut db, &args.path)?;
+
+    let mut diagnostics_reporter = DiagnosticsReporter::stderr().with_crates(&main_crate_ids);
+    if args.allow_warnings {
+        diagnostics_reporter = diagnostics_reporter.allow_warnings();
+    }
+
+    let sierra_program_with_dbg = compile_prepared_db(
+        &db,
+        main_crate_ids,
+        CompilerConfig {
+            diagnostics_reporter,
+            replace_ids: true,
+            ..Default::default()
+        },
+    )?;
+    let sierra_program = sierra_program_with_dbg.program;
+    let source_map = SourceMap::from_statements_locations(
+        &db,
+        &sierra_program_with_dbg.debug_info.statements_locations,
+    );
+
+    let function = find_function(&sierra_program, &args.function)?;
+
+    let (native_result, native_trace) = run_native(&sierra_program, &source_map, function, &args)?;
+    let (vm_result, vm_trace, observable) =
+        run_vm(&sierra_program, function, args.available_gas, args.values)?;
+
+    let statement_name = |statement_idx: StatementIdx| {
+        let name = match &sierra_program.statements[statement_idx.0] {
+            Statement::Invocation(invocation) => invocation.libfunc_id.to_string(),
+            Statement::Return(_) => "return".to_string(),
+        };
+        match source_map.location(statement_idx) {
+            Some(location) => format!(
+                "#{statement_idx} {name} ({}:{}:{})",
+                location.file, location.line, location.column
+            ),
+            None => format!("#{statement_idx} {name}"),
+        }
+    };
+
+    let Some(divergence) = native_trace.find_divergence(&vm_trace, |x| observable.contains(&x))
+    else {
+        println!(
+            "The traces match ({} statements).",
+            native_trace.entries.len()
+        );
+        if native_result != vm_result {
+            println!("The results differ:");
+            println!("  native: {native_result:?}");
+            println!("  vm:     {vm_result:?}");
+        }
+        return Ok(());
+    };
+
+    println!(
+        "The traces diverge after {} native statements:",
+        divergence.position
+    );
+    for entry in
+        &native_trace.entries[divergence.position.saturating_sub(args.context)..divergence.position]
+    {
+        println!(
+            "  {}{}",
+            statement_name(entry.statement_idx),
+            format_values(entry)
+        );
+    }
+    match &divergence.entry {
+        Some(entry) => println!(
+            "native: {}{}",
+            statement_name(entry.statement_idx),
+            format_values(entry)
+        ),
+        None => println!("native: finished with {native_result:?}"),
+    }
+    match &divergence.expected {
+        Some(entry) => println!(
+            "vm:     {}{}",
+            statement_name(entry.statement_idx),
+            format_values(entry)
+        ),
+        None => println!("vm:     finished with {vm_result:?}"),
+    }
+
+    Ok(())
+}
+
+fn format_values(entry: &TraceEntry) -> String {
+    match entry.values.as_slice() {
+        [] => String::new(),
+        values => format!(" [{}]", values.iter().join(", ")),
+    }
+}
+
+/// Run the function natively, returning its result and trace.
+fn run_native(
+    program: &Program,
+    source_map: &SourceMap,
+    function: &Function,
+    args: &Args,
+) -> anyhow::Result<(RunResultValue, StatementTrace)> {
+    let native_context = NativeContext::new();
+    let native_module = native_context.compile_with_source_map(
+        program,
+        false,
+        Some(Default::default()),
+        Some(source_map),
+    )?;
+
+    // The executor subtracts the entry cost of the function from the available gas itself.
+    let gas = args.available_gas.map(|x| x as u64);
+
+    let result = match args.run_mode {
+        RunMode::Aot => {
+            let executor =
+                AotNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
+            run(&executor, &function.id, gas)
+        }
+        RunMode::Jit => {
+            let executor =
+                JitNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
+            run(&executor, &function.id, gas)
+        }
+    }
+    .with_context(|| "Failed to run the function.")?;
+
+    Ok((
+        result_to_runresult(&result)?,
+        result.diagnostics.trace.unwrap_or_default(),
+    ))
+}
+
+fn run(
+    executor: &impl NativeExecutor,
+    function_id: &FunctionId,
+    gas: Option<u64>,
+) -> cairo_native::error::Result<ExecutionResult> {
+    executor.invoke_dynamic(
+        function_id,
+        &[],
+        ExecutionOptions {
+            gas,
+            ..ExecutionOptions::new()
+        }
+        .with_syscall_handler(&mut StubSyscallHandler::default())
+        .with_trace(true),
+    )
+}
+
+/// Run the function in the Cairo VM, returning its result, trace and the statements that the VM
+/// can see.
+///
+/// The VM only sees the statements compiled into CASM instructions, when their first instruction
+/// is executed. When `trace_values` is set, the values produced by every statement are read from
+/// the VM memory the same way they're traced natively: those of an invocation once it jumps to the
+/// next statement, and those of a return before it runs. Function calls don't have values.
+fn run_vm(
+    program: &Program,
+    function: &Function,
+    available_gas: Option<usize>,
+    trace_values: bool,
+) -> anyhow::Result<(RunResultValue, Vec<TraceEntry>, HashSet<StatementIdx>)> {
+    let runner = SierraCasmRunner::new(
+        program.clone(),
+        Some(Default::default()),
+        Default::default(),
+        None,
+    )?;
+
+    // The runner doesn't expose the VM trace, so the function is run the same way it would,
+    // keeping it.
+    let casm_program = compile(
+        program,
+        &calc_metadata(program, Default::default())?,
+        SierraToCasmConfig {
+            gas_usage_check: true,
+            max_bytecode_size: usize::MAX,
+        },
+    )?;
+    let initial_gas = runner.get_initial_available_gas(function, available_gas)?;
+    let (entry_code, builtins) = runner.create_entry_code(function, &[], initial_gas)?;
+    let footer = SierraCasmRunner::create_code_footer();
+    let (hints_dict, string_to_hint) =
+        build_hints_dict(entry_code.iter().chain(&casm_program.instructions));
+    let assembled_program = casm_program.clone().assemble_ex(&entry_code, &footer);
+
+    let mut hint_processor = CairoHintProcessor {
+        runner: Some(&runner),
+        starknet_state: StarknetState::default(),
+        string_to_hint,
+        run_resources: Default::default(),
+        syscalls_used_resources: Default::default(),
+        no_temporary_segments: true,
+    };
+    let vm_result = run_function(
+        assembled_program.bytecode.iter(),
+        builtins,
+        initialize_vm,
+        &mut hint_processor,
+        hints_dict,
+    )
+    .map_err(|e| anyhow!("{e}"))?;
+
+    // The returned values are read from the end of the execution's memory, the same way the
+    // runner does.
+    let return_types = runner.generic_id_and_size_from_concrete(&function.signature.ret_types);
+    let (results_data, _) =
+        SierraCasmRunner::get_results_data(&return_types, &vm_result.memory, vm_result.ap);
+    let value = match results_data.into_iter().next() {
+        Some((_, values)) => {
+            // Panic results return the values of their first variant.
+            let inner_type_size = function
+                .signature
+                .ret_types
+                .last()
+                .filter(|ty| {
+                    ty.debug_name.as_deref().is_some_and(|debug_name| {
+                        debug_name.starts_with("core::panics::PanicResult::")
+                            || debug_name.starts_with("Enum<ut@core::panics::PanicResult::")
+                    })
+                })
+                .and_then(|ty| program.type_declarations.iter().find(|x| &x.id == ty))
+                .and_then(
+                    |declaration| match declaration.long_id.generic_args.get(1) {
+                        Some(GenericArg::Type(ok_ty)) => {
+                            Some(runner.generic_id_and_size_from_concrete(&[ok_ty.clone()])[0].1)
+                        }
+                        _ => None,
+                    },
+                );
+            SierraCasmRunner::handle_main_return_value(inner_type_size, values, &vm_result.memory)
+        }
+        None => RunResultValue::Success(Vec::new()),
+    };
+
+    // The program follows the entry code in the first segment, which is relocated to address 1.
+    let program_offset = 1 + entry_code.iter().map(|x| x.body.op_size()).sum::<usize>();
+    let statements = casm_program
+        .debug_info
+        .sierra_statement_info
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.end_offset > x.start_offset)
+        .map(|(statement_idx, x)| (program_offset + x.start_offset, StatementIdx(statement_idx)))
+        .collect::<HashMap<_, _>>();
+
+    let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(program)?;
+    let mut trace = Vec::<TraceEntry>::new();
+    // The entry of the last invocation, whose values are read when the next statement starts.
+    let mut pending_invocation = None;
+    for state in &vm_result.relocated_trace {
+        let Some(&statement_idx) = statements.get(&state.pc) else {
+            continue;
+        };
+        let registers = (state.ap, state.fp);
+
+        if let Some(entry_idx) = pending_invocation.take() {
+            let entry: &mut TraceEntry = &mut trace[entry_idx];
+            entry.values = invocation_values(
+                program,
+                &registry,
+                &casm_program.debug_info.sierra_statement_info,
+                entry.statement_idx,
+                statement_idx,
+                &vm_result.memory,
+                registers,
+            );
+        }
+
+        let mut values = Vec::new();
+        if trace_values {
+            match (
+                &program.statements[statement_idx.0],
+                &casm_program.debug_info.sierra_statement_info[statement_idx.0]
+                    .additional_kind_info,
+            ) {
+                (Statement::Return(_), StatementKindDebugInfo::Return(info)) => {
+                    values = read_values(
+                        &registry,
+                        info.ref_values.iter().map(|x| (&x.ty, &x.expression)),
+                        &vm_result.memory,
+                        registers,
+                    );
+                }
+                (Statement::Invocation(invocation), _) => {
+                    if !matches!(
+                        registry.get_libfunc(&invocation.libfunc_id)?,
+                        CoreConcreteLibfunc::FunctionCall(_) | CoreConcreteLibfunc::CouponCall(_)
+                    ) {
+                        pending_invocation = Some(trace.len());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        trace.push(TraceEntry {
+            statement_idx,
+            values,
+        });
+    }
+
+    Ok((value, trace, statements.into_values().collect()))
+}
+
+/// Read the values produced by the invocation at `statement_idx` from the VM memory, given the
+/// registers when the next statement (`next_statement_idx`) starts.
+///
+/// The branch taken is the one leading to the next statement, skipping the statements without
+/// instructions in between (which don't change the registers).
+fn invocation_values(
+    program: &Program,
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    statement_info: &[SierraStatementDebugInfo],
+    statement_idx: StatementIdx,
+    next_statement_idx: StatementIdx,
+    memory: &[Option<Felt>],
+    registers: (usize, usize),
+) -> Vec<Felt> {
+    let (Statement::Invocation(invocation), StatementKindDebugInfo::Invoke(info)) = (
+        &program.statements[statement_idx.0],
+        &statement_info[statement_idx.0].additional_kind_info,
+    ) else {
+        return Vec::new();
+    };
+
+    let resolve_target = |target: &BranchTarget| {
+        let mut target = statement_idx.next(target);
+        while let (Some(info), Statement::Invocation(invocation)) =
+            (statement_info.get(target.0), &program.statements[target.0])
+        {
+            match invocation.branches.as_slice() {
+                [branch] if info.end_offset == info.start_offset => {
+                    target = target.next(&branch.target);
+                }
+                _ => break,
+            }
+        }
+        target
+    };
+
+    invocation
+        .branches
+        .iter()
+        .zip(&info.result_branch_changes)
+        .find(|(branch, _)| resolve_target(&branch.target) == next_statement_idx)
+        .map(|(_, changes)| {
+            read_values(
+                registry,
+                changes.refs.iter().map(|x| (&x.ty, &x.expression)),
+                memory,
+                registers,
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Read the felt and integer values (the same ones traced natively) of the given references from
+/// the VM memory. Returns nothing if any of them can't be read, so that they aren't compared.
+fn read_values<'a>(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    refs: impl Iterator<Item = (&'a ConcreteTypeId, &'a ReferenceExpression)>,
+    memory: &[Option<Felt>],
+    (ap, fp): (usize, usize),
+) -> Vec<Felt> {
+    let read_cell = |cell: &CellRef| {
+        let base = match cell.register {
+            Register::AP => ap,
+            Register::FP => fp,
+        };
+        let address = base.checked_add_signed(cell.offset.into())?;
+        memory.get(address).copied().flatten()
+    };
+
+    refs.filter(|(ty, _)| {
+        matches!(
+            registry.get_type(ty),
+            Ok(CoreTypeConcrete::Felt252(_)
+                | CoreTypeConcrete::Uint8(_)
+                | CoreTypeConcrete::Uint16(_)
+                | CoreTypeConcrete::Uint32(_)
+                | CoreTypeConcrete::Uint64(_)
+                | CoreTypeConcrete::Uint128(_)
+                | CoreTypeConcrete::Sint8(_)
+                | CoreTypeConcrete::Sint16(_)
+                | CoreTypeConcrete::Sint32(_)
+                | CoreTypeConcrete::Sint64(_)
+                | CoreTypeConcrete::Sint128(_))
+        )
+    })
+    .map(|(_, expression)| match expression.cells.as_slice() {
+        [CellExpression::Deref(cell)] => read_cell(cell),
+        [CellExpression::DoubleDeref(cell, offset)] => {
+            let address = usize::try_from(read_cell(cell)?.to_biguint())
+                .ok()?
+                .checked_add_signed((*offset).into())?;
+            memory.get(address).copied().flatten()
+        }
+        [CellExpression::Immediate(value)] => Some(Felt::from(value)),
+        [CellExpression::BinOp { op, a, b }] => {
+            let a = read_cell(a)?;
+            let b = match b {
+                DerefOrImmediate::Deref(cell) => read_cell(cell)?,
+                DerefOrImmediate::Immediate(value) => Felt::from(&value.value),
+            };
+            Some(match op {
+                CellOperator::Add => a + b,
+                CellOperator::Sub => a - b,
+                CellOperator::Mul => a * b,
+                CellOperator::Div => a.field_div(&NonZeroFelt::try_from(b).ok()?),
+            })
+        }
+        _ => None,
+    })
+    .collect::<Option<Vec<_>>>()
+    .unwrap_or_default()
+}
@@ -1,12 +1,19 @@
-use anyhow::Context;
+use anyhow::{ensure, Context};
 use cairo_lang_sierra::program::VersionedProgram;
 use cairo_lang_test_plugin::{TestCompilation, TestCompilationMetadata};
 use clap::{Parser, ValueEnum};
 use scarb_metadata::{Metadata, MetadataCommand, ScarbCommand};
 use scarb_ui::args::PackagesFilter;
-use std::{collections::HashSet, env, fs, path::Path};
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+};
 use utils::{
-    test::{display_tests_summary, filter_test_cases, find_testable_targets, run_tests},
+    test::{
+        coverage_to_lcov, display_tests_summary, filter_test_cases, find_testable_targets,
+        run_tests,
+    },
     RunArgs, RunMode,
 };
 
@@ -38,6 +45,13 @@ struct Args {
     /// Optimization level, Valid: 0, 1, 2, 3. Values higher than 3 are considered as 3.
     #[arg(short = 'O', long, default_value_t = 0)]
     opt_level: u8,
+    /// Write the Sierra statement coverage of the tests to this file, in the LCOV format.
+    /// Requires building with the `with-coverage` feature. Enable the
+    /// `unstable-add-statements-code-locations-debug-info` and
+    /// `unstable-add-statements-functions-debug-info` options of the `[cairo]` section of
+    /// Scarb.toml to map it to the Cairo source.
+    #[arg(long)]
+    coverage: Option<PathBuf>,
 }
 
 #[derive(ValueEnum, Clone, Debug, Default)]
@@ -60,6 +74,10 @@ impl TestKind {
 
 fn main() -> anyhow::Result<()> {
     let args: Args = Args::parse();
+    ensure!(
+        args.coverage.is_none() || cfg!(feature = "with-coverage"),
+        "coverage requires building with the `with-coverage` feature"
+    );
 
     let metadata = MetadataCommand::new().inherit_stderr().exec()?;
 
@@ -104,6 +122,7 @@ fn main() -> anyhow::Result<()> {
         .join(profile);
 
     let mut deduplicator = TargetGroupDeduplicator::default();
+    let mut lcov = String::new();
     for package in matched {
         println!("testing {} ...", package.name);
 
@@ -133,18 +152,25 @@ fn main() -> anyhow::Result<()> {
 
             let summary = run_tests(
                 compiled.metadata.named_tests,
-                compiled.sierra_program.program,
+                &compiled.sierra_program.program,
                 compiled.metadata.function_set_costs,
                 RunArgs {
                     run_mode: args.run_mode.clone(),
                     opt_level: args.opt_level,
+                    coverage: args.coverage.is_some(),
                 },
             )?;
 
             display_tests_summary(&summary, filtered_out);
+            // Each target is a different program, LCOV consumers merge the records of each file.
+            lcov.push_str(&coverage_to_lcov(&summary, &compiled.sierra_program));
         }
     }
 
+    if let Some(path) = &args.coverage {
+        fs::write(path, lcov)?;
+    }
+
     Ok(())
 }
 
@@ -19,6 +19,8 @@ pub mod test;
 pub(super) struct RunArgs {
     pub run_mode: RunMode,
     pub opt_level: u8,
+    /// Collect the Sierra statements executed by the tests.
+    pub coverage: bool,
 }
 
 #[derive(Clone, Debug, ValueEnum)]
@@ -235,6 +237,7 @@ mod tests {
                     debug_name: Some("debug_name".into()),
                 },
                 builtin_stats: Default::default(),
+                diagnostics: Default::default(),
             })
             .unwrap(),
             RunResultValue::Success(vec![
@@ -258,6 +261,7 @@ mod tests {
                     debug_name: Some("core::panics::PanicResult::Test".into()),
                 },
                 builtin_stats: Default::default(),
+                diagnostics: Default::default(),
             })
             .unwrap(),
             RunResultValue::Success(vec![Felt::from(24)])
@@ -276,6 +280,7 @@ mod tests {
                 debug_name: Some("core::panics::PanicResult::Test".into()),
             },
             builtin_stats: Default::default(),
+            diagnostics: Default::default(),
         })
         .unwrap();
     }
@@ -292,6 +297,7 @@ mod tests {
                 debug_name: None,
             },
             builtin_stats: Default::default(),
+            diagnostics: Default::default(),
         })
         .unwrap();
     }
@@ -316,6 +322,7 @@ mod tests {
                     debug_name: Some("core::panics::PanicResult::Test".into()),
                 },
                 builtin_stats: Default::default(),
+                diagnostics: Default::default(),
             })
             .unwrap(),
             RunResultValue::Panic(vec![Felt::from(42), Felt::from(100), Felt::from(1000)])
@@ -330,6 +337,7 @@ mod tests {
                 remaining_gas: None,
                 return_value: Value::Uint8(10),
                 builtin_stats: Default::default(),
+                diagnostics: Default::default(),
             })
             .unwrap(),
             RunResultValue::Success(vec![Felt::from(10)])
@@ -1,7 +1,11 @@
 use super::{find_function, format_for_panic, result_to_runresult, RunArgs, RunMode};
 use anyhow::Context;
 use cairo_lang_runner::RunResultValue;
-use cairo_lang_sierra::{extensions::gas::CostTokenType, ids::FunctionId, program::Program};
+use cairo_lang_sierra::{
+    extensions::gas::CostTokenType,
+    ids::FunctionId,
+    program::{Program, ProgramArtifact},
+};
 use cairo_lang_test_plugin::{
     test_config::{PanicExpectation, TestExpectation},
     TestConfig,
@@ -10,7 +14,9 @@ use cairo_lang_test_plugin::{TestCompilation, TestCompilationMetadata};
 use cairo_lang_utils::{casts::IntoOrPanic, ordered_hash_map::OrderedHashMap};
 use cairo_native::{
     context::NativeContext,
-    executor::{AotNativeExecutor, JitNativeExecutor},
+    coverage::StatementCoverage,
+    debug_info::SourceMap,
+    executor::{AotNativeExecutor, ExecutionOptions, JitNativeExecutor, NativeExecutor},
     metadata::gas::{GasMetadata, MetadataComputationConfig},
     starknet_stub::StubSyscallHandler,
 };
@@ -27,6 +33,8 @@ pub struct TestsSummary {
     pub failed: Vec<String>,
     pub ignored: Vec<String>,
     pub failed_run_results: Vec<RunResultValue>,
+    /// The statements executed by all the tests, if requested.
+    pub coverage: Option<StatementCoverage>,
 }
 
 /// The result of a ran test.
@@ -35,6 +43,8 @@ struct TestResult {
     status: TestStatus,
     /// The gas usage of the run if relevant.
     gas_usage: Option<i64>,
+    /// The statements executed by the run if requested.
+    coverage: Option<StatementCoverage>,
 }
 
 /// The status of a ran test.
@@ -126,10 +136,25 @@ pub fn display_tests_summary(summary: &TestsSummary, filtered_out: usize) {
     }
 }
 
+/// Export the coverage of the tests in the LCOV format, mapped to the Cairo source when the program
+/// has statement code locations (and functions) in its debug info.
+pub fn coverage_to_lcov(summary: &TestsSummary, sierra_program: &ProgramArtifact) -> String {
+    let source_map = sierra_program
+        .debug_info
+        .as_ref()
+        .map(|debug_info| SourceMap::from_annotations(&debug_info.annotations));
+
+    summary
+        .coverage
+        .as_ref()
+        .map(|coverage| coverage.to_lcov(&sierra_program.program, source_map.as_ref()))
+        .unwrap_or_default()
+}
+
 /// Runs the tests and process the results for a summary.
 pub fn run_tests(
     named_tests: Vec<(String, TestConfig)>,
-    sierra_program: Program,
+    sierra_program: &Program,
     function_set_costs: OrderedHashMap<FunctionId, OrderedHashMap<CostTokenType, i32>>,
     args: RunArgs,
 ) -> anyhow::Result<TestsSummary> {
@@ -137,38 +162,45 @@ pub fn run_tests(
 
     // Compile the sierra program into a MLIR module.
     let native_module = native_context
-        .compile(&sierra_program, false, Some(Default::default()))
+        .compile(sierra_program, false, Some(Default::default()))
         .unwrap();
 
-    let native_executor: Box<dyn Fn(_, _, _, &mut StubSyscallHandler) -> _> = match args.run_mode {
+    match args.run_mode {
         RunMode::Aot => {
             let executor =
                 AotNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
-            Box::new(move |function_id, args, gas, syscall_handler| {
-                executor.invoke_dynamic_with_syscall_handler(
-                    function_id,
-                    args,
-                    gas,
-                    syscall_handler,
-                )
-            })
+            run_tests_with_executor(
+                &executor,
+                named_tests,
+                sierra_program,
+                function_set_costs,
+                args.coverage,
+            )
         }
         RunMode::Jit => {
             let executor =
                 JitNativeExecutor::from_native_module(native_module, args.opt_level.into())?;
-            Box::new(move |function_id, args, gas, syscall_handler| {
-                executor.invoke_dynamic_with_syscall_handler(
-                    function_id,
-                    args,
-                    gas,
-                    syscall_handler,
-                )
-            })
+            run_tests_with_executor(
+                &executor,
+                named_tests,
+                sierra_program,
+                function_set_costs,
+                args.coverage,
+            )
         }
-    };
+    }
+}
 
+/// Runs the tests with the given executor and process the results for a summary.
+fn run_tests_with_executor(
+    executor: &impl NativeExecutor,
+    named_tests: Vec<(String, TestConfig)>,
+    sierra_program: &Program,
+    function_set_costs: OrderedHashMap<FunctionId, OrderedHashMap<CostTokenType, i32>>,
+    coverage: bool,
+) -> anyhow::Result<TestsSummary> {
     let gas_metadata = GasMetadata::new(
-        &sierra_program,
+        sierra_program,
         Some(MetadataComputationConfig {
             function_set_costs,
             linear_ap_change_solver: true,
@@ -183,6 +215,7 @@ pub fn run_tests(
         failed: vec![],
         ignored: vec![],
         failed_run_results: vec![],
+        coverage: coverage.then(StatementCoverage::new),
     }));
     named_tests
         .into_iter()
@@ -193,17 +226,22 @@ pub fn run_tests(
                 }
                 tracing::trace!("running test {name:?}");
 
-                let func = find_function(&sierra_program, name.as_str())?;
+                let func = find_function(sierra_program, name.as_str())?;
 
                 let initial_gas = test.available_gas.map(|x| x.try_into().unwrap());
 
-                let result = native_executor(
-                    &func.id,
-                    &[],
-                    initial_gas,
-                    &mut StubSyscallHandler::default(),
-                )
-                .with_context(|| format!("Failed to run the function `{}`.", name.as_str()))?;
+                let result = executor
+                    .invoke_dynamic(
+                        &func.id,
+                        &[],
+                        ExecutionOptions {
+                            gas: initial_gas,
+                            syscall_handler: Some(&mut StubSyscallHandler::default()),
+                            ..Default::default()
+                        }
+                        .with_coverage(coverage),
+                    )
+                    .with_context(|| format!("Failed to run the function `{}`.", name.as_str()))?;
 
                 let run_result = result_to_runresult(&result)?;
                 Ok((
@@ -237,6 +275,7 @@ pub fn run_tests(
                                     .initial_required_gas(&func.id)
                                     .map(|gas| gas.try_into().unwrap())
                             }),
+                        coverage: result.diagnostics.coverage,
                     }),
                 ))
             },
@@ -254,14 +293,22 @@ pub fn run_tests(
                 }
             };
             let summary = wrapped_summary.as_mut().unwrap();
+            if let (Some(summary_coverage), Some(Some(test_coverage))) = (
+                summary.coverage.as_mut(),
+                status.as_ref().map(|x| x.coverage.as_ref()),
+            ) {
+                summary_coverage.merge(test_coverage);
+            }
             let (res_type, status_str, gas_usage) = match status {
                 Some(TestResult {
                     status: TestStatus::Success,
                     gas_usage,
+                    ..
                 }) => (&mut summary.passed, "ok".bright_green(), gas_usage),
                 Some(TestResult {
                     status: TestStatus::Fail(run_result),
                     gas_usage,
+                    ..
                 }) => {
                     summary.failed_run_results.push(run_result);
                     (&mut summary.failed, "fail".bright_red(), gas_usage)
@@ -44,8 +44,22 @@
 //!
 //! [BFS algorithm]: https://en.wikipedia.org/wiki/Breadth-first_search
 
+#[cfg(feature = "with-debug-utils")]
+use crate::metadata::auto_breakpoint::{AutoBreakpoint, BreakpointEvent};
+#[cfg(any(
+    feature = "with-backtrace",
+    feature = "with-function-hooks",
+    feature = "with-profiling",
+    feature = "with-trace"
+))]
+use crate::metadata::runtime_bindings::RuntimeBindingsMeta;
+#[cfg(feature = "with-trace")]
+use crate::utils::get_integer_layout;
+#[cfg(any(feature = "with-coverage", feature = "with-trace"))]
+use crate::utils::GepIndex;
 use crate::{
     debug::libfunc_to_name,
+    debug_info::{sierra_statements_offset, SourceLocation, SourceMap},
     error::{panic::ToNativeAssertError, Error},
     libfuncs::{BranchArg, LibfuncBuilder, LibfuncHelper},
     metadata::{
@@ -58,6 +72,8 @@ use crate::{
     utils::{generate_function_name, BlockExt},
 };
 use bumpalo::Bump;
+#[cfg(any(feature = "with-backtrace", feature = "with-trace"))]
+use cairo_lang_sierra::extensions::core::CoreTypeConcrete;
 use cairo_lang_sierra::{
     edit_state,
     extensions::{
@@ -68,8 +84,14 @@ use cairo_lang_sierra::{
     program::{Function, Invocation, Program, Statement, StatementIdx},
     program_registry::ProgramRegistry,
 };
+#[cfg(feature = "with-backtrace")]
+use cairo_lang_sierra::{ids::UserTypeId, program::GenericArg};
 use cairo_lang_utils::ordered_hash_map::OrderedHashMap;
+#[cfg(feature = "with-coverage")]
+use cairo_native_runtime::ExecutionContext;
 use itertools::Itertools;
+#[cfg(feature = "with-coverage")]
+use melior::dialect::{arith, ods};
 use melior::{
     dialect::{
         arith::CmpiPredicate,
@@ -95,6 +117,8 @@ use mlir_sys::{
     mlirLLVMDISubroutineTypeAttrGet, MlirLLVMDIEmissionKind_MlirLLVMDIEmissionKindFull,
     MlirLLVMDINameTableKind_MlirLLVMDINameTableKindDefault,
 };
+#[cfg(feature = "with-function-hooks")]
+use std::alloc::Layout;
 use std::{
     cell::Cell,
     collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
@@ -109,6 +133,45 @@ use std::{
 type BlockStorage<'c, 'a> =
     HashMap<StatementIdx, (Option<(BlockRef<'c, 'a>, Vec<VarId>)>, BlockRef<'c, 'a>)>;
 
+/// Creates the locations of the statements being compiled.
+///
+/// Statements are located in the Cairo source when it's known (see [`SourceMap`]), or in the
+/// synthetic `program.sierra` file (which has one statement per line) otherwise.
+#[derive(Clone, Copy)]
+struct StatementLocations<'a> {
+    sierra_stmt_start_offset: usize,
+    source_map: Option<&'a SourceMap>,
+}
+
+impl<'a> StatementLocations<'a> {
+    fn get<'c>(&self, context: &'c Context, statement_idx: StatementIdx) -> Location<'c> {
+        match self.source_location(statement_idx) {
+            Some(location) => {
+                Location::new(context, &location.file, location.line, location.column)
+            }
+            None => Location::new(
+                context,
+                "program.sierra",
+                self.sierra_line(statement_idx),
+                0,
+            ),
+        }
+    }
+
+    fn source_location(&self, statement_idx: StatementIdx) -> Option<&'a SourceLocation> {
+        self.source_map?.location(statement_idx)
+    }
+
+    fn function_name(&self, statement_idx: StatementIdx) -> Option<&'a str> {
+        self.source_map?.function(statement_idx)
+    }
+
+    /// The line of the statement in the `program.sierra` file.
+    fn sierra_line(&self, statement_idx: StatementIdx) -> usize {
+        self.sierra_stmt_start_offset + statement_idx.0
+    }
+}
+
 /// Run the compiler on a program. The compiled program is stored in the MLIR module.
 ///
 /// The generics `TType` and `TLibfunc` contain the information required to generate the MLIR types
@@ -121,6 +184,10 @@ type BlockStorage<'c, 'a> =
 ///
 /// Additionally, it needs a reference to the MLIR context, the output module and the metadata
 /// storage. The last one is passed externally so that stuff can be initialized if necessary.
+///
+/// If a `source_map` is provided, the debug info points at the Cairo source of the program instead
+/// of its Sierra statements.
+#[allow(clippy::too_many_arguments)]
 pub fn compile(
     context: &Context,
     module: &Module,
@@ -129,6 +196,7 @@ pub fn compile(
     metadata: &mut MetadataStorage,
     di_compile_unit_id: Attribute,
     ignore_debug_names: bool,
+    source_map: Option<&SourceMap>,
 ) -> Result<(), Error> {
     if let Ok(x) = std::env::var("NATIVE_DEBUG_DUMP") {
         if x == "1" || x == "true" {
@@ -136,15 +204,13 @@ pub fn compile(
         }
     }
 
-    // Sierra programs have the following structure:
-    //   1. Type declarations, one per line.
-    //   2. Libfunc declarations, one per line.
-    //   3. All the program statements, one per line.
-    //   4. Function declarations, one per line.
-    // The four sections are separated by a single blank line.
-    let num_types = program.type_declarations.len() + 1;
-    let n_libfuncs = program.libfunc_declarations.len() + 1;
-    let sierra_stmt_start_offset = num_types + n_libfuncs + 1;
+    let locations = StatementLocations {
+        sierra_stmt_start_offset: sierra_statements_offset(program),
+        source_map,
+    };
+
+    #[cfg(feature = "with-coverage")]
+    generate_statement_count(context, module, program.statements.len())?;
 
     for function in &program.funcs {
         tracing::info!("Compiling function `{}`.", function.id);
@@ -156,7 +222,7 @@ pub fn compile(
             &program.statements,
             metadata,
             di_compile_unit_id,
-            sierra_stmt_start_offset,
+            locations,
             ignore_debug_names,
         )?;
     }
@@ -182,15 +248,10 @@ fn compile_func(
     statements: &[Statement],
     metadata: &mut MetadataStorage,
     di_compile_unit_id: Attribute,
-    sierra_stmt_start_offset: usize,
+    locations: StatementLocations<'_>,
     ignore_debug_names: bool,
 ) -> Result<(), Error> {
-    let fn_location = Location::new(
-        context,
-        "program.sierra",
-        sierra_stmt_start_offset + function.entry_point.0,
-        0,
-    );
+    let fn_location = locations.get(context, function.entry_point);
 
     let region = Region::new();
     let blocks_arena = Bump::new();
@@ -284,6 +345,10 @@ fn compile_func(
         None
     };
 
+    // Every function takes the execution context as its last argument, see
+    // `LibfuncHelper::execution_context()`.
+    arg_types.push(llvm::r#type::pointer(context, 0));
+
     let function_name = generate_function_name(&function.id, ignore_debug_names);
     // Don't care about whether it is for the contract executor for inner impls
     // so we don't have to pass the boolean to the function call libfunc.
@@ -338,16 +403,34 @@ fn compile_func(
                 std::ptr::null(),
             );
 
+            // Point at the Cairo function instead of the Sierra one when its source is known.
+            let (subprogram_file, subprogram_line) =
+                match locations.source_location(function.entry_point) {
+                    Some(location) => {
+                        let (name, directory) = location.file_name_and_directory();
+                        let file_attr = Attribute::from_raw(mlirLLVMDIFileAttrGet(
+                            context.to_raw(),
+                            StringAttribute::new(context, name).to_raw(),
+                            StringAttribute::new(context, directory).to_raw(),
+                        ));
+                        (file_attr, location.line)
+                    }
+                    None => (file_attr, locations.sierra_line(function.entry_point)),
+                };
+            let subprogram_name = locations
+                .function_name(function.entry_point)
+                .unwrap_or(&function_name);
+
             mlirLLVMDISubprogramAttrGet(
                 context.to_raw(),
                 id,
                 module_scope,
-                file_attr.to_raw(),
-                StringAttribute::new(context, &function_name).to_raw(),
+                subprogram_file.to_raw(),
+                StringAttribute::new(context, subprogram_name).to_raw(),
                 StringAttribute::new(context, &function_name).to_raw(),
-                file_attr.to_raw(),
-                (sierra_stmt_start_offset + function.entry_point.0) as u32,
-                (sierra_stmt_start_offset + function.entry_point.0) as u32,
+                subprogram_file.to_raw(),
+                subprogram_line as u32,
+                subprogram_line as u32,
                 0x8, // dwarf subprogram flag: definition
                 ty,
             )
@@ -356,31 +439,14 @@ fn compile_func(
 
     tracing::debug!("Generating function structure (region with blocks).");
     let (entry_block, blocks, is_recursive) = generate_function_structure(
-        context,
-        module,
-        &region,
-        registry,
-        function,
-        statements,
-        metadata,
-        sierra_stmt_start_offset,
+        context, module, &region, registry, function, statements, metadata, locations,
     )?;
 
     tracing::debug!("Generating the function implementation.");
     // Workaround for the `entry block of region may not have predecessors` error:
     let pre_entry_block_args = arg_types
         .iter()
-        .map(|ty| {
-            (
-                *ty,
-                Location::new(
-                    context,
-                    "program.sierra",
-                    sierra_stmt_start_offset + function.entry_point.0,
-                    0,
-                ),
-            )
-        })
+        .map(|ty| (*ty, locations.get(context, function.entry_point)))
         .collect::<Vec<_>>();
     let pre_entry_block =
         region.insert_block_before(entry_block, Block::new(&pre_entry_block_args));
@@ -391,12 +457,7 @@ fn compile_func(
         let mut count = 0;
         for param in &function.params {
             let type_info = registry.get_type(&param.ty)?;
-            let location = Location::new(
-                context,
-                "program.sierra",
-                sierra_stmt_start_offset + function.entry_point.0,
-                0,
-            );
+            let location = locations.get(context, function.entry_point);
 
             values.push((
                 &param.id,
@@ -420,6 +481,73 @@ fn compile_func(
         values.into_iter()
     })?;
 
+    // The entry block is also the target of tail-recursive calls, so every call is reported.
+    #[cfg(feature = "with-profiling")]
+    {
+        let function_id = entry_block.const_int(context, fn_location, function.id.id, 64)?;
+        metadata
+            .get_mut::<RuntimeBindingsMeta>()
+            .ok_or(Error::MissingMetadata)?
+            .record_event(
+                context,
+                module,
+                &entry_block,
+                cairo_native_runtime::PROFILE_FUNCTION_ENTER,
+                function_id,
+                fn_location,
+            )?;
+    }
+    #[cfg(feature = "with-backtrace")]
+    {
+        let function_id = entry_block.const_int(context, fn_location, function.id.id, 64)?;
+        metadata
+            .get_mut::<RuntimeBindingsMeta>()
+            .ok_or(Error::MissingMetadata)?
+            .record_event(
+                context,
+                module,
+                &entry_block,
+                cairo_native_runtime::CALL_STACK_PUSH,
+                function_id,
+                fn_location,
+            )?;
+    }
+    #[cfg(feature = "with-function-hooks")]
+    build_function_hook(
+        context,
+        module,
+        registry,
+        metadata,
+        &pre_entry_block,
+        &entry_block,
+        cairo_native_runtime::FUNCTION_HOOK_ENTER,
+        function.id.id,
+        &function.signature.param_types,
+        &function
+            .params
+            .iter()
+            .map(|x| initial_state[&x.id])
+            .collect::<Vec<_>>(),
+        fn_location,
+    )?;
+    #[cfg(feature = "with-debug-utils")]
+    AutoBreakpoint::maybe_breakpoint(
+        context,
+        module,
+        &entry_block,
+        fn_location,
+        metadata,
+        &BreakpointEvent::FunctionEntry(function.id.clone()),
+        &function.id,
+        function.entry_point,
+    )?;
+    // Reporting every libfunc invocation is much more expensive, so it's only done on request.
+    #[cfg(feature = "with-profiling")]
+    let profile_libfuncs = std::env::var("NATIVE_PROFILE_LIBFUNCS").is_ok_and(|x| x != "0");
+    // Same for the values produced by every traced statement.
+    #[cfg(feature = "with-trace")]
+    let trace_values = std::env::var("NATIVE_TRACE_VALUES").is_ok_and(|x| x != "0");
+
     tracing::trace!("Implementing the entry block.");
     entry_block.append_operation(cf::br(
         &blocks[&function.entry_point].1,
@@ -430,14 +558,7 @@ fn compile_func(
         .iter()
         .map(|x| initial_state[x])
         .collect::<Vec<_>>(),
-        {
-            Location::new(
-                context,
-                "program.sierra",
-                sierra_stmt_start_offset + function.entry_point.0,
-                0,
-            )
-        },
+        { locations.get(context, function.entry_point) },
     ));
 
     let mut tailrec_state = Option::<(Value, BlockRef)>::None;
@@ -494,24 +615,35 @@ fn compile_func(
                         invocation.libfunc_id
                     );
 
-                    let location = Location::new(
+                    let location = locations.get(context, statement_idx);
+
+                    #[cfg(feature = "with-coverage")]
+                    build_mark_statement(
                         context,
-                        "program.sierra",
-                        sierra_stmt_start_offset + statement_idx.0,
-                        0,
-                    );
+                        &pre_entry_block,
+                        block,
+                        statement_idx,
+                        location,
+                    )?;
 
                     #[cfg(feature = "with-debug-utils")]
-                    {
-                        // If this env var exists and is a valid statement, insert a debug trap before the libfunc call.
-                        // Only on when using with-debug-utils feature.
-                        if let Ok(x) = std::env::var("NATIVE_DEBUG_TRAP_AT_STMT") {
-                            if x.eq_ignore_ascii_case(&statement_idx.0.to_string()) {
-                                block.append_operation(
-                                    melior::dialect::ods::llvm::intr_debugtrap(context, location)
-                                        .into(),
-                                );
-                            }
+                    if let Some(auto_breakpoint) = metadata.get::<AutoBreakpoint>() {
+                        let events = auto_breakpoint.invocation_events(
+                            registry,
+                            statement_idx,
+                            invocation,
+                        )?;
+                        for event in events {
+                            AutoBreakpoint::maybe_breakpoint(
+                                context,
+                                module,
+                                block,
+                                location,
+                                metadata,
+                                &event,
+                                &function.id,
+                                statement_idx,
+                            )?;
                         }
                     }
 
@@ -524,19 +656,76 @@ fn compile_func(
 
                     let (state, _) = edit_state::take_args(state, invocation.args.iter())?;
 
+                    let libfunc = registry.get_libfunc(&invocation.libfunc_id)?;
+                    #[cfg_attr(not(feature = "with-trace"), allow(unused_mut))]
+                    let mut branches = generate_branching_targets(
+                        &blocks,
+                        statements,
+                        statement_idx,
+                        invocation,
+                        &state,
+                    );
+
+                    // When values are traced, the statement is traced after the libfunc along with
+                    // the values it produced, from a block between every branch and its target.
+                    // Function calls are still traced before they run, since the statements of the
+                    // callee are traced in between (and its return traces the produced values).
+                    #[cfg(feature = "with-trace")]
+                    let mut trace_blocks = Vec::new();
+                    #[cfg(feature = "with-trace")]
+                    if trace_values && libfunc.is_function_call().is_none() {
+                        for ((target, target_args), branch_signature) in
+                            branches.iter_mut().zip(libfunc.branch_signatures())
+                        {
+                            let trace_block: &Block = blocks_arena.alloc(
+                                region.append_block(Block::new(
+                                    &branch_signature
+                                        .vars
+                                        .iter()
+                                        .map(|var| {
+                                            Ok((
+                                                registry.get_type(&var.ty)?.build(
+                                                    context, module, registry, metadata, &var.ty,
+                                                )?,
+                                                location,
+                                            ))
+                                        })
+                                        .collect::<Result<Vec<_>, Error>>()?,
+                                )),
+                            );
+
+                            let trace_args = (0..branch_signature.vars.len())
+                                .map(BranchArg::Returned)
+                                .collect();
+                            trace_blocks.push((
+                                trace_block,
+                                branch_signature,
+                                *target,
+                                std::mem::replace(target_args, trace_args),
+                            ));
+                            *target = trace_block;
+                        }
+                    } else {
+                        build_trace_statement(
+                            context,
+                            module,
+                            registry,
+                            metadata,
+                            &pre_entry_block,
+                            block,
+                            statement_idx,
+                            Vec::new(),
+                            location,
+                        )?;
+                    }
+
                     let helper = LibfuncHelper {
                         module,
                         init_block: &pre_entry_block,
                         region: &region,
                         blocks_arena: &blocks_arena,
                         last_block: Cell::new(block),
-                        branches: generate_branching_targets(
-                            &blocks,
-                            statements,
-                            statement_idx,
-                            invocation,
-                            &state,
-                        ),
+                        branches,
                         results: invocation
                             .branches
                             .iter()
@@ -544,7 +733,6 @@ fn compile_func(
                             .collect::<Vec<_>>(),
                     };
 
-                    let libfunc = registry.get_libfunc(&invocation.libfunc_id)?;
                     if is_recursive {
                         if let Some(target) = libfunc.is_function_call() {
                             if target == &function.id && state.is_empty() {
@@ -594,6 +782,23 @@ fn compile_func(
                         }
                     }
 
+                    #[cfg(feature = "with-profiling")]
+                    if profile_libfuncs {
+                        let libfunc_id =
+                            block.const_int(context, location, invocation.libfunc_id.id, 64)?;
+                        metadata
+                            .get_mut::<RuntimeBindingsMeta>()
+                            .ok_or(Error::MissingMetadata)?
+                            .record_event(
+                                context,
+                                module,
+                                block,
+                                cairo_native_runtime::PROFILE_LIBFUNC,
+                                libfunc_id,
+                                location,
+                            )?;
+                    }
+
                     libfunc.build(
                         context,
                         registry,
@@ -604,6 +809,42 @@ fn compile_func(
                     )?;
                     assert!(block.terminator().is_some());
 
+                    #[cfg(feature = "with-trace")]
+                    for (trace_block, branch_signature, target, target_args) in trace_blocks {
+                        let results = (0..trace_block.argument_count())
+                            .map(|i| Ok(trace_block.argument(i)?.into()))
+                            .collect::<Result<Vec<Value>, Error>>()?;
+
+                        build_trace_statement(
+                            context,
+                            module,
+                            registry,
+                            metadata,
+                            &pre_entry_block,
+                            trace_block,
+                            statement_idx,
+                            branch_signature
+                                .vars
+                                .iter()
+                                .map(|var| &var.ty)
+                                .zip(results.iter().copied())
+                                .collect(),
+                            location,
+                        )?;
+
+                        trace_block.append_operation(cf::br(
+                            target,
+                            &target_args
+                                .into_iter()
+                                .map(|arg| match arg {
+                                    BranchArg::External(x) => x,
+                                    BranchArg::Returned(i) => results[i],
+                                })
+                                .collect::<Vec<_>>(),
+                            location,
+                        ));
+                    }
+
                     if let Some(tailrec_meta) = metadata.remove::<TailRecursionMeta>() {
                         if let Some(return_block) = tailrec_meta.return_target() {
                             tailrec_state = Some((tailrec_meta.depth_counter(), return_block));
@@ -636,17 +877,93 @@ fn compile_func(
                     let location = Location::name(
                         context,
                         &format!("return(stmt_idx={})", statement_idx),
-                        Location::new(
-                            context,
-                            "program.sierra",
-                            sierra_stmt_start_offset + statement_idx.0,
-                            0,
-                        ),
+                        locations.get(context, statement_idx),
                     );
 
                     let (_, mut values) = edit_state::take_args(state, var_ids.iter())?;
 
                     let mut block = *block;
+                    // Deferred returns are instrumented once they're actually generated.
+                    #[cfg(feature = "with-coverage")]
+                    if !is_recursive || tailrec_state.is_some() {
+                        build_mark_statement(
+                            context,
+                            &pre_entry_block,
+                            &block,
+                            statement_idx,
+                            location,
+                        )?;
+                    }
+                    // Tail-recursive returns are reported too, since they match the entries of
+                    // tail-recursive calls.
+                    #[cfg(feature = "with-profiling")]
+                    if !is_recursive || tailrec_state.is_some() {
+                        let function_id = block.const_int(context, location, function.id.id, 64)?;
+                        metadata
+                            .get_mut::<RuntimeBindingsMeta>()
+                            .ok_or(Error::MissingMetadata)?
+                            .record_event(
+                                context,
+                                module,
+                                &block,
+                                cairo_native_runtime::PROFILE_FUNCTION_EXIT,
+                                function_id,
+                                location,
+                            )?;
+                    }
+                    #[cfg(feature = "with-trace")]
+                    if !is_recursive || tailrec_state.is_some() {
+                        build_trace_statement(
+                            context,
+                            module,
+                            registry,
+                            metadata,
+                            &pre_entry_block,
+                            &block,
+                            statement_idx,
+                            if trace_values {
+                                function
+                                    .signature
+                                    .ret_types
+                                    .iter()
+                                    .zip(values.iter().copied())
+                                    .collect()
+                            } else {
+                                Vec::new()
+                            },
+                            location,
+                        )?;
+                    }
+                    #[cfg(feature = "with-backtrace")]
+                    if !is_recursive || tailrec_state.is_some() {
+                        build_call_stack_pop(
+                            context,
+                            module,
+                            registry,
+                            metadata,
+                            &pre_entry_block,
+                            &block,
+                            &function.signature.ret_types,
+                            &values,
+                            location,
+                        )?;
+                    }
+                    #[cfg(feature = "with-function-hooks")]
+                    if !is_recursive || tailrec_state.is_some() {
+                        build_function_hook(
+                            context,
+                            module,
+                            registry,
+                            metadata,
+                            &pre_entry_block,
+                            &block,
+                            cairo_native_runtime::FUNCTION_HOOK_EXIT,
+                            function.id.id,
+                            &function.signature.ret_types,
+                            &values,
+                            location,
+                        )?;
+                    }
                     if is_recursive {
                         match tailrec_state {
                             None => {
@@ -660,12 +977,7 @@ fn compile_func(
                                 let location = Location::name(
                                     context,
                                     &format!("return(stmt_idx={}, tail_recursion)", statement_idx),
-                                    Location::new(
-                                        context,
-                                        "program.sierra",
-                                        sierra_stmt_start_offset + statement_idx.0,
-                                        0,
-                                    ),
+                                    locations.get(context, statement_idx),
                                 );
 
                                 // Perform tail recursion.
@@ -913,12 +1225,7 @@ fn compile_func(
         ],
         Location::fused(
             context,
-            &[Location::new(
-                context,
-                "program.sierra",
-                sierra_stmt_start_offset + function.entry_point.0,
-                0,
-            )],
+            &[locations.get(context, function.entry_point)],
             di_subprogram,
         ),
     ));
@@ -930,18 +1237,349 @@ fn compile_func(
         &inner_function_name,
         &pre_entry_block_args,
         &return_types,
-        Location::new(
-            context,
-            "program.sierra",
-            sierra_stmt_start_offset + function.entry_point.0,
-            0,
-        ),
+        locations.get(context, function.entry_point),
     )?;
 
     tracing::debug!("Done generating function {}.", function.id);
     Ok(())
 }
 
+/// Mark a statement as executed in the coverage bitmap of the current execution, or in its discard
+/// word if coverage isn't collected.
+#[cfg(feature = "with-coverage")]
+fn build_mark_statement<'c, 'a>(
+    context: &'c Context,
+    init_block: &'a Block<'c>,
+    block: &'a Block<'c>,
+    statement_idx: StatementIdx,
+    location: Location<'c>,
+) -> Result<(), Error> {
+    let ptr_ty = llvm::r#type::pointer(context, 0);
+    let i8_ty = IntegerType::new(context, 8).into();
+    let i64_ty = IntegerType::new(context, 64).into();
+
+    // The execution context is the last argument of every function.
+    let execution_context = init_block.arg(init_block.argument_count() - 1)?;
+    let coverage_ptr = block.gep(
+        context,
+        location,
+        execution_context,
+        &[GepIndex::Const(ExecutionContext::COVERAGE.try_into()?)],
+        i8_ty,
+    )?;
+    let coverage = block.load(context, location, coverage_ptr, ptr_ty)?;
+
+    let null_ptr = block.append_op_result(llvm::zero(ptr_ty, location))?;
+    let has_coverage = block.append_op_result(
+        ods::llvm::icmp(
+            context,
+            IntegerType::new(context, 1).into(),
+            coverage,
+            null_ptr,
+            IntegerAttribute::new(i64_ty, 1).into(),
+            location,
+        )
+        .into(),
+    )?;
+    let word_ptr = block.gep(
+        context,
+        location,
+        coverage,
+        &[GepIndex::Const((statement_idx.0 / 64).try_into()?)],
+        i64_ty,
+    )?;
+    let discard_ptr = block.gep(
+        context,
+        location,
+        execution_context,
+        &[GepIndex::Const(
+            ExecutionContext::COVERAGE_DISCARD.try_into()?,
+        )],
+        i8_ty,
+    )?;
+    let word_ptr =
+        block.append_op_result(arith::select(has_coverage, word_ptr, discard_ptr, location))?;
+
+    let word = block.load(context, location, word_ptr, i64_ty)?;
+    let bit = block.const_int(context, location, 1u64 << (statement_idx.0 % 64), 64)?;
+    let word = block.append_op_result(arith::ori(word, bit, location))?;
+    block.store(context, location, word_ptr, word)?;
+
+    Ok(())
+}
+
+/// Generate the public `cairo_native__statement_count` function, which returns the number of
+/// statements of the program so that executors can allocate coverage bitmaps for it.
+#[cfg(feature = "with-coverage")]
+fn generate_statement_count(
+    context: &Context,
+    module: &Module,
+    statement_count: usize,
+) -> Result<(), Error> {
+    let location = Location::unknown(context);
+    let i64_ty = IntegerType::new(context, 64).into();
+
+    let region = Region::new();
+    let block = region.append_block(Block::new(&[]));
+    let statement_count = block.const_int(context, location, statement_count, 64)?;
+    block.append_operation(func::r#return(&[statement_count], location));
+
+    module.body().append_operation(func::func(
+        context,
+        StringAttribute::new(context, "cairo_native__statement_count"),
+        TypeAttribute::new(FunctionType::new(context, &[], &[i64_ty]).into()),
+        region,
+        &[(
+            Identifier::new(context, "sym_visibility"),
+            StringAttribute::new(context, "public").into(),
+        )],
+        location,
+    ));
+
+    Ok(())
+}
+
+/// Append a statement to the trace of the current execution, along with the felt and integer
+/// values among the `values` it produced.
+#[cfg(feature = "with-trace")]
+#[allow(clippy::too_many_arguments)]
+fn build_trace_statement<'c, 'a>(
+    context: &'c Context,
+    module: &Module,
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    metadata: &mut MetadataStorage,
+    init_block: &'a Block<'c>,
+    block: &'a Block<'c>,
+    statement_idx: StatementIdx,
+    values: Vec<(&ConcreteTypeId, Value<'c, 'a>)>,
+    location: Location<'c>,
+) -> Result<(), Error> {
+    let i256_ty = IntegerType::new(context, 256).into();
+
+    let mut traced_values = Vec::new();
+    for (type_id, value) in values {
+        // Values are extended to 256 bits, keeping their sign.
+        traced_values.push(match registry.get_type(type_id)? {
+            CoreTypeConcrete::Felt252(_)
+            | CoreTypeConcrete::Uint8(_)
+            | CoreTypeConcrete::Uint16(_)
+            | CoreTypeConcrete::Uint32(_)
+            | CoreTypeConcrete::Uint64(_)
+            | CoreTypeConcrete::Uint128(_) => block.extui(value, i256_ty, location)?,
+            CoreTypeConcrete::Sint8(_)
+            | CoreTypeConcrete::Sint16(_)
+            | CoreTypeConcrete::Sint32(_)
+            | CoreTypeConcrete::Sint64(_)
+            | CoreTypeConcrete::Sint128(_) => block.extsi(value, i256_ty, location)?,
+            _ => continue,
+        });
+    }
+
+    let values_ptr = if traced_values.is_empty() {
+        block.append_op_result(llvm::zero(llvm::r#type::pointer(context, 0), location))?
+    } else {
+        // The buffer is allocated in the function's entry, since statements may run many times.
+        let len = init_block.const_int(context, location, traced_values.len(), 64)?;
+        let values_ptr = init_block.alloca(
+            context,
+            location,
+            i256_ty,
+            len,
+            get_integer_layout(256).align(),
+        )?;
+
+        for (idx, value) in traced_values.iter().enumerate() {
+            let value_ptr = block.gep(
+                context,
+                location,
+                values_ptr,
+                &[GepIndex::Const(idx as i32)],
+                i256_ty,
+            )?;
+            block.store(context, location, value_ptr, *value)?;
+        }
+
+        values_ptr
+    };
+
+    metadata
+        .get_mut::<RuntimeBindingsMeta>()
+        .ok_or(Error::MissingMetadata)?
+        .trace_statement(
+            context,
+            module,
+            block,
+            statement_idx.0,
+            values_ptr,
+            traced_values.len(),
+            location,
+        )?;
+
+    Ok(())
+}
+
+/// Call the host function hook of the given kind with a pointer to the non-builtin `values`, laid
+/// out like a struct.
+#[cfg(feature = "with-function-hooks")]
+#[allow(clippy::too_many_arguments)]
+fn build_function_hook<'c, 'a>(
+    context: &'c Context,
+    module: &Module,
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    metadata: &mut MetadataStorage,
+    init_block: &'a Block<'c>,
+    block: &'a Block<'c>,
+    kind: u8,
+    function_id: u64,
+    type_ids: &[ConcreteTypeId],
+    values: &[Value<'c, 'a>],
+    location: Location<'c>,
+) -> Result<(), Error> {
+    let mut layout = Option::<Layout>::None;
+    let mut field_types = Vec::new();
+    let mut field_values = Vec::new();
+    for (type_id, value) in type_ids.iter().zip(values) {
+        let type_info = registry.get_type(type_id)?;
+        if type_info.is_builtin() {
+            continue;
+        }
+
+        let type_layout = type_info.layout(registry)?;
+        layout = Some(match layout {
+            Some(layout) => layout.extend(type_layout)?.0,
+            None => type_layout,
+        });
+        field_types.push(type_info.build(context, module, registry, metadata, type_id)?);
+        field_values.push(*value);
+    }
+
+    let values_ptr = match layout {
+        None => block.append_op_result(llvm::zero(llvm::r#type::pointer(context, 0), location))?,
+        Some(layout) => {
+            // The buffer is allocated in the function's entry, since tail-recursive calls run the
+            // hooks many times.
+            let struct_ty = llvm::r#type::r#struct(context, &field_types, false);
+            let values_ptr = init_block.alloca1(context, location, struct_ty, layout.align())?;
+
+            let container = block.append_op_result(llvm::undef(struct_ty, location))?;
+            let container = block.insert_values(context, location, container, &field_values)?;
+            block.store(context, location, values_ptr, container)?;
+
+            values_ptr
+        }
+    };
+
+    metadata
+        .get_mut::<RuntimeBindingsMeta>()
+        .ok_or(Error::MissingMetadata)?
+        .function_hook(
+            context,
+            module,
+            block,
+            kind,
+            function_id,
+            values_ptr,
+            location,
+        )?;
+
+    Ok(())
+}
+
+/// Pop the function from the call stack of the current execution, reporting whether the `values`
+/// it returns are a panic.
+#[cfg(feature = "with-backtrace")]
+#[allow(clippy::too_many_arguments)]
+fn build_call_stack_pop<'c, 'a>(
+    context: &'c Context,
+    module: &Module,
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    metadata: &mut MetadataStorage,
+    init_block: &'a Block<'c>,
+    block: &'a Block<'c>,
+    ret_types: &[ConcreteTypeId],
+    values: &[Value<'c, 'a>],
+    location: Location<'c>,
+) -> Result<(), Error> {
+    let panicking = match ret_types.last().zip(values.last()) {
+        Some((type_id, value)) if is_panic_result(registry, type_id)? => {
+            let type_info = registry.get_type(type_id)?;
+            let (layout, (tag_ty, _), _) = crate::types::r#enum::get_type_for_variants(
+                context,
+                module,
+                registry,
+                metadata,
+                type_info
+                    .variants()
+                    .to_native_assert_error("a panic result should be an enum")?,
+            )?;
+
+            let tag = if type_info.is_memory_allocated(registry)? {
+                let value_ptr = init_block.alloca1(
+                    context,
+                    location,
+                    type_info.build(context, module, registry, metadata, type_id)?,
+                    layout.align(),
+                )?;
+                block.store(context, location, value_ptr, *value)?;
+                block.load(context, location, value_ptr, tag_ty)?
+            } else {
+                block.extract_value(context, location, *value, tag_ty, 0)?
+            };
+
+            // Anything but the first variant (`Ok`) is a panic.
+            let k0 = block.const_int_from_type(context, location, 0, tag_ty)?;
+            let is_panic = block.cmpi(context, CmpiPredicate::Ne, tag, k0, location)?;
+            block.extui(is_panic, IntegerType::new(context, 64).into(), location)?
+        }
+        _ => block.const_int(context, location, 0, 64)?,
+    };
+
+    metadata
+        .get_mut::<RuntimeBindingsMeta>()
+        .ok_or(Error::MissingMetadata)?
+        .record_event(
+            context,
+            module,
+            block,
+            cairo_native_runtime::CALL_STACK_POP,
+            panicking,
+            location,
+        )?;
+
+    Ok(())
+}
+
+/// Whether a type is the `PanicResult` returned by the functions that may panic, which is the only
+/// enum whose second variant is a `(core::panics::Panic, Array<felt252>)` tuple.
+///
+/// The type is detected by its structure, since the program may not have debug names.
+#[cfg(feature = "with-backtrace")]
+fn is_panic_result(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    type_id: &ConcreteTypeId,
+) -> Result<bool, Error> {
+    let CoreTypeConcrete::Enum(info) = registry.get_type(type_id)? else {
+        return Ok(false);
+    };
+    let [_, err_ty] = info.variants.as_slice() else {
+        return Ok(false);
+    };
+    let CoreTypeConcrete::Struct(info) = registry.get_type(err_ty)? else {
+        return Ok(false);
+    };
+    let [panic_ty, _] = info.members.as_slice() else {
+        return Ok(false);
+    };
+    let CoreTypeConcrete::Struct(info) = registry.get_type(panic_ty)? else {
+        return Ok(false);
+    };
+
+    Ok(matches!(
+        info.info.long_id.generic_args.first(),
+        Some(GenericArg::UserType(x)) if x.id == UserTypeId::from_string("core::panics::Panic").id
+    ))
+}
+
 #[allow(clippy::too_many_arguments)]
 fn generate_function_structure<'c, 'a>(
     context: &'c Context,
@@ -951,7 +1589,7 @@ fn generate_function_structure<'c, 'a>(
     function: &Function,
     statements: &[Statement],
     metadata_storage: &mut MetadataStorage,
-    sierra_stmt_start_offset: usize,
+    locations: StatementLocations<'_>,
 ) -> Result<(BlockRef<'c, 'a>, BlockStorage<'c, 'a>, bool), Error> {
     let initial_state = edit_state::put_results::<Type>(
         OrderedHashMap::default(),
@@ -1001,12 +1639,7 @@ fn generate_function_structure<'c, 'a>(
                     let (state, types) =
                         edit_state::take_args(state.clone(), invocation.args.iter())?;
 
-                    let location = Location::new(
-                        context,
-                        "program.sierra",
-                        sierra_stmt_start_offset + statement_idx.0,
-                        0,
-                    );
+                    let location = locations.get(context, statement_idx);
 
                     for ty in types {
                         block.add_argument(ty, location);
@@ -1071,12 +1704,7 @@ fn generate_function_structure<'c, 'a>(
                         "State must be empty after a return statement."
                     );
 
-                    let location = Location::new(
-                        context,
-                        "program.sierra",
-                        sierra_stmt_start_offset + statement_idx.0,
-                        0,
-                    );
+                    let location = locations.get(context, statement_idx);
 
                     for ty in types {
                         block.add_argument(ty, location);
@@ -1097,17 +1725,7 @@ fn generate_function_structure<'c, 'a>(
             registry,
             metadata_storage,
         )
-        .map(|ty| {
-            Ok((
-                ty?,
-                Location::new(
-                    context,
-                    "program.sierra",
-                    sierra_stmt_start_offset + function.entry_point.0,
-                    0,
-                ),
-            ))
-        })
+        .map(|ty| Ok((ty?, locations.get(context, function.entry_point))))
         .collect::<Result<Vec<_>, Error>>()?
     }));
 
@@ -1133,17 +1751,7 @@ fn generate_function_structure<'c, 'a>(
                                 .map(|(var_id, ty)| (var_id.id, *ty))
                                 .collect::<BTreeMap<_, _>>()
                                 .into_values()
-                                .map(|ty| {
-                                    (
-                                        ty,
-                                        Location::new(
-                                            context,
-                                            "program.sierra",
-                                            sierra_stmt_start_offset + statement_idx.0,
-                                            0,
-                                        ),
-                                    )
-                                })
+                                .map(|ty| (ty, locations.get(context, statement_idx)))
                                 .collect::<Vec<_>>(),
                         ),
                     ),
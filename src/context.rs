@@ -1,4 +1,7 @@
+#[cfg(feature = "with-debug-utils")]
+use crate::metadata::auto_breakpoint::{AutoBreakpoint, BreakpointEvent};
 use crate::{
+    debug_info::SourceMap,
     error::{panic::ToNativeAssertError, Error},
     ffi::{get_data_layout_rep, get_target_triple},
     metadata::{
@@ -10,6 +13,8 @@ use crate::{
     native_assert,
     utils::run_pass_manager,
 };
+#[cfg(feature = "with-debug-utils")]
+use cairo_lang_sierra::program::StatementIdx;
 use cairo_lang_sierra::{
     extensions::core::{CoreLibfunc, CoreType},
     program::Program,
@@ -72,6 +77,58 @@ impl NativeContext {
         program: &Program,
         ignore_debug_names: bool,
         gas_metadata_config: Option<MetadataComputationConfig>,
+    ) -> Result<NativeModule, Error> {
+        self.compile_with_source_map(program, ignore_debug_names, gas_metadata_config, None)
+    }
+
+    /// Compiles a sierra program like [`compile`](Self::compile), using the given [`SourceMap`]
+    /// to generate debug info that points at the program's Cairo source.
+    pub fn compile_with_source_map(
+        &self,
+        program: &Program,
+        ignore_debug_names: bool,
+        gas_metadata_config: Option<MetadataComputationConfig>,
+        source_map: Option<&SourceMap>,
+    ) -> Result<NativeModule, Error> {
+        self.compile_with_metadata(
+            program,
+            ignore_debug_names,
+            gas_metadata_config,
+            source_map,
+            MetadataStorage::new(),
+        )
+    }
+
+    /// Compiles a sierra program like [`compile_with_source_map`](Self::compile_with_source_map),
+    /// inserting the given breakpoints.
+    #[cfg(feature = "with-debug-utils")]
+    pub fn compile_with_breakpoints(
+        &self,
+        program: &Program,
+        ignore_debug_names: bool,
+        gas_metadata_config: Option<MetadataComputationConfig>,
+        source_map: Option<&SourceMap>,
+        breakpoints: AutoBreakpoint,
+    ) -> Result<NativeModule, Error> {
+        let mut metadata = MetadataStorage::new();
+        metadata.insert(breakpoints);
+
+        self.compile_with_metadata(
+            program,
+            ignore_debug_names,
+            gas_metadata_config,
+            source_map,
+            metadata,
+        )
+    }
+
+    fn compile_with_metadata(
+        &self,
+        program: &Program,
+        ignore_debug_names: bool,
+        gas_metadata_config: Option<MetadataComputationConfig>,
+        source_map: Option<&SourceMap>,
+        mut metadata: MetadataStorage,
     ) -> Result<NativeModule, Error> {
         trace!("starting sierra to mlir compilation");
         let pre_sierra_compilation_instant = Instant::now();
@@ -158,7 +215,6 @@ impl NativeContext {
         let mut module = Module::from_operation(op)
             .to_native_assert_error("value should be module operation")?;
 
-        let mut metadata = MetadataStorage::new();
         // Make the runtime library available.
         metadata.insert(RuntimeBindingsMeta::default());
         // We assume that GasMetadata will be always present when the program uses the gas builtin.
@@ -170,6 +226,22 @@ impl NativeContext {
         // Create the Sierra program registry
         let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(program)?;
 
+        #[cfg(feature = "with-debug-utils")]
+        {
+            // If this env var exists and is a valid statement, insert a debug trap before it.
+            if let Some(statement_idx) = std::env::var("NATIVE_DEBUG_TRAP_AT_STMT")
+                .ok()
+                .and_then(|x| x.parse().ok())
+            {
+                metadata
+                    .get_or_insert_with(AutoBreakpoint::default)
+                    .add_event(BreakpointEvent::Statement(StatementIdx(statement_idx)));
+            }
+            if let Some(auto_breakpoint) = metadata.get_mut::<AutoBreakpoint>() {
+                auto_breakpoint.resolve_libfuncs(program);
+            }
+        }
+
         crate::compile(
             &self.context,
             &module,
@@ -178,6 +250,7 @@ impl NativeContext {
             &mut metadata,
             unsafe { Attribute::from_raw(di_unit_id) },
             ignore_debug_names,
+            source_map,
         )?;
 
         let sierra_compilation_time = pre_sierra_compilation_instant.elapsed().as_millis();
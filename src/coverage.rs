@@ -0,0 +1,243 @@
+//! # Statement coverage
+//!
+//! Programs compiled with the `with-coverage` feature set the bit of every Sierra statement they
+//! execute in a bitmap, which is preallocated by the executor for the executions that request it
+//! through [`ExecutionOptions::with_coverage`](crate::executor::ExecutionOptions::with_coverage)
+//! and passed through the `ExecutionContext`. It's returned along with their results as a
+//! [`StatementCoverage`].
+//!
+//! Programs compiled without the feature don't mark anything, so their coverage is empty.
+//!
+//! The coverage of many executions of the same program (for example, a test suite) can be merged
+//! and exported as LCOV with [`StatementCoverage::to_lcov`].
+
+use crate::debug_info::{sierra_statements_offset, SourceMap};
+use cairo_lang_sierra::program::{Program, StatementIdx};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+/// Signature of the `cairo_native__statement_count` function of programs compiled with coverage.
+pub(crate) type StatementCountFn = extern "C" fn() -> u64;
+
+/// The set of Sierra statements executed by a program, one bit per statement index.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct StatementCoverage {
+    bits: Vec<u64>,
+}
+
+impl StatementCoverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty coverage with room for a program's `statement_count` statements, to be
+    /// filled in by its execution through [`as_mut_ptr`](Self::as_mut_ptr).
+    pub(crate) fn with_statement_count(statement_count: usize) -> Self {
+        Self {
+            bits: vec![0; statement_count.div_ceil(64)],
+        }
+    }
+
+    /// The bitmap, with a bit per statement in 64-bit words.
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u64 {
+        self.bits.as_mut_ptr()
+    }
+
+    /// Drop the trailing words without any executed statement, so that coverages of the same
+    /// statements compare equal however they were filled in.
+    pub(crate) fn shrink(&mut self) {
+        let len = self.bits.iter().rposition(|x| *x != 0).map_or(0, |x| x + 1);
+        self.bits.truncate(len);
+    }
+
+    pub fn insert(&mut self, statement_idx: StatementIdx) {
+        let (word, bit) = (statement_idx.0 / 64, statement_idx.0 % 64);
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        self.bits[word] |= 1 << bit;
+    }
+
+    pub fn contains(&self, statement_idx: StatementIdx) -> bool {
+        let (word, bit) = (statement_idx.0 / 64, statement_idx.0 % 64);
+        self.bits.get(word).is_some_and(|x| x & (1 << bit) != 0)
+    }
+
+    /// The executed statements, in order.
+    pub fn statements(&self) -> impl Iterator<Item = StatementIdx> + '_ {
+        self.bits.iter().enumerate().flat_map(|(word, bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| StatementIdx(word * 64 + bit))
+        })
+    }
+
+    /// The number of executed statements.
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|x| x.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|x| *x == 0)
+    }
+
+    /// Add the statements executed by another execution of the same program.
+    pub fn merge(&mut self, other: &Self) {
+        if other.bits.len() > self.bits.len() {
+            self.bits.resize(other.bits.len(), 0);
+        }
+        for (bits, other_bits) in self.bits.iter_mut().zip(&other.bits) {
+            *bits |= other_bits;
+        }
+    }
+
+    /// Export the coverage of `program` in the LCOV format.
+    ///
+    /// Statements are mapped to the Cairo lines they were generated from when the `source_map`
+    /// knows them, or to their line in the program's Sierra code (see `NATIVE_DEBUG_DUMP`)
+    /// otherwise. A line is covered when any of its statements were executed, and a function when
+    /// its entry point was.
+    pub fn to_lcov(&self, program: &Program, source_map: Option<&SourceMap>) -> String {
+        let sierra_offset = sierra_statements_offset(program);
+        let location = |statement_idx: StatementIdx| match source_map
+            .and_then(|x| x.location(statement_idx))
+        {
+            Some(location) => (location.file.as_str(), location.line),
+            None => ("program.sierra", sierra_offset + statement_idx.0),
+        };
+
+        #[derive(Default)]
+        struct FileRecord<'a> {
+            lines: BTreeMap<usize, bool>,
+            functions: BTreeSet<(usize, &'a str, bool)>,
+        }
+
+        let function_names = program
+            .funcs
+            .iter()
+            .map(|function| {
+                source_map
+                    .and_then(|x| x.function(function.entry_point))
+                    .map(str::to_string)
+                    .unwrap_or_else(|| function.id.to_string())
+            })
+            .collect::<Vec<_>>();
+
+        let mut files = BTreeMap::<&str, FileRecord>::new();
+        for statement_idx in (0..program.statements.len()).map(StatementIdx) {
+            let (file, line) = location(statement_idx);
+            *files
+                .entry(file)
+                .or_default()
+                .lines
+                .entry(line)
+                .or_default() |= self.contains(statement_idx);
+        }
+
+        for (function, name) in program.funcs.iter().zip(&function_names) {
+            let (file, line) = location(function.entry_point);
+            files.entry(file).or_default().functions.insert((
+                line,
+                name,
+                self.contains(function.entry_point),
+            ));
+        }
+
+        let mut lcov = String::new();
+        for (file, record) in files {
+            // Writing into a `String` never fails.
+            let _ = writeln!(lcov, "TN:");
+            let _ = writeln!(lcov, "SF:{file}");
+            for (line, name, _) in &record.functions {
+                let _ = writeln!(lcov, "FN:{line},{name}");
+            }
+            for (_, name, is_covered) in &record.functions {
+                let _ = writeln!(lcov, "FNDA:{},{name}", u8::from(*is_covered));
+            }
+            let _ = writeln!(lcov, "FNF:{}", record.functions.len());
+            let _ = writeln!(
+                lcov,
+                "FNH:{}",
+                record.functions.iter().filter(|x| x.2).count()
+            );
+            for (line, is_covered) in &record.lines {
+                let _ = writeln!(lcov, "DA:{line},{}", u8::from(*is_covered));
+            }
+            let _ = writeln!(lcov, "LF:{}", record.lines.len());
+            let _ = writeln!(lcov, "LH:{}", record.lines.values().filter(|x| **x).count());
+            let _ = writeln!(lcov, "end_of_record");
+        }
+
+        lcov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::load_cairo;
+
+    #[test]
+    fn test_statement_coverage() {
+        let mut coverage = StatementCoverage::new();
+        assert!(coverage.is_empty());
+
+        coverage.insert(StatementIdx(3));
+        coverage.insert(StatementIdx(70));
+        let mut other = StatementCoverage::new();
+        other.insert(StatementIdx(3));
+        other.insert(StatementIdx(130));
+        coverage.merge(&other);
+
+        assert!(coverage.contains(StatementIdx(130)));
+        assert!(!coverage.contains(StatementIdx(4)));
+        assert!(!coverage.contains(StatementIdx(1000)));
+        assert_eq!(coverage.len(), 3);
+        assert_eq!(
+            coverage.statements().collect::<Vec<_>>(),
+            [StatementIdx(3), StatementIdx(70), StatementIdx(130)]
+        );
+
+        // Bitmaps preallocated for a program compare equal once shrunk.
+        let mut preallocated = StatementCoverage::with_statement_count(1000);
+        unsafe { *preallocated.as_mut_ptr().add(1) |= 1 << 6 };
+        preallocated.shrink();
+        let mut inserted = StatementCoverage::new();
+        inserted.insert(StatementIdx(70));
+        assert_eq!(preallocated, inserted);
+    }
+
+    #[test]
+    fn test_statement_coverage_to_lcov() {
+        let (_, program) = load_cairo! {
+            fn run_test() -> felt252 {
+                42
+            }
+        };
+
+        let mut coverage = StatementCoverage::new();
+        coverage.insert(program.funcs[0].entry_point);
+        let lcov = coverage.to_lcov(&program, None);
+
+        let sierra_line = sierra_statements_offset(&program) + program.funcs[0].entry_point.0;
+        assert!(lcov.starts_with("TN:\nSF:program.sierra\n"));
+        assert!(lcov.contains(&format!("FN:{sierra_line},{}\n", program.funcs[0].id)));
+        assert!(lcov.contains(&format!("FNDA:1,{}\n", program.funcs[0].id)));
+        assert!(lcov.contains(&format!("DA:{sierra_line},1\n")));
+        assert!(lcov.contains(&format!("LF:{}\nLH:1\n", program.statements.len())));
+        assert!(lcov.ends_with("end_of_record\n"));
+    }
+}
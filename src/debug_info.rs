@@ -0,0 +1,215 @@
+//! # Cairo source debug info
+//!
+//! By default, the debug info of compiled programs points at a synthetic `program.sierra` file,
+//! one line per Sierra statement. A [`SourceMap`] maps the statements back to the Cairo code they
+//! were generated from instead, so that debuggers, profilers and backtraces of compiled programs
+//! show the original `.cairo` files, lines and function names.
+//!
+//! The source map is built from the [`StatementsLocations`] returned by the Sierra generator (in
+//! the `debug_info` of `compile_prepared_db`'s output), or from the debug info annotations of a
+//! compiled program, and passed to
+//! [`NativeContext::compile_with_source_map`](crate::context::NativeContext::compile_with_source_map).
+
+use cairo_lang_defs::db::DefsGroup;
+use cairo_lang_sierra::{
+    debug_info::Annotations,
+    program::{Program, StatementIdx},
+};
+use cairo_lang_sierra_generator::{
+    statements_code_locations::{SourceCodeSpan, SourceFileFullPath},
+    statements_locations::StatementsLocations,
+};
+use std::{collections::HashMap, path::Path};
+
+/// Annotation with the statements' code locations, added by the Cairo compiler when requested.
+const CODE_LOCATIONS_ANNOTATION: &str = "github.com/software-mansion/cairo-coverage";
+/// Annotation with the statements' function names, added by the Cairo compiler when requested.
+const FUNCTIONS_ANNOTATION: &str = "github.com/software-mansion/cairo-profiler";
+
+/// The line of the first statement in the Sierra code of a program (as dumped by
+/// `NATIVE_DEBUG_DUMP`), which has one statement per line.
+pub(crate) fn sierra_statements_offset(program: &Program) -> usize {
+    // Sierra programs have the following structure:
+    //   1. Type declarations, one per line.
+    //   2. Libfunc declarations, one per line.
+    //   3. All the program statements, one per line.
+    //   4. Function declarations, one per line.
+    // The four sections are separated by a single blank line.
+    let num_types = program.type_declarations.len() + 1;
+    let n_libfuncs = program.libfunc_declarations.len() + 1;
+    num_types + n_libfuncs + 1
+}
+
+/// A position in a Cairo source file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    pub file: String,
+    /// One-based line number.
+    pub line: usize,
+    /// One-based column number.
+    pub column: usize,
+}
+
+impl SourceLocation {
+    /// The file name and directory of the location, as expected by the DWARF file entries.
+    pub(crate) fn file_name_and_directory(&self) -> (&str, &str) {
+        let path = Path::new(&self.file);
+        match (
+            path.file_name().and_then(|x| x.to_str()),
+            path.parent().and_then(|x| x.to_str()),
+        ) {
+            (Some(name), Some(directory)) => (name, directory),
+            _ => (&self.file, ""),
+        }
+    }
+}
+
+/// Cairo source locations and function names of a Sierra program's statements.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    locations: HashMap<StatementIdx, SourceLocation>,
+    functions: HashMap<StatementIdx, String>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a source map from the statement locations returned by the Sierra generator.
+    ///
+    /// When a statement comes from inlined code, the innermost location (the code that was
+    /// actually written) is used.
+    pub fn from_statements_locations(
+        db: &dyn DefsGroup,
+        statements_locations: &StatementsLocations,
+    ) -> Self {
+        Self::from_parts(
+            statements_locations
+                .extract_statements_source_code_locations(db)
+                .statements_to_code_location_map,
+            statements_locations
+                .extract_statements_functions(db)
+                .statements_to_functions_map,
+        )
+    }
+
+    /// Build a source map from the debug info annotations of a compiled program, such as the
+    /// test programs compiled with statement code locations (and functions) enabled.
+    ///
+    /// Missing or malformed annotations are ignored.
+    pub fn from_annotations(annotations: &Annotations) -> Self {
+        let annotation = |key, field| {
+            annotations
+                .get(key)
+                .and_then(|x| x.get(field))
+                .cloned()
+                .and_then(|x| serde_json::from_value(x).ok())
+                .unwrap_or_default()
+        };
+
+        Self::from_parts(
+            annotation(CODE_LOCATIONS_ANNOTATION, "statements_code_locations"),
+            annotation(FUNCTIONS_ANNOTATION, "statements_functions"),
+        )
+    }
+
+    fn from_parts(
+        code_locations: HashMap<StatementIdx, Vec<(SourceFileFullPath, SourceCodeSpan)>>,
+        functions: HashMap<StatementIdx, Vec<String>>,
+    ) -> Self {
+        let mut source_map = Self::new();
+
+        for (statement_idx, locations) in code_locations {
+            if let Some((file, span)) = locations.into_iter().next() {
+                source_map.insert_location(
+                    statement_idx,
+                    SourceLocation {
+                        file: file.0,
+                        line: span.start.line + 1,
+                        column: span.start.col + 1,
+                    },
+                );
+            }
+        }
+
+        for (statement_idx, names) in functions {
+            if let Some(name) = names.into_iter().next() {
+                source_map.insert_function(statement_idx, name);
+            }
+        }
+
+        source_map
+    }
+
+    pub fn insert_location(&mut self, statement_idx: StatementIdx, location: SourceLocation) {
+        self.locations.insert(statement_idx, location);
+    }
+
+    /// Set the name of the Cairo function a statement belongs to.
+    pub fn insert_function(&mut self, statement_idx: StatementIdx, name: String) {
+        self.functions.insert(statement_idx, name);
+    }
+
+    pub fn location(&self, statement_idx: StatementIdx) -> Option<&SourceLocation> {
+        self.locations.get(&statement_idx)
+    }
+
+    pub fn function(&self, statement_idx: StatementIdx) -> Option<&str> {
+        self.functions.get(&statement_idx).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::load_cairo_with_debug_info;
+
+    #[test]
+    fn test_source_map_from_statements_locations() {
+        let (_, program, db, statements_locations) = load_cairo_with_debug_info! {
+            fn run_test() -> felt252 {
+                let x = 40;
+                x + 2
+            }
+        };
+        let source_map = SourceMap::from_statements_locations(&db, &statements_locations);
+
+        let entry_point = program.funcs[0].entry_point;
+        assert!(source_map
+            .function(entry_point)
+            .is_some_and(|name| name.ends_with("run_test")));
+
+        // The program is stringified into a single line.
+        let location = source_map
+            .location(entry_point)
+            .expect("the entry point should have a location");
+        assert!(location.file.ends_with(".cairo"));
+        assert_eq!(location.line, 1);
+    }
+
+    #[test]
+    fn test_source_map_from_annotations() {
+        let (_, _, db, statements_locations) = load_cairo_with_debug_info! {
+            fn run_test() -> felt252 {
+                let x = 40;
+                x + 2
+            }
+        };
+
+        let mut annotations =
+            Annotations::from(statements_locations.extract_statements_source_code_locations(&db));
+        annotations.extend(Annotations::from(
+            statements_locations.extract_statements_functions(&db),
+        ));
+
+        assert_eq!(
+            SourceMap::from_annotations(&annotations),
+            SourceMap::from_statements_locations(&db, &statements_locations),
+        );
+        assert_eq!(
+            SourceMap::from_annotations(&Annotations::default()),
+            SourceMap::new()
+        );
+    }
+}
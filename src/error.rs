@@ -1,10 +1,10 @@
 //! Various error types used thorough the crate.
-use crate::metadata::gas::GasMetadataError;
+use crate::{metadata::gas::GasMetadataError, starknet::SyscallError};
 use cairo_lang_sierra::extensions::modules::utils::Range;
 use cairo_lang_sierra::{
     edit_state::EditStateError, ids::ConcreteTypeId, program_registry::ProgramRegistryError,
 };
-use num_bigint::BigInt;
+use num_bigint::{BigInt, BigUint};
 use panic::NativeAssertError;
 use std::{alloc::LayoutError, num::TryFromIntError};
 use thiserror::Error;
@@ -52,6 +52,9 @@ pub enum Error {
     #[error(transparent)]
     Compiler(#[from] CompilerError),
 
+    #[error(transparent)]
+    ValueInvariant(#[from] ValueInvariantError),
+
     #[error(transparent)]
     EditStateError(#[from] EditStateError),
 
@@ -76,9 +79,30 @@ pub enum Error {
     #[error("missing BuiltinCosts global symbol, should never happen, this is a bug")]
     MissingBuiltinCostsSymbol,
 
+    #[error("runtime symbol '{0}' not found")]
+    MissingRuntimeSymbol(String),
+
     #[error("selector not found in the AotContractExecutor mappings")]
     SelectorNotFound,
 
+    #[error("function with id {0} is not an entry point of the AotContractExecutor")]
+    EntryPointNotFound(u64),
+
+    #[error("abi error: {0}")]
+    AbiError(String),
+
+    #[error("syscall handler failed: {0}")]
+    SyscallHandlerFailed(String),
+
+    #[error("execution aborted by the syscall handler: {0}")]
+    SyscallHandlerAborted(String),
+
+    #[error("execution cancelled")]
+    Cancelled,
+
+    #[error("execution timed out")]
+    TimedOut,
+
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 
@@ -89,6 +113,16 @@ pub enum Error {
     SerdeJsonError(#[from] serde_json::Error),
 }
 
+impl From<SyscallError> for Error {
+    fn from(value: SyscallError) -> Self {
+        match value {
+            SyscallError::Abort(reason) => Self::SyscallHandlerAborted(reason),
+            SyscallError::Internal(msg) => Self::SyscallHandlerFailed(msg),
+            error => Self::SyscallHandlerFailed(error.to_string()),
+        }
+    }
+}
+
 impl Error {
     pub fn make_missing_parameter(ty: &ConcreteTypeId) -> Self {
         Self::MissingParameter(
@@ -123,6 +157,32 @@ pub enum CompilerError {
     },
 }
 
+/// Violations of a type's invariants found while decoding a value in strict mode.
+#[derive(Error, Debug)]
+pub enum ValueInvariantError {
+    #[error("felt252 value {0} is not below the prime")]
+    FeltOutOfRange(Box<BigUint>),
+    #[error("enum tag {tag} is out of range for {type_id:?} with {num_variants} variants")]
+    EnumTag {
+        type_id: ConcreteTypeId,
+        tag: usize,
+        num_variants: usize,
+    },
+    #[error("BoundedInt value {value} is out of range [{}, {})", range.lower, range.upper)]
+    BoundedIntOutOfRange {
+        value: Box<BigInt>,
+        range: Box<Range>,
+    },
+    #[error("array bounds are invalid: start = {start}, end = {end}, capacity = {capacity}")]
+    ArrayBounds { start: u32, end: u32, capacity: u32 },
+    #[error("dictionary has {len} entries but no element storage")]
+    DictNullElements { len: usize },
+    #[error("dictionary entry index {index} is out of range for {len} entries")]
+    DictIndexOutOfRange { index: usize, len: usize },
+    #[error("dictionary entry index {0} is used by more than one key")]
+    DictDuplicateIndex(usize),
+}
+
 /// In Cairo Native we want to avoid the use of panic, even in situation where
 /// it *should* never happen. The downside of this is that we lose:
 /// - Possible compiler opitimizations
@@ -0,0 +1,89 @@
+//! # Execution events
+//!
+//! Programs compiled with profiling or backtraces don't call back into the host for every function
+//! call. Instead, the runtime appends raw events (their kind, value and a timestamp) to a buffer
+//! preallocated by the executor, which is only aggregated when it fills up and after the
+//! execution.
+
+use crate::{backtrace::CallStackRecorder, profiler::ProfileRecorder};
+use cairo_native_runtime::{Event, EventBuffer};
+use std::{ffi::c_void, mem::MaybeUninit, ptr::NonNull};
+
+/// How many events fit in the buffer before it's flushed.
+const EVENT_BUFFER_CAPACITY: usize = 4096;
+
+/// Aggregates the events of an execution as they're flushed from its buffer.
+#[derive(Debug)]
+pub(crate) struct EventRecorder {
+    // Written by the runtime while the execution runs, so it's kept out of the recorder's borrows.
+    buffer: NonNull<[MaybeUninit<Event>]>,
+    profile: Option<ProfileRecorder>,
+    call_stack: CallStackRecorder,
+}
+
+impl EventRecorder {
+    /// Create a recorder that tracks the call stack, and profiles the execution if requested.
+    ///
+    /// The buffer is only allocated if the events are used, which requires backtraces or a
+    /// requested profile to be compiled in. Otherwise it's empty, so the runtime discards them.
+    pub fn new(profile: bool) -> Self {
+        let capacity =
+            if cfg!(feature = "with-backtrace") || (cfg!(feature = "with-profiling") && profile) {
+                EVENT_BUFFER_CAPACITY
+            } else {
+                0
+            };
+
+        Self {
+            buffer: NonNull::from(Box::leak(Box::new_uninit_slice(capacity))),
+            profile: profile.then(ProfileRecorder::new),
+            call_stack: CallStackRecorder::new(),
+        }
+    }
+
+    /// Take the profile (if requested) and the call stack aggregated from the events.
+    pub fn into_parts(mut self) -> (Option<ProfileRecorder>, CallStackRecorder) {
+        (self.profile.take(), std::mem::take(&mut self.call_stack))
+    }
+
+    /// The event buffer that records the events of an execution into this recorder.
+    ///
+    /// The recorder must outlive the execution, and not move while it runs. The events still in
+    /// the buffer once it ends are aggregated by [`flush`](Self::flush).
+    pub fn buffer(&mut self) -> EventBuffer {
+        EventBuffer {
+            events: self.buffer.as_ptr().cast(),
+            len: 0,
+            capacity: self.buffer.len() as u64,
+            data: (self as *mut Self).cast(),
+            flush: Some(flush_events),
+        }
+    }
+
+    /// Aggregate the events left in the buffer of an execution that just ended.
+    pub fn flush(&mut self, buffer: &EventBuffer) {
+        if buffer.len > 0 {
+            self.record(unsafe { std::slice::from_raw_parts(buffer.events, buffer.len as usize) });
+        }
+    }
+
+    fn record(&mut self, events: &[Event]) {
+        for event in events {
+            if let Some(profile) = &mut self.profile {
+                profile.record(event);
+            }
+            self.call_stack.record(event);
+        }
+    }
+}
+
+impl Drop for EventRecorder {
+    fn drop(&mut self) {
+        drop(unsafe { Box::from_raw(self.buffer.as_ptr()) });
+    }
+}
+
+extern "C" fn flush_events(data: *mut c_void, events: *const Event, len: u64) {
+    let recorder = unsafe { &mut *data.cast::<EventRecorder>() };
+    recorder.record(unsafe { std::slice::from_raw_parts(events, len as usize) });
+}
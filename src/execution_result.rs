@@ -2,7 +2,16 @@
 ///
 /// This module contains the structures used to interpret the program execution results, either
 /// normal programs or starknet contracts.
-use crate::{error::Error, native_panic, utils::decode_error_message, values::Value};
+use crate::{
+    backtrace::PanicBacktrace,
+    coverage::StatementCoverage,
+    error::Error,
+    native_panic,
+    profiler::ProfileReport,
+    trace::StatementTrace,
+    utils::{decode_error_message, mem_tracing::MemoryReport},
+    values::Value,
+};
 use starknet_types_core::felt::Felt;
 
 #[derive(
@@ -36,6 +45,43 @@ pub struct ExecutionResult {
     pub remaining_gas: Option<u64>,
     pub return_value: Value,
     pub builtin_stats: BuiltinStats,
+    /// What the execution recorded besides its results.
+    #[serde(default)]
+    pub diagnostics: ExecutionDiagnostics,
+}
+
+/// What an execution recorded besides its results, shared by the results of every executor.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(default)]
+pub struct ExecutionDiagnostics {
+    /// What the program printed, only present when requested through
+    /// [`ExecutionOptions::with_captured_output`](crate::executor::ExecutionOptions::with_captured_output).
+    pub output: Option<String>,
+    /// The profile of the execution, only present when requested through
+    /// [`ExecutionOptions::with_profiling`](crate::executor::ExecutionOptions::with_profiling).
+    pub profile: Option<ProfileReport>,
+    /// The Sierra statements executed, only present when requested through
+    /// [`ExecutionOptions::with_coverage`](crate::executor::ExecutionOptions::with_coverage).
+    pub coverage: Option<StatementCoverage>,
+    /// The Sierra statements executed in order, only present when requested through
+    /// [`ExecutionOptions::with_trace`](crate::executor::ExecutionOptions::with_trace).
+    pub trace: Option<StatementTrace>,
+    /// The Sierra functions that were running when the program panicked, only present for panics
+    /// of programs compiled with the `with-backtrace` feature.
+    pub backtrace: Option<PanicBacktrace>,
+    /// The allocations of the execution, only present with the `with-mem-tracing` feature.
+    pub memory: Option<MemoryReport>,
 }
 
 /// Starknet contract execution result.
@@ -56,6 +102,140 @@ pub struct ContractExecutionResult {
     pub failure_flag: bool,
     pub return_values: Vec<Felt>,
     pub error_msg: Option<String>,
+    #[serde(default)]
+    pub builtin_stats: BuiltinStats,
+    /// The syscall side effects of the execution, only present when requested through
+    /// [`ExecutionOptions::with_receipt`](crate::executor::ExecutionOptions::with_receipt).
+    #[serde(default)]
+    pub receipt: Option<ExecutionReceipt>,
+    /// What the execution recorded besides its results.
+    #[serde(default)]
+    pub diagnostics: ExecutionDiagnostics,
+}
+
+/// The syscall side effects of a contract execution, recorded by a
+/// [`CollectingMiddleware`](crate::starknet::CollectingMiddleware).
+///
+/// Nested calls are recorded even when they fail, the rest of the side effects only when their
+/// syscall succeeds.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct ExecutionReceipt {
+    pub events: Vec<OrderedEvent>,
+    pub l2_to_l1_messages: Vec<OrderedL2ToL1Message>,
+    pub storage_reads: Vec<StorageAccess>,
+    pub storage_writes: Vec<StorageAccess>,
+    pub nested_calls: Vec<NestedCall>,
+}
+
+/// An event emitted through the `emit_event` syscall.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct OrderedEvent {
+    /// The position of the syscall within the execution.
+    pub order: usize,
+    pub keys: Vec<Felt>,
+    pub data: Vec<Felt>,
+}
+
+/// A message sent through the `send_message_to_l1` syscall.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct OrderedL2ToL1Message {
+    /// The position of the syscall within the execution.
+    pub order: usize,
+    pub to_address: Felt,
+    pub payload: Vec<Felt>,
+}
+
+/// A storage read or write. For reads, the value is the one returned by the handler.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct StorageAccess {
+    /// The position of the syscall within the execution.
+    pub order: usize,
+    pub address_domain: u32,
+    pub address: Felt,
+    pub value: Felt,
+}
+
+/// A `call_contract`, `library_call` or `deploy` syscall and its result.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct NestedCall {
+    /// The position of the syscall within the execution.
+    pub order: usize,
+    pub kind: NestedCallKind,
+    pub calldata: Vec<Felt>,
+    /// The returned values on success, or the error data on failure.
+    pub result: Result<Vec<Felt>, Vec<Felt>>,
+    /// The side effects of the callee. Empty unless the handler runs its nested calls through
+    /// the outer handler (see
+    /// [`StarknetSyscallHandler::begin_nested_call`](crate::starknet::StarknetSyscallHandler::begin_nested_call)).
+    #[serde(default)]
+    pub receipt: ExecutionReceipt,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum NestedCallKind {
+    CallContract {
+        address: Felt,
+        entry_point_selector: Felt,
+    },
+    LibraryCall {
+        class_hash: Felt,
+        function_selector: Felt,
+    },
+    Deploy {
+        class_hash: Felt,
+        contract_address_salt: Felt,
+        deploy_from_zero: bool,
+        /// The address of the deployed contract, if it succeeded.
+        contract_address: Option<Felt>,
+    },
 }
 
 impl ContractExecutionResult {
@@ -156,6 +336,17 @@ impl ContractExecutionResult {
             return_values,
             failure_flag,
             error_msg,
+            builtin_stats: result.builtin_stats,
+            receipt: None,
+            diagnostics: result.diagnostics,
         })
     }
 }
+
+/// Starknet contract execution result, with the return values decoded using the contract ABI.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AbiExecutionResult {
+    pub execution: ContractExecutionResult,
+    /// The decoded return values, or `None` if the execution failed.
+    pub return_value: Option<serde_json::Value>,
+}
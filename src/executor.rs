@@ -3,13 +3,23 @@
 //! This module provides methods to execute the programs, either via JIT or compiled ahead
 //! of time. It also provides a cache to avoid recompiling previously compiled programs.
 
-pub use self::{aot::AotNativeExecutor, contract::AotContractExecutor, jit::JitNativeExecutor};
+use self::runtime_state::{ExecutionState, Reports, SetRuntimeStateFn};
+pub use self::{
+    abi::ContractAbi, aot::AotNativeExecutor, contract::AotContractExecutor,
+    interrupt::CancellationToken, jit::JitNativeExecutor,
+};
 use crate::{
     arch::{AbiArgument, ValueWithInfoWrapper},
-    error::{panic::ToNativeAssertError, Error},
-    execution_result::{BuiltinStats, ExecutionResult},
+    coverage::{self, StatementCoverage},
+    error::{panic::ToNativeAssertError, Error, ValueInvariantError},
+    execution_result::{
+        BuiltinStats, ContractExecutionResult, ExecutionDiagnostics, ExecutionResult,
+    },
+    function_hooks::FunctionHooks,
     native_panic,
-    starknet::{handler::StarknetSyscallHandlerCallbacks, StarknetSyscallHandler},
+    starknet::{
+        handler::StarknetSyscallHandlerCallbacks, DummySyscallHandler, StarknetSyscallHandler,
+    },
     types::TypeBuilder,
     utils::{libc_free, BuiltinCosts, RangeExt},
     values::Value,
@@ -22,18 +32,29 @@ use cairo_lang_sierra::{
         starknet::StarkNetTypeConcrete,
         ConcreteType,
     },
-    ids::ConcreteTypeId,
+    ids::{ConcreteTypeId, FunctionId},
     program::FunctionSignature,
     program_registry::ProgramRegistry,
 };
+use cairo_native_runtime::{BreakpointHandler, ExecutionContext, RuntimeState};
 use libc::c_void;
+use libloading::Library;
 use num_bigint::BigInt;
 use num_traits::One;
-use std::{alloc::Layout, arch::global_asm, ptr::NonNull};
+use starknet_types_core::felt::Felt;
+use std::{
+    alloc::Layout,
+    arch::global_asm,
+    ptr::NonNull,
+    time::{Duration, SystemTime},
+};
 
+mod abi;
 mod aot;
 mod contract;
+mod interrupt;
 mod jit;
+mod runtime_state;
 
 #[cfg(target_arch = "aarch64")]
 global_asm!(include_str!("arch/aarch64.s"));
@@ -54,6 +75,349 @@ extern "C" {
     );
 }
 
+/// Per-call configuration accepted by every executor's invoke methods.
+///
+/// Every field is optional, leaving them unset keeps the defaults:
+///   - `gas`: No gas is made available to the function.
+///   - `builtin_costs`: The costs returned by [`BuiltinCosts::default`].
+///   - `syscall_handler`: No syscall handler is provided, which is an error for entry points that
+///     require one.
+///   - `collect_receipt`: No [`ExecutionReceipt`](crate::execution_result::ExecutionReceipt) is
+///     attached to contract execution results.
+///   - `cancellation` and `deadline`: The execution can't be interrupted, unless it's running
+///     from a syscall handler of an execution that can. Only programs compiled with the
+///     `with-interrupts` feature can be interrupted at all.
+///   - `diagnostics`: Nothing is recorded besides the results, see [`DiagnosticOptions`].
+pub struct ExecutionOptions<S = DummySyscallHandler> {
+    pub gas: Option<u64>,
+    pub builtin_costs: Option<BuiltinCosts>,
+    pub syscall_handler: Option<S>,
+    pub collect_receipt: bool,
+    pub cancellation: Option<CancellationToken>,
+    pub deadline: Option<SystemTime>,
+    pub diagnostics: DiagnosticOptions,
+}
+
+/// What an execution records besides its results, returned as the [`ExecutionDiagnostics`] of
+/// the results.
+///
+/// Every field is optional, leaving them unset keeps the defaults:
+///   - `capture_output`: Prints are written to stdout, unless running from a syscall handler of an
+///     execution that captures them.
+///   - `profile`: No [`ProfileReport`](crate::profiler::ProfileReport) is attached to the results.
+///   - `coverage`: No [`StatementCoverage`](crate::coverage::StatementCoverage) is attached to the
+///     results.
+///   - `trace`: No [`StatementTrace`](crate::trace::StatementTrace) is attached to the results.
+///   - `function_hooks`: No [`FunctionHooks`](crate::function_hooks::FunctionHooks) are called.
+///   - `breakpoint_handler`: The breakpoints whose action is a callback are ignored.
+#[derive(Debug, Default)]
+pub struct DiagnosticOptions {
+    pub capture_output: bool,
+    pub profile: bool,
+    pub coverage: bool,
+    pub trace: bool,
+    pub function_hooks: Option<FunctionHooks>,
+    pub breakpoint_handler: Option<BreakpointHandler>,
+}
+
+impl DiagnosticOptions {
+    /// Create a [`DiagnosticOptions`] with every option unset.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ExecutionOptions {
+    /// Create an [`ExecutionOptions`] with every option unset.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> ExecutionOptions<S> {
+    pub fn with_gas(mut self, gas: u64) -> Self {
+        self.gas = Some(gas);
+        self
+    }
+
+    pub fn with_builtin_costs(mut self, builtin_costs: BuiltinCosts) -> Self {
+        self.builtin_costs = Some(builtin_costs);
+        self
+    }
+
+    pub fn with_syscall_handler<T>(self, syscall_handler: T) -> ExecutionOptions<T>
+    where
+        T: StarknetSyscallHandler,
+    {
+        ExecutionOptions {
+            gas: self.gas,
+            builtin_costs: self.builtin_costs,
+            syscall_handler: Some(syscall_handler),
+            collect_receipt: self.collect_receipt,
+            cancellation: self.cancellation,
+            deadline: self.deadline,
+            diagnostics: self.diagnostics,
+        }
+    }
+
+    /// Record the syscall side effects of contract executions, attaching them to the result as an
+    /// [`ExecutionReceipt`](crate::execution_result::ExecutionReceipt).
+    pub fn with_receipt(mut self, collect_receipt: bool) -> Self {
+        self.collect_receipt = collect_receipt;
+        self
+    }
+
+    /// Stop the execution with [`Error::Cancelled`] once the token is cancelled.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Stop the execution with [`Error::TimedOut`] if it's still running at the given time.
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop the execution with [`Error::TimedOut`] if it's still running after the given duration.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(SystemTime::now() + timeout)
+    }
+
+    /// Capture what the program prints instead of writing it to stdout, returning it in the
+    /// execution result.
+    pub fn with_captured_output(mut self, capture_output: bool) -> Self {
+        self.diagnostics.capture_output = capture_output;
+        self
+    }
+
+    /// Time the function calls (and libfunc invocations) of the execution and attribute the gas it
+    /// withdraws to them, returning a [`ProfileReport`](crate::profiler::ProfileReport) in the
+    /// execution result.
+    ///
+    /// Only programs compiled with the `with-profiling` feature report them, the report of any
+    /// other program is empty.
+    pub fn with_profiling(mut self, profile: bool) -> Self {
+        self.diagnostics.profile = profile;
+        self
+    }
+
+    /// Collect the Sierra statements executed, returning them as a
+    /// [`StatementCoverage`](crate::coverage::StatementCoverage) in the execution result.
+    ///
+    /// Only programs compiled with the `with-coverage` feature mark them, the coverage of any other
+    /// program is empty.
+    pub fn with_coverage(mut self, coverage: bool) -> Self {
+        self.diagnostics.coverage = coverage;
+        self
+    }
+
+    /// Record the Sierra statements executed, in order, returning them as a
+    /// [`StatementTrace`](crate::trace::StatementTrace) in the execution result.
+    ///
+    /// Only programs compiled with the `with-trace` feature record them, the trace of any other
+    /// program is empty.
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.diagnostics.trace = trace;
+        self
+    }
+
+    /// Call the given [`FunctionHooks`](crate::function_hooks::FunctionHooks) when entering and
+    /// returning from every Sierra function.
+    ///
+    /// Only programs compiled with the `with-function-hooks` feature call them.
+    pub fn with_function_hooks(mut self, function_hooks: FunctionHooks) -> Self {
+        self.diagnostics.function_hooks = Some(function_hooks);
+        self
+    }
+
+    /// Call the given [`BreakpointHandler`] whenever a breakpoint whose action is a callback is
+    /// hit, with the function id and statement index where it was hit.
+    ///
+    /// Only programs compiled with the `with-debug-utils` feature and
+    /// [`NativeContext::compile_with_breakpoints`](crate::context::NativeContext::compile_with_breakpoints)
+    /// have such breakpoints.
+    pub fn with_breakpoint_handler(mut self, breakpoint_handler: BreakpointHandler) -> Self {
+        self.diagnostics.breakpoint_handler = Some(breakpoint_handler);
+        self
+    }
+}
+
+impl<S> Default for ExecutionOptions<S> {
+    fn default() -> Self {
+        Self {
+            gas: None,
+            builtin_costs: None,
+            syscall_handler: None,
+            collect_receipt: false,
+            cancellation: None,
+            deadline: None,
+            diagnostics: DiagnosticOptions::default(),
+        }
+    }
+}
+
+impl<S> std::fmt::Debug for ExecutionOptions<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExecutionOptions")
+            .field("gas", &self.gas)
+            .field("builtin_costs", &self.builtin_costs)
+            .field("syscall_handler", &self.syscall_handler.is_some())
+            .field("collect_receipt", &self.collect_receipt)
+            .field("cancellation", &self.cancellation)
+            .field("deadline", &self.deadline)
+            .field("diagnostics", &self.diagnostics)
+            .finish()
+    }
+}
+
+/// Common interface over the JIT, AOT and contract executors.
+///
+/// It allows writing code that is generic over the execution backend (or a mock of it).
+pub trait NativeExecutor {
+    /// Execute a function with the given arguments and [`ExecutionOptions`].
+    fn invoke_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Value],
+        options: ExecutionOptions<S>,
+    ) -> Result<ExecutionResult, Error>
+    where
+        S: StarknetSyscallHandler;
+
+    /// Execute a contract entry point with the given calldata and [`ExecutionOptions`].
+    fn invoke_contract_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        options: ExecutionOptions<S>,
+    ) -> Result<ContractExecutionResult, Error>
+    where
+        S: StarknetSyscallHandler;
+
+    /// Find a symbol in the compiled program, if it exists.
+    fn find_symbol_ptr(&self, name: &str) -> Option<*mut c_void>;
+}
+
+impl NativeExecutor for AotNativeExecutor {
+    fn invoke_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Value],
+        options: ExecutionOptions<S>,
+    ) -> Result<ExecutionResult, Error>
+    where
+        S: StarknetSyscallHandler,
+    {
+        self.invoke_dynamic_with_options(function_id, args, options)
+    }
+
+    fn invoke_contract_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        options: ExecutionOptions<S>,
+    ) -> Result<ContractExecutionResult, Error>
+    where
+        S: StarknetSyscallHandler,
+    {
+        self.invoke_contract_dynamic_with_options(function_id, args, options)
+    }
+
+    fn find_symbol_ptr(&self, name: &str) -> Option<*mut c_void> {
+        AotNativeExecutor::find_symbol_ptr(self, name)
+    }
+}
+
+impl NativeExecutor for JitNativeExecutor<'_> {
+    fn invoke_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Value],
+        options: ExecutionOptions<S>,
+    ) -> Result<ExecutionResult, Error>
+    where
+        S: StarknetSyscallHandler,
+    {
+        self.invoke_dynamic_with_options(function_id, args, options)
+    }
+
+    fn invoke_contract_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        options: ExecutionOptions<S>,
+    ) -> Result<ContractExecutionResult, Error>
+    where
+        S: StarknetSyscallHandler,
+    {
+        self.invoke_contract_dynamic_with_options(function_id, args, options)
+    }
+
+    fn find_symbol_ptr(&self, name: &str) -> Option<*mut c_void> {
+        JitNativeExecutor::find_symbol_ptr(self, name)
+    }
+}
+
+/// The runtime functions used to install the state of an execution, along with the statement
+/// count of programs compiled with coverage.
+///
+/// They're looked up in each executor's module once it's built, since AOT-compiled libraries link
+/// their own copy of the runtime.
+#[derive(Clone, Copy)]
+pub(crate) struct RuntimeSymbols {
+    set_costs_builtin: Option<extern "C" fn(*const u64) -> *const u64>,
+    pub set_runtime_state: SetRuntimeStateFn,
+    /// The number of statements of the program, or zero if it wasn't compiled with coverage.
+    pub statement_count: usize,
+}
+
+impl RuntimeSymbols {
+    /// Look up the runtime functions of a module.
+    ///
+    /// Libraries compiled before the runtime state was added don't have its setter, but their
+    /// programs don't use the state either, so it's replaced by a function that installs nothing.
+    /// Only the builtin costs setter is required, which is checked on invocation.
+    pub fn resolve(find_symbol_ptr: impl Fn(&str) -> Option<*mut c_void>) -> Self {
+        /// Stands in for a missing runtime state setter, returning the given state as the
+        /// previous one.
+        extern "C" fn missing_setter(state: *mut RuntimeState) -> *mut RuntimeState {
+            state
+        }
+
+        Self {
+            set_costs_builtin: find_symbol_ptr("cairo_native__set_costs_builtin")
+                .map(|ptr| unsafe { std::mem::transmute::<*mut c_void, _>(ptr) }),
+            set_runtime_state: find_symbol_ptr("cairo_native__set_runtime_state")
+                .map_or(missing_setter, |ptr| unsafe {
+                    std::mem::transmute::<*mut c_void, SetRuntimeStateFn>(ptr)
+                }),
+            statement_count: find_symbol_ptr("cairo_native__statement_count").map_or(0, |ptr| {
+                let statement_count: coverage::StatementCountFn =
+                    unsafe { std::mem::transmute(ptr) };
+                statement_count() as usize
+            }),
+        }
+    }
+
+    /// The builtin costs setter, required by every program.
+    pub fn set_costs_builtin(&self) -> Result<extern "C" fn(*const u64) -> *const u64, Error> {
+        self.set_costs_builtin.ok_or_else(|| {
+            Error::MissingRuntimeSymbol("cairo_native__set_costs_builtin".to_string())
+        })
+    }
+}
+
+/// Find a symbol of an AOT-compiled library.
+pub(crate) fn find_library_symbol(library: &Library, name: &str) -> Option<*mut c_void> {
+    unsafe {
+        library
+            .get::<*mut ()>(name.as_bytes())
+            .ok()
+            .map(|x| x.into_raw().into_raw())
+    }
+}
+
 /// Internal method.
 ///
 /// Invokes the given function by constructing the function call depending on the arguments given.
@@ -63,15 +427,31 @@ extern "C" {
 /// constructs the function call in place.
 ///
 /// To pass the arguments, they are stored in a arena.
+///
+/// When `strict_decoding` is set, the return values are validated against their type's invariants
+/// while being parsed (see [`Value::from_ptr`]).
+///
+/// The gas of the `options` is ignored, `gas` is used instead since it already accounts for the
+/// function's initial cost.
 fn invoke_dynamic(
     registry: &ProgramRegistry<CoreType, CoreLibfunc>,
     function_ptr: *const c_void,
-    set_builtin_costs_fnptr: extern "C" fn(*const u64) -> *const u64,
+    runtime: RuntimeSymbols,
     function_signature: &FunctionSignature,
     args: &[Value],
     gas: u64,
-    mut syscall_handler: Option<impl StarknetSyscallHandler>,
+    options: ExecutionOptions<impl StarknetSyscallHandler>,
+    strict_decoding: bool,
 ) -> Result<ExecutionResult, Error> {
+    let ExecutionOptions {
+        builtin_costs,
+        mut syscall_handler,
+        cancellation,
+        deadline,
+        diagnostics,
+        ..
+    } = options;
+
     tracing::info!("Invoking function with signature: {function_signature:?}.");
     let arena = Bump::new();
     let mut invoke_data = Vec::<u8>::new();
@@ -129,24 +509,29 @@ fn invoke_dynamic(
     let mut syscall_handler = syscall_handler
         .as_mut()
         .map(|syscall_handler| StarknetSyscallHandlerCallbacks::new(syscall_handler));
-    // We only care for the previous syscall handler if we actually modify it
     #[cfg(feature = "with-cheatcode")]
-    let previous_syscall_handler = syscall_handler.as_mut().map(|syscall_handler| {
-        let previous_syscall_handler = crate::starknet::SYSCALL_HANDLER_VTABLE.get();
-        let syscall_handler_ptr = std::ptr::addr_of!(*syscall_handler) as *mut ();
-        crate::starknet::SYSCALL_HANDLER_VTABLE.set(syscall_handler_ptr);
-
-        previous_syscall_handler
-    });
+    let mut cheatcode_context = syscall_handler
+        .as_mut()
+        .map(crate::starknet::handler::CheatcodeContext::new);
+    let mut execution_context = ExecutionContext::default();
+    let execution_interrupt = interrupt::install(cancellation.as_ref(), deadline);
+    execution_context.interrupt = execution_interrupt.as_ptr();
+    #[cfg(feature = "with-cheatcode")]
+    if let Some(cheatcode_context) = &mut cheatcode_context {
+        execution_context.cheatcode_context =
+            (cheatcode_context as *mut crate::starknet::handler::CheatcodeContext).cast();
+    }
+    // Programs compiled with coverage mark their statements in a bitmap preallocated for them.
+    let mut coverage = diagnostics
+        .coverage
+        .then(|| StatementCoverage::with_statement_count(runtime.statement_count));
+    if let Some(coverage) = coverage.as_mut().filter(|_| runtime.statement_count > 0) {
+        execution_context.coverage = coverage.as_mut_ptr();
+    }
 
     // Order matters, for the libfunc impl
-    let builtin_costs_stack: [u64; 7] = BuiltinCosts::default().into();
-    // Note: the ptr into a slice is valid, it can be used with cast()
-    // Care should be taken if you dereference it and take the .as_ptr() of the slice, since when you
-    // deref it, it will be a copy on the stack, so you will get the ptr of the value in the stack.
-    let builtin_costs: *mut [u64; 7] = Box::into_raw(Box::new(builtin_costs_stack));
-    // We may be inside a recursive contract, save the possible saved builtin costs to restore it after our call.
-    let old_builtincosts_ptr = set_builtin_costs_fnptr(builtin_costs.cast());
+    let builtin_costs: [u64; 7] = builtin_costs.unwrap_or_default().into();
+    let set_costs_builtin = runtime.set_costs_builtin()?;
 
     // Generate argument list.
     let mut iter = args.iter();
@@ -174,7 +559,7 @@ fn invoke_dynamic(
                     .to_bytes(&mut invoke_data)?;
             }
             CoreTypeConcrete::BuiltinCosts(_) => {
-                builtin_costs.to_bytes(&mut invoke_data)?;
+                builtin_costs.as_ptr().to_bytes(&mut invoke_data)?;
             }
             type_info if type_info.is_builtin() => 0u64.to_bytes(&mut invoke_data)?,
             type_info => ValueWithInfoWrapper {
@@ -191,6 +576,9 @@ fn invoke_dynamic(
         }
     }
 
+    // Every function takes the execution context as its last argument.
+    (&mut execution_context as *mut ExecutionContext).to_bytes(&mut invoke_data)?;
+
     // Pad invoke data to the 16 byte boundary avoid segfaults.
     #[cfg(target_arch = "aarch64")]
     const REGISTER_BYTES: usize = 64;
@@ -209,6 +597,14 @@ fn invoke_dynamic(
     #[cfg(target_arch = "aarch64")]
     let mut ret_registers = [0; 4];
 
+    let mut state = ExecutionState::new(&diagnostics);
+    // The state shared with the caller (if we're running from a syscall) is only replaced right
+    // before invoking the program, so that returning early leaves it untouched.
+    // We may be inside a recursive contract, save the possible saved builtin costs to restore it after our call.
+    let old_builtincosts_ptr = set_costs_builtin(builtin_costs.as_ptr());
+    let previous_state = state.install(runtime.set_runtime_state);
+    // Keep the abort error of the caller apart from ours.
+    let previous_abort_error = crate::starknet::handler::replace_abort_error(None);
     unsafe {
         invoke_trampoline(
             function_ptr,
@@ -218,11 +614,18 @@ fn invoke_dynamic(
         );
     }
 
-    // If the syscall handler was changed, then reset the previous one.
-    // It's only necessary to restore the pointer if it's been modified i.e. if previous_syscall_handler is Some(...)
-    #[cfg(feature = "with-cheatcode")]
-    if let Some(previous_syscall_handler) = previous_syscall_handler {
-        crate::starknet::SYSCALL_HANDLER_VTABLE.set(previous_syscall_handler);
+    let abort_error = crate::starknet::handler::replace_abort_error(previous_abort_error);
+    let Reports {
+        output,
+        events,
+        trace,
+    } = state.restore(runtime.set_runtime_state, previous_state);
+    set_costs_builtin(old_builtincosts_ptr);
+    let interrupt_result = execution_interrupt.result();
+    drop(execution_interrupt);
+    let (profile, call_stack) = events.into_parts();
+    if let Some(coverage) = &mut coverage {
+        coverage.shrink();
     }
 
     // Parse final gas.
@@ -314,7 +717,13 @@ fn invoke_dynamic(
             if type_info.is_builtin() {
                 None
             } else {
-                Some(parse_result(ret_type, registry, return_ptr, ret_registers))
+                Some(parse_result(
+                    ret_type,
+                    registry,
+                    return_ptr,
+                    ret_registers,
+                    strict_decoding,
+                ))
             }
         })
         .transpose()?
@@ -323,22 +732,25 @@ fn invoke_dynamic(
             debug_name: None,
         });
 
-    // Restore the old ptr and get back our builtincost box and free it.
-    let our_builtincosts_ptr = set_builtin_costs_fnptr(old_builtincosts_ptr);
-
-    if !our_builtincosts_ptr.is_null() && old_builtincosts_ptr.is_aligned() {
-        unsafe {
-            let _ = Box::<[u64; 7]>::from_raw(our_builtincosts_ptr.cast_mut().cast());
-        };
+    // The results are parsed anyway to free them.
+    interrupt_result?;
+    if let Some(abort_error) = abort_error {
+        return Err(abort_error.into());
     }
 
-    #[cfg(feature = "with-mem-tracing")]
-    crate::utils::mem_tracing::report_stats();
-
     Ok(ExecutionResult {
         remaining_gas,
         return_value,
         builtin_stats,
+        diagnostics: ExecutionDiagnostics {
+            output: output.map(|output| String::from_utf8_lossy(&output).into_owned()),
+            profile: profile.map(|profile| profile.finish(registry)),
+            coverage,
+            trace,
+            backtrace: call_stack.finish(registry),
+            // Filled in by the executors, which track the allocations around this call.
+            memory: None,
+        },
     })
 }
 
@@ -349,6 +761,7 @@ fn parse_result(
     mut return_ptr: Option<NonNull<()>>,
     #[cfg(target_arch = "x86_64")] mut ret_registers: [u64; 2],
     #[cfg(target_arch = "aarch64")] mut ret_registers: [u64; 4],
+    strict: bool,
 ) -> Result<Value, Error> {
     let type_info = registry.get_type(type_id)?;
 
@@ -373,11 +786,12 @@ fn parse_result(
             type_id,
             registry,
             true,
+            strict,
         )?),
         CoreTypeConcrete::Box(info) => unsafe {
             let ptr =
                 return_ptr.unwrap_or_else(|| NonNull::new_unchecked(ret_registers[0] as *mut ()));
-            let value = Value::from_ptr(ptr, &info.ty, registry, true)?;
+            let value = Value::from_ptr(ptr, &info.ty, registry, true, strict)?;
             libc_free(ptr.cast().as_ptr());
             Ok(value)
         },
@@ -386,6 +800,7 @@ fn parse_result(
             type_id,
             registry,
             true,
+            strict,
         )?),
         CoreTypeConcrete::Felt252(_)
         | CoreTypeConcrete::StarkNet(
@@ -394,7 +809,9 @@ fn parse_result(
             | StarkNetTypeConcrete::StorageAddress(_)
             | StarkNetTypeConcrete::StorageBaseAddress(_),
         ) => match return_ptr {
-            Some(return_ptr) => Ok(Value::from_ptr(return_ptr, type_id, registry, true)?),
+            Some(return_ptr) => Ok(Value::from_ptr(
+                return_ptr, type_id, registry, true, strict,
+            )?),
             None => {
                 #[cfg(target_arch = "x86_64")]
                 // Since x86_64's return values hold at most two different 64bit registers,
@@ -403,17 +820,16 @@ fn parse_result(
                 return Err(Error::ParseAttributeError);
 
                 #[cfg(target_arch = "aarch64")]
-                Ok(Value::Felt252({
-                    let data = unsafe {
-                        std::mem::transmute::<&mut [u64; 4], &mut [u8; 32]>(&mut ret_registers)
-                    };
-                    data[31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-                    starknet_types_core::felt::Felt::from_bytes_le(data)
-                }))
+                Ok(Value::Felt252(crate::values::felt_from_bytes(
+                    unsafe { std::mem::transmute::<[u64; 4], [u8; 32]>(ret_registers) },
+                    strict,
+                )?))
             }
         },
         CoreTypeConcrete::Bytes31(_) => match return_ptr {
-            Some(return_ptr) => Ok(Value::from_ptr(return_ptr, type_id, registry, true)?),
+            Some(return_ptr) => Ok(Value::from_ptr(
+                return_ptr, type_id, registry, true, strict,
+            )?),
             None => {
                 #[cfg(target_arch = "x86_64")]
                 // Since x86_64's return values hold at most two different 64bit registers,
@@ -428,7 +844,9 @@ fn parse_result(
             }
         },
         CoreTypeConcrete::BoundedInt(info) => match return_ptr {
-            Some(return_ptr) => Ok(Value::from_ptr(return_ptr, type_id, registry, true)?),
+            Some(return_ptr) => Ok(Value::from_ptr(
+                return_ptr, type_id, registry, true, strict,
+            )?),
             None => {
                 let mut data = if info.range.offset_bit_width() <= 64 {
                     BigInt::from(ret_registers[0])
@@ -439,6 +857,14 @@ fn parse_result(
                 data &= (BigInt::one() << info.range.offset_bit_width()) - BigInt::one();
                 data += &info.range.lower;
 
+                if strict && data >= info.range.upper {
+                    return Err(ValueInvariantError::BoundedIntOutOfRange {
+                        value: Box::new(data),
+                        range: Box::new(info.range.clone()),
+                    }
+                    .into());
+                }
+
                 Ok(Value::BoundedInt {
                     value: data.into(),
                     range: info.range.clone(),
@@ -480,17 +906,17 @@ fn parse_result(
             None => Ok(Value::Sint32(ret_registers[0] as i32)),
         },
         CoreTypeConcrete::Sint64(_) => match return_ptr {
-            Some(return_ptr) => Ok(Value::Uint64(unsafe { *return_ptr.cast().as_ref() })),
+            Some(return_ptr) => Ok(Value::Sint64(unsafe { *return_ptr.cast().as_ref() })),
             None => Ok(Value::Sint64(ret_registers[0] as i64)),
         },
         CoreTypeConcrete::Sint128(_) => match return_ptr {
-            Some(return_ptr) => Ok(Value::Uint128(unsafe { *return_ptr.cast().as_ref() })),
+            Some(return_ptr) => Ok(Value::Sint128(unsafe { *return_ptr.cast().as_ref() })),
             None => Ok(Value::Sint128(
                 ((ret_registers[1] as i128) << 64) | ret_registers[0] as i128,
             )),
         },
         CoreTypeConcrete::NonZero(info) => {
-            parse_result(&info.ty, registry, return_ptr, ret_registers)
+            parse_result(&info.ty, registry, return_ptr, ret_registers, strict)
         }
         CoreTypeConcrete::Nullable(info) => unsafe {
             let ptr = return_ptr.map_or(ret_registers[0] as *mut (), |x| {
@@ -500,7 +926,7 @@ fn parse_result(
                 Ok(Value::Null)
             } else {
                 let ptr = NonNull::new_unchecked(ptr);
-                let value = Value::from_ptr(ptr, &info.ty, registry, true)?;
+                let value = Value::from_ptr(ptr, &info.ty, registry, true, strict)?;
                 libc_free(ptr.as_ptr().cast());
                 Ok(value)
             }
@@ -555,8 +981,26 @@ fn parse_result(
                     ),
                 }
             };
+            if tag >= info.variants.len() {
+                if strict {
+                    return Err(ValueInvariantError::EnumTag {
+                        type_id: type_id.clone(),
+                        tag,
+                        num_variants: info.variants.len(),
+                    }
+                    .into());
+                }
+
+                native_panic!("enum tag {tag} is out of range for {type_id:?}");
+            }
             let value = match ptr {
-                Ok(ptr) => Box::new(Value::from_ptr(ptr, &info.variants[tag], registry, true)?),
+                Ok(ptr) => Box::new(Value::from_ptr(
+                    ptr,
+                    &info.variants[tag],
+                    registry,
+                    true,
+                    strict,
+                )?),
                 Err(offset) => {
                     ret_registers.copy_within(offset.., 0);
                     Box::new(parse_result(
@@ -564,6 +1008,7 @@ fn parse_result(
                         registry,
                         None,
                         ret_registers,
+                        strict,
                     )?)
                 }
             };
@@ -586,17 +1031,18 @@ fn parse_result(
                     type_id,
                     registry,
                     true,
+                    strict,
                 )?)
             }
         }
         CoreTypeConcrete::Felt252Dict(_) | CoreTypeConcrete::SquashedFelt252Dict(_) => unsafe {
             let ptr = return_ptr
                 .unwrap_or_else(|| NonNull::new_unchecked((&raw mut ret_registers[0]) as *mut ()));
-            Ok(Value::from_ptr(ptr, type_id, registry, true)?)
+            Ok(Value::from_ptr(ptr, type_id, registry, true, strict)?)
         },
 
         CoreTypeConcrete::Snapshot(info) => {
-            parse_result(&info.ty, registry, return_ptr, ret_registers)
+            parse_result(&info.ty, registry, return_ptr, ret_registers, strict)
         }
 
         // Builtins are handled before the call to parse_result
@@ -631,16 +1077,43 @@ fn parse_result(
 mod tests {
     use super::*;
     use crate::{
-        context::NativeContext, starknet_stub::StubSyscallHandler, utils::test::load_cairo,
-        utils::test::load_starknet, OptLevel,
+        context::NativeContext,
+        starknet::{SyscallError, SyscallLayer, SyscallMiddleware, SyscallRequest},
+        starknet_stub::StubSyscallHandler,
+        utils::test::load_cairo,
+        utils::test::load_starknet,
+        OptLevel,
     };
-    use cairo_lang_sierra::program::Program;
+    use cairo_lang_sierra::program::{Function, Program};
     use rstest::*;
     use starknet_types_core::felt::Felt;
 
+    /// Compile a test program into an AOT executor.
+    fn aot_executor(program: &Program) -> AotNativeExecutor {
+        let native_context = NativeContext::new();
+        let module = native_context
+            .compile(program, false, Some(Default::default()))
+            .expect("failed to compile context");
+        AotNativeExecutor::from_native_module(module, OptLevel::default()).unwrap()
+    }
+
+    /// Find a function of a test program by its name, without the module path.
+    fn find_function<'a>(program: &'a Program, name: &str) -> &'a Function {
+        program
+            .funcs
+            .iter()
+            .find(|f| {
+                f.id.debug_name
+                    .as_deref()
+                    .is_some_and(|x| x.ends_with(&format!("::{name}")))
+            })
+            .expect("should have a function")
+    }
+
     #[fixture]
     fn program() -> Program {
         let (_, program) = load_cairo! {
+            use core::debug::PrintTrait;
             use core::starknet::{SyscallResultTrait, get_block_hash_syscall};
 
             fn run_test() -> felt252 {
@@ -650,6 +1123,17 @@ mod tests {
             fn get_block_hash() -> felt252 {
                 get_block_hash_syscall(1).unwrap_syscall()
             }
+
+            fn print_hello() {
+                'h'.print();
+            }
+
+            fn run_forever() -> felt252 {
+                let mut i = 0;
+                loop {
+                    i += 1;
+                }
+            }
         };
         program
     }
@@ -714,6 +1198,459 @@ mod tests {
         assert_eq!(result.return_value, Value::Felt252(Felt::from(42)));
     }
 
+    #[rstest]
+    fn test_invoke_dynamic_with_options(program: Program) {
+        let executor = aot_executor(&program);
+
+        // The first function in the program is `run_test`.
+        let entrypoint_function_id = &program.funcs.first().expect("should have a function").id;
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                entrypoint_function_id,
+                &[],
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_builtin_costs(BuiltinCosts::default()),
+            )
+            .unwrap();
+
+        assert_eq!(result.return_value, Value::Felt252(Felt::from(42)));
+    }
+
+    #[test]
+    fn test_invoke_dynamic_strict_signed() {
+        let (_, program) = load_cairo! {
+            fn run_i64() -> i64 {
+                repeat_i64(-5, 3)
+            }
+
+            fn run_i128() -> i128 {
+                repeat_i128(-5, 3)
+            }
+
+            fn repeat_i64(value: i64, n: u8) -> i64 {
+                if n == 0 {
+                    value
+                } else {
+                    repeat_i64(value, n - 1)
+                }
+            }
+
+            fn repeat_i128(value: i128, n: u8) -> i128 {
+                if n == 0 {
+                    value
+                } else {
+                    repeat_i128(value, n - 1)
+                }
+            }
+        };
+        let executor = aot_executor(&program).with_strict_decoding(true);
+
+        // The recursion makes them return their remaining gas too, so their values are read from
+        // the return pointer.
+        for (name, expected) in [
+            ("run_i64", Value::Sint64(-5)),
+            ("run_i128", Value::Sint128(-5)),
+        ] {
+            let result = executor
+                .invoke_dynamic_with_options(
+                    &find_function(&program, name).id,
+                    &[],
+                    ExecutionOptions::new().with_gas(u64::MAX),
+                )
+                .unwrap();
+            assert_eq!(result.return_value, expected);
+        }
+    }
+
+    #[cfg(feature = "with-interrupts")]
+    #[rstest]
+    fn test_invoke_dynamic_interrupted(program: Program) {
+        let executor = aot_executor(&program);
+
+        let function_id = &find_function(&program, "run_forever").id;
+
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let result = executor.invoke_dynamic_with_options(
+            function_id,
+            &[],
+            ExecutionOptions::new()
+                .with_gas(u64::MAX)
+                .with_cancellation(cancellation),
+        );
+        assert!(matches!(result, Err(Error::Cancelled)));
+
+        let result = executor.invoke_dynamic_with_options(
+            function_id,
+            &[],
+            ExecutionOptions::new()
+                .with_gas(u64::MAX)
+                .with_timeout(Duration::ZERO),
+        );
+        assert!(matches!(result, Err(Error::TimedOut)));
+
+        // The interrupt flag doesn't leak into the following executions.
+        let result = executor
+            .invoke_dynamic_with_options(
+                &program.funcs.first().expect("should have a function").id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+        assert_eq!(result.return_value, Value::Felt252(Felt::from(42)));
+    }
+
+    #[test]
+    fn test_invoke_dynamic_aborted() {
+        let (_, program) = load_cairo! {
+            use core::starknet::get_block_hash_syscall;
+
+            fn run_test(block_number: u64) {
+                let _ = get_block_hash_syscall(block_number);
+                let _ = get_block_hash_syscall(block_number + 10);
+            }
+        };
+
+        /// Runs `run_test(1)` from the first syscall of `run_test(0)`, aborting it from its first
+        /// syscall.
+        struct RunNested<'a> {
+            executor: &'a AotNativeExecutor,
+            function_id: &'a FunctionId,
+            block_numbers: Vec<u64>,
+            result: Option<Result<ExecutionResult, Error>>,
+        }
+
+        impl SyscallMiddleware for RunNested<'_> {
+            fn before_syscall(
+                &mut self,
+                request: &SyscallRequest,
+                _remaining_gas: &mut u64,
+            ) -> Result<(), SyscallError> {
+                let SyscallRequest::GetBlockHash { block_number } = *request else {
+                    return Ok(());
+                };
+                self.block_numbers.push(block_number);
+
+                match block_number {
+                    0 => {
+                        let (executor, function_id) = (self.executor, self.function_id);
+                        let result = executor.invoke_dynamic_with_options(
+                            function_id,
+                            &[Value::Uint64(1)],
+                            ExecutionOptions::new()
+                                .with_gas(u64::MAX)
+                                .with_syscall_handler(SyscallLayer::new(
+                                    &mut StubSyscallHandler::default(),
+                                    &mut *self,
+                                )),
+                        );
+                        self.result = Some(result);
+                    }
+                    1 => return Err(SyscallError::Abort("stop".to_string())),
+                    _ => {}
+                }
+
+                Ok(())
+            }
+        }
+
+        let executor = aot_executor(&program);
+
+        let function_id = &program.funcs[0].id;
+        let mut run_nested = RunNested {
+            executor: &executor,
+            function_id,
+            block_numbers: Vec::new(),
+            result: None,
+        };
+        executor
+            .invoke_dynamic_with_options(
+                function_id,
+                &[Value::Uint64(0)],
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_syscall_handler(SyscallLayer::new(
+                        &mut StubSyscallHandler::default(),
+                        &mut run_nested,
+                    )),
+            )
+            .unwrap();
+
+        assert!(matches!(
+            run_nested.result,
+            Some(Err(Error::SyscallHandlerAborted(ref reason))) if reason == "stop"
+        ));
+        // The second syscall of the aborted execution never reached the handler, while its caller
+        // kept running.
+        assert_eq!(run_nested.block_numbers, [0, 1, 10]);
+    }
+
+    #[rstest]
+    fn test_invoke_dynamic_captured_output(program: Program) {
+        let executor = aot_executor(&program);
+
+        let function_id = &find_function(&program, "print_hello").id;
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                function_id,
+                &[],
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_captured_output(true),
+            )
+            .unwrap();
+        assert_eq!(
+            result.diagnostics.output.as_deref(),
+            Some("[DEBUG]\t0x68 ('h')\n")
+        );
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                function_id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+        assert_eq!(result.diagnostics.output, None);
+    }
+
+    #[cfg(feature = "with-profiling")]
+    #[rstest]
+    fn test_invoke_dynamic_profiled(program: Program) {
+        let executor = aot_executor(&program);
+
+        let function_id = &find_function(&program, "run_test").id;
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                function_id,
+                &[],
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_profiling(true),
+            )
+            .unwrap();
+        let profile = result.diagnostics.profile.expect("should have a profile");
+        assert!(profile
+            .functions
+            .iter()
+            .any(|x| x.function_id == function_id.id && x.calls == 1));
+        assert!(profile.to_folded().contains("run_test"));
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                function_id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+        assert_eq!(result.diagnostics.profile, None);
+    }
+
+    #[cfg(feature = "with-coverage")]
+    #[rstest]
+    fn test_invoke_dynamic_coverage(program: Program) {
+        let executor = aot_executor(&program);
+
+        let function = find_function(&program, "run_test");
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                &function.id,
+                &[],
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_coverage(true),
+            )
+            .unwrap();
+        let coverage = result.diagnostics.coverage.expect("should have a coverage");
+        assert!(coverage.contains(function.entry_point));
+        assert!(coverage.len() < program.statements.len());
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                &function.id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+        assert_eq!(result.diagnostics.coverage, None);
+    }
+
+    #[cfg(feature = "with-trace")]
+    #[rstest]
+    fn test_invoke_dynamic_trace(program: Program) {
+        let executor = aot_executor(&program);
+
+        let function = find_function(&program, "run_test");
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                &function.id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX).with_trace(true),
+            )
+            .unwrap();
+        let trace = result.diagnostics.trace.expect("should have a trace");
+        assert_eq!(trace.statements().next(), Some(function.entry_point));
+        assert!(matches!(
+            program.statements[trace.entries.last().unwrap().statement_idx.0],
+            cairo_lang_sierra::program::Statement::Return(_)
+        ));
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                &function.id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+        assert_eq!(result.diagnostics.trace, None);
+    }
+
+    #[cfg(feature = "with-backtrace")]
+    #[test]
+    fn test_invoke_dynamic_backtrace() {
+        let (_, program) = load_cairo! {
+            fn run_test() -> felt252 {
+                outer(true)
+            }
+
+            #[inline(never)]
+            fn outer(fail: bool) -> felt252 {
+                inner(fail) + 1
+            }
+
+            #[inline(never)]
+            fn inner(fail: bool) -> felt252 {
+                if fail {
+                    panic!("failed");
+                }
+                41
+            }
+        };
+
+        let executor = aot_executor(&program);
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                &program.funcs[0].id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+        let backtrace = result
+            .diagnostics
+            .backtrace
+            .expect("should have a backtrace");
+        let names = backtrace
+            .frames
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>();
+        assert!(names[0].ends_with("::inner"));
+        assert!(names[1].ends_with("::outer"));
+        assert!(names[2].ends_with("::run_test"));
+    }
+
+    #[cfg(feature = "with-mem-tracing")]
+    #[test]
+    fn test_invoke_dynamic_memory_report() {
+        let (_, program) = load_cairo! {
+            use core::dict::Felt252Dict;
+
+            fn run_test() -> felt252 {
+                let mut values = array![1, 2, 3];
+                values.append(4);
+
+                let mut dict: Felt252Dict<felt252> = Default::default();
+                dict.insert(1, values.len().into());
+
+                let boxed = BoxTrait::new(dict.get(1));
+                let nullable = NullableTrait::new(boxed.unbox());
+                nullable.deref() + *values.at(3)
+            }
+        };
+
+        let native_context = NativeContext::new();
+        let module = native_context
+            .compile(&program, false, Some(Default::default()))
+            .expect("failed to compile context");
+        let executor = JitNativeExecutor::from_native_module(module, OptLevel::default()).unwrap();
+
+        let result = executor
+            .invoke_dynamic_with_options(
+                &program.funcs[0].id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+
+        let memory = result
+            .diagnostics
+            .memory
+            .expect("should have a memory report");
+        assert!(memory.total_allocations > 0);
+        assert!(memory.is_leak_free(), "leaked {:?}", memory.outstanding);
+    }
+
+    #[rstest]
+    fn test_native_executor_trait(program: Program) {
+        fn run_test(executor: &impl NativeExecutor, function_id: &FunctionId) -> Value {
+            executor
+                .invoke_dynamic(function_id, &[], ExecutionOptions::new().with_gas(u64::MAX))
+                .unwrap()
+                .return_value
+        }
+
+        // The first function in the program is `run_test`.
+        let entrypoint_function_id = &program.funcs.first().expect("should have a function").id;
+
+        let executor = aot_executor(&program);
+        assert_eq!(
+            run_test(&executor, entrypoint_function_id),
+            Value::Felt252(Felt::from(42))
+        );
+
+        let module = native_context
+            .compile(&program, false, Some(Default::default()))
+            .expect("failed to compile context");
+        let executor = JitNativeExecutor::from_native_module(module, OptLevel::default()).unwrap();
+        assert_eq!(
+            run_test(&executor, entrypoint_function_id),
+            Value::Felt252(Felt::from(42))
+        );
+    }
+
+    #[rstest]
+    fn test_invoke_contract_dynamic_with_options_missing_syscall_handler(
+        starknet_program: Program,
+    ) {
+        let native_context = NativeContext::new();
+        let module = native_context
+            .compile(&starknet_program, false, Some(Default::default()))
+            .expect("failed to compile context");
+        let executor = JitNativeExecutor::from_native_module(module, OptLevel::default()).unwrap();
+
+        // The last function in the program is the `get` wrapper function.
+        let entrypoint_function_id = &starknet_program
+            .funcs
+            .last()
+            .expect("should have a function")
+            .id;
+
+        let result = executor.invoke_contract_dynamic_with_options(
+            entrypoint_function_id,
+            &[],
+            ExecutionOptions::new().with_gas(u64::MAX),
+        );
+
+        assert!(matches!(result, Err(Error::MissingSyscallHandler)));
+    }
+
     #[rstest]
     fn test_invoke_contract_dynamic_aot(starknet_program: Program) {
         let native_context = NativeContext::new();
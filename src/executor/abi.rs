@@ -0,0 +1,688 @@
+//! # Contract ABI (de)serialization
+//!
+//! Conversion between JSON values and the felts expected by (and returned from) the contract entry
+//! points, following the contract class ABI and Cairo's `Serde` encoding.
+//!
+//! The JSON representation of each type is:
+//!
+//! - `felt252`, `ContractAddress`, `ClassHash` and `bytes31`: A hex or decimal string (numbers are
+//!   also accepted as input). They're always returned as hex strings.
+//! - Integers: Numbers, or decimal strings for integers wider than 64 bits. `u256` is handled the
+//!   same way.
+//! - `bool`: A JSON boolean.
+//! - `ByteArray`: A JSON string.
+//! - Arrays, spans and tuples: JSON arrays.
+//! - Structs: JSON objects with a key for each member.
+//! - Enums: A JSON object with a single key (the variant name) and its payload, or just the variant
+//!   name as a string when it has no payload.
+
+use crate::{
+    error::{Error, Result},
+    utils::{HALF_PRIME, PRIME},
+};
+use cairo_lang_starknet_classes::{
+    abi::{Contract, EventFieldKind, EventKind, Item},
+    keccak::starknet_keccak,
+};
+use num_bigint::{BigInt, BigUint, Sign};
+use num_traits::{One, ToPrimitive, Zero};
+use serde_json::{Map, Value as JsonValue};
+use starknet_types_core::felt::Felt;
+use std::{collections::HashMap, str::FromStr};
+
+/// A contract ABI, indexed to serialize calldata and deserialize results and events.
+#[derive(Debug, Clone, Default)]
+pub struct ContractAbi {
+    functions: HashMap<String, AbiFunction>,
+    structs: HashMap<String, Vec<(String, String)>>,
+    enums: HashMap<String, Vec<(String, String)>>,
+    events: HashMap<String, EventKind>,
+    /// The top level event enum of the contract, if any.
+    root_event: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct AbiFunction {
+    inputs: Vec<(String, String)>,
+    outputs: Vec<String>,
+}
+
+impl ContractAbi {
+    pub fn new(abi: &Contract) -> Self {
+        let mut this = Self::default();
+        for item in abi.clone() {
+            this.insert_item(item);
+        }
+
+        this
+    }
+
+    fn insert_item(&mut self, item: Item) {
+        match item {
+            Item::Function(function) => {
+                self.functions.insert(
+                    function.name,
+                    AbiFunction {
+                        inputs: function
+                            .inputs
+                            .into_iter()
+                            .map(|x| (x.name, x.ty))
+                            .collect(),
+                        outputs: function.outputs.into_iter().map(|x| x.ty).collect(),
+                    },
+                );
+            }
+            Item::Constructor(constructor) => {
+                self.functions.insert(
+                    constructor.name,
+                    AbiFunction {
+                        inputs: constructor
+                            .inputs
+                            .into_iter()
+                            .map(|x| (x.name, x.ty))
+                            .collect(),
+                        outputs: Vec::new(),
+                    },
+                );
+            }
+            Item::L1Handler(l1_handler) => {
+                self.functions.insert(
+                    l1_handler.name,
+                    AbiFunction {
+                        inputs: l1_handler
+                            .inputs
+                            .into_iter()
+                            .map(|x| (x.name, x.ty))
+                            .collect(),
+                        outputs: l1_handler.outputs.into_iter().map(|x| x.ty).collect(),
+                    },
+                );
+            }
+            Item::Event(event) => {
+                // The contract's event enum is always the last one.
+                if matches!(event.kind, EventKind::Enum { .. }) {
+                    self.root_event = Some(event.name.clone());
+                }
+                self.events.insert(event.name, event.kind);
+            }
+            Item::Struct(item) => {
+                self.structs.insert(
+                    item.name,
+                    item.members.into_iter().map(|x| (x.name, x.ty)).collect(),
+                );
+            }
+            Item::Enum(item) => {
+                self.enums.insert(
+                    item.name,
+                    item.variants.into_iter().map(|x| (x.name, x.ty)).collect(),
+                );
+            }
+            Item::Interface(interface) => {
+                for item in interface.items {
+                    self.insert_item(item);
+                }
+            }
+            Item::Impl(_) => {}
+        }
+    }
+
+    /// Compute the entry point selector for a function name.
+    pub fn selector(name: &str) -> Felt {
+        Felt::from(starknet_keccak(name.as_bytes()))
+    }
+
+    /// Serialize the arguments of a function into its calldata.
+    ///
+    /// The arguments must be a JSON object with a key for each input (or a JSON array with the
+    /// inputs in order).
+    pub fn encode_calldata(&self, function: &str, args: &JsonValue) -> Result<Vec<Felt>> {
+        let function = self.find_function(function)?;
+
+        let mut calldata = Vec::new();
+        for (idx, (name, ty)) in function.inputs.iter().enumerate() {
+            let value = match args {
+                JsonValue::Object(args) => args.get(name),
+                JsonValue::Array(args) => args.get(idx),
+                JsonValue::Null => None,
+                _ => return Err(abi_error("arguments must be an object or an array")),
+            }
+            .ok_or_else(|| abi_error(format!("missing argument `{name}`")))?;
+
+            self.encode(ty, value, &mut calldata)?;
+        }
+
+        Ok(calldata)
+    }
+
+    /// Deserialize the return values of a function.
+    ///
+    /// Functions with a single output return it directly, otherwise an array with every output is
+    /// returned.
+    pub fn decode_outputs(&self, function: &str, data: &[Felt]) -> Result<JsonValue> {
+        let function = self.find_function(function)?;
+
+        let mut data = data.iter().copied();
+        let mut outputs = function
+            .outputs
+            .iter()
+            .map(|ty| self.decode(ty, &mut data))
+            .collect::<Result<Vec<_>>>()?;
+        if data.next().is_some() {
+            return Err(abi_error("trailing data after the return values"));
+        }
+
+        Ok(match outputs.len() {
+            0 => JsonValue::Null,
+            1 => outputs.remove(0),
+            _ => JsonValue::Array(outputs),
+        })
+    }
+
+    /// Deserialize an event emitted by the contract from its keys and data.
+    pub fn decode_event(&self, keys: &[Felt], data: &[Felt]) -> Result<JsonValue> {
+        let root_event = self
+            .root_event
+            .as_deref()
+            .ok_or_else(|| abi_error("the contract doesn't declare any events"))?;
+
+        let mut keys = keys.iter().copied();
+        let mut data = data.iter().copied();
+        let value = self
+            .decode_event_inner(root_event, &mut keys, &mut data)?
+            .ok_or_else(|| abi_error("the event doesn't match any declared event"))?;
+
+        if keys.next().is_some() || data.next().is_some() {
+            return Err(abi_error("trailing keys or data after the event"));
+        }
+
+        Ok(value)
+    }
+
+    fn find_function(&self, name: &str) -> Result<&AbiFunction> {
+        self.functions
+            .get(name)
+            .ok_or_else(|| abi_error(format!("function `{name}` not found in the ABI")))
+    }
+
+    fn decode_event_inner(
+        &self,
+        ty: &str,
+        keys: &mut (impl Iterator<Item = Felt> + Clone),
+        data: &mut impl Iterator<Item = Felt>,
+    ) -> Result<Option<JsonValue>> {
+        let Some(kind) = self.events.get(ty) else {
+            return Ok(None);
+        };
+
+        Ok(Some(match kind {
+            EventKind::Struct { members } => {
+                let mut fields = Map::new();
+                for member in members {
+                    let value = match member.kind {
+                        EventFieldKind::KeySerde => self.decode(&member.ty, keys)?,
+                        EventFieldKind::DataSerde => self.decode(&member.ty, data)?,
+                        EventFieldKind::Nested | EventFieldKind::Flat => self
+                            .decode_event_inner(&member.ty, keys, data)?
+                            .ok_or_else(|| abi_error(format!("unknown event `{}`", member.ty)))?,
+                    };
+                    fields.insert(member.name.clone(), value);
+                }
+
+                JsonValue::Object(fields)
+            }
+            EventKind::Enum { variants } => {
+                let mut value = None;
+                for variant in variants {
+                    let payload = match variant.kind {
+                        EventFieldKind::Flat => {
+                            let mut flat_keys = keys.clone();
+                            let payload =
+                                self.decode_event_inner(&variant.ty, &mut flat_keys, data)?;
+                            if payload.is_some() {
+                                *keys = flat_keys;
+                            }
+                            payload
+                        }
+                        _ => {
+                            let mut nested_keys = keys.clone();
+                            if nested_keys.next() != Some(Self::selector(&variant.name)) {
+                                continue;
+                            }
+                            *keys = nested_keys;
+
+                            Some(match variant.kind {
+                                EventFieldKind::KeySerde => self.decode(&variant.ty, keys)?,
+                                EventFieldKind::DataSerde => self.decode(&variant.ty, data)?,
+                                _ => match self.decode_event_inner(&variant.ty, keys, data)? {
+                                    Some(payload) => payload,
+                                    None => self.decode(&variant.ty, data)?,
+                                },
+                            })
+                        }
+                    };
+
+                    if let Some(payload) = payload {
+                        value = Some(JsonValue::Object(Map::from_iter([(
+                            variant.name.clone(),
+                            payload,
+                        )])));
+                        break;
+                    }
+                }
+
+                match value {
+                    Some(value) => value,
+                    None => return Ok(None),
+                }
+            }
+        }))
+    }
+
+    fn encode(&self, ty: &str, value: &JsonValue, output: &mut Vec<Felt>) -> Result<()> {
+        if let Some(members) = parse_tuple(ty) {
+            let JsonValue::Array(values) = value else {
+                return Err(unexpected_value(ty, value));
+            };
+            if values.len() != members.len() {
+                return Err(unexpected_value(ty, value));
+            }
+
+            for (ty, value) in members.iter().zip(values) {
+                self.encode(ty, value, output)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(inner) = parse_generic(ty, "core::array::Array")
+            .or_else(|| parse_generic(ty, "core::array::Span"))
+        {
+            let JsonValue::Array(values) = value else {
+                return Err(unexpected_value(ty, value));
+            };
+
+            output.push(values.len().into());
+            for value in values {
+                self.encode(inner, value, output)?;
+            }
+            return Ok(());
+        }
+
+        match ty {
+            "core::felt252"
+            | "core::starknet::contract_address::ContractAddress"
+            | "core::starknet::class_hash::ClassHash"
+            | "core::starknet::storage_access::StorageAddress"
+            | "core::bytes_31::bytes31" => output.push(json_to_felt(ty, value)?),
+            "core::bool" => match value {
+                JsonValue::Bool(value) => output.push(Felt::from(*value)),
+                _ => return Err(unexpected_value(ty, value)),
+            },
+            "core::integer::u256" if !value.is_object() => {
+                let value = json_to_bigint(ty, value)?;
+                check_int_range(ty, &value, 256, false)?;
+
+                let value = value.magnitude();
+                let mask = (BigUint::one() << 128u32) - BigUint::one();
+                output.push(Felt::from(value & &mask));
+                output.push(Felt::from(value >> 128u32));
+            }
+            "core::byte_array::ByteArray" if value.is_string() => {
+                let Some(value) = value.as_str() else {
+                    return Err(unexpected_value(ty, value));
+                };
+                encode_byte_array(value.as_bytes(), output);
+            }
+            _ => {
+                if let Some((bits, signed)) = parse_integer(ty) {
+                    let value = json_to_bigint(ty, value)?;
+                    check_int_range(ty, &value, bits, signed)?;
+                    output.push(Felt::from(&value));
+                } else if let Some(members) = self.structs.get(ty) {
+                    let JsonValue::Object(fields) = value else {
+                        return Err(unexpected_value(ty, value));
+                    };
+
+                    for (name, member_ty) in members {
+                        let value = fields.get(name).ok_or_else(|| {
+                            abi_error(format!("missing member `{name}` of `{ty}`"))
+                        })?;
+                        self.encode(member_ty, value, output)?;
+                    }
+                } else if let Some(variants) = self.enums.get(ty) {
+                    let (name, payload) = match value {
+                        JsonValue::String(name) => (name.as_str(), &JsonValue::Null),
+                        JsonValue::Object(fields) if fields.len() == 1 => fields
+                            .iter()
+                            .next()
+                            .map(|(name, payload)| (name.as_str(), payload))
+                            .ok_or_else(|| unexpected_value(ty, value))?,
+                        _ => return Err(unexpected_value(ty, value)),
+                    };
+
+                    let (index, (_, variant_ty)) = variants
+                        .iter()
+                        .enumerate()
+                        .find(|(_, (variant, _))| variant == name)
+                        .ok_or_else(|| abi_error(format!("unknown variant `{name}` of `{ty}`")))?;
+
+                    output.push(index.into());
+                    if variant_ty != "()" {
+                        self.encode(variant_ty, payload, output)?;
+                    }
+                } else {
+                    return Err(abi_error(format!("unknown type `{ty}`")));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode(&self, ty: &str, data: &mut impl Iterator<Item = Felt>) -> Result<JsonValue> {
+        let mut next = || {
+            data.next()
+                .ok_or_else(|| abi_error(format!("not enough data to decode `{ty}`")))
+        };
+
+        if let Some(members) = parse_tuple(ty) {
+            return Ok(JsonValue::Array(
+                members
+                    .iter()
+                    .map(|ty| self.decode(ty, data))
+                    .collect::<Result<_>>()?,
+            ));
+        }
+
+        if let Some(inner) = parse_generic(ty, "core::array::Array")
+            .or_else(|| parse_generic(ty, "core::array::Span"))
+        {
+            let len = felt_to_usize(ty, next()?)?;
+            return Ok(JsonValue::Array(
+                (0..len)
+                    .map(|_| self.decode(inner, data))
+                    .collect::<Result<_>>()?,
+            ));
+        }
+
+        Ok(match ty {
+            "core::felt252"
+            | "core::starknet::contract_address::ContractAddress"
+            | "core::starknet::class_hash::ClassHash"
+            | "core::starknet::storage_access::StorageAddress"
+            | "core::bytes_31::bytes31" => JsonValue::String(next()?.to_hex_string()),
+            "core::bool" => JsonValue::Bool(next()? != Felt::ZERO),
+            "core::integer::u256" => {
+                let low = next()?.to_biguint();
+                let high = next()?.to_biguint();
+                JsonValue::String(((high << 128u32) + low).to_string())
+            }
+            "core::byte_array::ByteArray" => JsonValue::String(decode_byte_array(data)?),
+            _ => {
+                if let Some((bits, signed)) = parse_integer(ty) {
+                    let value = next()?;
+                    let value = if signed && value.to_biguint() > *HALF_PRIME {
+                        -BigInt::from_biguint(Sign::Plus, &*PRIME - value.to_biguint())
+                    } else {
+                        value.to_bigint()
+                    };
+
+                    match (bits <= 64, value.to_i64()) {
+                        (true, Some(value)) => JsonValue::from(value),
+                        _ => JsonValue::String(value.to_string()),
+                    }
+                } else if let Some(members) = self.structs.get(ty) {
+                    let mut fields = Map::new();
+                    for (name, member_ty) in members {
+                        fields.insert(name.clone(), self.decode(member_ty, data)?);
+                    }
+
+                    JsonValue::Object(fields)
+                } else if let Some(variants) = self.enums.get(ty) {
+                    let index = felt_to_usize(ty, next()?)?;
+                    let (name, variant_ty) = variants
+                        .get(index)
+                        .ok_or_else(|| abi_error(format!("invalid variant {index} of `{ty}`")))?;
+
+                    if variant_ty == "()" {
+                        JsonValue::String(name.clone())
+                    } else {
+                        JsonValue::Object(Map::from_iter([(
+                            name.clone(),
+                            self.decode(variant_ty, data)?,
+                        )]))
+                    }
+                } else {
+                    return Err(abi_error(format!("unknown type `{ty}`")));
+                }
+            }
+        })
+    }
+}
+
+fn abi_error(msg: impl Into<String>) -> Error {
+    Error::AbiError(msg.into())
+}
+
+fn unexpected_value(ty: &str, value: &JsonValue) -> Error {
+    abi_error(format!("expected a value of type `{ty}`, got `{value}`"))
+}
+
+/// Return the bit width and signedness of an integer type.
+fn parse_integer(ty: &str) -> Option<(u32, bool)> {
+    let name = ty.strip_prefix("core::integer::")?;
+    let (signed, bits) = match name.split_at_checked(1)? {
+        ("u", bits) => (false, bits),
+        ("i", bits) => (true, bits),
+        _ => return None,
+    };
+
+    match bits.parse() {
+        Ok(bits @ (8 | 16 | 32 | 64 | 128)) => Some((bits, signed)),
+        _ => None,
+    }
+}
+
+/// Return the generic argument of `ty` if it's an instance of `name`.
+fn parse_generic<'a>(ty: &'a str, name: &str) -> Option<&'a str> {
+    ty.strip_prefix(name)?
+        .strip_prefix("::<")?
+        .strip_suffix('>')
+}
+
+/// Split a tuple type into its members.
+fn parse_tuple(ty: &str) -> Option<Vec<&str>> {
+    let inner = ty.strip_prefix('(')?.strip_suffix(')')?;
+
+    let mut members = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (idx, c) in inner.char_indices() {
+        match c {
+            '(' | '<' => depth += 1,
+            ')' | '>' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                members.push(inner[start..idx].trim());
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    if !inner[start..].trim().is_empty() {
+        members.push(inner[start..].trim());
+    }
+
+    Some(members)
+}
+
+fn json_to_bigint(ty: &str, value: &JsonValue) -> Result<BigInt> {
+    match value {
+        JsonValue::Number(number) => number
+            .as_u64()
+            .map(BigInt::from)
+            .or_else(|| number.as_i64().map(BigInt::from))
+            .ok_or_else(|| unexpected_value(ty, value)),
+        JsonValue::String(string) => {
+            let (negative, digits) = match string.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, string.as_str()),
+            };
+            let magnitude = match digits.strip_prefix("0x") {
+                Some(hex) => BigUint::parse_bytes(hex.as_bytes(), 16),
+                None => BigUint::parse_bytes(digits.as_bytes(), 10),
+            }
+            .ok_or_else(|| unexpected_value(ty, value))?;
+
+            Ok(BigInt::from_biguint(
+                if negative { Sign::Minus } else { Sign::Plus },
+                magnitude,
+            ))
+        }
+        _ => Err(unexpected_value(ty, value)),
+    }
+}
+
+fn json_to_felt(ty: &str, value: &JsonValue) -> Result<Felt> {
+    match value {
+        JsonValue::String(string) if !string.starts_with('-') => {
+            Felt::from_str(string).map_err(|_| unexpected_value(ty, value))
+        }
+        _ => Ok(Felt::from(&json_to_bigint(ty, value)?)),
+    }
+}
+
+fn check_int_range(ty: &str, value: &BigInt, bits: u32, signed: bool) -> Result<()> {
+    let (min, max) = if signed {
+        (
+            -(BigInt::one() << (bits - 1)),
+            (BigInt::one() << (bits - 1)) - BigInt::one(),
+        )
+    } else {
+        (BigInt::zero(), (BigInt::one() << bits) - BigInt::one())
+    };
+
+    if *value < min || *value > max {
+        return Err(abi_error(format!(
+            "value {value} is out of range for `{ty}`"
+        )));
+    }
+
+    Ok(())
+}
+
+fn felt_to_usize(ty: &str, value: Felt) -> Result<usize> {
+    value
+        .to_usize()
+        .ok_or_else(|| abi_error(format!("invalid length or variant {value} for `{ty}`")))
+}
+
+/// Serialize a string as a `ByteArray`: the full 31-byte words, then the pending word and its
+/// length.
+fn encode_byte_array(data: &[u8], output: &mut Vec<Felt>) {
+    let chunks = data.chunks(31);
+    let (full_words, pending_word) = match data.len() % 31 {
+        0 => (chunks.collect::<Vec<_>>(), &[][..]),
+        _ => {
+            let mut chunks = chunks.collect::<Vec<_>>();
+            let pending_word = chunks.pop().unwrap_or_default();
+            (chunks, pending_word)
+        }
+    };
+
+    output.push(full_words.len().into());
+    output.extend(
+        full_words
+            .iter()
+            .map(|word| Felt::from_bytes_be_slice(word)),
+    );
+    output.push(Felt::from_bytes_be_slice(pending_word));
+    output.push(pending_word.len().into());
+}
+
+fn decode_byte_array(data: &mut impl Iterator<Item = Felt>) -> Result<String> {
+    let ty = "core::byte_array::ByteArray";
+    let mut next = || {
+        data.next()
+            .ok_or_else(|| abi_error(format!("not enough data to decode `{ty}`")))
+    };
+
+    let num_words = felt_to_usize(ty, next()?)?;
+    let mut bytes = Vec::new();
+    for _ in 0..num_words {
+        bytes.extend_from_slice(&next()?.to_bytes_be()[1..]);
+    }
+
+    let pending_word = next()?;
+    let pending_word_len = felt_to_usize(ty, next()?)?;
+    if pending_word_len > 31 {
+        return Err(abi_error(format!("invalid pending word length for `{ty}`")));
+    }
+    bytes.extend_from_slice(&pending_word.to_bytes_be()[32 - pending_word_len..]);
+
+    String::from_utf8(bytes).map_err(|_| abi_error(format!("`{ty}` is not valid UTF-8")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_tuple() {
+        assert_eq!(parse_tuple("()"), Some(vec![]));
+        assert_eq!(
+            parse_tuple(
+                "(core::felt252, (core::integer::u8, core::array::Array::<core::felt252>))"
+            ),
+            Some(vec![
+                "core::felt252",
+                "(core::integer::u8, core::array::Array::<core::felt252>)"
+            ])
+        );
+        assert_eq!(parse_tuple("core::felt252"), None);
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let abi = ContractAbi::default();
+
+        for (ty, value) in [
+            ("core::felt252", json!("0x2a")),
+            ("core::integer::u8", json!(255)),
+            ("core::integer::i32", json!(-7)),
+            (
+                "core::integer::u128",
+                json!("340282366920938463463374607431768211455"),
+            ),
+            (
+                "core::integer::u256",
+                json!("340282366920938463463374607431768211456"),
+            ),
+            ("core::bool", json!(true)),
+            (
+                "core::byte_array::ByteArray",
+                json!("a string that is longer than a single word"),
+            ),
+            ("core::array::Array::<core::integer::u16>", json!([1, 2, 3])),
+            ("(core::felt252, core::integer::u64)", json!(["0x1", 2])),
+        ] {
+            let mut data = Vec::new();
+            abi.encode(ty, &value, &mut data).unwrap();
+
+            let mut data = data.into_iter();
+            assert_eq!(abi.decode(ty, &mut data).unwrap(), value, "{ty}");
+            assert_eq!(data.next(), None);
+        }
+    }
+
+    #[test]
+    fn test_encode_out_of_range() {
+        let abi = ContractAbi::default();
+
+        assert!(abi
+            .encode("core::integer::u8", &json!(256), &mut Vec::new())
+            .is_err());
+        assert!(abi
+            .encode("core::integer::i8", &json!(-129), &mut Vec::new())
+            .is_err());
+    }
+}
@@ -3,9 +3,10 @@ use std::io;
 use crate::{
     error::Error,
     execution_result::{ContractExecutionResult, ExecutionResult},
+    executor::{find_library_symbol, ExecutionOptions, RuntimeSymbols},
     metadata::gas::GasMetadata,
     module::NativeModule,
-    starknet::{DummySyscallHandler, StarknetSyscallHandler},
+    starknet::{CollectingMiddleware, StarknetSyscallHandler, SyscallLayer},
     utils::generate_function_name,
     values::Value,
     OptLevel,
@@ -29,23 +30,28 @@ pub struct AotNativeExecutor {
     library: Library,
     #[educe(Debug(ignore))]
     registry: ProgramRegistry<CoreType, CoreLibfunc>,
+    #[educe(Debug(ignore))]
+    runtime: RuntimeSymbols,
 
     gas_metadata: GasMetadata,
+    strict_decoding: bool,
 }
 
 unsafe impl Send for AotNativeExecutor {}
 unsafe impl Sync for AotNativeExecutor {}
 
 impl AotNativeExecutor {
-    pub const fn new(
+    pub fn new(
         library: Library,
         registry: ProgramRegistry<CoreType, CoreLibfunc>,
         gas_metadata: GasMetadata,
     ) -> Self {
         Self {
+            runtime: RuntimeSymbols::resolve(|name| find_library_symbol(&library, name)),
             library,
             registry,
             gas_metadata,
+            strict_decoding: false,
         }
     }
 
@@ -65,11 +71,34 @@ impl AotNativeExecutor {
         let object_data = crate::module_to_object(&module, opt_level)?;
         crate::object_to_shared_lib(&object_data, &library_path)?;
 
-        Ok(Self {
-            library: unsafe { Library::new(&library_path)? },
+        Ok(Self::new(
+            unsafe { Library::new(&library_path)? },
             registry,
-            gas_metadata: metadata.remove().ok_or(Error::MissingMetadata)?,
-        })
+            metadata.remove().ok_or(Error::MissingMetadata)?,
+        ))
+    }
+
+    /// Enable or disable the strict decoding of return values.
+    ///
+    /// When enabled, return values that violate their type's invariants are reported as an
+    /// [`Error::ValueInvariant`]. The checked invariants, and what happens to them when disabled,
+    /// are:
+    ///   - Felts are below the prime. Otherwise they're reduced modulo the prime.
+    ///   - Enum tags refer to one of the enum's variants. Otherwise the decoding fails with an
+    ///     [`Error::NativeAssert`] anyway, since there's no variant to mask them into.
+    ///   - Bounded ints are below the upper bound of their range. Otherwise the value is kept,
+    ///     even though it's out of range.
+    ///   - Arrays don't start past their end, nor end past their capacity. Otherwise the bounds
+    ///     are trusted.
+    ///   - Dictionaries have storage for their entries, and each entry has its own element within
+    ///     it. Otherwise the entries are trusted.
+    pub fn with_strict_decoding(mut self, strict_decoding: bool) -> Self {
+        self.strict_decoding = strict_decoding;
+        self
+    }
+
+    pub const fn program_registry(&self) -> &ProgramRegistry<CoreType, CoreLibfunc> {
+        &self.registry
     }
 
     pub fn invoke_dynamic(
@@ -78,30 +107,13 @@ impl AotNativeExecutor {
         args: &[Value],
         gas: Option<u64>,
     ) -> Result<ExecutionResult, Error> {
-        let available_gas = self
-            .gas_metadata
-            .get_initial_available_gas(function_id, gas)
-            .map_err(crate::error::Error::GasMetadataError)?;
-
-        let set_costs_builtin: extern "C" fn(*const u64) -> *const u64 = unsafe {
-            std::mem::transmute(
-                self.library
-                    .get::<extern "C" fn(*const u64) -> *const u64>(
-                        b"cairo_native__set_costs_builtin",
-                    )?
-                    .into_raw()
-                    .into_raw(),
-            )
-        };
-
-        super::invoke_dynamic(
-            &self.registry,
-            self.find_function_ptr(function_id)?,
-            set_costs_builtin,
-            self.extract_signature(function_id)?,
+        self.invoke_dynamic_with_options(
+            function_id,
             args,
-            available_gas,
-            Option::<DummySyscallHandler>::None,
+            ExecutionOptions {
+                gas,
+                ..ExecutionOptions::new()
+            },
         )
     }
 
@@ -111,32 +123,53 @@ impl AotNativeExecutor {
         args: &[Value],
         gas: Option<u64>,
         syscall_handler: impl StarknetSyscallHandler,
+    ) -> Result<ExecutionResult, Error> {
+        self.invoke_dynamic_with_options(
+            function_id,
+            args,
+            ExecutionOptions {
+                gas,
+                syscall_handler: Some(syscall_handler),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Execute a program with the given [`ExecutionOptions`].
+    pub fn invoke_dynamic_with_options(
+        &self,
+        function_id: &FunctionId,
+        args: &[Value],
+        options: ExecutionOptions<impl StarknetSyscallHandler>,
     ) -> Result<ExecutionResult, Error> {
         let available_gas = self
             .gas_metadata
-            .get_initial_available_gas(function_id, gas)
+            .get_initial_available_gas(function_id, options.gas)
             .map_err(crate::error::Error::GasMetadataError)?;
 
-        let set_costs_builtin: extern "C" fn(*const u64) -> *const u64 = unsafe {
-            std::mem::transmute(
-                self.library
-                    .get::<extern "C" fn(*const u64) -> *const u64>(
-                        b"cairo_native__set_costs_builtin",
-                    )?
-                    .into_raw()
-                    .into_raw(),
-            )
-        };
+        // Track the allocations of the execution, including its arguments and results.
+        #[cfg(feature = "with-mem-tracing")]
+        let mem_tracker = crate::utils::mem_tracing::ExecutionTracker::start();
 
-        super::invoke_dynamic(
+        let result = super::invoke_dynamic(
             &self.registry,
             self.find_function_ptr(function_id)?,
-            set_costs_builtin,
+            self.runtime,
             self.extract_signature(function_id)?,
             args,
             available_gas,
-            Some(syscall_handler),
-        )
+            options,
+            self.strict_decoding,
+        )?;
+
+        #[cfg(feature = "with-mem-tracing")]
+        let result = {
+            let mut result = result;
+            result.diagnostics.memory = Some(mem_tracker.finish());
+            result
+        };
+
+        Ok(result)
     }
 
     pub fn invoke_contract_dynamic(
@@ -146,36 +179,57 @@ impl AotNativeExecutor {
         gas: Option<u64>,
         syscall_handler: impl StarknetSyscallHandler,
     ) -> Result<ContractExecutionResult, Error> {
-        let available_gas = self
-            .gas_metadata
-            .get_initial_available_gas(function_id, gas)
-            .map_err(crate::error::Error::GasMetadataError)?;
-
-        let set_costs_builtin: extern "C" fn(*const u64) -> *const u64 = unsafe {
-            std::mem::transmute(
-                self.library
-                    .get::<extern "C" fn(*const u64) -> *const u64>(
-                        b"cairo_native__set_costs_builtin",
-                    )?
-                    .into_raw()
-                    .into_raw(),
-            )
-        };
+        self.invoke_contract_dynamic_with_options(
+            function_id,
+            args,
+            ExecutionOptions {
+                gas,
+                syscall_handler: Some(syscall_handler),
+                ..Default::default()
+            },
+        )
+    }
 
-        ContractExecutionResult::from_execution_result(super::invoke_dynamic(
-            &self.registry,
-            self.find_function_ptr(function_id)?,
-            set_costs_builtin,
-            self.extract_signature(function_id)?,
-            &[Value::Struct {
-                fields: vec![Value::Array(
-                    args.iter().cloned().map(Value::Felt252).collect(),
-                )],
-                debug_name: None,
-            }],
-            available_gas,
-            Some(syscall_handler),
-        )?)
+    /// Execute a contract entry point with the given [`ExecutionOptions`].
+    ///
+    /// Contract entry points always require a syscall handler.
+    pub fn invoke_contract_dynamic_with_options(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        mut options: ExecutionOptions<impl StarknetSyscallHandler>,
+    ) -> Result<ContractExecutionResult, Error> {
+        let syscall_handler = options
+            .syscall_handler
+            .take()
+            .ok_or(Error::MissingSyscallHandler)?;
+        let args = &[Value::Struct {
+            fields: vec![Value::Array(
+                args.iter().cloned().map(Value::Felt252).collect(),
+            )],
+            debug_name: None,
+        }];
+
+        if options.collect_receipt {
+            let mut collector = CollectingMiddleware::new();
+            let mut result = ContractExecutionResult::from_execution_result(
+                self.invoke_dynamic_with_options(
+                    function_id,
+                    args,
+                    options
+                        .with_syscall_handler(SyscallLayer::new(syscall_handler, &mut collector)),
+                )?,
+            )?;
+            result.receipt = Some(collector.into_receipt());
+
+            Ok(result)
+        } else {
+            ContractExecutionResult::from_execution_result(self.invoke_dynamic_with_options(
+                function_id,
+                args,
+                options.with_syscall_handler(syscall_handler),
+            )?)
+        }
     }
 
     pub fn find_function_ptr(&self, function_id: &FunctionId) -> Result<*mut c_void, Error> {
@@ -193,12 +247,7 @@ impl AotNativeExecutor {
     }
 
     pub fn find_symbol_ptr(&self, name: &str) -> Option<*mut c_void> {
-        unsafe {
-            self.library
-                .get::<*mut ()>(name.as_bytes())
-                .ok()
-                .map(|x| x.into_raw().into_raw())
-        }
+        find_library_symbol(&self.library, name)
     }
 
     fn extract_signature(&self, function_id: &FunctionId) -> Result<&FunctionSignature, Error> {
@@ -34,18 +34,30 @@
 use crate::{
     arch::AbiArgument,
     context::NativeContext,
-    error::{panic::ToNativeAssertError, Error, Result},
-    execution_result::{BuiltinStats, ContractExecutionResult},
-    executor::invoke_trampoline,
+    coverage::StatementCoverage,
+    error::{panic::ToNativeAssertError, Error, Result, ValueInvariantError},
+    execution_result::{
+        AbiExecutionResult, BuiltinStats, ContractExecutionResult, ExecutionDiagnostics,
+        ExecutionResult,
+    },
+    executor::{
+        find_library_symbol, interrupt, invoke_trampoline,
+        runtime_state::{ExecutionState, Reports},
+        ContractAbi, ExecutionOptions, NativeExecutor, RuntimeSymbols,
+    },
     metadata::gas::MetadataComputationConfig,
     module::NativeModule,
     native_panic,
-    starknet::{handler::StarknetSyscallHandlerCallbacks, StarknetSyscallHandler},
+    starknet::{
+        handler::StarknetSyscallHandlerCallbacks, CollectingMiddleware, StarknetSyscallHandler,
+        SyscallLayer,
+    },
     types::TypeBuilder,
     utils::{
         decode_error_message, generate_function_name, get_integer_layout, libc_free, libc_malloc,
         BuiltinCosts,
     },
+    values::{felt_from_bytes, Value},
     OptLevel,
 };
 use bumpalo::Bump;
@@ -57,8 +69,10 @@ use cairo_lang_sierra::{
     ids::FunctionId,
     program::Program,
 };
+use cairo_lang_starknet_classes::abi::Contract;
 use cairo_lang_starknet_classes::casm_contract_class::ENTRY_POINT_COST;
 use cairo_lang_starknet_classes::contract_class::ContractEntryPoints;
+use cairo_native_runtime::ExecutionContext;
 use educe::Educe;
 use libloading::Library;
 use serde::{Deserialize, Serialize};
@@ -79,9 +93,14 @@ use tempfile::NamedTempFile;
 pub struct AotContractExecutor {
     #[educe(Debug(ignore))]
     library: Arc<Library>,
+    #[educe(Debug(ignore))]
+    runtime: RuntimeSymbols,
     path: PathBuf,
     is_temp_path: bool,
     contract_info: NativeContractInfo,
+    strict_decoding: bool,
+    #[educe(Debug(ignore))]
+    abi: Option<Arc<ContractAbi>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -89,6 +108,9 @@ pub struct NativeContractInfo {
     pub version: ContractInfoVersion,
     pub entry_points_info: BTreeMap<u64, EntryPointInfo>,
     pub entry_point_selector_to_id: BTreeMap<Felt, u64>,
+    /// The debug names of the program's functions, used to name them in profiles.
+    #[serde(default)]
+    pub function_names: BTreeMap<u64, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -248,15 +270,24 @@ impl AotContractExecutor {
         let object_data = crate::module_to_object(&module, opt_level)?;
         crate::object_to_shared_lib(&object_data, &library_path)?;
 
+        let library = unsafe { Library::new(&library_path)? };
         Ok(Self {
-            library: Arc::new(unsafe { Library::new(&library_path)? }),
+            runtime: RuntimeSymbols::resolve(|name| find_library_symbol(&library, name)),
+            library: Arc::new(library),
             path: library_path,
             is_temp_path: true,
             contract_info: NativeContractInfo {
                 version: ContractInfoVersion::Version0,
                 entry_points_info: infos,
                 entry_point_selector_to_id,
+                function_names: sierra_program
+                    .funcs
+                    .iter()
+                    .filter_map(|x| Some((x.id.id, x.id.debug_name.as_ref()?.to_string())))
+                    .collect(),
             },
+            strict_decoding: false,
+            abi: None,
         })
     }
 
@@ -279,14 +310,87 @@ impl AotContractExecutor {
     pub fn load(library_path: &Path) -> Result<Self> {
         let info_str = std::fs::read_to_string(library_path.with_extension("json"))?;
         let contract_info: NativeContractInfo = serde_json::from_str(&info_str)?;
+        let library = unsafe { Library::new(library_path)? };
         Ok(Self {
-            library: Arc::new(unsafe { Library::new(library_path)? }),
+            runtime: RuntimeSymbols::resolve(|name| find_library_symbol(&library, name)),
+            library: Arc::new(library),
             path: library_path.to_path_buf(),
             is_temp_path: false,
             contract_info,
+            strict_decoding: false,
+            abi: None,
+        })
+    }
+
+    /// Enable or disable the strict decoding of return values.
+    ///
+    /// Entry points always return a result holding an array of felts. When enabled, return
+    /// values that violate the invariants of those types are reported as an
+    /// [`Error::ValueInvariant`]. The checked invariants, and what happens to them when disabled,
+    /// are:
+    ///   - Felts are below the prime. Otherwise they're reduced modulo the prime.
+    ///   - The array doesn't start past its end, nor end past its capacity. Otherwise the bounds
+    ///     are trusted.
+    ///
+    /// The result's tag isn't checked, since any tag is masked into one of its two variants.
+    pub fn with_strict_decoding(mut self, strict_decoding: bool) -> Self {
+        self.strict_decoding = strict_decoding;
+        self
+    }
+
+    /// Check whether the contract has an entry point with the given selector.
+    pub fn has_entry_point(&self, selector: Felt) -> bool {
+        self.contract_info
+            .entry_point_selector_to_id
+            .contains_key(&selector)
+    }
+
+    /// Attach the contract ABI, enabling [`call`](Self::call).
+    ///
+    /// The ABI is not saved alongside the library, so it must be attached again after loading it.
+    pub fn with_abi(mut self, abi: &Contract) -> Self {
+        self.abi = Some(Arc::new(ContractAbi::new(abi)));
+        self
+    }
+
+    /// Calls the entry point by its name, serializing the arguments and deserializing the return
+    /// values using the contract ABI.
+    ///
+    /// Check out [`ContractAbi`] for the JSON representation of each type. The arguments may be
+    /// either an object with a key for each input or an array with them in order.
+    pub fn call(
+        &self,
+        name: &str,
+        args: serde_json::Value,
+        options: ExecutionOptions<impl StarknetSyscallHandler>,
+    ) -> Result<AbiExecutionResult> {
+        let abi = self
+            .abi
+            .as_ref()
+            .ok_or_else(|| Error::AbiError("no ABI attached to the executor".to_string()))?;
+
+        let calldata = abi.encode_calldata(name, &args)?;
+        let execution = self.run_with_options(ContractAbi::selector(name), &calldata, options)?;
+
+        let return_value = match execution.failure_flag {
+            true => None,
+            false => Some(abi.decode_outputs(name, &execution.return_values)?),
+        };
+
+        Ok(AbiExecutionResult {
+            execution,
+            return_value,
         })
     }
 
+    /// Decode an event emitted by the contract using the attached ABI.
+    pub fn decode_event(&self, keys: &[Felt], data: &[Felt]) -> Result<serde_json::Value> {
+        self.abi
+            .as_ref()
+            .ok_or_else(|| Error::AbiError("no ABI attached to the executor".to_string()))?
+            .decode_event(keys, data)
+    }
+
     /// Runs the entry point by the given selector.
     ///
     /// - selector: The selector of the entry point to run.
@@ -302,11 +406,32 @@ impl AotContractExecutor {
         args: &[Felt],
         gas: u64,
         builtin_costs: Option<BuiltinCosts>,
-        mut syscall_handler: impl StarknetSyscallHandler,
+        syscall_handler: impl StarknetSyscallHandler,
     ) -> Result<ContractExecutionResult> {
-        let arena = Bump::new();
-        let mut invoke_data = Vec::<u8>::new();
+        self.run_with_options(
+            selector,
+            args,
+            ExecutionOptions {
+                gas: Some(gas),
+                builtin_costs,
+                syscall_handler: Some(syscall_handler),
+                ..Default::default()
+            },
+        )
+    }
 
+    /// Runs the entry point by the given selector with the given [`ExecutionOptions`].
+    ///
+    /// A syscall handler is always required. When no gas is provided, the entry point is run
+    /// without any gas available.
+    ///
+    /// The entry point gas cost is not deducted from the gas counter.
+    pub fn run_with_options(
+        &self,
+        selector: Felt,
+        args: &[Felt],
+        options: ExecutionOptions<impl StarknetSyscallHandler>,
+    ) -> Result<ContractExecutionResult> {
         let function_id = FunctionId {
             id: *self
                 .contract_info
@@ -315,17 +440,71 @@ impl AotContractExecutor {
                 .ok_or(Error::SelectorNotFound)?,
             debug_name: None,
         };
-        let function_ptr = self.find_function_ptr(&function_id, true)?;
+
+        self.run_entry_point(&function_id, args, options)
+    }
+
+    /// Runs the given entry point, collecting its receipt if requested.
+    fn run_entry_point(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        mut options: ExecutionOptions<impl StarknetSyscallHandler>,
+    ) -> Result<ContractExecutionResult> {
+        let syscall_handler = options
+            .syscall_handler
+            .take()
+            .ok_or(Error::MissingSyscallHandler)?;
+
+        if options.collect_receipt {
+            let mut collector = CollectingMiddleware::new();
+            let mut result = self.run_entry_point_with_handler(
+                function_id,
+                args,
+                options.with_syscall_handler(SyscallLayer::new(syscall_handler, &mut collector)),
+            )?;
+            result.receipt = Some(collector.into_receipt());
+
+            Ok(result)
+        } else {
+            self.run_entry_point_with_handler(
+                function_id,
+                args,
+                options.with_syscall_handler(syscall_handler),
+            )
+        }
+    }
+
+    fn run_entry_point_with_handler(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        options: ExecutionOptions<impl StarknetSyscallHandler>,
+    ) -> Result<ContractExecutionResult> {
+        let ExecutionOptions {
+            gas,
+            builtin_costs,
+            syscall_handler,
+            cancellation,
+            deadline,
+            diagnostics,
+            ..
+        } = options;
+        let gas = gas.unwrap_or(0);
+        let mut syscall_handler = syscall_handler.ok_or(Error::MissingSyscallHandler)?;
+
+        // Track the allocations of the execution, including its arguments and results.
+        #[cfg(feature = "with-mem-tracing")]
+        let mem_tracker = crate::utils::mem_tracing::ExecutionTracker::start();
+
+        let arena = Bump::new();
+        let mut invoke_data = Vec::<u8>::new();
+
+        let function_ptr = self.find_function_ptr(function_id, true)?;
+        let runtime = self.runtime;
+        let set_costs_builtin = runtime.set_costs_builtin()?;
 
         let builtin_costs: [u64; 7] = builtin_costs.unwrap_or_default().into();
-        let set_costs_builtin = unsafe {
-            self.library
-                .get::<extern "C" fn(*const u64) -> *const u64>(
-                    b"cairo_native__set_costs_builtin",
-                )?
-        };
-        // We may be inside a recursive contract, save the possible saved builtin costs to restore it after our call.
-        let old_builtincosts_ptr = set_costs_builtin(builtin_costs.as_ptr());
 
         //  it can vary from contract to contract thats why we need to store/ load it.
         let builtins_size: usize = self.contract_info.entry_points_info[&function_id.id]
@@ -343,6 +522,24 @@ impl AotContractExecutor {
         return_ptr.as_ptr().to_bytes(&mut invoke_data)?;
 
         let mut syscall_handler = StarknetSyscallHandlerCallbacks::new(&mut syscall_handler);
+        #[cfg(feature = "with-cheatcode")]
+        let mut cheatcode_context =
+            crate::starknet::handler::CheatcodeContext::new(&mut syscall_handler);
+        let mut execution_context = ExecutionContext::default();
+        let execution_interrupt = interrupt::install(cancellation.as_ref(), deadline);
+        execution_context.interrupt = execution_interrupt.as_ptr();
+        #[cfg(feature = "with-cheatcode")]
+        {
+            execution_context.cheatcode_context =
+                (&mut cheatcode_context as *mut crate::starknet::handler::CheatcodeContext).cast();
+        }
+        // Programs compiled with coverage mark their statements in a bitmap preallocated for them.
+        let mut coverage = diagnostics
+            .coverage
+            .then(|| StatementCoverage::with_statement_count(runtime.statement_count));
+        if let Some(coverage) = coverage.as_mut().filter(|_| runtime.statement_count > 0) {
+            execution_context.coverage = coverage.as_mut_ptr();
+        }
 
         for b in &self.contract_info.entry_points_info[&function_id.id].builtins {
             match b {
@@ -410,6 +607,9 @@ impl AotContractExecutor {
             };
         }
 
+        // Every function takes the execution context as its last argument.
+        (&mut execution_context as *mut ExecutionContext).to_bytes(&mut invoke_data)?;
+
         // Pad invoke data to the 16 byte boundary avoid segfaults.
         #[cfg(target_arch = "aarch64")]
         const REGISTER_BYTES: usize = 64;
@@ -428,6 +628,14 @@ impl AotContractExecutor {
         #[cfg(target_arch = "aarch64")]
         let mut ret_registers = [0; 4];
 
+        let mut state = ExecutionState::new(&diagnostics);
+        // The state shared with the caller (if we're running from a syscall) is only replaced
+        // right before invoking the program, so that returning early leaves it untouched.
+        // We may be inside a recursive contract, save the possible saved builtin costs to restore it after our call.
+        let old_builtincosts_ptr = set_costs_builtin(builtin_costs.as_ptr());
+        let previous_state = state.install(runtime.set_runtime_state);
+        // Keep the abort error of the caller apart from ours.
+        let previous_abort_error = crate::starknet::handler::replace_abort_error(None);
         unsafe {
             invoke_trampoline(
                 function_ptr,
@@ -437,6 +645,20 @@ impl AotContractExecutor {
             );
         }
 
+        let abort_error = crate::starknet::handler::replace_abort_error(previous_abort_error);
+        let Reports {
+            output,
+            events,
+            trace,
+        } = state.restore(runtime.set_runtime_state, previous_state);
+        set_costs_builtin(old_builtincosts_ptr);
+        let interrupt_result = execution_interrupt.result();
+        drop(execution_interrupt);
+        let (profile, call_stack) = events.into_parts();
+        if let Some(coverage) = &mut coverage {
+            coverage.shrink();
+        }
+
         // Parse final gas.
         unsafe fn read_value<T>(ptr: &mut NonNull<()>) -> &T {
             let align_offset = ptr
@@ -517,7 +739,16 @@ impl AotContractExecutor {
         let array_ptr: *mut u8 = unsafe { *read_value(value_ptr) };
         let start: u32 = unsafe { *read_value(value_ptr) };
         let end: u32 = unsafe { *read_value(value_ptr) };
-        let _cap: u32 = unsafe { *read_value(value_ptr) };
+        let cap: u32 = unsafe { *read_value(value_ptr) };
+
+        if self.strict_decoding && (start > end || end > cap) {
+            return Err(ValueInvariantError::ArrayBounds {
+                start,
+                end,
+                capacity: cap,
+            }
+            .into());
+        }
 
         let elem_stride = felt_layout.pad_to_align().size();
 
@@ -532,11 +763,9 @@ impl AotContractExecutor {
             // safe to create a NonNull because if the array has elements, the data_ptr can't be null.
             let cur_elem_ptr = NonNull::new(unsafe { data_ptr.byte_add(elem_stride * i) })
                 .to_native_assert_error("data_ptr should not be null")?;
-            let data = unsafe { cur_elem_ptr.cast::<[u8; 32]>().as_mut() };
-            data[31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-            let data = Felt::from_bytes_le_slice(data);
+            let data = unsafe { *cur_elem_ptr.cast::<[u8; 32]>().as_ref() };
 
-            array_value.push(data);
+            array_value.push(felt_from_bytes(data, self.strict_decoding)?);
         }
 
         if !array_ptr.is_null() {
@@ -562,20 +791,48 @@ impl AotContractExecutor {
             None
         };
 
-        // Restore the original builtin costs pointer.
-        set_costs_builtin(old_builtincosts_ptr);
+        // The results are parsed anyway to free them.
+        interrupt_result?;
+        if let Some(abort_error) = abort_error {
+            return Err(abort_error.into());
+        }
 
         #[cfg(feature = "with-mem-tracing")]
-        crate::utils::mem_tracing::report_stats();
+        let memory = Some(mem_tracker.finish());
+        #[cfg(not(feature = "with-mem-tracing"))]
+        let memory = None;
 
         Ok(ContractExecutionResult {
             remaining_gas,
             failure_flag: tag != 0,
             return_values: array_value,
             error_msg,
+            builtin_stats,
+            receipt: None,
+            diagnostics: ExecutionDiagnostics {
+                output: output.map(|output| String::from_utf8_lossy(&output).into_owned()),
+                profile: profile
+                    .map(|profile| profile.finish_with_names(&self.contract_info.function_names)),
+                coverage,
+                trace,
+                backtrace: call_stack.finish_with_names(&self.contract_info.function_names),
+                memory,
+            },
         })
     }
 
+    fn check_entry_point(&self, function_id: &FunctionId) -> Result<()> {
+        if self
+            .contract_info
+            .entry_points_info
+            .contains_key(&function_id.id)
+        {
+            Ok(())
+        } else {
+            Err(Error::EntryPointNotFound(function_id.id))
+        }
+    }
+
     pub fn find_function_ptr(
         &self,
         function_id: &FunctionId,
@@ -594,12 +851,102 @@ impl AotContractExecutor {
     }
 
     pub fn find_symbol_ptr(&self, name: &str) -> Option<*mut c_void> {
-        unsafe {
-            self.library
-                .get::<*mut ()>(name.as_bytes())
-                .ok()
-                .map(|x| x.into_raw().into_raw())
-        }
+        find_library_symbol(&self.library, name)
+    }
+}
+
+impl NativeExecutor for AotContractExecutor {
+    /// Execute a contract entry point.
+    ///
+    /// The contract executor doesn't keep the program registry, therefore only entry points are
+    /// supported. The arguments must be the calldata span (a struct with an array of felts) and
+    /// the return value is rebuilt as the entry point's `PanicResult`.
+    fn invoke_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Value],
+        options: ExecutionOptions<S>,
+    ) -> Result<ExecutionResult>
+    where
+        S: StarknetSyscallHandler,
+    {
+        let calldata = match args {
+            [Value::Struct { fields, .. }] => match fields.as_slice() {
+                [Value::Array(data)] => data,
+                _ => return Err(Error::UnexpectedValue("Struct { Array<felt252> }".into())),
+            },
+            _ => return Err(Error::UnexpectedValue("Struct { Array<felt252> }".into())),
+        };
+        let calldata = calldata
+            .iter()
+            .map(|x| match x {
+                Value::Felt252(x) => Ok(*x),
+                _ => Err(Error::UnexpectedValue("felt252".into())),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.check_entry_point(function_id)?;
+        let result = self.run_entry_point(function_id, &calldata, options)?;
+
+        let data = Value::Array(
+            result
+                .return_values
+                .into_iter()
+                .map(Value::Felt252)
+                .collect(),
+        );
+        let return_value = if result.failure_flag {
+            Value::Enum {
+                tag: 1,
+                value: Box::new(Value::Struct {
+                    fields: vec![
+                        Value::Struct {
+                            fields: Vec::new(),
+                            debug_name: None,
+                        },
+                        data,
+                    ],
+                    debug_name: None,
+                }),
+                debug_name: None,
+            }
+        } else {
+            Value::Enum {
+                tag: 0,
+                value: Box::new(Value::Struct {
+                    fields: vec![Value::Struct {
+                        fields: vec![data],
+                        debug_name: None,
+                    }],
+                    debug_name: None,
+                }),
+                debug_name: None,
+            }
+        };
+
+        Ok(ExecutionResult {
+            remaining_gas: Some(result.remaining_gas),
+            return_value,
+            builtin_stats: result.builtin_stats,
+            diagnostics: result.diagnostics,
+        })
+    }
+
+    fn invoke_contract_dynamic<S>(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        options: ExecutionOptions<S>,
+    ) -> Result<ContractExecutionResult>
+    where
+        S: StarknetSyscallHandler,
+    {
+        self.check_entry_point(function_id)?;
+        self.run_entry_point(function_id, args, options)
+    }
+
+    fn find_symbol_ptr(&self, name: &str) -> Option<*mut c_void> {
+        AotContractExecutor::find_symbol_ptr(self, name)
     }
 }
 
@@ -776,6 +1123,90 @@ mod tests {
         assert_eq!(result.return_values, vec![Felt::from(2), Felt::from(4)]);
     }
 
+    #[rstest]
+    fn test_contract_executor_call_abi(starknet_program: ContractClass) {
+        let executor = AotContractExecutor::new(
+            &starknet_program.extract_sierra_program().unwrap(),
+            &starknet_program.entry_points_by_type,
+            OptLevel::Default,
+        )
+        .unwrap()
+        .with_abi(starknet_program.abi.as_ref().unwrap());
+
+        let result = executor
+            .call(
+                "get",
+                serde_json::json!({ "x": "0x2" }),
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_syscall_handler(&mut StubSyscallHandler::default()),
+            )
+            .unwrap();
+
+        assert_eq!(
+            result.execution.return_values,
+            vec![Felt::from(2), Felt::from(4)]
+        );
+        assert_eq!(result.return_value, Some(serde_json::json!(["0x2", "0x4"])));
+
+        assert!(matches!(
+            executor.call(
+                "set",
+                serde_json::json!({}),
+                ExecutionOptions::new().with_syscall_handler(&mut StubSyscallHandler::default()),
+            ),
+            Err(Error::AbiError(_))
+        ));
+    }
+
+    #[rstest]
+    fn test_contract_executor_native_executor_trait(starknet_program: ContractClass) {
+        let executor = AotContractExecutor::new(
+            &starknet_program.extract_sierra_program().unwrap(),
+            &starknet_program.entry_points_by_type,
+            OptLevel::Default,
+        )
+        .unwrap();
+
+        // The last function in the program is the `get` wrapper function.
+        let entry_point = starknet_program
+            .entry_points_by_type
+            .external
+            .last()
+            .unwrap();
+        let function_id = FunctionId::new(entry_point.function_idx as u64);
+
+        let result = NativeExecutor::invoke_contract_dynamic(
+            &executor,
+            &function_id,
+            &[2.into()],
+            ExecutionOptions::new()
+                .with_gas(u64::MAX)
+                .with_syscall_handler(&mut StubSyscallHandler::default()),
+        )
+        .unwrap();
+        assert_eq!(result.return_values, vec![Felt::from(2), Felt::from(4)]);
+
+        let result = NativeExecutor::invoke_dynamic(
+            &executor,
+            &function_id,
+            &[Value::Struct {
+                fields: vec![Value::Array(vec![Value::Felt252(2.into())])],
+                debug_name: None,
+            }],
+            ExecutionOptions::new()
+                .with_gas(u64::MAX)
+                .with_syscall_handler(&mut StubSyscallHandler::default()),
+        )
+        .unwrap();
+        assert_eq!(
+            ContractExecutionResult::from_execution_result(result)
+                .unwrap()
+                .return_values,
+            vec![Felt::from(2), Felt::from(4)]
+        );
+    }
+
     #[rstest]
     #[case(OptLevel::Aggressive)]
     fn test_contract_executor_factorial(
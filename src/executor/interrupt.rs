@@ -0,0 +1,268 @@
+//! Cancellation and deadlines for running executions.
+//!
+//! Programs compiled with the `with-interrupts` feature check the interrupt flag of their
+//! execution whenever they withdraw gas (see `ExecutionContext::interrupt` in the runtime). Once
+//! it's set, every withdrawal fails and the program stops as if it had run out of gas, then the
+//! executor returns [`Error::Cancelled`] or [`Error::TimedOut`] instead of its results. Programs
+//! compiled without it never check the flag, so they can't be interrupted.
+
+use crate::error::Error;
+use cairo_native_runtime::{INTERRUPT_CANCELLED, INTERRUPT_NONE, INTERRUPT_TIMED_OUT};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Condvar, LazyLock, Mutex, Weak,
+    },
+    thread,
+    time::SystemTime,
+};
+
+thread_local! {
+    // The interrupt flag of the execution running on this thread, inherited by nested executions
+    // that don't have their own token or deadline.
+    static CURRENT_FLAG: RefCell<Option<Arc<InterruptFlag>>> = const { RefCell::new(None) };
+}
+
+/// The deadlines of every running execution, watched by a single timer thread which is started
+/// the first time an execution has a deadline.
+static TIMER: LazyLock<Arc<Timer>> = LazyLock::new(|| {
+    let timer = Arc::new(Timer::default());
+    thread::Builder::new()
+        .name("cairo-native-timer".to_string())
+        .spawn({
+            let timer = timer.clone();
+            move || timer.run()
+        })
+        .expect("failed to spawn the deadline timer thread");
+
+    timer
+});
+
+/// A handle to cancel executions from another thread.
+///
+/// Cloned tokens share their state: cancelling one of them cancels every execution that was
+/// started with any of them.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<Mutex<TokenState>>);
+
+#[derive(Debug, Default)]
+struct TokenState {
+    is_cancelled: bool,
+    // The interrupt flags of the executions currently running with this token.
+    flags: Vec<Arc<InterruptFlag>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the executions using this token to stop as soon as possible.
+    pub fn cancel(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.is_cancelled = true;
+        for flag in &state.flags {
+            flag.interrupt(INTERRUPT_CANCELLED);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.lock().unwrap().is_cancelled
+    }
+
+    fn register(&self, flag: &Arc<InterruptFlag>) {
+        let mut state = self.0.lock().unwrap();
+        if state.is_cancelled {
+            flag.interrupt(INTERRUPT_CANCELLED);
+        }
+        state.flags.push(flag.clone());
+    }
+
+    fn unregister(&self, flag: &Arc<InterruptFlag>) {
+        self.0
+            .lock()
+            .unwrap()
+            .flags
+            .retain(|other| !Arc::ptr_eq(other, flag));
+    }
+}
+
+/// The flag checked by the compiled code, along with the flags of the nested executions that have
+/// their own token or deadline (which have to be interrupted along with it).
+#[derive(Debug, Default)]
+struct InterruptFlag {
+    value: AtomicU8,
+    children: Mutex<Vec<Weak<InterruptFlag>>>,
+}
+
+impl InterruptFlag {
+    /// Set the flag (and its children's), unless it was already interrupted for another reason.
+    fn interrupt(&self, reason: u8) {
+        if self
+            .value
+            .compare_exchange(INTERRUPT_NONE, reason, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            for child in self.children.lock().unwrap().iter() {
+                if let Some(child) = child.upgrade() {
+                    child.interrupt(reason);
+                }
+            }
+        }
+    }
+
+    fn add_child(&self, child: &Arc<InterruptFlag>) {
+        let mut children = self.children.lock().unwrap();
+        // Checked while holding the lock so that a concurrent interruption can't be missed.
+        match self.value.load(Ordering::Relaxed) {
+            INTERRUPT_NONE => children.push(Arc::downgrade(child)),
+            reason => child.interrupt(reason),
+        }
+    }
+
+    fn remove_child(&self, child: &Arc<InterruptFlag>) {
+        self.children
+            .lock()
+            .unwrap()
+            .retain(|other| !std::ptr::eq(other.as_ptr(), Arc::as_ptr(child)));
+    }
+}
+
+#[derive(Default)]
+struct Timer {
+    next_id: AtomicU64,
+    // Sorted by deadline. The id keeps the keys of executions with the same deadline apart.
+    deadlines: Mutex<BTreeMap<(SystemTime, u64), Weak<InterruptFlag>>>,
+    condvar: Condvar,
+}
+
+impl Timer {
+    fn insert(&self, deadline: SystemTime, flag: &Arc<InterruptFlag>) -> (SystemTime, u64) {
+        let key = (deadline, self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.deadlines
+            .lock()
+            .unwrap()
+            .insert(key, Arc::downgrade(flag));
+        // The new deadline may be earlier than the one the timer is waiting for.
+        self.condvar.notify_one();
+
+        key
+    }
+
+    fn remove(&self, key: &(SystemTime, u64)) {
+        self.deadlines.lock().unwrap().remove(key);
+    }
+
+    fn run(&self) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        loop {
+            let now = SystemTime::now();
+            while let Some(entry) = deadlines.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                if let Some(flag) = entry.remove().upgrade() {
+                    flag.interrupt(INTERRUPT_TIMED_OUT);
+                }
+            }
+
+            deadlines = match deadlines.first_key_value() {
+                Some(((deadline, _), _)) => {
+                    let timeout = deadline.duration_since(now).unwrap_or_default();
+                    self.condvar.wait_timeout(deadlines, timeout).unwrap().0
+                }
+                None => self.condvar.wait(deadlines).unwrap(),
+            };
+        }
+    }
+}
+
+/// The interrupt flag of an execution, installed by [`install`].
+///
+/// Dropping it restores the flag of the caller, stops watching the deadline and unregisters the
+/// flag from the token and the caller's flag.
+pub(crate) struct ExecutionInterrupt {
+    flag: Arc<InterruptFlag>,
+    previous: Option<Arc<InterruptFlag>>,
+    cancellation: Option<CancellationToken>,
+    deadline: Option<(SystemTime, u64)>,
+}
+
+impl ExecutionInterrupt {
+    /// The flag to hand to the compiled code, through `ExecutionContext::interrupt`.
+    pub fn as_ptr(&self) -> *const AtomicU8 {
+        &self.flag.value
+    }
+
+    /// Return an error if the execution was interrupted.
+    pub fn result(&self) -> Result<(), Error> {
+        match self.flag.value.load(Ordering::Relaxed) {
+            INTERRUPT_CANCELLED => Err(Error::Cancelled),
+            INTERRUPT_TIMED_OUT => Err(Error::TimedOut),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for ExecutionInterrupt {
+    fn drop(&mut self) {
+        if let Some(key) = &self.deadline {
+            TIMER.remove(key);
+        }
+        if let Some(cancellation) = &self.cancellation {
+            cancellation.unregister(&self.flag);
+        }
+        if let Some(previous) = &self.previous {
+            if !Arc::ptr_eq(previous, &self.flag) {
+                previous.remove_child(&self.flag);
+            }
+        }
+
+        CURRENT_FLAG.set(self.previous.take());
+    }
+}
+
+/// Install the interrupt flag of an execution.
+///
+/// The flag is inherited from the current execution when neither a token nor a deadline is
+/// provided, so that nested calls made from syscall handlers are interrupted too (and their
+/// interruption is reported by the caller as well). Otherwise the nested execution gets its own
+/// flag, which is still interrupted along with the caller's.
+pub(crate) fn install(
+    cancellation: Option<&CancellationToken>,
+    deadline: Option<SystemTime>,
+) -> ExecutionInterrupt {
+    let previous = CURRENT_FLAG.take();
+    let flag = match &previous {
+        Some(flag) if cancellation.is_none() && deadline.is_none() => flag.clone(),
+        Some(parent) => {
+            let flag = Arc::new(InterruptFlag::default());
+            parent.add_child(&flag);
+            flag
+        }
+        None => Arc::new(InterruptFlag::default()),
+    };
+    CURRENT_FLAG.set(Some(flag.clone()));
+
+    if let Some(cancellation) = cancellation {
+        cancellation.register(&flag);
+    }
+    let deadline = deadline.and_then(|deadline| {
+        // Deadlines that have already passed interrupt the execution right away.
+        if deadline <= SystemTime::now() {
+            flag.interrupt(INTERRUPT_TIMED_OUT);
+            return None;
+        }
+
+        Some(TIMER.insert(deadline, &flag))
+    });
+
+    ExecutionInterrupt {
+        flag,
+        previous,
+        cancellation: cancellation.cloned(),
+        deadline,
+    }
+}
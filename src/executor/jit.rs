@@ -1,9 +1,10 @@
 use crate::{
     error::Error,
     execution_result::{ContractExecutionResult, ExecutionResult},
+    executor::{ExecutionOptions, RuntimeSymbols},
     metadata::gas::GasMetadata,
     module::NativeModule,
-    starknet::{DummySyscallHandler, StarknetSyscallHandler},
+    starknet::{CollectingMiddleware, StarknetSyscallHandler, SyscallLayer},
     utils::{create_engine, generate_function_name},
     values::Value,
     OptLevel,
@@ -24,8 +25,10 @@ pub struct JitNativeExecutor<'m> {
 
     module: Module<'m>,
     registry: ProgramRegistry<CoreType, CoreLibfunc>,
+    runtime: RuntimeSymbols,
 
     gas_metadata: GasMetadata,
+    strict_decoding: bool,
 }
 
 unsafe impl Send for JitNativeExecutor<'_> {}
@@ -36,6 +39,7 @@ impl std::fmt::Debug for JitNativeExecutor<'_> {
         f.debug_struct("JitNativeExecutor")
             .field("module", &self.module)
             .field("gas_metadata", &self.gas_metadata)
+            .field("strict_decoding", &self.strict_decoding)
             .finish()
     }
 }
@@ -51,17 +55,31 @@ impl<'m> JitNativeExecutor<'m> {
             metadata,
         } = native_module;
 
+        let engine = create_engine(&module, &metadata, opt_level);
         Ok(Self {
-            engine: create_engine(&module, &metadata, opt_level),
+            runtime: RuntimeSymbols::resolve(|name| find_engine_symbol(&engine, name)),
+            engine,
             module,
             registry,
             gas_metadata: metadata
                 .get::<GasMetadata>()
                 .cloned()
                 .ok_or(Error::MissingMetadata)?,
+            strict_decoding: false,
         })
     }
 
+    /// Enable or disable the strict decoding of return values.
+    ///
+    /// When enabled, return values that violate their type's invariants are reported as an
+    /// [`Error::ValueInvariant`]. See
+    /// [`AotNativeExecutor::with_strict_decoding`](super::AotNativeExecutor::with_strict_decoding)
+    /// for the checked invariants.
+    pub fn with_strict_decoding(mut self, strict_decoding: bool) -> Self {
+        self.strict_decoding = strict_decoding;
+        self
+    }
+
     pub const fn program_registry(&self) -> &ProgramRegistry<CoreType, CoreLibfunc> {
         &self.registry
     }
@@ -77,22 +95,13 @@ impl<'m> JitNativeExecutor<'m> {
         args: &[Value],
         gas: Option<u64>,
     ) -> Result<ExecutionResult, Error> {
-        let available_gas = self
-            .gas_metadata
-            .get_initial_available_gas(function_id, gas)
-            .map_err(crate::error::Error::GasMetadataError)?;
-
-        let set_builtin_costs_fnptr: extern "C" fn(*const u64) -> *const u64 =
-            unsafe { std::mem::transmute(self.engine.lookup("cairo_native__set_costs_builtin")) };
-
-        super::invoke_dynamic(
-            &self.registry,
-            self.find_function_ptr(function_id),
-            set_builtin_costs_fnptr,
-            self.extract_signature(function_id)?,
+        self.invoke_dynamic_with_options(
+            function_id,
             args,
-            available_gas,
-            Option::<DummySyscallHandler>::None,
+            ExecutionOptions {
+                gas,
+                ..ExecutionOptions::new()
+            },
         )
     }
 
@@ -103,24 +112,53 @@ impl<'m> JitNativeExecutor<'m> {
         args: &[Value],
         gas: Option<u64>,
         syscall_handler: impl StarknetSyscallHandler,
+    ) -> Result<ExecutionResult, Error> {
+        self.invoke_dynamic_with_options(
+            function_id,
+            args,
+            ExecutionOptions {
+                gas,
+                syscall_handler: Some(syscall_handler),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Execute a program with the given [`ExecutionOptions`].
+    pub fn invoke_dynamic_with_options(
+        &self,
+        function_id: &FunctionId,
+        args: &[Value],
+        options: ExecutionOptions<impl StarknetSyscallHandler>,
     ) -> Result<ExecutionResult, Error> {
         let available_gas = self
             .gas_metadata
-            .get_initial_available_gas(function_id, gas)
+            .get_initial_available_gas(function_id, options.gas)
             .map_err(crate::error::Error::GasMetadataError)?;
 
-        let set_builtin_costs_fnptr: extern "C" fn(*const u64) -> *const u64 =
-            unsafe { std::mem::transmute(self.engine.lookup("cairo_native__set_costs_builtin")) };
+        // Track the allocations of the execution, including its arguments and results.
+        #[cfg(feature = "with-mem-tracing")]
+        let mem_tracker = crate::utils::mem_tracing::ExecutionTracker::start();
 
-        super::invoke_dynamic(
+        let result = super::invoke_dynamic(
             &self.registry,
             self.find_function_ptr(function_id),
-            set_builtin_costs_fnptr,
+            self.runtime,
             self.extract_signature(function_id)?,
             args,
             available_gas,
-            Some(syscall_handler),
-        )
+            options,
+            self.strict_decoding,
+        )?;
+
+        #[cfg(feature = "with-mem-tracing")]
+        let result = {
+            let mut result = result;
+            result.diagnostics.memory = Some(mem_tracker.finish());
+            result
+        };
+
+        Ok(result)
     }
 
     pub fn invoke_contract_dynamic(
@@ -130,28 +168,57 @@ impl<'m> JitNativeExecutor<'m> {
         gas: Option<u64>,
         syscall_handler: impl StarknetSyscallHandler,
     ) -> Result<ContractExecutionResult, Error> {
-        let available_gas = self
-            .gas_metadata
-            .get_initial_available_gas(function_id, gas)
-            .map_err(crate::error::Error::GasMetadataError)?;
-
-        let set_builtin_costs_fnptr: extern "C" fn(*const u64) -> *const u64 =
-            unsafe { std::mem::transmute(self.engine.lookup("cairo_native__set_costs_builtin")) };
+        self.invoke_contract_dynamic_with_options(
+            function_id,
+            args,
+            ExecutionOptions {
+                gas,
+                syscall_handler: Some(syscall_handler),
+                ..Default::default()
+            },
+        )
+    }
 
-        ContractExecutionResult::from_execution_result(super::invoke_dynamic(
-            &self.registry,
-            self.find_function_ptr(function_id),
-            set_builtin_costs_fnptr,
-            self.extract_signature(function_id)?,
-            &[Value::Struct {
-                fields: vec![Value::Array(
-                    args.iter().cloned().map(Value::Felt252).collect(),
-                )],
-                debug_name: None,
-            }],
-            available_gas,
-            Some(syscall_handler),
-        )?)
+    /// Execute a contract entry point with the given [`ExecutionOptions`].
+    ///
+    /// Contract entry points always require a syscall handler.
+    pub fn invoke_contract_dynamic_with_options(
+        &self,
+        function_id: &FunctionId,
+        args: &[Felt],
+        mut options: ExecutionOptions<impl StarknetSyscallHandler>,
+    ) -> Result<ContractExecutionResult, Error> {
+        let syscall_handler = options
+            .syscall_handler
+            .take()
+            .ok_or(Error::MissingSyscallHandler)?;
+        let args = &[Value::Struct {
+            fields: vec![Value::Array(
+                args.iter().cloned().map(Value::Felt252).collect(),
+            )],
+            debug_name: None,
+        }];
+
+        if options.collect_receipt {
+            let mut collector = CollectingMiddleware::new();
+            let mut result = ContractExecutionResult::from_execution_result(
+                self.invoke_dynamic_with_options(
+                    function_id,
+                    args,
+                    options
+                        .with_syscall_handler(SyscallLayer::new(syscall_handler, &mut collector)),
+                )?,
+            )?;
+            result.receipt = Some(collector.into_receipt());
+
+            Ok(result)
+        } else {
+            ContractExecutionResult::from_execution_result(self.invoke_dynamic_with_options(
+                function_id,
+                args,
+                options.with_syscall_handler(syscall_handler),
+            )?)
+        }
     }
 
     pub fn find_function_ptr(&self, function_id: &FunctionId) -> *mut c_void {
@@ -163,13 +230,7 @@ impl<'m> JitNativeExecutor<'m> {
     }
 
     pub fn find_symbol_ptr(&self, name: &str) -> Option<*mut c_void> {
-        let ptr = self.engine.lookup(name) as *mut c_void;
-
-        if ptr.is_null() {
-            None
-        } else {
-            Some(ptr)
-        }
+        find_engine_symbol(&self.engine, name)
     }
 
     fn extract_signature(&self, function_id: &FunctionId) -> Result<&FunctionSignature, Error> {
@@ -179,3 +240,14 @@ impl<'m> JitNativeExecutor<'m> {
             .map(|func| &func.signature)?)
     }
 }
+
+/// Find a symbol of a JIT-compiled module.
+fn find_engine_symbol(engine: &ExecutionEngine, name: &str) -> Option<*mut c_void> {
+    let ptr = engine.lookup(name) as *mut c_void;
+
+    if ptr.is_null() {
+        None
+    } else {
+        Some(ptr)
+    }
+}
@@ -0,0 +1,99 @@
+//! The state the runtime reports to while a program runs.
+//!
+//! Everything a program sends back to the host through the runtime (its printed output, events,
+//! trace, function hook and breakpoint calls, and allocations) goes through a single
+//! [`RuntimeState`], which the executors install right before invoking the program and restore
+//! right after. Nested executions install their own, so they never report to the ones of their
+//! caller (except for the printed output, which they share unless they capture their own).
+
+use crate::{
+    events::EventRecorder,
+    executor::DiagnosticOptions,
+    trace::{self, StatementTrace},
+};
+use cairo_native_runtime::{PrintSink, RuntimeState};
+use std::ffi::c_void;
+
+/// Signature of the `cairo_native__set_runtime_state` runtime function.
+pub(crate) type SetRuntimeStateFn = extern "C" fn(*mut RuntimeState) -> *mut RuntimeState;
+
+/// What an execution reported through its runtime state.
+#[derive(Debug)]
+pub(crate) struct Reports {
+    pub output: Option<Vec<u8>>,
+    pub events: EventRecorder,
+    pub trace: Option<StatementTrace>,
+}
+
+/// The runtime state of an execution, along with what it reports to.
+///
+/// It's boxed since the runtime keeps pointers into it while it's installed.
+#[derive(Debug)]
+pub(crate) struct ExecutionState {
+    state: RuntimeState,
+    reports: Reports,
+}
+
+impl ExecutionState {
+    pub fn new(diagnostics: &DiagnosticOptions) -> Box<Self> {
+        let mut this = Box::new(Self {
+            state: RuntimeState::EMPTY,
+            reports: Reports {
+                output: diagnostics.capture_output.then(Vec::new),
+                events: EventRecorder::new(diagnostics.profile),
+                trace: diagnostics.trace.then(StatementTrace::new),
+            },
+        });
+
+        if let Some(output) = &mut this.reports.output {
+            this.state.print_sink = PrintSink {
+                data: (output as *mut Vec<u8>).cast(),
+                write: Some(write_to_buffer),
+            };
+        }
+        this.state.event_buffer = this.reports.events.buffer();
+        this.state.trace_sink = trace::sink(this.reports.trace.as_mut());
+        if let Some(function_hooks) = diagnostics.function_hooks {
+            this.state.function_hooks = function_hooks;
+        }
+        if let Some(breakpoint_handler) = diagnostics.breakpoint_handler {
+            this.state.breakpoint_handler = breakpoint_handler;
+        }
+        #[cfg(feature = "with-mem-tracing")]
+        {
+            this.state.memory_tracer = crate::utils::mem_tracing::memory_tracer();
+        }
+
+        this
+    }
+
+    /// Install the state for the current execution, returning the one of the caller.
+    pub fn install(&mut self, set_runtime_state: SetRuntimeStateFn) -> *mut RuntimeState {
+        let previous = set_runtime_state(&mut self.state);
+        if self.reports.output.is_none() {
+            if let Some(previous) = unsafe { previous.as_ref() } {
+                self.state.print_sink = previous.print_sink;
+            }
+        }
+
+        previous
+    }
+
+    /// Restore the state of the caller, returning what the execution reported (including the
+    /// events left in its buffer).
+    pub fn restore(
+        mut self: Box<Self>,
+        set_runtime_state: SetRuntimeStateFn,
+        previous: *mut RuntimeState,
+    ) -> Reports {
+        set_runtime_state(previous);
+        self.reports.events.flush(&self.state.event_buffer);
+
+        self.reports
+    }
+}
+
+extern "C" fn write_to_buffer(data: *mut c_void, bytes: *const u8, len: usize) {
+    let buffer = unsafe { &mut *data.cast::<Vec<u8>>() };
+    buffer.extend_from_slice(unsafe { std::slice::from_raw_parts(bytes, len) });
+}
@@ -0,0 +1,158 @@
+//! # Function hooks
+//!
+//! Programs compiled with the `with-function-hooks` feature call the host's [`FunctionHooks`] when
+//! entering and returning from every Sierra function (including tail-recursive calls), which is
+//! enough to build function-level stepping debuggers or call tree viewers. Executions install the
+//! hooks through
+//! [`ExecutionOptions::with_function_hooks`](crate::executor::ExecutionOptions::with_function_hooks).
+//!
+//! The hooks receive the function id and a pointer to the function's non-builtin arguments (on
+//! entry) or return values (on exit), which can be decoded with [`decode_arguments`] and
+//! [`decode_return_values`] while the hook runs.
+//!
+//! Programs compiled without the feature don't call the hooks.
+
+use crate::{error::Error, types::TypeBuilder, values::Value};
+use cairo_lang_sierra::{
+    extensions::core::{CoreLibfunc, CoreType},
+    ids::{ConcreteTypeId, FunctionId},
+    program_registry::ProgramRegistry,
+};
+pub use cairo_native_runtime::FunctionHooks;
+use std::{alloc::Layout, ffi::c_void, ptr::NonNull};
+
+/// Decode the arguments received by an `enter` hook of the function.
+///
+/// # Safety
+///
+/// The `values` must be the pointer received by the hook, which is only valid while it runs.
+pub unsafe fn decode_arguments(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    function_id: u64,
+    values: *const c_void,
+) -> Result<Vec<Value>, Error> {
+    let function = registry.get_function(&FunctionId::new(function_id))?;
+    decode_values(registry, &function.signature.param_types, values)
+}
+
+/// Decode the return values received by an `exit` hook of the function.
+///
+/// # Safety
+///
+/// The `values` must be the pointer received by the hook, which is only valid while it runs.
+pub unsafe fn decode_return_values(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    function_id: u64,
+    values: *const c_void,
+) -> Result<Vec<Value>, Error> {
+    let function = registry.get_function(&FunctionId::new(function_id))?;
+    decode_values(registry, &function.signature.ret_types, values)
+}
+
+unsafe fn decode_values(
+    registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+    type_ids: &[ConcreteTypeId],
+    values: *const c_void,
+) -> Result<Vec<Value>, Error> {
+    let mut layout = Option::<Layout>::None;
+    let mut decoded = Vec::new();
+    for type_id in type_ids {
+        let type_info = registry.get_type(type_id)?;
+        if type_info.is_builtin() {
+            continue;
+        }
+
+        let type_layout = type_info.layout(registry)?;
+        let (new_layout, offset) = match layout {
+            Some(layout) => layout.extend(type_layout)?,
+            None => (type_layout, 0),
+        };
+        layout = Some(new_layout);
+
+        // The values still belong to the program, so they must not be dropped.
+        let value_ptr = NonNull::new(values.cast_mut().byte_add(offset).cast())
+            .ok_or_else(|| Error::UnexpectedValue("null function hook values".to_string()))?;
+        decoded.push(Value::from_ptr(value_ptr, type_id, registry, false, false)?);
+    }
+
+    Ok(decoded)
+}
+
+#[cfg(all(test, feature = "with-function-hooks"))]
+mod tests {
+    use super::*;
+    use crate::{
+        context::NativeContext,
+        executor::{ExecutionOptions, JitNativeExecutor},
+        utils::test::load_cairo,
+        OptLevel,
+    };
+    use starknet_types_core::felt::Felt;
+
+    struct CallRecorder<'a> {
+        registry: &'a ProgramRegistry<CoreType, CoreLibfunc>,
+        calls: Vec<(bool, u64, Vec<Value>)>,
+    }
+
+    extern "C" fn record_enter(data: *mut c_void, function_id: u64, values: *const c_void) {
+        let recorder = unsafe { &mut *data.cast::<CallRecorder>() };
+        let values = unsafe { decode_arguments(recorder.registry, function_id, values) };
+        recorder.calls.push((true, function_id, values.unwrap()));
+    }
+
+    extern "C" fn record_exit(data: *mut c_void, function_id: u64, values: *const c_void) {
+        let recorder = unsafe { &mut *data.cast::<CallRecorder>() };
+        let values = unsafe { decode_return_values(recorder.registry, function_id, values) };
+        recorder.calls.push((false, function_id, values.unwrap()));
+    }
+
+    #[test]
+    fn test_function_hooks() {
+        let (_, program) = load_cairo! {
+            fn run_test() -> felt252 {
+                double(21)
+            }
+
+            #[inline(never)]
+            fn double(x: felt252) -> felt252 {
+                x + x
+            }
+        };
+        let run_test_id = program.funcs[0].id.id;
+        let double_id = program.funcs[1].id.id;
+
+        let native_context = NativeContext::new();
+        let module = native_context
+            .compile(&program, false, Some(Default::default()))
+            .expect("failed to compile context");
+        let executor = JitNativeExecutor::from_native_module(module, OptLevel::default()).unwrap();
+
+        let mut recorder = CallRecorder {
+            registry: executor.program_registry(),
+            calls: Vec::new(),
+        };
+        executor
+            .invoke_dynamic_with_options(
+                &program.funcs[0].id,
+                &[],
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_function_hooks(FunctionHooks {
+                        data: (&mut recorder as *mut CallRecorder).cast(),
+                        enter: Some(record_enter),
+                        exit: Some(record_exit),
+                    }),
+            )
+            .unwrap();
+
+        assert_eq!(
+            recorder.calls,
+            [
+                (true, run_test_id, vec![]),
+                (true, double_id, vec![Value::Felt252(Felt::from(21))]),
+                (false, double_id, vec![Value::Felt252(Felt::from(42))]),
+                (false, run_test_id, vec![Value::Felt252(Felt::from(42))]),
+            ]
+        );
+    }
+}
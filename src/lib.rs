@@ -15,20 +15,27 @@ pub use self::{
 };
 
 mod arch;
+pub mod backtrace;
 pub mod cache;
 mod compiler;
 pub mod context;
+pub mod coverage;
 pub mod debug;
+pub mod debug_info;
 pub mod docs;
 pub mod error;
+mod events;
 pub mod execution_result;
 pub mod executor;
 mod ffi;
+pub mod function_hooks;
 mod libfuncs;
 pub mod metadata;
 pub mod module;
+pub mod profiler;
 pub mod starknet;
 pub mod starknet_stub;
+pub mod trace;
 mod types;
 pub mod utils;
 mod values;
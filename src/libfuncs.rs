@@ -304,6 +304,16 @@ where
         self.init_block
     }
 
+    /// Return the pointer to the current
+    /// [`ExecutionContext`](cairo_native_runtime::ExecutionContext).
+    ///
+    /// It's passed to every function as its last argument, so it's the last argument of the init
+    /// block.
+    pub fn execution_context(&self) -> Result<Value<'ctx, 'this>> {
+        let init_block: &'this Block<'ctx> = self.init_block;
+        Ok(init_block.argument(init_block.argument_count() - 1)?.into())
+    }
+
     /// Inserts a new block after all the current libfunc's blocks.
     pub fn append_block(&self, block: Block<'ctx>) -> &'this Block<'ctx> {
         let block = self
@@ -67,21 +67,6 @@ pub fn build_init<'ctx, 'this>(
     metadata: &mut MetadataStorage,
     info: &EnumInitConcreteLibfunc,
 ) -> Result<()> {
-    #[cfg(feature = "with-debug-utils")]
-    if let Some(auto_breakpoint) =
-        metadata.get::<crate::metadata::auto_breakpoint::AutoBreakpoint>()
-    {
-        auto_breakpoint.maybe_breakpoint(
-            entry,
-            location,
-            metadata,
-            &crate::metadata::auto_breakpoint::BreakpointEvent::EnumInit {
-                type_id: info.signature.branch_signatures[0].vars[0].ty.clone(),
-                variant_idx: info.index,
-            },
-        )?;
-    }
-
     let val = build_enum_value(
         context,
         registry,
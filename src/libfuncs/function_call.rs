@@ -183,6 +183,7 @@ pub fn build<'ctx, 'this>(
         } else {
             None
         };
+        arguments.push(helper.execution_context()?);
 
         let function_call_result = entry.append_op_result(
             OperationBuilder::new("llvm.call", location)
@@ -15,6 +15,12 @@ use cairo_lang_sierra::{
     },
     program_registry::ProgramRegistry,
 };
+#[cfg(feature = "with-interrupts")]
+use cairo_native_runtime::{ExecutionContext, INTERRUPT_NONE};
+#[cfg(feature = "with-interrupts")]
+use melior::{dialect::llvm, ir::attribute::IntegerAttribute};
+#[cfg(any(feature = "with-interrupts", feature = "with-profiling"))]
+use melior::{dialect::arith, ir::Value};
 use melior::{
     dialect::{arith::CmpiPredicate, ods},
     ir::{r#type::IntegerType, Block, Location},
@@ -137,6 +143,18 @@ pub fn build_withdraw_gas<'ctx, 'this>(
         total_gas_cost_value,
         location,
     )?;
+    #[cfg(feature = "with-interrupts")]
+    let is_enough = build_interrupt_check(context, helper, entry, location, is_enough)?;
+    #[cfg(feature = "with-profiling")]
+    build_profile_withdrawal(
+        context,
+        helper,
+        entry,
+        location,
+        metadata,
+        is_enough,
+        total_gas_cost_value,
+    )?;
 
     let resulting_gas = entry.append_op_result(
         ods::llvm::intr_usub_sat(context, current_gas, total_gas_cost_value, location).into(),
@@ -160,7 +178,7 @@ pub fn build_builtin_withdraw_gas<'ctx, 'this>(
     entry: &'this Block<'ctx>,
     location: Location<'ctx>,
     helper: &LibfuncHelper<'ctx, 'this>,
-    metadata: &MetadataStorage,
+    metadata: &mut MetadataStorage,
     _info: &SignatureOnlyConcreteLibfunc,
 ) -> Result<()> {
     let range_check = super::increment_builtin_counter(context, entry, location, entry.arg(0)?)?;
@@ -169,7 +187,8 @@ pub fn build_builtin_withdraw_gas<'ctx, 'this>(
 
     let gas_cost = metadata
         .get::<GasCost>()
-        .expect("builtin_withdraw_gas should always have a gas cost");
+        .expect("builtin_withdraw_gas should always have a gas cost")
+        .clone();
 
     let u64_type: melior::ir::Type = IntegerType::new(context, 64).into();
 
@@ -215,6 +234,18 @@ pub fn build_builtin_withdraw_gas<'ctx, 'this>(
         total_gas_cost_value,
         location,
     )?;
+    #[cfg(feature = "with-interrupts")]
+    let is_enough = build_interrupt_check(context, helper, entry, location, is_enough)?;
+    #[cfg(feature = "with-profiling")]
+    build_profile_withdrawal(
+        context,
+        helper,
+        entry,
+        location,
+        metadata,
+        is_enough,
+        total_gas_cost_value,
+    )?;
 
     let resulting_gas = entry.append_op_result(
         ods::llvm::intr_usub_sat(context, current_gas, total_gas_cost_value, location).into(),
@@ -231,6 +262,85 @@ pub fn build_builtin_withdraw_gas<'ctx, 'this>(
     Ok(())
 }
 
+/// Make a gas withdrawal fail if the execution has been cancelled or timed out.
+///
+/// Every function entry and loop iteration withdraws gas, which makes it a cheap place to check
+/// the interrupt flag of the execution context. Failing the withdrawal makes the program stop as if
+/// it had run out of gas, after which the executor reports the actual reason.
+#[cfg(feature = "with-interrupts")]
+fn build_interrupt_check<'ctx, 'this>(
+    context: &'ctx Context,
+    helper: &LibfuncHelper<'ctx, 'this>,
+    entry: &'this Block<'ctx>,
+    location: Location<'ctx>,
+    is_enough: Value<'ctx, 'this>,
+) -> Result<Value<'ctx, 'this>> {
+    let interrupt_ptr_ptr = entry.gep(
+        context,
+        location,
+        helper.execution_context()?,
+        &[GepIndex::Const(ExecutionContext::INTERRUPT.try_into()?)],
+        IntegerType::new(context, 8).into(),
+    )?;
+    let interrupt_ptr = entry.load(
+        context,
+        location,
+        interrupt_ptr_ptr,
+        llvm::r#type::pointer(context, 0),
+    )?;
+    // The flag is set from another thread, so it has to be read with a (relaxed) atomic load.
+    let interrupt = {
+        let i64_ty = IntegerType::new(context, 64).into();
+
+        let mut op = ods::llvm::load(
+            context,
+            IntegerType::new(context, 8).into(),
+            interrupt_ptr,
+            location,
+        );
+        op.set_alignment(IntegerAttribute::new(i64_ty, 1));
+        // `llvm::AtomicOrdering::Monotonic`.
+        op.set_ordering(IntegerAttribute::new(i64_ty, 2));
+
+        entry.append_op_result(op.into())?
+    };
+
+    let k0 = entry.const_int(context, location, INTERRUPT_NONE, 8)?;
+    let is_running = entry.cmpi(context, CmpiPredicate::Eq, interrupt, k0, location)?;
+
+    entry.append_op_result(arith::andi(is_enough, is_running, location))
+}
+
+/// Report the gas withdrawn (zero if the withdrawal failed) to the profiler, which attributes it to
+/// the function being executed.
+#[cfg(feature = "with-profiling")]
+fn build_profile_withdrawal<'ctx, 'this>(
+    context: &'ctx Context,
+    module: &melior::ir::Module,
+    entry: &'this Block<'ctx>,
+    location: Location<'ctx>,
+    metadata: &mut MetadataStorage,
+    is_enough: Value<'ctx, 'this>,
+    gas_cost: Value<'ctx, 'this>,
+) -> Result<()> {
+    let k0 = entry.const_int(context, location, 0, 64)?;
+    let withdrawn = entry.append_op_result(arith::select(is_enough, gas_cost, k0, location))?;
+
+    metadata
+        .get_mut::<RuntimeBindingsMeta>()
+        .ok_or(Error::MissingMetadata)?
+        .record_event(
+            context,
+            module,
+            entry,
+            cairo_native_runtime::PROFILE_GAS_WITHDRAWN,
+            withdrawn,
+            location,
+        )?;
+
+    Ok(())
+}
+
 /// Generate MLIR operations for the `get_builtin_costs` libfunc.
 pub fn build_get_builtin_costs<'ctx, 'this>(
     context: &'ctx Context,
@@ -4,7 +4,7 @@ use crate::{
     error::Result,
     libfuncs::LibfuncHelper,
     metadata::{runtime_bindings::RuntimeBindingsMeta, MetadataStorage},
-    utils::{get_integer_layout, BlockExt, ProgramRegistryExt},
+    utils::{get_integer_layout, BlockExt, GepIndex, ProgramRegistryExt},
 };
 use cairo_lang_sierra::{
     extensions::{
@@ -14,6 +14,7 @@ use cairo_lang_sierra::{
     },
     program_registry::ProgramRegistry,
 };
+use cairo_native_runtime::ExecutionContext;
 use melior::{
     dialect::llvm::{self, alloca, AllocaOptions, LoadStoreOptions},
     ir::{
@@ -93,7 +94,22 @@ pub fn build<'ctx, 'this>(
     )?;
     entry.store(context, location, args_ptr, entry.argument(0)?.into())?;
 
-    // Call runtime cheatcode syscall wrapper
+    // Call runtime cheatcode syscall wrapper, passing it the handler of the current execution.
+    let cheatcode_context_ptr = entry.gep(
+        context,
+        location,
+        helper.execution_context()?,
+        &[GepIndex::Const(
+            ExecutionContext::CHEATCODE_CONTEXT.try_into()?,
+        )],
+        IntegerType::new(context, 8).into(),
+    )?;
+    let cheatcode_context = entry.load(
+        context,
+        location,
+        cheatcode_context_ptr,
+        llvm::r#type::pointer(context, 0),
+    )?;
     metadata
         .get_mut::<RuntimeBindingsMeta>()
         .expect("Runtime library not available.")
@@ -103,6 +119,7 @@ pub fn build<'ctx, 'this>(
             entry,
             location,
             result_ptr,
+            cheatcode_context,
             selector_ptr,
             args_ptr,
         )?;
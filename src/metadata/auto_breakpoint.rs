@@ -1,47 +1,249 @@
+//! # Automatic breakpoints
+//!
+//! Breakpoints inserted into the compiled code when some event happens: a Sierra statement is
+//! executed, a function is entered, a kind of libfunc is invoked or an enum variant is built. Each
+//! breakpoint either traps (for debuggers) or calls the execution's [`BreakpointHandler`] with the
+//! Sierra location.
+//!
+//! They're registered on an [`AutoBreakpoint`] passed to
+//! [`NativeContext::compile_with_breakpoints`](crate::context::NativeContext::compile_with_breakpoints),
+//! while the handler is installed per execution through
+//! [`ExecutionOptions::with_breakpoint_handler`](crate::executor::ExecutionOptions::with_breakpoint_handler).
+//! The `NATIVE_DEBUG_TRAP_AT_STMT` environment variable adds a trap on the given statement.
+
 #![cfg(feature = "with-debug-utils")]
 
-use super::{debug_utils::DebugUtils, MetadataStorage};
+use super::{debug_utils::DebugUtils, runtime_bindings::RuntimeBindingsMeta, MetadataStorage};
 use crate::error::Error;
-use cairo_lang_sierra::ids::ConcreteTypeId;
-use melior::ir::{Block, Location};
-use std::collections::HashSet;
+use cairo_lang_sierra::{
+    extensions::{
+        core::{CoreConcreteLibfunc, CoreLibfunc, CoreType},
+        enm::EnumConcreteLibfunc,
+    },
+    ids::{ConcreteLibfuncId, ConcreteTypeId, FunctionId, GenericLibfuncId},
+    program::{Invocation, Program, StatementIdx},
+    program_registry::ProgramRegistry,
+};
+pub use cairo_native_runtime::BreakpointHandler;
+use melior::{
+    ir::{Block, Location, Module},
+    Context,
+};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum BreakpointEvent {
+    /// Before the statement is executed, if it's an invocation.
+    Statement(StatementIdx),
+    /// When entering the function, including tail-recursive calls.
+    FunctionEntry(FunctionId),
+    /// Before every invocation of a kind of libfunc, like `felt252_dict_squash`.
+    Libfunc(GenericLibfuncId),
+    /// Before building the variant of an enum.
     EnumInit {
         type_id: ConcreteTypeId,
         variant_idx: usize,
     },
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BreakpointAction {
+    /// Trap into the debugger.
+    Trap,
+    /// Call the [`BreakpointHandler`] of the execution, if it has one.
+    Callback,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct AutoBreakpoint {
-    events: HashSet<BreakpointEvent>,
+    events: HashMap<BreakpointEvent, BreakpointAction>,
+    /// The concrete libfuncs of the [`BreakpointEvent::Libfunc`] events' kinds.
+    libfunc_kinds: HashMap<ConcreteLibfuncId, GenericLibfuncId>,
 }
 
 impl AutoBreakpoint {
+    /// Add a breakpoint that traps on the event.
     pub fn add_event(&mut self, event: BreakpointEvent) {
-        self.events.insert(event);
+        self.add_breakpoint(event, BreakpointAction::Trap);
+    }
+
+    /// Add a breakpoint on the event, replacing the previous action if there was one.
+    pub fn add_breakpoint(&mut self, event: BreakpointEvent, action: BreakpointAction) {
+        self.events.insert(event, action);
     }
 
     pub fn has_event(&self, event: &BreakpointEvent) -> bool {
-        self.events.contains(event)
+        self.events.contains_key(event)
+    }
+
+    /// Find the concrete libfuncs of the program matching the kinds of the libfunc breakpoints.
+    pub(crate) fn resolve_libfuncs(&mut self, program: &Program) {
+        let kinds = self
+            .events
+            .keys()
+            .filter_map(|x| match x {
+                BreakpointEvent::Libfunc(generic_id) => Some(generic_id),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        self.libfunc_kinds = program
+            .libfunc_declarations
+            .iter()
+            .filter(|x| kinds.contains(&x.long_id.generic_id))
+            .map(|x| (x.id.clone(), x.long_id.generic_id.clone()))
+            .collect();
     }
 
-    pub fn maybe_breakpoint(
+    /// The events that happen when the invocation is executed.
+    pub(crate) fn invocation_events(
         &self,
-        block: &Block,
-        location: Location,
-        metadata: &MetadataStorage,
+        registry: &ProgramRegistry<CoreType, CoreLibfunc>,
+        statement_idx: StatementIdx,
+        invocation: &Invocation,
+    ) -> Result<Vec<BreakpointEvent>, Error> {
+        let mut events = vec![BreakpointEvent::Statement(statement_idx)];
+        if let Some(generic_id) = self.libfunc_kinds.get(&invocation.libfunc_id) {
+            events.push(BreakpointEvent::Libfunc(generic_id.clone()));
+        }
+        if let CoreConcreteLibfunc::Enum(EnumConcreteLibfunc::Init(info)) =
+            registry.get_libfunc(&invocation.libfunc_id)?
+        {
+            events.push(BreakpointEvent::EnumInit {
+                type_id: info.signature.branch_signatures[0].vars[0].ty.clone(),
+                variant_idx: info.index,
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// Insert the breakpoint of the event into the block, if the program has one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn maybe_breakpoint<'c>(
+        context: &'c Context,
+        module: &Module,
+        block: &Block<'c>,
+        location: Location<'c>,
+        metadata: &mut MetadataStorage,
         event: &BreakpointEvent,
+        function_id: &FunctionId,
+        statement_idx: StatementIdx,
     ) -> Result<(), Error> {
-        if self.has_event(event) {
-            metadata
-                .get::<DebugUtils>()
-                .ok_or(Error::MissingMetadata)?
-                .debug_breakpoint_trap(block, location)?;
+        let action = metadata
+            .get::<AutoBreakpoint>()
+            .and_then(|auto_breakpoint| auto_breakpoint.events.get(event).copied());
+
+        match action {
+            None => {}
+            Some(BreakpointAction::Trap) => {
+                metadata
+                    .get::<DebugUtils>()
+                    .ok_or(Error::MissingMetadata)?
+                    .debug_breakpoint_trap(block, location)?;
+            }
+            Some(BreakpointAction::Callback) => {
+                metadata
+                    .get_mut::<RuntimeBindingsMeta>()
+                    .ok_or(Error::MissingMetadata)?
+                    .breakpoint_hit(
+                        context,
+                        module,
+                        block,
+                        function_id.id,
+                        statement_idx.0 as u64,
+                        location,
+                    )?;
+            }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::NativeContext,
+        executor::{ExecutionOptions, JitNativeExecutor},
+        utils::test::load_cairo,
+        OptLevel,
+    };
+    use std::ffi::c_void;
+
+    extern "C" fn record_hit(data: *mut c_void, function_id: u64, statement_idx: u64) {
+        let hits = unsafe { &mut *data.cast::<Vec<(u64, u64)>>() };
+        hits.push((function_id, statement_idx));
+    }
+
+    #[test]
+    fn test_breakpoint_callbacks() {
+        let (_, program) = load_cairo! {
+            fn run_test() -> felt252 {
+                let mut x = 0;
+                x += inner(1);
+                x += inner(2);
+                x
+            }
+
+            #[inline(never)]
+            fn inner(x: felt252) -> felt252 {
+                x * x
+            }
+        };
+        let inner_id = program
+            .funcs
+            .iter()
+            .find(|x| x.id.to_string().ends_with("::inner"))
+            .unwrap()
+            .id
+            .clone();
+
+        let mut breakpoints = AutoBreakpoint::default();
+        breakpoints.add_breakpoint(
+            BreakpointEvent::FunctionEntry(inner_id.clone()),
+            BreakpointAction::Callback,
+        );
+        breakpoints.add_breakpoint(
+            BreakpointEvent::Libfunc(GenericLibfuncId::from_string("felt252_mul")),
+            BreakpointAction::Callback,
+        );
+
+        let native_context = NativeContext::new();
+        let module = native_context
+            .compile_with_breakpoints(&program, false, Some(Default::default()), None, breakpoints)
+            .unwrap();
+        let executor = JitNativeExecutor::from_native_module(module, OptLevel::default()).unwrap();
+
+        let mut hits = Vec::<(u64, u64)>::new();
+        executor
+            .invoke_dynamic_with_options(
+                &program.funcs[0].id,
+                &[],
+                ExecutionOptions::new()
+                    .with_gas(u64::MAX)
+                    .with_breakpoint_handler(BreakpointHandler {
+                        data: (&mut hits as *mut Vec<(u64, u64)>).cast(),
+                        hit: Some(record_hit),
+                    }),
+            )
+            .unwrap();
+
+        // Both calls enter `inner` and multiply once.
+        assert_eq!(hits.len(), 4);
+        assert!(hits
+            .iter()
+            .all(|(function_id, _)| *function_id == inner_id.id));
+
+        // Executions without a handler ignore the breakpoints.
+        executor
+            .invoke_dynamic_with_options(
+                &program.funcs[0].id,
+                &[],
+                ExecutionOptions::new().with_gas(u64::MAX),
+            )
+            .unwrap();
+        assert_eq!(hits.len(), 4);
+    }
+}
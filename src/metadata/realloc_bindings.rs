@@ -2,6 +2,10 @@
 //!
 //! This metadata ensures that the bindings to the C function `realloc` exist in the current
 //! compilation context.
+//!
+//! With the `with-mem-tracing` feature, the runtime's `cairo_native__realloc` and
+//! `cairo_native__free` are called instead, which report the allocations to the memory tracer of
+//! the execution.
 
 use melior::{
     dialect::llvm,
@@ -14,6 +18,15 @@ use melior::{
     Context, Error,
 };
 
+#[cfg(not(feature = "with-mem-tracing"))]
+const REALLOC_SYMBOL: &str = "realloc";
+#[cfg(not(feature = "with-mem-tracing"))]
+const FREE_SYMBOL: &str = "free";
+#[cfg(feature = "with-mem-tracing")]
+const REALLOC_SYMBOL: &str = "cairo_native__realloc";
+#[cfg(feature = "with-mem-tracing")]
+const FREE_SYMBOL: &str = "cairo_native__free";
+
 /// Memory allocation `realloc` metadata.
 #[derive(Debug)]
 pub struct ReallocBindingsMeta;
@@ -23,7 +36,7 @@ impl ReallocBindingsMeta {
     pub fn new(context: &Context, module: &Module) -> Self {
         module.body().append_operation(llvm::func(
             context,
-            StringAttribute::new(context, "realloc"),
+            StringAttribute::new(context, REALLOC_SYMBOL),
             TypeAttribute::new(llvm::r#type::function(
                 llvm::r#type::pointer(context, 0),
                 &[
@@ -41,7 +54,7 @@ impl ReallocBindingsMeta {
         ));
         module.body().append_operation(llvm::func(
             context,
-            StringAttribute::new(context, "free"),
+            StringAttribute::new(context, FREE_SYMBOL),
             TypeAttribute::new(llvm::r#type::function(
                 llvm::r#type::void(context),
                 &[llvm::r#type::pointer(context, 0)],
@@ -68,7 +81,7 @@ impl ReallocBindingsMeta {
         OperationBuilder::new("llvm.call", location)
             .add_attributes(&[(
                 Identifier::new(context, "callee"),
-                FlatSymbolRefAttribute::new(context, "realloc").into(),
+                FlatSymbolRefAttribute::new(context, REALLOC_SYMBOL).into(),
             )])
             .add_operands(&[ptr, len])
             .add_results(&[llvm::r#type::pointer(context, 0)])
@@ -84,7 +97,7 @@ impl ReallocBindingsMeta {
         OperationBuilder::new("llvm.call", location)
             .add_attributes(&[(
                 Identifier::new(context, "callee"),
-                FlatSymbolRefAttribute::new(context, "free").into(),
+                FlatSymbolRefAttribute::new(context, FREE_SYMBOL).into(),
             )])
             .add_operands(&[ptr])
             .build()
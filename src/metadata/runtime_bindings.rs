@@ -35,6 +35,14 @@ enum RuntimeBinding {
     DictDrop,
     DictDup,
     GetGasBuiltin,
+    #[cfg(any(feature = "with-profiling", feature = "with-backtrace"))]
+    RecordEvent,
+    #[cfg(feature = "with-trace")]
+    TraceStatement,
+    #[cfg(feature = "with-function-hooks")]
+    FunctionHook,
+    #[cfg(feature = "with-debug-utils")]
+    BreakpointHit,
     DebugPrint,
     #[cfg(feature = "with-cheatcode")]
     VtableCheatcode,
@@ -885,9 +893,255 @@ impl RuntimeBindingsMeta {
         )))
     }
 
+    /// Register if necessary, then invoke the `record_event()` runtime function.
+    ///
+    /// Appends an event of the given kind (one of the `PROFILE_*` or `CALL_STACK_*` constants of
+    /// the runtime) and its `i64` value to the event buffer of the current execution.
+    #[cfg(any(feature = "with-profiling", feature = "with-backtrace"))]
+    pub fn record_event<'c, 'a>(
+        &mut self,
+        context: &'c Context,
+        module: &Module,
+        block: &'a Block<'c>,
+        kind: u8,
+        value: Value<'c, '_>,
+        location: Location<'c>,
+    ) -> Result<OperationRef<'c, 'a>>
+    where
+        'c: 'a,
+    {
+        if self.active_map.insert(RuntimeBinding::RecordEvent) {
+            module.body().append_operation(func::func(
+                context,
+                StringAttribute::new(context, "cairo_native__record_event"),
+                TypeAttribute::new(
+                    FunctionType::new(
+                        context,
+                        &[
+                            IntegerType::new(context, 8).into(),
+                            IntegerType::new(context, 64).into(),
+                        ],
+                        &[],
+                    )
+                    .into(),
+                ),
+                Region::new(),
+                &[
+                    (
+                        Identifier::new(context, "sym_visibility"),
+                        StringAttribute::new(context, "private").into(),
+                    ),
+                    (
+                        Identifier::new(context, "llvm.linkage"),
+                        Attribute::parse(context, "#llvm.linkage<external>")
+                            .ok_or(Error::ParseAttributeError)?,
+                    ),
+                ],
+                Location::unknown(context),
+            ));
+        }
+
+        let kind = block.const_int(context, location, kind, 8)?;
+
+        Ok(block.append_operation(func::call(
+            context,
+            FlatSymbolRefAttribute::new(context, "cairo_native__record_event"),
+            &[kind, value],
+            &[],
+            location,
+        )))
+    }
+
+    /// Register if necessary, then invoke the `trace_statement()` runtime function.
+    ///
+    /// Appends the statement to the trace of the current execution, along with the `len` values
+    /// (`i256`s) pointed to by `values`.
+    #[cfg(feature = "with-trace")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn trace_statement<'c, 'a>(
+        &mut self,
+        context: &'c Context,
+        module: &Module,
+        block: &'a Block<'c>,
+        statement_idx: usize,
+        values: Value<'c, '_>,
+        len: usize,
+        location: Location<'c>,
+    ) -> Result<OperationRef<'c, 'a>>
+    where
+        'c: 'a,
+    {
+        if self.active_map.insert(RuntimeBinding::TraceStatement) {
+            module.body().append_operation(func::func(
+                context,
+                StringAttribute::new(context, "cairo_native__trace_statement"),
+                TypeAttribute::new(
+                    FunctionType::new(
+                        context,
+                        &[
+                            IntegerType::new(context, 64).into(),
+                            llvm::r#type::pointer(context, 0),
+                            IntegerType::new(context, 64).into(),
+                        ],
+                        &[],
+                    )
+                    .into(),
+                ),
+                Region::new(),
+                &[
+                    (
+                        Identifier::new(context, "sym_visibility"),
+                        StringAttribute::new(context, "private").into(),
+                    ),
+                    (
+                        Identifier::new(context, "llvm.linkage"),
+                        Attribute::parse(context, "#llvm.linkage<external>")
+                            .ok_or(Error::ParseAttributeError)?,
+                    ),
+                ],
+                Location::unknown(context),
+            ));
+        }
+
+        let statement_idx = block.const_int(context, location, statement_idx, 64)?;
+        let len = block.const_int(context, location, len, 64)?;
+
+        Ok(block.append_operation(func::call(
+            context,
+            FlatSymbolRefAttribute::new(context, "cairo_native__trace_statement"),
+            &[statement_idx, values, len],
+            &[],
+            location,
+        )))
+    }
+
+    /// Register if necessary, then invoke the `breakpoint_hit()` runtime function.
+    ///
+    /// Calls the breakpoint handler of the current execution with the function id and statement
+    /// index where the breakpoint was hit.
+    #[cfg(feature = "with-debug-utils")]
+    pub fn breakpoint_hit<'c, 'a>(
+        &mut self,
+        context: &'c Context,
+        module: &Module,
+        block: &'a Block<'c>,
+        function_id: u64,
+        statement_idx: u64,
+        location: Location<'c>,
+    ) -> Result<OperationRef<'c, 'a>>
+    where
+        'c: 'a,
+    {
+        if self.active_map.insert(RuntimeBinding::BreakpointHit) {
+            module.body().append_operation(func::func(
+                context,
+                StringAttribute::new(context, "cairo_native__breakpoint_hit"),
+                TypeAttribute::new(
+                    FunctionType::new(
+                        context,
+                        &[
+                            IntegerType::new(context, 64).into(),
+                            IntegerType::new(context, 64).into(),
+                        ],
+                        &[],
+                    )
+                    .into(),
+                ),
+                Region::new(),
+                &[
+                    (
+                        Identifier::new(context, "sym_visibility"),
+                        StringAttribute::new(context, "private").into(),
+                    ),
+                    (
+                        Identifier::new(context, "llvm.linkage"),
+                        Attribute::parse(context, "#llvm.linkage<external>")
+                            .ok_or(Error::ParseAttributeError)?,
+                    ),
+                ],
+                Location::unknown(context),
+            ));
+        }
+
+        let function_id = block.const_int(context, location, function_id, 64)?;
+        let statement_idx = block.const_int(context, location, statement_idx, 64)?;
+
+        Ok(block.append_operation(func::call(
+            context,
+            FlatSymbolRefAttribute::new(context, "cairo_native__breakpoint_hit"),
+            &[function_id, statement_idx],
+            &[],
+            location,
+        )))
+    }
+
+    /// Register if necessary, then invoke the `function_hook()` runtime function.
+    ///
+    /// Calls the host hook of the given kind (see `FUNCTION_HOOK_ENTER` and `FUNCTION_HOOK_EXIT`)
+    /// with the function id and a pointer to the values.
+    #[cfg(feature = "with-function-hooks")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn function_hook<'c, 'a>(
+        &mut self,
+        context: &'c Context,
+        module: &Module,
+        block: &'a Block<'c>,
+        kind: u8,
+        function_id: u64,
+        values: Value<'c, '_>,
+        location: Location<'c>,
+    ) -> Result<OperationRef<'c, 'a>>
+    where
+        'c: 'a,
+    {
+        if self.active_map.insert(RuntimeBinding::FunctionHook) {
+            module.body().append_operation(func::func(
+                context,
+                StringAttribute::new(context, "cairo_native__function_hook"),
+                TypeAttribute::new(
+                    FunctionType::new(
+                        context,
+                        &[
+                            IntegerType::new(context, 8).into(),
+                            IntegerType::new(context, 64).into(),
+                            llvm::r#type::pointer(context, 0),
+                        ],
+                        &[],
+                    )
+                    .into(),
+                ),
+                Region::new(),
+                &[
+                    (
+                        Identifier::new(context, "sym_visibility"),
+                        StringAttribute::new(context, "private").into(),
+                    ),
+                    (
+                        Identifier::new(context, "llvm.linkage"),
+                        Attribute::parse(context, "#llvm.linkage<external>")
+                            .ok_or(Error::ParseAttributeError)?,
+                    ),
+                ],
+                Location::unknown(context),
+            ));
+        }
+
+        let kind = block.const_int(context, location, kind, 8)?;
+        let function_id = block.const_int(context, location, function_id, 64)?;
+
+        Ok(block.append_operation(func::call(
+            context,
+            FlatSymbolRefAttribute::new(context, "cairo_native__function_hook"),
+            &[kind, function_id, values],
+            &[],
+            location,
+        )))
+    }
+
     /// Register if necessary, then invoke the `vtable_cheatcode()` runtime function.
     ///
-    /// Calls the cheatcode syscall with the given arguments.
+    /// Calls the cheatcode syscall with the given arguments, using the handler of the given
+    /// cheatcode context (read from the execution context).
     ///
     /// The result is stored in `result_ptr`.
     #[allow(clippy::too_many_arguments)]
@@ -899,6 +1153,7 @@ impl RuntimeBindingsMeta {
         block: &'a Block<'c>,
         location: Location<'c>,
         result_ptr: Value<'c, 'a>,
+        cheatcode_context: Value<'c, 'a>,
         selector_ptr: Value<'c, 'a>,
         args: Value<'c, 'a>,
     ) -> Result<OperationRef<'c, 'a>>
@@ -916,6 +1171,7 @@ impl RuntimeBindingsMeta {
                             llvm::r#type::pointer(context, 0),
                             llvm::r#type::pointer(context, 0),
                             llvm::r#type::pointer(context, 0),
+                            llvm::r#type::pointer(context, 0),
                         ],
                         &[],
                     )
@@ -940,7 +1196,7 @@ impl RuntimeBindingsMeta {
         Ok(block.append_operation(func::call(
             context,
             FlatSymbolRefAttribute::new(context, "cairo_native__vtable_cheatcode"),
-            &[result_ptr, selector_ptr, args],
+            &[result_ptr, cheatcode_context, selector_ptr, args],
             &[],
             location,
         )))
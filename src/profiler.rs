@@ -0,0 +1,419 @@
+//! # Profiling
+//!
+//! Programs compiled with the `with-profiling` feature record every Sierra function entry and exit
+//! in the runtime's event buffer, along with every libfunc invocation when the
+//! `NATIVE_PROFILE_LIBFUNCS` environment variable is set while compiling. Executions that request
+//! it through [`ExecutionOptions::with_profiling`](crate::executor::ExecutionOptions::with_profiling)
+//! aggregate those events (once the buffer fills up, and after the execution) into a
+//! [`ProfileReport`], which is returned along with their results.
+//!
+//! The gas withdrawn by the `withdraw_gas` and `withdraw_gas_all` libfuncs is reported too, and
+//! attributed to the function that withdrew it.
+//!
+//! Programs compiled without the feature don't report anything, so their reports are empty.
+//!
+//! The report's call stacks can be exported with [`ProfileReport::to_folded`] (by time) or
+//! [`ProfileReport::to_folded_gas`] (by gas) and fed to flamegraph tools.
+
+use crate::debug::libfunc_to_name;
+use cairo_lang_sierra::{
+    extensions::core::{CoreLibfunc, CoreType},
+    ids::{ConcreteLibfuncId, FunctionId},
+    program_registry::ProgramRegistry,
+};
+use cairo_native_runtime::{
+    Event, PROFILE_FUNCTION_ENTER, PROFILE_FUNCTION_EXIT, PROFILE_GAS_WITHDRAWN, PROFILE_LIBFUNC,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+/// The profile of an execution.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct ProfileReport {
+    /// Every function that was called, the most expensive ones (by self time) first.
+    pub functions: Vec<FunctionProfile>,
+    /// Every libfunc that was invoked, the most invoked ones first.
+    pub libfuncs: Vec<LibfuncProfile>,
+    /// The self time of every call stack that was executed.
+    pub stacks: Vec<StackProfile>,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct FunctionProfile {
+    pub function_id: u64,
+    pub name: String,
+    pub calls: u64,
+    /// Time spent in the function itself, excluding the functions it called.
+    pub self_time: Duration,
+    /// Time spent in the function, including the functions it called. Time spent in recursive
+    /// calls is only counted once.
+    pub total_time: Duration,
+    /// Gas withdrawn by the function itself, excluding the functions it called.
+    pub self_gas: u64,
+    /// Gas withdrawn by the function, including the functions it called. Gas withdrawn by
+    /// recursive calls is only counted once.
+    pub total_gas: u64,
+}
+
+/// The invocations of a libfunc, aggregated over all its concrete instances.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct LibfuncProfile {
+    pub name: String,
+    pub calls: u64,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct StackProfile {
+    /// The names of the functions in the stack, the outermost one first.
+    pub frames: Vec<String>,
+    /// Time spent in the innermost function of the stack.
+    pub self_time: Duration,
+    /// Gas withdrawn by the innermost function of the stack.
+    pub self_gas: u64,
+}
+
+impl ProfileReport {
+    /// Export the call stacks in the folded stacks format, one line per stack with its self time
+    /// in nanoseconds, as expected by flamegraph tools (such as `inferno` or `flamegraph.pl`).
+    pub fn to_folded(&self) -> String {
+        self.stacks
+            .iter()
+            .map(|stack| {
+                format!(
+                    "{} {}\n",
+                    stack.frames.join(";"),
+                    stack.self_time.as_nanos()
+                )
+            })
+            .collect()
+    }
+
+    /// Export the call stacks in the folded stacks format, one line per stack with the gas it
+    /// withdrew. Stacks that didn't withdraw any gas are omitted.
+    pub fn to_folded_gas(&self) -> String {
+        self.stacks
+            .iter()
+            .filter(|stack| stack.self_gas != 0)
+            .map(|stack| format!("{} {}\n", stack.frames.join(";"), stack.self_gas))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    function_id: u64,
+    entered: u64,
+    children_time: Duration,
+    self_gas: u64,
+    children_gas: u64,
+}
+
+#[derive(Debug, Default)]
+struct FunctionStats {
+    calls: u64,
+    self_time: Duration,
+    total_time: Duration,
+    self_gas: u64,
+    total_gas: u64,
+}
+
+/// Aggregates the events recorded by a program into a [`ProfileReport`].
+#[derive(Debug, Default)]
+pub(crate) struct ProfileRecorder {
+    stack: Vec<Frame>,
+    last_timestamp: u64,
+    functions: HashMap<u64, FunctionStats>,
+    libfuncs: HashMap<u64, u64>,
+    stacks: HashMap<Vec<u64>, (Duration, u64)>,
+}
+
+impl ProfileRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aggregate an event of the runtime's event buffer, ignoring those unrelated to profiling.
+    pub fn record(&mut self, event: &Event) {
+        self.last_timestamp = event.timestamp;
+        match event.kind {
+            PROFILE_FUNCTION_ENTER => self.enter(event.value, event.timestamp),
+            PROFILE_FUNCTION_EXIT => self.exit(event.timestamp),
+            PROFILE_LIBFUNC => *self.libfuncs.entry(event.value).or_default() += 1,
+            PROFILE_GAS_WITHDRAWN => self.withdraw_gas(event.value),
+            _ => {}
+        }
+    }
+
+    fn enter(&mut self, function_id: u64, now: u64) {
+        self.functions.entry(function_id).or_default().calls += 1;
+        self.stack.push(Frame {
+            function_id,
+            entered: now,
+            children_time: Duration::ZERO,
+            self_gas: 0,
+            children_gas: 0,
+        });
+    }
+
+    fn withdraw_gas(&mut self, amount: u64) {
+        // Gas withdrawn outside of any function (which shouldn't happen) isn't attributed.
+        if let Some(frame) = self.stack.last_mut() {
+            frame.self_gas += amount;
+        }
+    }
+
+    fn exit(&mut self, now: u64) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+
+        let elapsed = Duration::from_nanos(now.saturating_sub(frame.entered));
+        let self_time = elapsed.saturating_sub(frame.children_time);
+        let gas = frame.self_gas + frame.children_gas;
+
+        let stats = self.functions.entry(frame.function_id).or_default();
+        stats.self_time += self_time;
+        stats.self_gas += frame.self_gas;
+        if self
+            .stack
+            .iter()
+            .all(|x| x.function_id != frame.function_id)
+        {
+            stats.total_time += elapsed;
+            stats.total_gas += gas;
+        }
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children_time += elapsed;
+            parent.children_gas += gas;
+        }
+
+        let stack = self
+            .stack
+            .iter()
+            .map(|x| x.function_id)
+            .chain([frame.function_id])
+            .collect();
+        let stack_stats = self.stacks.entry(stack).or_default();
+        stack_stats.0 += self_time;
+        stack_stats.1 += frame.self_gas;
+    }
+
+    /// Build the report, naming the functions and libfuncs after the program's registry.
+    pub fn finish(self, registry: &ProgramRegistry<CoreType, CoreLibfunc>) -> ProfileReport {
+        self.build(
+            |function_id| {
+                registry
+                    .get_function(&FunctionId::new(function_id))
+                    .ok()
+                    .map(|x| x.id.to_string())
+            },
+            |libfunc_id| {
+                registry
+                    .get_libfunc(&ConcreteLibfuncId::new(libfunc_id))
+                    .ok()
+                    .map(|x| libfunc_to_name(x).to_string())
+            },
+        )
+    }
+
+    /// Build the report when only the function names are known (the libfuncs are named after
+    /// their ids).
+    pub fn finish_with_names(self, function_names: &BTreeMap<u64, String>) -> ProfileReport {
+        self.build(
+            |function_id| function_names.get(&function_id).cloned(),
+            |_| None,
+        )
+    }
+
+    fn build(
+        mut self,
+        function_name: impl Fn(u64) -> Option<String>,
+        libfunc_name: impl Fn(u64) -> Option<String>,
+    ) -> ProfileReport {
+        // Functions still running when the execution stopped (for example, after being aborted by
+        // a syscall handler) end with the last event.
+        while !self.stack.is_empty() {
+            self.exit(self.last_timestamp);
+        }
+
+        let function_name = |function_id: u64| {
+            function_name(function_id).unwrap_or_else(|| format!("[{function_id}]"))
+        };
+
+        let mut functions = self
+            .functions
+            .into_iter()
+            .map(|(function_id, stats)| FunctionProfile {
+                function_id,
+                name: function_name(function_id),
+                calls: stats.calls,
+                self_time: stats.self_time,
+                total_time: stats.total_time,
+                self_gas: stats.self_gas,
+                total_gas: stats.total_gas,
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.self_time.cmp(&a.self_time).then(a.name.cmp(&b.name)));
+
+        let mut libfunc_calls = HashMap::<String, u64>::new();
+        for (libfunc_id, calls) in self.libfuncs {
+            let name = libfunc_name(libfunc_id).unwrap_or_else(|| format!("[{libfunc_id}]"));
+            *libfunc_calls.entry(name).or_default() += calls;
+        }
+        let mut libfuncs = libfunc_calls
+            .into_iter()
+            .map(|(name, calls)| LibfuncProfile { name, calls })
+            .collect::<Vec<_>>();
+        libfuncs.sort_by(|a, b| b.calls.cmp(&a.calls).then(a.name.cmp(&b.name)));
+
+        let mut stacks = self
+            .stacks
+            .into_iter()
+            .map(|(stack, (self_time, self_gas))| StackProfile {
+                frames: stack.into_iter().map(function_name).collect(),
+                self_time,
+                self_gas,
+            })
+            .collect::<Vec<_>>();
+        stacks.sort();
+
+        ProfileReport {
+            functions,
+            libfuncs,
+            stacks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test::load_cairo;
+
+    #[test]
+    fn test_profile_recorder() {
+        let (_, program) = load_cairo! {
+            fn run_test() -> felt252 {
+                helper()
+            }
+
+            #[inline(never)]
+            fn helper() -> felt252 {
+                42
+            }
+        };
+        let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(&program).unwrap();
+        let function_id = |name: &str| {
+            program
+                .funcs
+                .iter()
+                .find(|x| x.id.to_string().ends_with(name))
+                .unwrap()
+                .id
+                .id
+        };
+        let outer = function_id("run_test");
+        let inner = function_id("helper");
+
+        let event = |kind, value, ms: u64| Event {
+            kind,
+            value,
+            timestamp: ms * 1_000_000,
+        };
+        let mut recorder = ProfileRecorder::new();
+        for event in [
+            event(PROFILE_FUNCTION_ENTER, outer, 0),
+            event(PROFILE_GAS_WITHDRAWN, 100, 0),
+            event(PROFILE_FUNCTION_ENTER, inner, 1),
+            event(PROFILE_GAS_WITHDRAWN, 20, 1),
+            event(PROFILE_FUNCTION_EXIT, inner, 3),
+            event(PROFILE_FUNCTION_ENTER, inner, 4),
+            event(PROFILE_GAS_WITHDRAWN, 30, 4),
+            event(PROFILE_FUNCTION_EXIT, inner, 5),
+            event(PROFILE_FUNCTION_EXIT, outer, 10),
+        ] {
+            recorder.record(&event);
+        }
+        let report = recorder.finish(&registry);
+
+        let [first, second] = report.functions.as_slice() else {
+            panic!("expected two functions, got {:?}", report.functions);
+        };
+        assert_eq!(first.function_id, outer);
+        assert_eq!(first.calls, 1);
+        assert_eq!(first.self_time, Duration::from_millis(7));
+        assert_eq!(first.total_time, Duration::from_millis(10));
+        assert_eq!(first.self_gas, 100);
+        assert_eq!(first.total_gas, 150);
+        assert_eq!(second.function_id, inner);
+        assert_eq!(second.calls, 2);
+        assert_eq!(second.self_time, Duration::from_millis(3));
+        assert_eq!(second.total_time, Duration::from_millis(3));
+        assert_eq!(second.self_gas, 50);
+        assert_eq!(second.total_gas, 50);
+
+        assert_eq!(
+            report.to_folded(),
+            format!(
+                "{outer_name} 7000000\n{outer_name};{inner_name} 3000000\n",
+                outer_name = first.name,
+                inner_name = second.name,
+            )
+        );
+        assert_eq!(
+            report.to_folded_gas(),
+            format!(
+                "{outer_name} 100\n{outer_name};{inner_name} 50\n",
+                outer_name = first.name,
+                inner_name = second.name,
+            )
+        );
+    }
+}
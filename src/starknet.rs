@@ -1,9 +1,34 @@
 //! Starknet related code for `cairo_native`
 
+pub(crate) use self::syscall::ResponseValue;
+pub use self::{
+    collector::CollectingMiddleware,
+    error::SyscallError,
+    middleware::{
+        CountingMiddleware, FailureInjectionMiddleware, LatencyHistogram, LatencyMiddleware,
+        LoggingMiddleware, SyscallLayer, SyscallMiddleware,
+    },
+    replay::{
+        RecordingMiddleware, ReplayDivergence, ReplayingSyscallHandler, SyscallTrace,
+        SyscallTraceEntry,
+    },
+    syscall::{SyscallRequest, SyscallResponse},
+};
+use crate::{
+    error::Error, execution_result::ContractExecutionResult, executor::AotContractExecutor,
+};
+use cairo_lang_starknet_classes::casm_contract_class::ENTRY_POINT_COST;
 use serde::{Deserialize, Serialize};
 use starknet_types_core::felt::Felt;
+use std::sync::Arc;
 
-pub type SyscallResult<T> = std::result::Result<T, Vec<Felt>>;
+mod collector;
+mod error;
+mod middleware;
+mod replay;
+mod syscall;
+
+pub type SyscallResult<T> = std::result::Result<T, SyscallError>;
 
 #[repr(C)]
 #[derive(Debug)]
@@ -331,6 +356,302 @@ pub trait StarknetSyscallHandler {
     fn cheatcode(&mut self, _selector: Felt, _input: &[Felt]) -> Vec<Felt> {
         unimplemented!();
     }
+
+    /// Start a `deploy`, `library_call` or `call_contract` syscall whose entry point is run by the
+    /// caller (the outermost handler), so that wrappers like [`SyscallLayer`] also see the
+    /// syscalls of the callee. Once it has run, the call must be finished with
+    /// [`end_nested_call`](Self::end_nested_call).
+    ///
+    /// Handlers that run their nested calls themselves (or don't have any) return `None`, the
+    /// default, and the syscall is made as usual.
+    fn begin_nested_call(
+        &mut self,
+        _request: &SyscallRequest,
+        _remaining_gas: &mut u64,
+    ) -> Option<NestedCallStart> {
+        None
+    }
+
+    /// Finish the innermost nested call started by
+    /// [`begin_nested_call`](Self::begin_nested_call), given the result of its entry point.
+    fn end_nested_call(
+        &mut self,
+        _result: Result<ContractExecutionResult, Error>,
+        _remaining_gas: &mut u64,
+    ) -> SyscallResult<SyscallResponse> {
+        Err(SyscallError::Internal(
+            "no nested call in progress".to_string(),
+        ))
+    }
+}
+
+/// How a nested call started by [`StarknetSyscallHandler::begin_nested_call`] continues.
+#[derive(Debug)]
+pub enum NestedCallStart {
+    /// The call finished without running an entry point (for example, because it failed or was
+    /// mocked).
+    Finished(SyscallResult<SyscallResponse>),
+    /// The entry point must be run, then the call finished with its result.
+    Run(NestedEntryPoint),
+}
+
+/// The gas withdrawn by every contract entry point when it starts.
+///
+/// The costs of the syscalls that run an entry point include it, so it's refunded to the callee
+/// before running it, as the Cairo runner does.
+pub const ENTRY_POINT_INITIAL_BUDGET: u64 = ENTRY_POINT_COST as u64;
+
+/// The entry point run by a nested call.
+#[derive(Debug, Clone)]
+pub struct NestedEntryPoint {
+    pub executor: Arc<AotContractExecutor>,
+    pub selector: Felt,
+    pub calldata: Vec<Felt>,
+}
+
+impl NestedEntryPoint {
+    pub fn run(
+        &self,
+        syscall_handler: impl StarknetSyscallHandler,
+        remaining_gas: u64,
+    ) -> Result<ContractExecutionResult, Error> {
+        self.executor.run(
+            self.selector,
+            &self.calldata,
+            remaining_gas,
+            None,
+            syscall_handler,
+        )
+    }
+}
+
+impl<T> StarknetSyscallHandler for &mut T
+where
+    T: StarknetSyscallHandler + ?Sized,
+{
+    fn get_block_hash(
+        &mut self,
+        block_number: u64,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        (**self).get_block_hash(block_number, remaining_gas)
+    }
+
+    fn get_execution_info(&mut self, remaining_gas: &mut u64) -> SyscallResult<ExecutionInfo> {
+        (**self).get_execution_info(remaining_gas)
+    }
+
+    fn get_execution_info_v2(&mut self, remaining_gas: &mut u64) -> SyscallResult<ExecutionInfoV2> {
+        (**self).get_execution_info_v2(remaining_gas)
+    }
+
+    fn deploy(
+        &mut self,
+        class_hash: Felt,
+        contract_address_salt: Felt,
+        calldata: &[Felt],
+        deploy_from_zero: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(Felt, Vec<Felt>)> {
+        (**self).deploy(
+            class_hash,
+            contract_address_salt,
+            calldata,
+            deploy_from_zero,
+            remaining_gas,
+        )
+    }
+
+    fn replace_class(&mut self, class_hash: Felt, remaining_gas: &mut u64) -> SyscallResult<()> {
+        (**self).replace_class(class_hash, remaining_gas)
+    }
+
+    fn library_call(
+        &mut self,
+        class_hash: Felt,
+        function_selector: Felt,
+        calldata: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Vec<Felt>> {
+        (**self).library_call(class_hash, function_selector, calldata, remaining_gas)
+    }
+
+    fn call_contract(
+        &mut self,
+        address: Felt,
+        entry_point_selector: Felt,
+        calldata: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Vec<Felt>> {
+        (**self).call_contract(address, entry_point_selector, calldata, remaining_gas)
+    }
+
+    fn storage_read(
+        &mut self,
+        address_domain: u32,
+        address: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        (**self).storage_read(address_domain, address, remaining_gas)
+    }
+
+    fn storage_write(
+        &mut self,
+        address_domain: u32,
+        address: Felt,
+        value: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        (**self).storage_write(address_domain, address, value, remaining_gas)
+    }
+
+    fn emit_event(
+        &mut self,
+        keys: &[Felt],
+        data: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        (**self).emit_event(keys, data, remaining_gas)
+    }
+
+    fn send_message_to_l1(
+        &mut self,
+        to_address: Felt,
+        payload: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        (**self).send_message_to_l1(to_address, payload, remaining_gas)
+    }
+
+    fn keccak(&mut self, input: &[u64], remaining_gas: &mut u64) -> SyscallResult<U256> {
+        (**self).keccak(input, remaining_gas)
+    }
+
+    fn secp256k1_new(
+        &mut self,
+        x: U256,
+        y: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256k1Point>> {
+        (**self).secp256k1_new(x, y, remaining_gas)
+    }
+
+    fn secp256k1_add(
+        &mut self,
+        p0: Secp256k1Point,
+        p1: Secp256k1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256k1Point> {
+        (**self).secp256k1_add(p0, p1, remaining_gas)
+    }
+
+    fn secp256k1_mul(
+        &mut self,
+        p: Secp256k1Point,
+        m: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256k1Point> {
+        (**self).secp256k1_mul(p, m, remaining_gas)
+    }
+
+    fn secp256k1_get_point_from_x(
+        &mut self,
+        x: U256,
+        y_parity: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256k1Point>> {
+        (**self).secp256k1_get_point_from_x(x, y_parity, remaining_gas)
+    }
+
+    fn secp256k1_get_xy(
+        &mut self,
+        p: Secp256k1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(U256, U256)> {
+        (**self).secp256k1_get_xy(p, remaining_gas)
+    }
+
+    fn secp256r1_new(
+        &mut self,
+        x: U256,
+        y: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256r1Point>> {
+        (**self).secp256r1_new(x, y, remaining_gas)
+    }
+
+    fn secp256r1_add(
+        &mut self,
+        p0: Secp256r1Point,
+        p1: Secp256r1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256r1Point> {
+        (**self).secp256r1_add(p0, p1, remaining_gas)
+    }
+
+    fn secp256r1_mul(
+        &mut self,
+        p: Secp256r1Point,
+        m: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256r1Point> {
+        (**self).secp256r1_mul(p, m, remaining_gas)
+    }
+
+    fn secp256r1_get_point_from_x(
+        &mut self,
+        x: U256,
+        y_parity: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256r1Point>> {
+        (**self).secp256r1_get_point_from_x(x, y_parity, remaining_gas)
+    }
+
+    fn secp256r1_get_xy(
+        &mut self,
+        p: Secp256r1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(U256, U256)> {
+        (**self).secp256r1_get_xy(p, remaining_gas)
+    }
+
+    fn sha256_process_block(
+        &mut self,
+        state: &mut [u32; 8],
+        block: &[u32; 16],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        (**self).sha256_process_block(state, block, remaining_gas)
+    }
+
+    fn get_class_hash_at(
+        &mut self,
+        contract_address: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        (**self).get_class_hash_at(contract_address, remaining_gas)
+    }
+
+    #[cfg(feature = "with-cheatcode")]
+    fn cheatcode(&mut self, selector: Felt, input: &[Felt]) -> Vec<Felt> {
+        (**self).cheatcode(selector, input)
+    }
+
+    fn begin_nested_call(
+        &mut self,
+        request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> Option<NestedCallStart> {
+        (**self).begin_nested_call(request, remaining_gas)
+    }
+
+    fn end_nested_call(
+        &mut self,
+        result: Result<ContractExecutionResult, Error>,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<SyscallResponse> {
+        (**self).end_nested_call(result, remaining_gas)
+    }
 }
 
 pub struct DummySyscallHandler;
@@ -558,6 +879,66 @@ pub(crate) mod handler {
         };
     }
 
+    thread_local! {
+        static ABORT_ERROR: std::cell::RefCell<Option<SyscallError>> =
+            const { std::cell::RefCell::new(None) };
+    }
+
+    /// Replace the error that aborted the current execution (either an [`SyscallError::Abort`] or
+    /// an [`SyscallError::Internal`]), returning the previous one.
+    ///
+    /// Executors clear it before running a program (keeping the caller's one) and restore it
+    /// afterwards, failing if their program was aborted.
+    pub fn replace_abort_error(abort_error: Option<SyscallError>) -> Option<SyscallError> {
+        ABORT_ERROR.replace(abort_error)
+    }
+
+    /// Run a syscall of the handler, catching its panics so that they don't unwind into native
+    /// code.
+    ///
+    /// Errors that abort the execution are stored and the remaining gas is set to zero, so that
+    /// the program runs out of gas (dropping its values) as soon as possible. Every syscall after
+    /// that fails without reaching the handler.
+    fn call_handler<R>(
+        gas: &mut u64,
+        syscall: impl FnOnce(&mut u64) -> SyscallResult<R>,
+    ) -> SyscallResult<R> {
+        let result = match ABORT_ERROR.with_borrow(Clone::clone) {
+            Some(abort_error) => Err(abort_error),
+            None => std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| syscall(gas)))
+                .unwrap_or_else(|payload| {
+                    let msg = payload
+                        .downcast_ref::<&str>()
+                        .map(ToString::to_string)
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    Err(SyscallError::Internal(format!(
+                        "syscall handler panicked: {msg}"
+                    )))
+                }),
+        };
+
+        if let Some(error) = result.as_ref().err().filter(|error| error.is_abort()) {
+            abort_execution(error);
+            *gas = 0;
+        }
+
+        result
+    }
+
+    /// Abort the current execution with the given error, unless it was already aborted.
+    ///
+    /// Handlers use it from syscalls that can't fail (like `cheatcode`) to abort the execution
+    /// the same way failing syscalls do.
+    pub(crate) fn abort_execution(error: &SyscallError) {
+        ABORT_ERROR.with_borrow_mut(|abort_error| {
+            if abort_error.is_none() {
+                tracing::error!("aborting execution: {error}");
+                *abort_error = Some(error.clone());
+            }
+        });
+    }
+
     #[repr(C)]
     pub union SyscallResultAbi<T> {
         pub ok: ManuallyDrop<SyscallResultAbiOk<T>>,
@@ -826,6 +1207,62 @@ pub(crate) mod handler {
         ),
     }
 
+    /// The syscall handler of an execution, as seen by the `cheatcode` libfunc.
+    ///
+    /// The handler's type is erased, but the callback is the one monomorphized for it.
+    #[cfg(feature = "with-cheatcode")]
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct CheatcodeContext {
+        handler: *mut (),
+        cheatcode: unsafe extern "C" fn(
+            result_ptr: &mut ArrayAbi<Felt252Abi>,
+            ptr: *mut (),
+            selector: &Felt252Abi,
+            input: &ArrayAbi<Felt252Abi>,
+        ),
+    }
+
+    #[cfg(feature = "with-cheatcode")]
+    impl CheatcodeContext {
+        pub fn new<T>(callbacks: &mut StarknetSyscallHandlerCallbacks<'_, T>) -> Self
+        where
+            T: StarknetSyscallHandler,
+        {
+            Self {
+                handler: std::ptr::addr_of_mut!(*callbacks.self_ptr).cast(),
+                cheatcode: StarknetSyscallHandlerCallbacks::<T>::wrap_cheatcode_erased,
+            }
+        }
+
+        pub fn call(
+            &mut self,
+            result_ptr: &mut ArrayAbi<Felt252Abi>,
+            selector: &Felt252Abi,
+            input: &ArrayAbi<Felt252Abi>,
+        ) {
+            unsafe { (self.cheatcode)(result_ptr, self.handler, selector, input) }
+        }
+
+        /// Fail a cheatcode called without a syscall handler, consuming its input.
+        ///
+        /// The `cheatcode` libfunc has no failure branch, so the program gets a serialized
+        /// `Result::Err` (a `1` followed by the `'no syscall handler'` short string) as the
+        /// cheatcode's result. Test libraries that decode their cheatcode results that way panic
+        /// with it, while code ignoring the result (like the corelib's `starknet::testing`
+        /// setters) carries on. The execution itself is not aborted.
+        pub fn fail(result_ptr: &mut ArrayAbi<Felt252Abi>, input: &ArrayAbi<Felt252Abi>) {
+            type Callbacks<'a> = StarknetSyscallHandlerCallbacks<'a, DummySyscallHandler>;
+
+            let result = [Felt::ONE, Felt::from_bytes_be_slice(b"no syscall handler")]
+                .map(|x| Felt252Abi(x.to_bytes_le()));
+            unsafe {
+                Callbacks::drop_mlir_array(input);
+                *result_ptr = Callbacks::alloc_mlir_array(&result);
+            }
+        }
+    }
+
     impl<'a, T> StarknetSyscallHandlerCallbacks<'a, T>
     where
         T: 'a,
@@ -953,7 +1390,8 @@ pub(crate) mod handler {
             }
         }
 
-        fn wrap_error<E>(e: &[Felt]) -> SyscallResultAbi<E> {
+        fn wrap_error<E>(e: SyscallError) -> SyscallResultAbi<E> {
+            let e = e.into_felts();
             SyscallResultAbi {
                 err: ManuallyDrop::new(SyscallResultAbiErr {
                     tag: 1u8,
@@ -971,7 +1409,7 @@ pub(crate) mod handler {
             gas: &mut u64,
             block_number: u64,
         ) {
-            let result = ptr.get_block_hash(block_number, gas);
+            let result = call_handler(gas, |gas| ptr.get_block_hash(block_number, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -980,7 +1418,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(Felt252Abi(x.to_bytes_le())),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -998,8 +1436,8 @@ pub(crate) mod handler {
                 Self::drop_mlir_array(input);
             }
 
-            let result = ptr
-                .cheatcode(selector, &input_vec)
+            let result = call_handler(&mut 0, |_| Ok(ptr.cheatcode(selector, &input_vec)))
+                .unwrap_or_default()
                 .into_iter()
                 .map(|x| Felt252Abi(x.to_bytes_le()))
                 .collect::<Vec<_>>();
@@ -1007,12 +1445,22 @@ pub(crate) mod handler {
             *result_ptr = unsafe { Self::alloc_mlir_array(&result) };
         }
 
+        #[cfg(feature = "with-cheatcode")]
+        unsafe extern "C" fn wrap_cheatcode_erased(
+            result_ptr: &mut ArrayAbi<Felt252Abi>,
+            ptr: *mut (),
+            selector: &Felt252Abi,
+            input: &ArrayAbi<Felt252Abi>,
+        ) {
+            Self::wrap_cheatcode(result_ptr, &mut *ptr.cast::<T>(), selector, input);
+        }
+
         extern "C" fn wrap_get_execution_info(
             result_ptr: &mut SyscallResultAbi<NonNull<ExecutionInfoAbi>>,
             ptr: &mut T,
             gas: &mut u64,
         ) {
-            let result = ptr.get_execution_info(gas);
+            let result = call_handler(gas, |gas| ptr.get_execution_info(gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1067,7 +1515,7 @@ pub(crate) mod handler {
                         },
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1076,7 +1524,7 @@ pub(crate) mod handler {
             ptr: &mut T,
             gas: &mut u64,
         ) {
-            let result = ptr.get_execution_info_v2(gas);
+            let result = call_handler(gas, |gas| ptr.get_execution_info_v2(gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1163,7 +1611,7 @@ pub(crate) mod handler {
                         },
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1185,13 +1633,15 @@ pub(crate) mod handler {
                 Self::drop_mlir_array(calldata);
             }
 
-            let result = ptr.deploy(
-                class_hash,
-                contract_address_salt,
-                &calldata_vec,
-                deploy_from_zero,
-                gas,
-            );
+            let result = call_handler(gas, |gas| {
+                ptr.deploy(
+                    class_hash,
+                    contract_address_salt,
+                    &calldata_vec,
+                    deploy_from_zero,
+                    gas,
+                )
+            });
 
             *result_ptr = match result {
                 Ok(x) => {
@@ -1204,7 +1654,7 @@ pub(crate) mod handler {
                         }),
                     }
                 }
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1215,7 +1665,7 @@ pub(crate) mod handler {
             class_hash: &Felt252Abi,
         ) {
             let class_hash = Felt::from(class_hash);
-            let result = ptr.replace_class(class_hash, gas);
+            let result = call_handler(gas, |gas| ptr.replace_class(class_hash, gas));
 
             *result_ptr = match result {
                 Ok(_) => SyscallResultAbi {
@@ -1224,7 +1674,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(()),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1245,7 +1695,9 @@ pub(crate) mod handler {
                 Self::drop_mlir_array(calldata);
             }
 
-            let result = ptr.library_call(class_hash, function_selector, &calldata_vec, gas);
+            let result = call_handler(gas, |gas| {
+                ptr.library_call(class_hash, function_selector, &calldata_vec, gas)
+            });
 
             *result_ptr = match result {
                 Ok(x) => {
@@ -1258,7 +1710,7 @@ pub(crate) mod handler {
                         }),
                     }
                 }
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1279,7 +1731,9 @@ pub(crate) mod handler {
                 Self::drop_mlir_array(calldata);
             }
 
-            let result = ptr.call_contract(address, entry_point_selector, &calldata_vec, gas);
+            let result = call_handler(gas, |gas| {
+                ptr.call_contract(address, entry_point_selector, &calldata_vec, gas)
+            });
 
             *result_ptr = match result {
                 Ok(x) => {
@@ -1292,7 +1746,7 @@ pub(crate) mod handler {
                         }),
                     }
                 }
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1304,7 +1758,7 @@ pub(crate) mod handler {
             address: &Felt252Abi,
         ) {
             let address = Felt::from(address);
-            let result = ptr.storage_read(address_domain, address, gas);
+            let result = call_handler(gas, |gas| ptr.storage_read(address_domain, address, gas));
 
             *result_ptr = match result {
                 Ok(res) => SyscallResultAbi {
@@ -1313,7 +1767,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(Felt252Abi(res.to_bytes_le())),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1327,7 +1781,9 @@ pub(crate) mod handler {
         ) {
             let address = Felt::from(address);
             let value = Felt::from(value);
-            let result = ptr.storage_write(address_domain, address, value, gas);
+            let result = call_handler(gas, |gas| {
+                ptr.storage_write(address_domain, address, value, gas)
+            });
 
             *result_ptr = match result {
                 Ok(_) => SyscallResultAbi {
@@ -1336,7 +1792,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(()),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1359,7 +1815,7 @@ pub(crate) mod handler {
                 Self::drop_mlir_array(data);
             }
 
-            let result = ptr.emit_event(&keys_vec, &data_vec, gas);
+            let result = call_handler(gas, |gas| ptr.emit_event(&keys_vec, &data_vec, gas));
 
             *result_ptr = match result {
                 Ok(_) => SyscallResultAbi {
@@ -1368,7 +1824,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(()),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1386,7 +1842,9 @@ pub(crate) mod handler {
                 Self::drop_mlir_array(payload);
             }
 
-            let result = ptr.send_message_to_l1(to_address, &payload_vec, gas);
+            let result = call_handler(gas, |gas| {
+                ptr.send_message_to_l1(to_address, &payload_vec, gas)
+            });
 
             *result_ptr = match result {
                 Ok(_) => SyscallResultAbi {
@@ -1395,7 +1853,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(()),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1416,7 +1874,7 @@ pub(crate) mod handler {
                 }
             };
 
-            let result = ptr.keccak(input_vec, gas);
+            let result = call_handler(gas, |gas| ptr.keccak(input_vec, gas));
             unsafe {
                 Self::drop_mlir_array(input);
             }
@@ -1428,7 +1886,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(x),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1441,7 +1899,7 @@ pub(crate) mod handler {
         ) {
             let x = *x;
             let y = *y;
-            let result = ptr.secp256k1_new(x, y, gas);
+            let result = call_handler(gas, |gas| ptr.secp256k1_new(x, y, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1453,7 +1911,7 @@ pub(crate) mod handler {
                         }),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1466,7 +1924,7 @@ pub(crate) mod handler {
         ) {
             let p0 = *p0;
             let p1 = *p1;
-            let result = ptr.secp256k1_add(p0, p1, gas);
+            let result = call_handler(gas, |gas| ptr.secp256k1_add(p0, p1, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1475,7 +1933,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(x),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1489,7 +1947,7 @@ pub(crate) mod handler {
             // Seems like it's important to dereference and create a local instead of at call site directly.
             let scalar = *scalar;
             let p = *p;
-            let result = ptr.secp256k1_mul(p, scalar, gas);
+            let result = call_handler(gas, |gas| ptr.secp256k1_mul(p, scalar, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1498,7 +1956,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(x),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1511,7 +1969,7 @@ pub(crate) mod handler {
         ) {
             let x = *x;
             let y_parity = *y_parity;
-            let result = ptr.secp256k1_get_point_from_x(x, y_parity, gas);
+            let result = call_handler(gas, |gas| ptr.secp256k1_get_point_from_x(x, y_parity, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1523,7 +1981,7 @@ pub(crate) mod handler {
                         }),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1534,7 +1992,7 @@ pub(crate) mod handler {
             p: &Secp256k1Point,
         ) {
             let p = *p;
-            let result = ptr.secp256k1_get_xy(p, gas);
+            let result = call_handler(gas, |gas| ptr.secp256k1_get_xy(p, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1543,7 +2001,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(x),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1556,7 +2014,7 @@ pub(crate) mod handler {
         ) {
             let x = *x;
             let y = *y;
-            let result = ptr.secp256r1_new(x, y, gas);
+            let result = call_handler(gas, |gas| ptr.secp256r1_new(x, y, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1568,7 +2026,7 @@ pub(crate) mod handler {
                         }),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1581,7 +2039,7 @@ pub(crate) mod handler {
         ) {
             let p0 = *p0;
             let p1 = *p1;
-            let result = ptr.secp256r1_add(p0, p1, gas);
+            let result = call_handler(gas, |gas| ptr.secp256r1_add(p0, p1, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1590,7 +2048,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(x),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1603,7 +2061,7 @@ pub(crate) mod handler {
         ) {
             let scalar = *scalar;
             let p = *p;
-            let result = ptr.secp256r1_mul(p, scalar, gas);
+            let result = call_handler(gas, |gas| ptr.secp256r1_mul(p, scalar, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1612,7 +2070,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(x),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1625,7 +2083,7 @@ pub(crate) mod handler {
         ) {
             let x = *x;
             let y_parity = *y_parity;
-            let result = ptr.secp256r1_get_point_from_x(x, y_parity, gas);
+            let result = call_handler(gas, |gas| ptr.secp256r1_get_point_from_x(x, y_parity, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1637,7 +2095,7 @@ pub(crate) mod handler {
                         }),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1648,7 +2106,7 @@ pub(crate) mod handler {
             p: &Secp256r1Point,
         ) {
             let p = *p;
-            let result = ptr.secp256r1_get_xy(p, gas);
+            let result = call_handler(gas, |gas| ptr.secp256r1_get_xy(p, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1657,7 +2115,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(x),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1669,7 +2127,7 @@ pub(crate) mod handler {
             block: &[u32; 16],
         ) {
             let state_ref = unsafe { state.as_mut().unwrap() };
-            let result = ptr.sha256_process_block(state_ref, block, gas);
+            let result = call_handler(gas, |gas| ptr.sha256_process_block(state_ref, block, gas));
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1678,7 +2136,7 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(state),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
 
@@ -1688,7 +2146,9 @@ pub(crate) mod handler {
             gas: &mut u64,
             contract_address: &Felt252Abi,
         ) {
-            let result = ptr.get_class_hash_at(contract_address.into(), gas);
+            let result = call_handler(gas, |gas| {
+                ptr.get_class_hash_at(contract_address.into(), gas)
+            });
 
             *result_ptr = match result {
                 Ok(x) => SyscallResultAbi {
@@ -1697,36 +2157,88 @@ pub(crate) mod handler {
                         payload: ManuallyDrop::new(Felt252Abi(x.to_bytes_le())),
                     }),
                 },
-                Err(e) => Self::wrap_error(&e),
+                Err(e) => Self::wrap_error(e),
             };
         }
     }
-}
 
-#[cfg(feature = "with-cheatcode")]
-thread_local!(pub static SYSCALL_HANDLER_VTABLE: std::cell::Cell<*mut ()> = const { std::cell::Cell::new(std::ptr::null_mut()) });
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_call_handler_abort() {
+            let previous_abort_error = replace_abort_error(None);
+
+            let mut gas = 100;
+            let result: SyscallResult<()> = call_handler(&mut gas, |_| panic!("oops"));
+            assert_eq!(
+                result,
+                Err(SyscallError::Internal(
+                    "syscall handler panicked: oops".to_string()
+                ))
+            );
+            assert_eq!(gas, 0);
+
+            // Syscalls after an abort don't reach the handler.
+            let mut gas = 100;
+            let result = call_handler(&mut gas, |_| Ok(Felt::ONE));
+            assert!(result.is_err_and(|error| error.is_abort()));
+
+            assert!(replace_abort_error(previous_abort_error).is_some());
+        }
+
+        #[cfg(feature = "with-cheatcode")]
+        #[test]
+        fn test_cheatcode_without_handler() {
+            type Callbacks<'a> = StarknetSyscallHandlerCallbacks<'a, DummySyscallHandler>;
+
+            let previous_abort_error = replace_abort_error(None);
+
+            let selector = Felt252Abi(Felt::from_bytes_be_slice(b"set_nonce").to_bytes_le());
+            let input =
+                unsafe { Callbacks::alloc_mlir_array(&[Felt252Abi(Felt::ONE.to_bytes_le())]) };
+            let mut result = ArrayAbi {
+                ptr: null_mut(),
+                since: 0,
+                until: 0,
+                capacity: 0,
+            };
+            crate::starknet::cairo_native__vtable_cheatcode(
+                &mut result,
+                null_mut(),
+                &selector,
+                &input,
+            );
+
+            assert_eq!(
+                Vec::<Felt>::from(&result),
+                [Felt::ONE, Felt::from_bytes_be_slice(b"no syscall handler")],
+            );
+            unsafe { Callbacks::drop_mlir_array(&result) };
+
+            // The execution goes on, so the syscalls made after the cheatcode still work.
+            assert!(replace_abort_error(previous_abort_error).is_none());
+        }
+    }
+}
 
 #[allow(non_snake_case)]
 #[cfg(feature = "with-cheatcode")]
-/// Runtime function that calls the `cheatcode` syscall
+/// Runtime function that calls the `cheatcode` syscall of the given context's handler.
 ///
-/// The Cairo compiler doesn't specify that the cheatcode syscall needs the syscall handler,
-/// so a pointer to `StarknetSyscallHandlerCallbacks` is stored as a `thread::LocalKey` and accesed in runtime by this function.
+/// The Cairo compiler doesn't specify that the cheatcode syscall needs the syscall handler, so the
+/// `cheatcode` libfunc reads the context from the execution context instead. When the execution
+/// has no syscall handler (a null context), the cheatcode fails with a panic result (see
+/// [`handler::CheatcodeContext::fail`]).
 pub extern "C" fn cairo_native__vtable_cheatcode(
     result_ptr: &mut ArrayAbi<Felt252Abi>,
+    cheatcode_context: *mut handler::CheatcodeContext,
     selector: &Felt252Abi,
     input: &ArrayAbi<Felt252Abi>,
 ) {
-    let ptr = SYSCALL_HANDLER_VTABLE.with(|ptr| ptr.get());
-    assert!(!ptr.is_null());
-
-    let callbacks_ptr = ptr as *mut handler::StarknetSyscallHandlerCallbacks<DummySyscallHandler>;
-    let callbacks = unsafe { callbacks_ptr.as_mut().expect("should not be null") };
-
-    // The `StarknetSyscallHandler` is stored as a reference in the first field of `StarknetSyscalLHandlerCallbacks`,
-    // so we can interpret `ptr` as a double pointer to the handler.
-    let handler_ptr_ptr = ptr as *mut *mut DummySyscallHandler;
-    let handler = unsafe { (*handler_ptr_ptr).as_mut().expect("should not be null") };
-
-    (callbacks.cheatcode)(result_ptr, handler, selector, input);
+    match unsafe { cheatcode_context.as_mut() } {
+        Some(cheatcode_context) => cheatcode_context.call(result_ptr, selector, input),
+        None => handler::CheatcodeContext::fail(result_ptr, input),
+    }
 }
@@ -0,0 +1,352 @@
+//! A syscall middleware that records the side effects of an execution.
+
+use super::{SyscallError, SyscallMiddleware, SyscallRequest, SyscallResponse, SyscallResult};
+use crate::execution_result::{
+    ExecutionReceipt, NestedCall, NestedCallKind, OrderedEvent, OrderedL2ToL1Message, StorageAccess,
+};
+use starknet_types_core::felt::Felt;
+
+/// Records the emitted events, L2 to L1 messages, storage accesses and nested calls of the
+/// syscalls it sees into an [`ExecutionReceipt`].
+///
+/// The side effects of the syscalls made by a nested call are recorded into its
+/// [`NestedCall::receipt`], with their own order, rather than into the caller's receipt.
+///
+/// Pass it by mutable reference to a [`SyscallLayer`](super::SyscallLayer) to retrieve the
+/// receipt once the execution has finished.
+#[derive(Debug, Default)]
+pub struct CollectingMiddleware {
+    receipt: ExecutionReceipt,
+    /// The number of syscalls recorded so far, used to order the receipt's entries.
+    order: usize,
+    /// The order of every recorded syscall in progress.
+    pending: Vec<usize>,
+    /// The receipt and next order of every caller whose nested call is in progress.
+    callers: Vec<(ExecutionReceipt, usize)>,
+}
+
+impl CollectingMiddleware {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn receipt(&self) -> &ExecutionReceipt {
+        &self.receipt
+    }
+
+    pub fn into_receipt(self) -> ExecutionReceipt {
+        self.receipt
+    }
+}
+
+/// Whether the syscall has side effects recorded in the receipt.
+const fn is_recorded(request: &SyscallRequest) -> bool {
+    matches!(
+        request,
+        SyscallRequest::Deploy { .. }
+            | SyscallRequest::LibraryCall { .. }
+            | SyscallRequest::CallContract { .. }
+            | SyscallRequest::StorageRead { .. }
+            | SyscallRequest::StorageWrite { .. }
+            | SyscallRequest::EmitEvent { .. }
+            | SyscallRequest::SendMessageToL1 { .. }
+    )
+}
+
+/// The returned values of a nested call, or its error data.
+fn nested_call_result(result: &SyscallResult<SyscallResponse>) -> Result<Vec<Felt>, Vec<Felt>> {
+    match result {
+        Ok(SyscallResponse::Felts(retdata) | SyscallResponse::Deploy { retdata, .. }) => {
+            Ok(retdata.clone())
+        }
+        Ok(_) => Ok(Vec::new()),
+        Err(error) => Err(error.clone().into_felts()),
+    }
+}
+
+impl SyscallMiddleware for CollectingMiddleware {
+    fn before_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        _remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        if is_recorded(request) {
+            self.pending.push(self.order);
+            self.order += 1;
+        }
+        if request.is_nested_call() {
+            let caller = std::mem::take(&mut self.receipt);
+            self.callers.push((caller, self.order));
+            self.order = 0;
+        }
+
+        Ok(())
+    }
+
+    fn after_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        result: &SyscallResult<SyscallResponse>,
+        _remaining_gas: u64,
+    ) {
+        if !is_recorded(request) {
+            return;
+        }
+        let Some(order) = self.pending.pop() else {
+            return;
+        };
+        let mut callee = ExecutionReceipt::default();
+        if request.is_nested_call() {
+            if let Some((caller, next_order)) = self.callers.pop() {
+                callee = std::mem::replace(&mut self.receipt, caller);
+                self.order = next_order;
+            }
+        }
+
+        match request {
+            SyscallRequest::Deploy {
+                class_hash,
+                contract_address_salt,
+                calldata,
+                deploy_from_zero,
+            } => self.receipt.nested_calls.push(NestedCall {
+                order,
+                kind: NestedCallKind::Deploy {
+                    class_hash: *class_hash,
+                    contract_address_salt: *contract_address_salt,
+                    deploy_from_zero: *deploy_from_zero,
+                    contract_address: match result {
+                        Ok(SyscallResponse::Deploy {
+                            contract_address, ..
+                        }) => Some(*contract_address),
+                        _ => None,
+                    },
+                },
+                calldata: calldata.clone(),
+                result: nested_call_result(result),
+                receipt: callee,
+            }),
+            SyscallRequest::LibraryCall {
+                class_hash,
+                function_selector,
+                calldata,
+            } => self.receipt.nested_calls.push(NestedCall {
+                order,
+                kind: NestedCallKind::LibraryCall {
+                    class_hash: *class_hash,
+                    function_selector: *function_selector,
+                },
+                calldata: calldata.clone(),
+                result: nested_call_result(result),
+                receipt: callee,
+            }),
+            SyscallRequest::CallContract {
+                address,
+                entry_point_selector,
+                calldata,
+            } => self.receipt.nested_calls.push(NestedCall {
+                order,
+                kind: NestedCallKind::CallContract {
+                    address: *address,
+                    entry_point_selector: *entry_point_selector,
+                },
+                calldata: calldata.clone(),
+                result: nested_call_result(result),
+                receipt: callee,
+            }),
+            SyscallRequest::StorageRead {
+                address_domain,
+                address,
+            } => {
+                if let Ok(SyscallResponse::Felt(value)) = result {
+                    self.receipt.storage_reads.push(StorageAccess {
+                        order,
+                        address_domain: *address_domain,
+                        address: *address,
+                        value: *value,
+                    });
+                }
+            }
+            SyscallRequest::StorageWrite {
+                address_domain,
+                address,
+                value,
+            } => {
+                if result.is_ok() {
+                    self.receipt.storage_writes.push(StorageAccess {
+                        order,
+                        address_domain: *address_domain,
+                        address: *address,
+                        value: *value,
+                    });
+                }
+            }
+            SyscallRequest::EmitEvent { keys, data } => {
+                if result.is_ok() {
+                    self.receipt.events.push(OrderedEvent {
+                        order,
+                        keys: keys.clone(),
+                        data: data.clone(),
+                    });
+                }
+            }
+            SyscallRequest::SendMessageToL1 {
+                to_address,
+                payload,
+            } => {
+                if result.is_ok() {
+                    self.receipt.l2_to_l1_messages.push(OrderedL2ToL1Message {
+                        order,
+                        to_address: *to_address,
+                        payload: payload.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        executor::AotContractExecutor,
+        starknet::{StarknetSyscallHandler, SyscallLayer},
+        starknet_stub::StubSyscallHandler,
+        utils::test::load_starknet_contract,
+        OptLevel,
+    };
+    use cairo_lang_starknet_classes::keccak::starknet_keccak;
+
+    #[test]
+    fn test_collecting_middleware() {
+        let mut stub = StubSyscallHandler::default();
+        let mut collector = CollectingMiddleware::new();
+        let mut gas = u64::MAX;
+
+        let mut handler = SyscallLayer::new(&mut stub, &mut collector);
+        handler
+            .storage_write(0, 1.into(), 2.into(), &mut gas)
+            .unwrap();
+        handler.keccak(&[0; 17], &mut gas).unwrap();
+        handler.storage_read(0, 1.into(), &mut gas).unwrap();
+        handler
+            .emit_event(&[3.into()], &[4.into()], &mut gas)
+            .unwrap();
+        assert!(handler
+            .call_contract(5.into(), 6.into(), &[7.into()], &mut gas)
+            .is_err());
+        drop(handler);
+
+        let receipt = collector.into_receipt();
+        assert_eq!(
+            receipt.storage_writes,
+            [StorageAccess {
+                order: 0,
+                address_domain: 0,
+                address: 1.into(),
+                value: 2.into(),
+            }]
+        );
+        assert_eq!(receipt.storage_reads[0].order, 1);
+        assert_eq!(receipt.storage_reads[0].value, Felt::from(2));
+        assert_eq!(
+            receipt.events,
+            [OrderedEvent {
+                order: 2,
+                keys: vec![3.into()],
+                data: vec![4.into()],
+            }]
+        );
+        assert_eq!(receipt.nested_calls[0].order, 3);
+        assert_eq!(receipt.nested_calls[0].calldata, [Felt::from(7)]);
+        assert!(receipt.nested_calls[0].result.is_err());
+    }
+
+    #[test]
+    fn test_collecting_nested_calls() {
+        let (_, contract) = load_starknet_contract! {
+            #[starknet::interface]
+            trait IForwarder<TContractState> {
+                fn forward(self: @TContractState, address: starknet::ContractAddress);
+                fn emit(self: @TContractState);
+            }
+
+            #[starknet::contract]
+            mod contract {
+                use starknet::SyscallResultTrait;
+
+                #[storage]
+                struct Storage {}
+
+                #[abi(embed_v0)]
+                impl IForwarderImpl of super::IForwarder<ContractState> {
+                    fn forward(self: @ContractState, address: starknet::ContractAddress) {
+                        starknet::syscalls::call_contract_syscall(
+                            address, selector!("emit"), array![].span(),
+                        )
+                            .unwrap_syscall();
+                    }
+
+                    fn emit(self: @ContractState) {
+                        starknet::syscalls::emit_event_syscall(
+                            array![1].span(), array![2].span(),
+                        )
+                            .unwrap_syscall();
+                    }
+                }
+            }
+        };
+        let executor = AotContractExecutor::new(
+            &contract.extract_sierra_program().unwrap(),
+            &contract.entry_points_by_type,
+            OptLevel::Default,
+        )
+        .unwrap();
+
+        let mut stub = StubSyscallHandler::default();
+        stub.declare(Felt::from(1234), executor);
+        let mut collector = CollectingMiddleware::new();
+        let mut gas = u64::MAX;
+
+        let mut handler = SyscallLayer::new(&mut stub, &mut collector);
+        let (a_address, _) = handler
+            .deploy(1234.into(), 1.into(), &[], false, &mut gas)
+            .unwrap();
+        let (b_address, _) = handler
+            .deploy(1234.into(), 2.into(), &[], false, &mut gas)
+            .unwrap();
+        handler
+            .call_contract(
+                a_address,
+                Felt::from(starknet_keccak(b"forward")),
+                &[b_address],
+                &mut gas,
+            )
+            .unwrap();
+        drop(handler);
+
+        // The receipt of the call to A holds the call to B, whose receipt holds its event.
+        let receipt = collector.into_receipt();
+        assert!(receipt.events.is_empty());
+        assert_eq!(receipt.nested_calls.len(), 3);
+        let a_receipt = &receipt.nested_calls[2].receipt;
+        assert!(a_receipt.events.is_empty());
+        assert_eq!(
+            a_receipt.nested_calls[0].kind,
+            NestedCallKind::CallContract {
+                address: b_address,
+                entry_point_selector: Felt::from(starknet_keccak(b"emit")),
+            }
+        );
+        assert_eq!(
+            a_receipt.nested_calls[0].receipt.events,
+            [OrderedEvent {
+                order: 0,
+                keys: vec![1.into()],
+                data: vec![2.into()],
+            }]
+        );
+    }
+}
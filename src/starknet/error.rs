@@ -0,0 +1,160 @@
+//! Typed errors returned by syscall handlers.
+
+use crate::utils::encode_str_as_felts;
+use serde::{Deserialize, Serialize};
+use starknet_types_core::felt::Felt;
+use thiserror::Error;
+
+/// The reason a syscall failed.
+///
+/// Every variant except [`Abort`](Self::Abort) and [`Internal`](Self::Internal) is visible to the
+/// Cairo program, which receives the felts returned by [`into_felts`](Self::into_felts). The other
+/// two abort the execution instead: the program's remaining gas is zeroed and every syscall after
+/// that fails, so it unwinds through its normal return path (freeing its memory), and then the
+/// executor discards its results and returns an [`Error`](crate::error::Error). A handler that
+/// panics is treated as an internal error.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyscallError {
+    #[error("out of gas")]
+    OutOfGas,
+    #[error("class hash not found")]
+    ClassHashNotFound,
+    #[error("contract not deployed")]
+    ContractNotDeployed,
+    #[error("contract address unavailable")]
+    ContractAddressUnavailable,
+    #[error("entry point not found")]
+    EntryPointNotFound,
+    #[error("invalid calldata length")]
+    InvalidCalldataLength,
+    #[error("invalid argument")]
+    InvalidArgument,
+    #[error("block number out of range")]
+    BlockNumberOutOfRange,
+    #[error("unimplemented syscall")]
+    Unimplemented,
+    /// A nested call failed with the given return data.
+    #[error("nested call reverted")]
+    Revert(Vec<Felt>),
+    /// Any other error, as the felts returned to Cairo.
+    #[error("syscall failed")]
+    Custom(Vec<Felt>),
+    /// Stop the whole execution, for reasons unrelated to the program (I/O errors, deadlines...).
+    #[error("execution aborted by the syscall handler: {0}")]
+    Abort(String),
+    /// A bug or an unrecoverable failure of the handler itself.
+    #[error("internal syscall handler error: {0}")]
+    Internal(String),
+}
+
+impl SyscallError {
+    /// The failure reasons that Starknet represents as a single short string.
+    const STANDARD_REASONS: [Self; 9] = [
+        Self::OutOfGas,
+        Self::ClassHashNotFound,
+        Self::ContractNotDeployed,
+        Self::ContractAddressUnavailable,
+        Self::EntryPointNotFound,
+        Self::InvalidCalldataLength,
+        Self::InvalidArgument,
+        Self::BlockNumberOutOfRange,
+        Self::Unimplemented,
+    ];
+
+    /// Parse the felts returned by a failed syscall, recognizing the standard failure reasons.
+    ///
+    /// Anything else is parsed as [`Custom`](Self::Custom), so that converting the result back
+    /// using [`into_felts`](Self::into_felts) returns the same felts.
+    pub fn from_felts(felts: Vec<Felt>) -> Self {
+        Self::STANDARD_REASONS
+            .into_iter()
+            .find(|error| error.clone().into_felts() == felts)
+            .unwrap_or(Self::Custom(felts))
+    }
+
+    /// The felts returned to the Cairo program.
+    ///
+    /// Parsing them back with [`from_felts`](Self::from_felts) returns the same error, except for:
+    ///   - [`Revert`](Self::Revert), whose felts are the return data of the callee as is. They're
+    ///     parsed as whatever they spell, usually [`Custom`](Self::Custom).
+    ///   - [`Abort`](Self::Abort) and [`Internal`](Self::Internal), which never reach the program.
+    ///     Their message is encoded as a [`Custom`](Self::Custom) error.
+    pub fn into_felts(self) -> Vec<Felt> {
+        let short_string: &[u8] = match self {
+            Self::Revert(felts) | Self::Custom(felts) => return felts,
+            Self::Abort(msg) | Self::Internal(msg) => return encode_str_as_felts(&msg),
+            Self::OutOfGas => b"Out of gas",
+            Self::ClassHashNotFound => b"CLASS_HASH_NOT_FOUND",
+            Self::ContractNotDeployed => b"CONTRACT_NOT_DEPLOYED",
+            Self::ContractAddressUnavailable => b"CONTRACT_ADDRESS_UNAVAILABLE",
+            Self::EntryPointNotFound => b"ENTRYPOINT_NOT_FOUND",
+            Self::InvalidCalldataLength => b"INVALID_CALLDATA_LENGTH",
+            Self::InvalidArgument => b"Invalid argument",
+            Self::BlockNumberOutOfRange => b"Block number out of range",
+            Self::Unimplemented => b"Unimplemented",
+        };
+
+        vec![Felt::from_bytes_be_slice(short_string)]
+    }
+
+    /// Whether the error aborts the execution, instead of only failing the syscall.
+    pub fn is_abort(&self) -> bool {
+        matches!(self, Self::Abort(_) | Self::Internal(_))
+    }
+}
+
+impl From<Vec<Felt>> for SyscallError {
+    fn from(felts: Vec<Felt>) -> Self {
+        Self::from_felts(felts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(SyscallError::OutOfGas)]
+    #[case(SyscallError::ClassHashNotFound)]
+    #[case(SyscallError::ContractNotDeployed)]
+    #[case(SyscallError::ContractAddressUnavailable)]
+    #[case(SyscallError::EntryPointNotFound)]
+    #[case(SyscallError::InvalidCalldataLength)]
+    #[case(SyscallError::InvalidArgument)]
+    #[case(SyscallError::BlockNumberOutOfRange)]
+    #[case(SyscallError::Unimplemented)]
+    #[case(SyscallError::Custom(vec![Felt::from(1), Felt::from(2)]))]
+    #[case(SyscallError::Custom(Vec::new()))]
+    fn test_syscall_error_felts_roundtrip(#[case] error: SyscallError) {
+        assert_eq!(SyscallError::from_felts(error.clone().into_felts()), error);
+    }
+
+    #[test]
+    fn test_syscall_error_standard_reasons() {
+        assert_eq!(
+            SyscallError::OutOfGas.into_felts(),
+            [Felt::from_bytes_be_slice(b"Out of gas")]
+        );
+        assert_eq!(
+            SyscallError::from_felts(vec![Felt::from_bytes_be_slice(b"CONTRACT_NOT_DEPLOYED")]),
+            SyscallError::ContractNotDeployed
+        );
+    }
+    #[test]
+    fn test_syscall_error_revert_felts() {
+        // The return data of a revert is parsed as whatever it spells.
+        let return_data = vec![Felt::from(1), Felt::from(2)];
+        assert_eq!(
+            SyscallError::from_felts(SyscallError::Revert(return_data.clone()).into_felts()),
+            SyscallError::Custom(return_data)
+        );
+        assert_eq!(
+            SyscallError::from_felts(
+                SyscallError::Revert(vec![Felt::from_bytes_be_slice(b"Out of gas")]).into_felts()
+            ),
+            SyscallError::OutOfGas
+        );
+    }
+}
@@ -0,0 +1,885 @@
+//! Composable middleware for syscall handlers.
+//!
+//! A [`SyscallLayer`] wraps a syscall handler, running the hooks of a [`SyscallMiddleware`] around
+//! every syscall. Middlewares can be combined using tuples (`(A, B)` runs `A`'s hooks first) or by
+//! nesting layers.
+//!
+//! The following middlewares are provided:
+//!   - [`LoggingMiddleware`]: Logs every syscall and its result using `tracing`.
+//!   - [`CountingMiddleware`]: Counts the calls to every syscall.
+//!   - [`LatencyMiddleware`]: Builds a latency histogram for every syscall.
+//!   - [`FailureInjectionMiddleware`]: Makes syscalls fail with a given error.
+
+use super::{
+    syscall::ResponseValue, ExecutionInfo, ExecutionInfoV2, NestedCallStart, Secp256k1Point,
+    Secp256r1Point, StarknetSyscallHandler, SyscallError, SyscallRequest, SyscallResponse,
+    SyscallResult, ENTRY_POINT_INITIAL_BUDGET, U256,
+};
+use crate::{error::Error, execution_result::ContractExecutionResult};
+use starknet_types_core::felt::Felt;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// Hooks run around every syscall by a [`SyscallLayer`].
+pub trait SyscallMiddleware {
+    /// Called before forwarding a syscall to the inner handler.
+    ///
+    /// Returning an error makes the syscall fail with it without reaching the inner handler (or
+    /// calling `after_syscall`).
+    fn before_syscall(
+        &mut self,
+        _request: &SyscallRequest,
+        _remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        Ok(())
+    }
+
+    /// Called with the result of every syscall whose `before_syscall` succeeded, including the
+    /// ones failed by a later middleware.
+    fn after_syscall(
+        &mut self,
+        _request: &SyscallRequest,
+        _result: &SyscallResult<SyscallResponse>,
+        _remaining_gas: u64,
+    ) {
+    }
+}
+
+impl<M> SyscallMiddleware for &mut M
+where
+    M: SyscallMiddleware + ?Sized,
+{
+    fn before_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        (**self).before_syscall(request, remaining_gas)
+    }
+
+    fn after_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        result: &SyscallResult<SyscallResponse>,
+        remaining_gas: u64,
+    ) {
+        (**self).after_syscall(request, result, remaining_gas)
+    }
+}
+
+impl<A, B> SyscallMiddleware for (A, B)
+where
+    A: SyscallMiddleware,
+    B: SyscallMiddleware,
+{
+    fn before_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        self.0.before_syscall(request, remaining_gas)?;
+        if let Err(error) = self.1.before_syscall(request, remaining_gas) {
+            // Only `A` has seen the syscall, so only it is told about the failure.
+            self.0
+                .after_syscall(request, &Err(error.clone()), *remaining_gas);
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    fn after_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        result: &SyscallResult<SyscallResponse>,
+        remaining_gas: u64,
+    ) {
+        self.1.after_syscall(request, result, remaining_gas);
+        self.0.after_syscall(request, result, remaining_gas);
+    }
+}
+
+/// A syscall handler that runs a [`SyscallMiddleware`] around every syscall of the inner handler.
+///
+/// When the inner handler supports it (see [`StarknetSyscallHandler::begin_nested_call`]), nested
+/// calls are run with the layer itself as their handler, so the middleware also sees the
+/// syscalls made by the callees.
+///
+/// Middlewares can be passed by mutable reference to inspect their state after the execution.
+#[derive(Debug)]
+pub struct SyscallLayer<S, M> {
+    inner: S,
+    middleware: M,
+    /// The requests of the nested calls in progress.
+    pending: Vec<SyscallRequest>,
+}
+
+impl<S, M> SyscallLayer<S, M>
+where
+    M: SyscallMiddleware,
+{
+    pub fn new(inner: S, middleware: M) -> Self {
+        Self {
+            inner,
+            middleware,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn into_parts(self) -> (S, M) {
+        (self.inner, self.middleware)
+    }
+
+    fn call<T>(
+        &mut self,
+        request: SyscallRequest,
+        remaining_gas: &mut u64,
+        syscall: impl FnOnce(&mut S, &mut u64) -> SyscallResult<T>,
+    ) -> SyscallResult<T>
+    where
+        T: ResponseValue + Clone,
+    {
+        self.middleware.before_syscall(&request, remaining_gas)?;
+
+        let result = syscall(&mut self.inner, remaining_gas);
+        self.middleware.after_syscall(
+            &request,
+            &result.clone().map(ResponseValue::into_response),
+            *remaining_gas,
+        );
+
+        result
+    }
+}
+
+impl<S, M> SyscallLayer<S, M>
+where
+    S: StarknetSyscallHandler,
+    M: SyscallMiddleware,
+{
+    /// Make a `deploy`, `library_call` or `call_contract` syscall, running its entry point with
+    /// this layer as the handler if the inner handler lets it.
+    fn call_nested<T>(
+        &mut self,
+        request: SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<T>
+    where
+        T: ResponseValue,
+    {
+        T::from_result(match self.begin_nested_call(&request, remaining_gas) {
+            Some(NestedCallStart::Finished(result)) => result,
+            Some(NestedCallStart::Run(entry_point)) => {
+                let result = entry_point.run(
+                    &mut *self,
+                    remaining_gas.saturating_add(ENTRY_POINT_INITIAL_BUDGET),
+                );
+                self.end_nested_call(result, remaining_gas)
+            }
+            None => Err(SyscallError::Internal(format!(
+                "`{}` is not a nested call",
+                request.name()
+            ))),
+        })
+    }
+}
+
+/// Forward a nested call to a handler that runs it itself.
+fn forward_nested_call(
+    handler: &mut impl StarknetSyscallHandler,
+    request: &SyscallRequest,
+    remaining_gas: &mut u64,
+) -> SyscallResult<SyscallResponse> {
+    match request {
+        SyscallRequest::Deploy {
+            class_hash,
+            contract_address_salt,
+            calldata,
+            deploy_from_zero,
+        } => handler
+            .deploy(
+                *class_hash,
+                *contract_address_salt,
+                calldata,
+                *deploy_from_zero,
+                remaining_gas,
+            )
+            .map(ResponseValue::into_response),
+        SyscallRequest::LibraryCall {
+            class_hash,
+            function_selector,
+            calldata,
+        } => handler
+            .library_call(*class_hash, *function_selector, calldata, remaining_gas)
+            .map(ResponseValue::into_response),
+        SyscallRequest::CallContract {
+            address,
+            entry_point_selector,
+            calldata,
+        } => handler
+            .call_contract(*address, *entry_point_selector, calldata, remaining_gas)
+            .map(ResponseValue::into_response),
+        _ => Err(SyscallError::Internal(format!(
+            "`{}` is not a nested call",
+            request.name()
+        ))),
+    }
+}
+
+impl<S, M> StarknetSyscallHandler for SyscallLayer<S, M>
+where
+    S: StarknetSyscallHandler,
+    M: SyscallMiddleware,
+{
+    fn get_block_hash(
+        &mut self,
+        block_number: u64,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        self.call(
+            SyscallRequest::GetBlockHash { block_number },
+            remaining_gas,
+            |inner, remaining_gas| inner.get_block_hash(block_number, remaining_gas),
+        )
+    }
+
+    fn get_execution_info(&mut self, remaining_gas: &mut u64) -> SyscallResult<ExecutionInfo> {
+        self.call(
+            SyscallRequest::GetExecutionInfo,
+            remaining_gas,
+            |inner, remaining_gas| inner.get_execution_info(remaining_gas),
+        )
+    }
+
+    fn get_execution_info_v2(&mut self, remaining_gas: &mut u64) -> SyscallResult<ExecutionInfoV2> {
+        self.call(
+            SyscallRequest::GetExecutionInfoV2,
+            remaining_gas,
+            |inner, remaining_gas| inner.get_execution_info_v2(remaining_gas),
+        )
+    }
+
+    fn deploy(
+        &mut self,
+        class_hash: Felt,
+        contract_address_salt: Felt,
+        calldata: &[Felt],
+        deploy_from_zero: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(Felt, Vec<Felt>)> {
+        self.call_nested(
+            SyscallRequest::Deploy {
+                class_hash,
+                contract_address_salt,
+                calldata: calldata.to_vec(),
+                deploy_from_zero,
+            },
+            remaining_gas,
+        )
+    }
+
+    fn replace_class(&mut self, class_hash: Felt, remaining_gas: &mut u64) -> SyscallResult<()> {
+        self.call(
+            SyscallRequest::ReplaceClass { class_hash },
+            remaining_gas,
+            |inner, remaining_gas| inner.replace_class(class_hash, remaining_gas),
+        )
+    }
+
+    fn library_call(
+        &mut self,
+        class_hash: Felt,
+        function_selector: Felt,
+        calldata: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Vec<Felt>> {
+        self.call_nested(
+            SyscallRequest::LibraryCall {
+                class_hash,
+                function_selector,
+                calldata: calldata.to_vec(),
+            },
+            remaining_gas,
+        )
+    }
+
+    fn call_contract(
+        &mut self,
+        address: Felt,
+        entry_point_selector: Felt,
+        calldata: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Vec<Felt>> {
+        self.call_nested(
+            SyscallRequest::CallContract {
+                address,
+                entry_point_selector,
+                calldata: calldata.to_vec(),
+            },
+            remaining_gas,
+        )
+    }
+
+    fn storage_read(
+        &mut self,
+        address_domain: u32,
+        address: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        self.call(
+            SyscallRequest::StorageRead {
+                address_domain,
+                address,
+            },
+            remaining_gas,
+            |inner, remaining_gas| inner.storage_read(address_domain, address, remaining_gas),
+        )
+    }
+
+    fn storage_write(
+        &mut self,
+        address_domain: u32,
+        address: Felt,
+        value: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        self.call(
+            SyscallRequest::StorageWrite {
+                address_domain,
+                address,
+                value,
+            },
+            remaining_gas,
+            |inner, remaining_gas| {
+                inner.storage_write(address_domain, address, value, remaining_gas)
+            },
+        )
+    }
+
+    fn emit_event(
+        &mut self,
+        keys: &[Felt],
+        data: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        self.call(
+            SyscallRequest::EmitEvent {
+                keys: keys.to_vec(),
+                data: data.to_vec(),
+            },
+            remaining_gas,
+            |inner, remaining_gas| inner.emit_event(keys, data, remaining_gas),
+        )
+    }
+
+    fn send_message_to_l1(
+        &mut self,
+        to_address: Felt,
+        payload: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        self.call(
+            SyscallRequest::SendMessageToL1 {
+                to_address,
+                payload: payload.to_vec(),
+            },
+            remaining_gas,
+            |inner, remaining_gas| inner.send_message_to_l1(to_address, payload, remaining_gas),
+        )
+    }
+
+    fn keccak(&mut self, input: &[u64], remaining_gas: &mut u64) -> SyscallResult<U256> {
+        self.call(
+            SyscallRequest::Keccak {
+                input: input.to_vec(),
+            },
+            remaining_gas,
+            |inner, remaining_gas| inner.keccak(input, remaining_gas),
+        )
+    }
+
+    fn secp256k1_new(
+        &mut self,
+        x: U256,
+        y: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256k1Point>> {
+        self.call(
+            SyscallRequest::Secp256k1New { x, y },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256k1_new(x, y, remaining_gas),
+        )
+    }
+
+    fn secp256k1_add(
+        &mut self,
+        p0: Secp256k1Point,
+        p1: Secp256k1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256k1Point> {
+        self.call(
+            SyscallRequest::Secp256k1Add { p0, p1 },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256k1_add(p0, p1, remaining_gas),
+        )
+    }
+
+    fn secp256k1_mul(
+        &mut self,
+        p: Secp256k1Point,
+        m: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256k1Point> {
+        self.call(
+            SyscallRequest::Secp256k1Mul { p, m },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256k1_mul(p, m, remaining_gas),
+        )
+    }
+
+    fn secp256k1_get_point_from_x(
+        &mut self,
+        x: U256,
+        y_parity: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256k1Point>> {
+        self.call(
+            SyscallRequest::Secp256k1GetPointFromX { x, y_parity },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256k1_get_point_from_x(x, y_parity, remaining_gas),
+        )
+    }
+
+    fn secp256k1_get_xy(
+        &mut self,
+        p: Secp256k1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(U256, U256)> {
+        self.call(
+            SyscallRequest::Secp256k1GetXy { p },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256k1_get_xy(p, remaining_gas),
+        )
+    }
+
+    fn secp256r1_new(
+        &mut self,
+        x: U256,
+        y: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256r1Point>> {
+        self.call(
+            SyscallRequest::Secp256r1New { x, y },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256r1_new(x, y, remaining_gas),
+        )
+    }
+
+    fn secp256r1_add(
+        &mut self,
+        p0: Secp256r1Point,
+        p1: Secp256r1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256r1Point> {
+        self.call(
+            SyscallRequest::Secp256r1Add { p0, p1 },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256r1_add(p0, p1, remaining_gas),
+        )
+    }
+
+    fn secp256r1_mul(
+        &mut self,
+        p: Secp256r1Point,
+        m: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256r1Point> {
+        self.call(
+            SyscallRequest::Secp256r1Mul { p, m },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256r1_mul(p, m, remaining_gas),
+        )
+    }
+
+    fn secp256r1_get_point_from_x(
+        &mut self,
+        x: U256,
+        y_parity: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256r1Point>> {
+        self.call(
+            SyscallRequest::Secp256r1GetPointFromX { x, y_parity },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256r1_get_point_from_x(x, y_parity, remaining_gas),
+        )
+    }
+
+    fn secp256r1_get_xy(
+        &mut self,
+        p: Secp256r1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(U256, U256)> {
+        self.call(
+            SyscallRequest::Secp256r1GetXy { p },
+            remaining_gas,
+            |inner, remaining_gas| inner.secp256r1_get_xy(p, remaining_gas),
+        )
+    }
+
+    fn get_class_hash_at(
+        &mut self,
+        contract_address: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        self.call(
+            SyscallRequest::GetClassHashAt { contract_address },
+            remaining_gas,
+            |inner, remaining_gas| inner.get_class_hash_at(contract_address, remaining_gas),
+        )
+    }
+
+    fn sha256_process_block(
+        &mut self,
+        state: &mut [u32; 8],
+        block: &[u32; 16],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        self.call(
+            SyscallRequest::Sha256ProcessBlock {
+                state: *state,
+                block: *block,
+            },
+            remaining_gas,
+            |inner, remaining_gas| {
+                inner
+                    .sha256_process_block(state, block, remaining_gas)
+                    .map(|()| *state)
+            },
+        )
+        .map(|_| ())
+    }
+
+    #[cfg(feature = "with-cheatcode")]
+    fn cheatcode(&mut self, selector: Felt, input: &[Felt]) -> Vec<Felt> {
+        self.call(
+            SyscallRequest::Cheatcode {
+                selector,
+                input: input.to_vec(),
+            },
+            &mut 0,
+            |inner, _| Ok(inner.cheatcode(selector, input)),
+        )
+        .unwrap_or_default()
+    }
+
+    fn begin_nested_call(
+        &mut self,
+        request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> Option<NestedCallStart> {
+        if !request.is_nested_call() {
+            return None;
+        }
+        if let Err(error) = self.middleware.before_syscall(request, remaining_gas) {
+            return Some(NestedCallStart::Finished(Err(error)));
+        }
+
+        let result = match self.inner.begin_nested_call(request, remaining_gas) {
+            Some(NestedCallStart::Run(entry_point)) => {
+                self.pending.push(request.clone());
+                return Some(NestedCallStart::Run(entry_point));
+            }
+            Some(NestedCallStart::Finished(result)) => result,
+            None => forward_nested_call(&mut self.inner, request, remaining_gas),
+        };
+        self.middleware
+            .after_syscall(request, &result, *remaining_gas);
+
+        Some(NestedCallStart::Finished(result))
+    }
+
+    fn end_nested_call(
+        &mut self,
+        result: Result<ContractExecutionResult, Error>,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<SyscallResponse> {
+        let result = self.inner.end_nested_call(result, remaining_gas);
+        if let Some(request) = self.pending.pop() {
+            self.middleware
+                .after_syscall(&request, &result, *remaining_gas);
+        }
+
+        result
+    }
+}
+
+/// Logs every syscall and its result at the debug level.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoggingMiddleware;
+
+impl SyscallMiddleware for LoggingMiddleware {
+    fn before_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        tracing::debug!(
+            syscall = request.name(),
+            ?request,
+            remaining_gas,
+            "syscall called"
+        );
+        Ok(())
+    }
+
+    fn after_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        result: &SyscallResult<SyscallResponse>,
+        remaining_gas: u64,
+    ) {
+        tracing::debug!(
+            syscall = request.name(),
+            ?result,
+            remaining_gas,
+            "syscall finished"
+        );
+    }
+}
+
+/// Counts the calls to every syscall.
+#[derive(Debug, Clone, Default)]
+pub struct CountingMiddleware {
+    counts: BTreeMap<&'static str, usize>,
+}
+
+impl CountingMiddleware {
+    /// The number of calls, by syscall name.
+    pub fn counts(&self) -> &BTreeMap<&'static str, usize> {
+        &self.counts
+    }
+
+    pub fn count(&self, syscall: &str) -> usize {
+        self.counts.get(syscall).copied().unwrap_or_default()
+    }
+}
+
+impl SyscallMiddleware for CountingMiddleware {
+    fn before_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        _remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        *self.counts.entry(request.name()).or_default() += 1;
+        Ok(())
+    }
+}
+
+/// A histogram of latencies, with power-of-two buckets in nanoseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// The bucket `i` counts the latencies in `[2^i, 2^(i+1))` nanoseconds (the first one also
+    /// includes zero).
+    pub buckets: [u64; 64],
+    pub count: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[nanos.max(1).ilog2() as usize] += 1;
+
+        self.min = match self.count {
+            0 => latency,
+            _ => self.min.min(latency),
+        };
+        self.max = self.max.max(latency);
+        self.total += latency;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        u32::try_from(self.count)
+            .ok()
+            .filter(|count| *count != 0)
+            .map(|count| self.total / count)
+    }
+}
+
+/// Builds a [`LatencyHistogram`] for every syscall.
+///
+/// The latency includes the inner handler and the middlewares that run after this one.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyMiddleware {
+    histograms: BTreeMap<&'static str, LatencyHistogram>,
+    /// The start time of every syscall in progress. Nested calls may run syscalls while another
+    /// is still in progress.
+    start_times: Vec<Instant>,
+}
+
+impl LatencyMiddleware {
+    /// The latency histograms, by syscall name.
+    pub fn histograms(&self) -> &BTreeMap<&'static str, LatencyHistogram> {
+        &self.histograms
+    }
+}
+
+impl SyscallMiddleware for LatencyMiddleware {
+    fn before_syscall(
+        &mut self,
+        _request: &SyscallRequest,
+        _remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        self.start_times.push(Instant::now());
+        Ok(())
+    }
+
+    fn after_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        _result: &SyscallResult<SyscallResponse>,
+        _remaining_gas: u64,
+    ) {
+        if let Some(start_time) = self.start_times.pop() {
+            self.histograms
+                .entry(request.name())
+                .or_default()
+                .record(start_time.elapsed());
+        }
+    }
+}
+
+/// Makes syscalls fail with a given error instead of reaching the inner handler.
+#[derive(Debug, Clone, Default)]
+pub struct FailureInjectionMiddleware {
+    rules: Vec<FailureRule>,
+    counts: BTreeMap<&'static str, usize>,
+}
+
+#[derive(Debug, Clone)]
+struct FailureRule {
+    syscall: String,
+    /// Only fail the nth call (zero-based) to the syscall, or every call if `None`.
+    nth_call: Option<usize>,
+    error: SyscallError,
+}
+
+impl FailureInjectionMiddleware {
+    /// Make every call to the syscall fail.
+    pub fn fail(mut self, syscall: &str, error: SyscallError) -> Self {
+        self.rules.push(FailureRule {
+            syscall: syscall.to_string(),
+            nth_call: None,
+            error,
+        });
+        self
+    }
+
+    /// Make only the nth call (zero-based) to the syscall fail.
+    pub fn fail_nth(mut self, syscall: &str, nth_call: usize, error: SyscallError) -> Self {
+        self.rules.push(FailureRule {
+            syscall: syscall.to_string(),
+            nth_call: Some(nth_call),
+            error,
+        });
+        self
+    }
+}
+
+impl SyscallMiddleware for FailureInjectionMiddleware {
+    fn before_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        _remaining_gas: &mut u64,
+    ) -> Result<(), SyscallError> {
+        let count = self.counts.entry(request.name()).or_default();
+        let nth_call = *count;
+        *count += 1;
+
+        match self.rules.iter().find(|rule| {
+            rule.syscall == request.name() && rule.nth_call.is_none_or(|n| n == nth_call)
+        }) {
+            Some(rule) => Err(rule.error.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::starknet_stub::StubSyscallHandler;
+
+    #[test]
+    fn test_syscall_layer() {
+        let mut stub = StubSyscallHandler::default();
+        let mut counter = CountingMiddleware::default();
+        let failures = FailureInjectionMiddleware::default().fail_nth(
+            "storage_read",
+            1,
+            SyscallError::Custom(vec![Felt::from_bytes_be_slice(b"injected")]),
+        );
+
+        let mut handler = SyscallLayer::new(&mut stub, (&mut counter, failures));
+        handler
+            .storage_write(0, 1.into(), 2.into(), &mut u64::MAX)
+            .unwrap();
+        assert_eq!(
+            handler.storage_read(0, 1.into(), &mut u64::MAX),
+            Ok(Felt::from(2))
+        );
+        assert_eq!(
+            handler.storage_read(0, 1.into(), &mut u64::MAX),
+            Err(SyscallError::Custom(vec![Felt::from_bytes_be_slice(
+                b"injected"
+            )]))
+        );
+        drop(handler);
+
+        assert_eq!(counter.count("storage_write"), 1);
+        assert_eq!(counter.count("storage_read"), 2);
+        assert_eq!(counter.count("emit_event"), 0);
+    }
+
+    #[test]
+    fn test_failed_before_syscall() {
+        let mut stub = StubSyscallHandler::default();
+        let mut latency = LatencyMiddleware::default();
+        let failures = FailureInjectionMiddleware::default().fail(
+            "storage_read",
+            SyscallError::Custom(vec![Felt::from_bytes_be_slice(b"injected")]),
+        );
+
+        // The latency middleware never sees the syscalls failed before reaching it.
+        let mut handler = SyscallLayer::new(&mut stub, (failures, &mut latency));
+        assert!(handler.storage_read(0, 1.into(), &mut u64::MAX).is_err());
+        handler
+            .storage_write(0, 1.into(), 2.into(), &mut u64::MAX)
+            .unwrap();
+        drop(handler);
+
+        assert!(!latency.histograms().contains_key("storage_read"));
+        assert_eq!(latency.histograms()["storage_write"].count, 1);
+        assert!(latency.start_times.is_empty());
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_nanos(0));
+        histogram.record(Duration::from_nanos(5));
+        histogram.record(Duration::from_nanos(7));
+
+        assert_eq!(histogram.buckets[0], 1);
+        assert_eq!(histogram.buckets[2], 2);
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.min, Duration::from_nanos(0));
+        assert_eq!(histogram.max, Duration::from_nanos(7));
+        assert_eq!(histogram.mean(), Some(Duration::from_nanos(4)));
+    }
+}
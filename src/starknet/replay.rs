@@ -0,0 +1,547 @@
+//! A syscall middleware to record the syscalls of an execution into a [`SyscallTrace`], and a
+//! syscall handler to replay them later without the original handler (or its state).
+//!
+//! Traces are stored as JSON.
+
+use super::{
+    syscall::ResponseValue, ExecutionInfo, ExecutionInfoV2, Secp256k1Point, Secp256r1Point,
+    StarknetSyscallHandler, SyscallError, SyscallMiddleware, SyscallRequest, SyscallResponse,
+    SyscallResult, U256,
+};
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use starknet_types_core::felt::Felt;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+/// The syscalls of an execution, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyscallTrace {
+    pub entries: Vec<SyscallTraceEntry>,
+}
+
+/// A syscall, its result and the gas available before and after it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyscallTraceEntry {
+    pub request: SyscallRequest,
+    pub result: std::result::Result<SyscallResponse, SyscallError>,
+    pub gas_before: u64,
+    pub gas_after: u64,
+    /// The number of nested calls the syscall was made in. Syscalls of nested calls are only
+    /// seen when their entry points are run through the layer (see
+    /// [`StarknetSyscallHandler::begin_nested_call`]).
+    #[serde(default)]
+    pub depth: usize,
+}
+
+impl SyscallTrace {
+    /// Save the trace as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+
+    /// Load a trace saved with [`save`](Self::save).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+/// Records every syscall it sees into a [`SyscallTrace`].
+///
+/// Pass it by mutable reference to a [`SyscallLayer`](super::SyscallLayer) to retrieve the trace
+/// once the execution has finished.
+#[derive(Debug, Default)]
+pub struct RecordingMiddleware {
+    trace: SyscallTrace,
+    /// The gas available before every syscall in progress.
+    gas_before: Vec<u64>,
+}
+
+impl RecordingMiddleware {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trace(&self) -> &SyscallTrace {
+        &self.trace
+    }
+
+    pub fn into_trace(self) -> SyscallTrace {
+        self.trace
+    }
+}
+
+impl SyscallMiddleware for RecordingMiddleware {
+    fn before_syscall(
+        &mut self,
+        _request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> std::result::Result<(), SyscallError> {
+        self.gas_before.push(*remaining_gas);
+        Ok(())
+    }
+
+    fn after_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        result: &SyscallResult<SyscallResponse>,
+        remaining_gas: u64,
+    ) {
+        if let Some(gas_before) = self.gas_before.pop() {
+            self.trace.entries.push(SyscallTraceEntry {
+                request: request.clone(),
+                result: result.clone(),
+                gas_before,
+                gas_after: remaining_gas,
+                depth: self.gas_before.len(),
+            });
+        }
+    }
+}
+
+/// The first syscall that didn't match the trace being replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayDivergence {
+    /// The position of the syscall within the trace.
+    pub position: usize,
+    /// The syscall found in the trace at that position, if any.
+    pub expected: Option<SyscallRequest>,
+    pub actual: SyscallRequest,
+}
+
+/// A syscall handler that serves the responses of a [`SyscallTrace`].
+///
+/// Syscalls must be issued in the same order and with the same arguments as recorded. Otherwise,
+/// the divergence is stored (see [`divergence`](Self::divergence)) and every syscall from then on
+/// fails with an internal error, aborting the execution.
+///
+/// An execution that makes fewer syscalls than recorded isn't a divergence: callers must check
+/// [`is_finished`](Self::is_finished) once it has finished to know whether the whole trace was
+/// replayed.
+///
+/// The gas charged by every syscall is the same as when it was recorded. Syscalls recorded within
+/// nested calls are skipped, since the nested calls are replayed from their recorded results.
+#[derive(Debug)]
+pub struct ReplayingSyscallHandler {
+    trace: SyscallTrace,
+    position: usize,
+    divergence: Option<ReplayDivergence>,
+}
+
+impl ReplayingSyscallHandler {
+    pub fn new(trace: SyscallTrace) -> Self {
+        Self {
+            trace,
+            position: 0,
+            divergence: None,
+        }
+    }
+
+    pub fn divergence(&self) -> Option<&ReplayDivergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Whether every syscall in the trace has been replayed without divergences.
+    pub fn is_finished(&self) -> bool {
+        self.divergence.is_none()
+            && self.trace.entries[self.position..]
+                .iter()
+                .all(|entry| entry.depth > 0)
+    }
+
+    fn replay<T>(&mut self, request: SyscallRequest, remaining_gas: &mut u64) -> SyscallResult<T>
+    where
+        T: ResponseValue,
+    {
+        if self.divergence.is_none() {
+            while self
+                .trace
+                .entries
+                .get(self.position)
+                .is_some_and(|entry| entry.depth > 0)
+            {
+                self.position += 1;
+            }
+
+            let entry = self.trace.entries.get(self.position);
+            if let Some(entry) = entry.filter(|entry| entry.request == request) {
+                let response = match entry.result.clone() {
+                    Ok(response) => T::from_response(response).map(Ok),
+                    Err(error) => Some(Err(error)),
+                };
+
+                if let Some(result) = response {
+                    *remaining_gas = remaining_gas
+                        .saturating_sub(entry.gas_before.saturating_sub(entry.gas_after));
+                    self.position += 1;
+                    return result;
+                }
+            }
+
+            self.divergence = Some(ReplayDivergence {
+                position: self.position,
+                expected: entry.map(|entry| entry.request.clone()),
+                actual: request,
+            });
+        }
+
+        Err(SyscallError::Internal(format!(
+            "replay diverged at syscall {}",
+            self.position
+        )))
+    }
+}
+
+impl StarknetSyscallHandler for &mut ReplayingSyscallHandler {
+    fn get_block_hash(
+        &mut self,
+        block_number: u64,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        self.replay(SyscallRequest::GetBlockHash { block_number }, remaining_gas)
+    }
+
+    fn get_execution_info(&mut self, remaining_gas: &mut u64) -> SyscallResult<ExecutionInfo> {
+        self.replay(SyscallRequest::GetExecutionInfo, remaining_gas)
+    }
+
+    fn get_execution_info_v2(&mut self, remaining_gas: &mut u64) -> SyscallResult<ExecutionInfoV2> {
+        self.replay(SyscallRequest::GetExecutionInfoV2, remaining_gas)
+    }
+
+    fn deploy(
+        &mut self,
+        class_hash: Felt,
+        contract_address_salt: Felt,
+        calldata: &[Felt],
+        deploy_from_zero: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(Felt, Vec<Felt>)> {
+        self.replay(
+            SyscallRequest::Deploy {
+                class_hash,
+                contract_address_salt,
+                calldata: calldata.to_vec(),
+                deploy_from_zero,
+            },
+            remaining_gas,
+        )
+    }
+
+    fn replace_class(&mut self, class_hash: Felt, remaining_gas: &mut u64) -> SyscallResult<()> {
+        self.replay(SyscallRequest::ReplaceClass { class_hash }, remaining_gas)
+    }
+
+    fn library_call(
+        &mut self,
+        class_hash: Felt,
+        function_selector: Felt,
+        calldata: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Vec<Felt>> {
+        self.replay(
+            SyscallRequest::LibraryCall {
+                class_hash,
+                function_selector,
+                calldata: calldata.to_vec(),
+            },
+            remaining_gas,
+        )
+    }
+
+    fn call_contract(
+        &mut self,
+        address: Felt,
+        entry_point_selector: Felt,
+        calldata: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Vec<Felt>> {
+        self.replay(
+            SyscallRequest::CallContract {
+                address,
+                entry_point_selector,
+                calldata: calldata.to_vec(),
+            },
+            remaining_gas,
+        )
+    }
+
+    fn storage_read(
+        &mut self,
+        address_domain: u32,
+        address: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        self.replay(
+            SyscallRequest::StorageRead {
+                address_domain,
+                address,
+            },
+            remaining_gas,
+        )
+    }
+
+    fn storage_write(
+        &mut self,
+        address_domain: u32,
+        address: Felt,
+        value: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        self.replay(
+            SyscallRequest::StorageWrite {
+                address_domain,
+                address,
+                value,
+            },
+            remaining_gas,
+        )
+    }
+
+    fn emit_event(
+        &mut self,
+        keys: &[Felt],
+        data: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        self.replay(
+            SyscallRequest::EmitEvent {
+                keys: keys.to_vec(),
+                data: data.to_vec(),
+            },
+            remaining_gas,
+        )
+    }
+
+    fn send_message_to_l1(
+        &mut self,
+        to_address: Felt,
+        payload: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        self.replay(
+            SyscallRequest::SendMessageToL1 {
+                to_address,
+                payload: payload.to_vec(),
+            },
+            remaining_gas,
+        )
+    }
+
+    fn keccak(&mut self, input: &[u64], remaining_gas: &mut u64) -> SyscallResult<U256> {
+        self.replay(
+            SyscallRequest::Keccak {
+                input: input.to_vec(),
+            },
+            remaining_gas,
+        )
+    }
+
+    fn secp256k1_new(
+        &mut self,
+        x: U256,
+        y: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256k1Point>> {
+        self.replay(SyscallRequest::Secp256k1New { x, y }, remaining_gas)
+    }
+
+    fn secp256k1_add(
+        &mut self,
+        p0: Secp256k1Point,
+        p1: Secp256k1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256k1Point> {
+        self.replay(SyscallRequest::Secp256k1Add { p0, p1 }, remaining_gas)
+    }
+
+    fn secp256k1_mul(
+        &mut self,
+        p: Secp256k1Point,
+        m: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256k1Point> {
+        self.replay(SyscallRequest::Secp256k1Mul { p, m }, remaining_gas)
+    }
+
+    fn secp256k1_get_point_from_x(
+        &mut self,
+        x: U256,
+        y_parity: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256k1Point>> {
+        self.replay(
+            SyscallRequest::Secp256k1GetPointFromX { x, y_parity },
+            remaining_gas,
+        )
+    }
+
+    fn secp256k1_get_xy(
+        &mut self,
+        p: Secp256k1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(U256, U256)> {
+        self.replay(SyscallRequest::Secp256k1GetXy { p }, remaining_gas)
+    }
+
+    fn secp256r1_new(
+        &mut self,
+        x: U256,
+        y: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256r1Point>> {
+        self.replay(SyscallRequest::Secp256r1New { x, y }, remaining_gas)
+    }
+
+    fn secp256r1_add(
+        &mut self,
+        p0: Secp256r1Point,
+        p1: Secp256r1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256r1Point> {
+        self.replay(SyscallRequest::Secp256r1Add { p0, p1 }, remaining_gas)
+    }
+
+    fn secp256r1_mul(
+        &mut self,
+        p: Secp256r1Point,
+        m: U256,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Secp256r1Point> {
+        self.replay(SyscallRequest::Secp256r1Mul { p, m }, remaining_gas)
+    }
+
+    fn secp256r1_get_point_from_x(
+        &mut self,
+        x: U256,
+        y_parity: bool,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Option<Secp256r1Point>> {
+        self.replay(
+            SyscallRequest::Secp256r1GetPointFromX { x, y_parity },
+            remaining_gas,
+        )
+    }
+
+    fn secp256r1_get_xy(
+        &mut self,
+        p: Secp256r1Point,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(U256, U256)> {
+        self.replay(SyscallRequest::Secp256r1GetXy { p }, remaining_gas)
+    }
+
+    fn get_class_hash_at(
+        &mut self,
+        contract_address: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Felt> {
+        self.replay(
+            SyscallRequest::GetClassHashAt { contract_address },
+            remaining_gas,
+        )
+    }
+
+    fn sha256_process_block(
+        &mut self,
+        state: &mut [u32; 8],
+        block: &[u32; 16],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<()> {
+        *state = self.replay(
+            SyscallRequest::Sha256ProcessBlock {
+                state: *state,
+                block: *block,
+            },
+            remaining_gas,
+        )?;
+        Ok(())
+    }
+
+    #[cfg(feature = "with-cheatcode")]
+    fn cheatcode(&mut self, selector: Felt, input: &[Felt]) -> Vec<Felt> {
+        self.replay(
+            SyscallRequest::Cheatcode {
+                selector,
+                input: input.to_vec(),
+            },
+            &mut 0,
+        )
+        .unwrap_or_else(|error| {
+            // Cheatcodes can't fail, so the divergence aborts the execution directly.
+            super::handler::abort_execution(&error);
+            Vec::new()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{starknet::SyscallLayer, starknet_stub::StubSyscallHandler};
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut stub = StubSyscallHandler::default();
+        let mut recorder = RecordingMiddleware::new();
+
+        let mut handler = SyscallLayer::new(&mut stub, &mut recorder);
+        handler
+            .storage_write(0, 1.into(), 2.into(), &mut u64::MAX)
+            .unwrap();
+        let value = handler.storage_read(0, 1.into(), &mut u64::MAX).unwrap();
+        drop(handler);
+
+        let trace = recorder.into_trace();
+        assert_eq!(trace.entries.len(), 2);
+
+        let trace: SyscallTrace =
+            serde_json::from_str(&serde_json::to_string(&trace).unwrap()).unwrap();
+        let mut replayer = ReplayingSyscallHandler::new(trace.clone());
+        let mut handler = &mut replayer;
+
+        let mut remaining_gas = u64::MAX;
+        handler
+            .storage_write(0, 1.into(), 2.into(), &mut remaining_gas)
+            .unwrap();
+        assert_eq!(
+            u64::MAX - remaining_gas,
+            trace.entries[0].gas_before - trace.entries[0].gas_after
+        );
+        assert_eq!(
+            handler.storage_read(0, 1.into(), &mut u64::MAX).unwrap(),
+            value
+        );
+        assert!(replayer.is_finished());
+
+        // Syscalls with different arguments diverge.
+        let mut replayer = ReplayingSyscallHandler::new(trace);
+        let mut handler = &mut replayer;
+        assert!(handler
+            .storage_write(0, 1.into(), 3.into(), &mut u64::MAX)
+            .is_err());
+        assert_eq!(replayer.divergence().unwrap().position, 0);
+    }
+
+    #[cfg(feature = "with-cheatcode")]
+    #[test]
+    fn test_cheatcode_divergence_aborts() {
+        use crate::starknet::handler::replace_abort_error;
+
+        let mut replayer = ReplayingSyscallHandler::new(SyscallTrace::default());
+        let mut handler = &mut replayer;
+
+        let previous = replace_abort_error(None);
+        assert!(handler.cheatcode(1.into(), &[]).is_empty());
+        let abort_error = replace_abort_error(previous);
+
+        assert!(replayer.divergence().is_some());
+        assert!(matches!(abort_error, Some(SyscallError::Internal(_))));
+    }
+}
@@ -0,0 +1,259 @@
+//! Value-level description of the syscalls, used by the handler adapters that need to inspect,
+//! store or compare them.
+
+use super::{
+    ExecutionInfo, ExecutionInfoV2, Secp256k1Point, Secp256r1Point, SyscallError, SyscallResult,
+    U256,
+};
+use serde::{Deserialize, Serialize};
+use starknet_types_core::felt::Felt;
+
+/// A syscall and its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "syscall", rename_all = "snake_case")]
+pub enum SyscallRequest {
+    GetBlockHash {
+        block_number: u64,
+    },
+    GetExecutionInfo,
+    GetExecutionInfoV2,
+    Deploy {
+        class_hash: Felt,
+        contract_address_salt: Felt,
+        calldata: Vec<Felt>,
+        deploy_from_zero: bool,
+    },
+    ReplaceClass {
+        class_hash: Felt,
+    },
+    LibraryCall {
+        class_hash: Felt,
+        function_selector: Felt,
+        calldata: Vec<Felt>,
+    },
+    CallContract {
+        address: Felt,
+        entry_point_selector: Felt,
+        calldata: Vec<Felt>,
+    },
+    StorageRead {
+        address_domain: u32,
+        address: Felt,
+    },
+    StorageWrite {
+        address_domain: u32,
+        address: Felt,
+        value: Felt,
+    },
+    EmitEvent {
+        keys: Vec<Felt>,
+        data: Vec<Felt>,
+    },
+    SendMessageToL1 {
+        to_address: Felt,
+        payload: Vec<Felt>,
+    },
+    Keccak {
+        input: Vec<u64>,
+    },
+    Secp256k1New {
+        x: U256,
+        y: U256,
+    },
+    Secp256k1Add {
+        p0: Secp256k1Point,
+        p1: Secp256k1Point,
+    },
+    Secp256k1Mul {
+        p: Secp256k1Point,
+        m: U256,
+    },
+    Secp256k1GetPointFromX {
+        x: U256,
+        y_parity: bool,
+    },
+    Secp256k1GetXy {
+        p: Secp256k1Point,
+    },
+    Secp256r1New {
+        x: U256,
+        y: U256,
+    },
+    Secp256r1Add {
+        p0: Secp256r1Point,
+        p1: Secp256r1Point,
+    },
+    Secp256r1Mul {
+        p: Secp256r1Point,
+        m: U256,
+    },
+    Secp256r1GetPointFromX {
+        x: U256,
+        y_parity: bool,
+    },
+    Secp256r1GetXy {
+        p: Secp256r1Point,
+    },
+    Sha256ProcessBlock {
+        state: [u32; 8],
+        block: [u32; 16],
+    },
+    GetClassHashAt {
+        contract_address: Felt,
+    },
+    Cheatcode {
+        selector: Felt,
+        input: Vec<Felt>,
+    },
+}
+
+impl SyscallRequest {
+    /// The name of the syscall, as used by the Cairo corelib.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::GetBlockHash { .. } => "get_block_hash",
+            Self::GetExecutionInfo => "get_execution_info",
+            Self::GetExecutionInfoV2 => "get_execution_info_v2",
+            Self::Deploy { .. } => "deploy",
+            Self::ReplaceClass { .. } => "replace_class",
+            Self::LibraryCall { .. } => "library_call",
+            Self::CallContract { .. } => "call_contract",
+            Self::StorageRead { .. } => "storage_read",
+            Self::StorageWrite { .. } => "storage_write",
+            Self::EmitEvent { .. } => "emit_event",
+            Self::SendMessageToL1 { .. } => "send_message_to_l1",
+            Self::Keccak { .. } => "keccak",
+            Self::Secp256k1New { .. } => "secp256k1_new",
+            Self::Secp256k1Add { .. } => "secp256k1_add",
+            Self::Secp256k1Mul { .. } => "secp256k1_mul",
+            Self::Secp256k1GetPointFromX { .. } => "secp256k1_get_point_from_x",
+            Self::Secp256k1GetXy { .. } => "secp256k1_get_xy",
+            Self::Secp256r1New { .. } => "secp256r1_new",
+            Self::Secp256r1Add { .. } => "secp256r1_add",
+            Self::Secp256r1Mul { .. } => "secp256r1_mul",
+            Self::Secp256r1GetPointFromX { .. } => "secp256r1_get_point_from_x",
+            Self::Secp256r1GetXy { .. } => "secp256r1_get_xy",
+            Self::Sha256ProcessBlock { .. } => "sha256_process_block",
+            Self::GetClassHashAt { .. } => "get_class_hash_at",
+            Self::Cheatcode { .. } => "cheatcode",
+        }
+    }
+
+    /// Whether the syscall runs an entry point (`deploy`, `library_call` or `call_contract`).
+    pub const fn is_nested_call(&self) -> bool {
+        matches!(
+            self,
+            Self::Deploy { .. } | Self::LibraryCall { .. } | Self::CallContract { .. }
+        )
+    }
+}
+
+/// The value returned by a successful syscall.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SyscallResponse {
+    Unit,
+    Felt(Felt),
+    Felts(Vec<Felt>),
+    ExecutionInfo(ExecutionInfo),
+    ExecutionInfoV2(ExecutionInfoV2),
+    Deploy {
+        contract_address: Felt,
+        retdata: Vec<Felt>,
+    },
+    U256(U256),
+    U256Pair(U256, U256),
+    Secp256k1Point(Secp256k1Point),
+    OptionalSecp256k1Point(Option<Secp256k1Point>),
+    Secp256r1Point(Secp256r1Point),
+    OptionalSecp256r1Point(Option<Secp256r1Point>),
+    /// The updated state of `sha256_process_block`.
+    Sha256State([u32; 8]),
+}
+
+/// Conversion between the syscalls' return types and [`SyscallResponse`].
+pub(crate) trait ResponseValue: Sized {
+    fn into_response(self) -> SyscallResponse;
+    fn from_response(response: SyscallResponse) -> Option<Self>;
+
+    /// Convert the result of a syscall back into its return type.
+    fn from_result(result: SyscallResult<SyscallResponse>) -> SyscallResult<Self> {
+        result.and_then(|response| {
+            Self::from_response(response)
+                .ok_or_else(|| SyscallError::Internal("unexpected syscall response".to_string()))
+        })
+    }
+}
+
+macro_rules! impl_response_value {
+    ( $ty:ty, $variant:ident ) => {
+        impl ResponseValue for $ty {
+            fn into_response(self) -> SyscallResponse {
+                SyscallResponse::$variant(self)
+            }
+
+            fn from_response(response: SyscallResponse) -> Option<Self> {
+                match response {
+                    SyscallResponse::$variant(value) => Some(value),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_response_value!(Felt, Felt);
+impl_response_value!(Vec<Felt>, Felts);
+impl_response_value!(ExecutionInfo, ExecutionInfo);
+impl_response_value!(ExecutionInfoV2, ExecutionInfoV2);
+impl_response_value!(U256, U256);
+impl_response_value!(Secp256k1Point, Secp256k1Point);
+impl_response_value!(Option<Secp256k1Point>, OptionalSecp256k1Point);
+impl_response_value!(Secp256r1Point, Secp256r1Point);
+impl_response_value!(Option<Secp256r1Point>, OptionalSecp256r1Point);
+impl_response_value!([u32; 8], Sha256State);
+
+impl ResponseValue for () {
+    fn into_response(self) -> SyscallResponse {
+        SyscallResponse::Unit
+    }
+
+    fn from_response(response: SyscallResponse) -> Option<Self> {
+        match response {
+            SyscallResponse::Unit => Some(()),
+            _ => None,
+        }
+    }
+}
+
+impl ResponseValue for (Felt, Vec<Felt>) {
+    fn into_response(self) -> SyscallResponse {
+        SyscallResponse::Deploy {
+            contract_address: self.0,
+            retdata: self.1,
+        }
+    }
+
+    fn from_response(response: SyscallResponse) -> Option<Self> {
+        match response {
+            SyscallResponse::Deploy {
+                contract_address,
+                retdata,
+            } => Some((contract_address, retdata)),
+            _ => None,
+        }
+    }
+}
+
+impl ResponseValue for (U256, U256) {
+    fn into_response(self) -> SyscallResponse {
+        SyscallResponse::U256Pair(self.0, self.1)
+    }
+
+    fn from_response(response: SyscallResponse) -> Option<Self> {
+        match response {
+            SyscallResponse::U256Pair(x, y) => Some((x, y)),
+            _ => None,
+        }
+    }
+}
@@ -1,31 +1,247 @@
 //! A (somewhat) usable implementation of the starknet syscall handler trait.
 
+pub use crate::utils::{decode_felts_as_str, encode_str_as_felts};
 use std::{
     collections::{HashMap, VecDeque},
     fmt,
+    sync::Arc,
 };
 
-use crate::starknet::{
-    BlockInfo, ExecutionInfo, ExecutionInfoV2, Secp256k1Point, Secp256r1Point,
-    StarknetSyscallHandler, SyscallResult, TxInfo, TxV2Info, U256,
+use crate::{
+    error::Error,
+    execution_result::ContractExecutionResult,
+    executor::AotContractExecutor,
+    starknet::{
+        BlockInfo, ExecutionInfo, ExecutionInfoV2, NestedCallStart, NestedEntryPoint,
+        ResponseValue, Secp256k1Point, Secp256r1Point, StarknetSyscallHandler, SyscallError,
+        SyscallRequest, SyscallResponse, SyscallResult, TxInfo, TxV2Info,
+        ENTRY_POINT_INITIAL_BUDGET, U256,
+    },
 };
 use ark_ec::short_weierstrass::{Affine, Projective, SWCurveConfig};
 use ark_ff::{BigInt, PrimeField};
-use itertools::Itertools;
+use cairo_lang_starknet_classes::keccak::starknet_keccak;
 use num_bigint::BigUint;
 use num_traits::Zero;
-use starknet_types_core::felt::Felt;
+use starknet_types_core::{
+    felt::Felt,
+    hash::{Pedersen, StarkHash},
+};
 use tracing::instrument;
 
 /// A (somewhat) usable implementation of the starknet syscall handler trait.
 ///
-/// Currently gas is not deducted.
+/// It also works as a local multi-contract environment: classes can be declared with
+/// [`declare`](Self::declare) and then deployed (either through [`deploy_contract`](Self::deploy_contract)
+/// or the deploy syscall). Calls to deployed contracts and library calls to declared classes are
+/// dispatched to their executors, running them with the outermost handler: this same one, or a
+/// [`SyscallLayer`](crate::starknet::SyscallLayer) wrapping it (so that its middleware also sees
+/// the syscalls of the callees).
+///
+/// State changes (storage writes, events, messages, deployments and class replacements) are
+/// journaled: nested calls that fail have their changes reverted, like the sequencer does. The same
+/// can be achieved for top-level calls using [`checkpoint`](Self::checkpoint) and
+/// [`rollback`](Self::rollback). The cheatcodes that consume logs or spied events are journaled
+/// too, but changes made directly to the fields aren't.
+///
+/// Testing cheats (pranks, warps, rolls, mocked calls and event spying) are kept in
+/// [`cheats`](Self::cheats) and can be set either directly or through the cheatcode syscall.
+///
+/// Syscalls deduct their gas cost as specified in [`syscall_costs`](Self::syscall_costs), failing
+/// with `Out of gas` when there isn't enough gas left.
 #[derive(Debug, Clone)]
 pub struct StubSyscallHandler {
-    pub storage: HashMap<(u32, Felt), Felt>,
+    /// The storage of every contract, by contract address.
+    pub storage: HashMap<Felt, HashMap<(u32, Felt), Felt>>,
     pub events: Vec<StubEvent>,
     pub execution_info: ExecutionInfoV2,
     pub logs: HashMap<Felt, ContractLogs>,
+    /// The declared classes, by class hash.
+    pub classes: HashMap<Felt, Arc<AotContractExecutor>>,
+    /// The class hash of every deployed contract, by contract address.
+    pub contracts: HashMap<Felt, Felt>,
+    pub syscall_costs: SyscallCosts,
+    pub cheats: Cheats,
+    journal: Vec<JournalEntry>,
+    /// The nested calls in progress.
+    frames: Vec<CallFrame>,
+}
+
+/// Testing cheats applied by a [`StubSyscallHandler`].
+///
+/// Pranks, warps and rolls only affect the execution info seen by their target contract.
+#[derive(Debug, Clone, Default)]
+pub struct Cheats {
+    /// The caller address seen by a contract, by target contract address.
+    pub pranks: HashMap<Felt, Felt>,
+    /// The block timestamp seen by a contract, by target contract address.
+    pub warps: HashMap<Felt, u64>,
+    /// The block number seen by a contract, by target contract address.
+    pub rolls: HashMap<Felt, u64>,
+    /// The return values of mocked calls, by contract address and entry point selector.
+    pub mocked_calls: HashMap<(Felt, Felt), Vec<Felt>>,
+    /// The events emitted since event spying started, along with their emitter.
+    pub spied_events: Option<VecDeque<(Felt, StubEvent)>>,
+}
+
+/// The gas costs of every syscall.
+///
+/// The defaults match the costs charged by `cairo-lang-runner`'s syscall handler. The per-element
+/// costs are zero by default since the runner doesn't charge them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyscallCosts {
+    pub get_block_hash: u64,
+    pub get_execution_info: u64,
+    pub deploy: u64,
+    pub replace_class: u64,
+    pub library_call: u64,
+    pub call_contract: u64,
+    pub storage_read: u64,
+    pub storage_write: u64,
+    pub emit_event: u64,
+    pub send_message_to_l1: u64,
+    pub keccak: u64,
+    pub secp256k1_new: u64,
+    pub secp256k1_add: u64,
+    pub secp256k1_mul: u64,
+    pub secp256k1_get_point_from_x: u64,
+    pub secp256k1_get_xy: u64,
+    pub secp256r1_new: u64,
+    pub secp256r1_add: u64,
+    pub secp256r1_mul: u64,
+    pub secp256r1_get_point_from_x: u64,
+    pub secp256r1_get_xy: u64,
+    pub sha256_process_block: u64,
+    pub get_class_hash_at: u64,
+
+    /// Cost of every keccak permutation round (17 words of input).
+    pub keccak_round: u64,
+    /// Cost of every calldata element of `deploy`, `library_call` and `call_contract`.
+    pub calldata_element: u64,
+    /// Cost of every key and data element of `emit_event`.
+    pub event_element: u64,
+    /// Cost of every payload element of `send_message_to_l1`.
+    pub l2_to_l1_payload_element: u64,
+}
+
+impl Default for SyscallCosts {
+    fn default() -> Self {
+        const STEP: u64 = 100;
+        const RANGE_CHECK: u64 = 70;
+        const BITWISE: u64 = 594;
+
+        // The costs exclude the base amount that is pre-charged by the compiler.
+        const ENTRY_POINT: u64 = ENTRY_POINT_INITIAL_BUDGET + 500 * STEP;
+        const CALL_CONTRACT: u64 = 10 * STEP + ENTRY_POINT;
+
+        Self {
+            get_block_hash: 50 * STEP,
+            get_execution_info: 10 * STEP,
+            deploy: 200 * STEP + ENTRY_POINT,
+            replace_class: 50 * STEP,
+            library_call: CALL_CONTRACT,
+            call_contract: CALL_CONTRACT,
+            storage_read: 50 * STEP,
+            storage_write: 50 * STEP,
+            emit_event: 10 * STEP,
+            send_message_to_l1: 50 * STEP,
+            keccak: 0,
+            secp256k1_new: 340 * STEP + 36 * RANGE_CHECK,
+            secp256k1_add: 254 * STEP + 29 * RANGE_CHECK,
+            secp256k1_mul: 121810 * STEP + 10739 * RANGE_CHECK,
+            secp256k1_get_point_from_x: 260 * STEP + 29 * RANGE_CHECK,
+            secp256k1_get_xy: 24 * STEP + 9 * RANGE_CHECK,
+            secp256r1_new: 340 * STEP + 36 * RANGE_CHECK,
+            secp256r1_add: 254 * STEP + 29 * RANGE_CHECK,
+            secp256r1_mul: 121810 * STEP + 10739 * RANGE_CHECK,
+            secp256r1_get_point_from_x: 260 * STEP + 29 * RANGE_CHECK,
+            secp256r1_get_xy: 24 * STEP + 9 * RANGE_CHECK,
+            sha256_process_block: 1852 * STEP + 65 * RANGE_CHECK + 1115 * BITWISE,
+            get_class_hash_at: 50 * STEP,
+
+            keccak_round: 180000,
+            calldata_element: 0,
+            event_element: 0,
+            l2_to_l1_payload_element: 0,
+        }
+    }
+}
+
+/// Decode a cheatcode argument into a smaller integer, or `None` if it's out of range.
+#[cfg(feature = "with-cheatcode")]
+fn felt_to_int<T>(value: Felt) -> Option<T>
+where
+    T: TryFrom<BigUint>,
+{
+    value.to_biguint().try_into().ok()
+}
+
+/// Deduct the gas cost of a syscall, failing with `Out of gas` if there isn't enough gas left.
+fn deduct_gas(remaining_gas: &mut u64, amount: u64) -> SyscallResult<()> {
+    match remaining_gas.checked_sub(amount) {
+        Some(gas) => {
+            *remaining_gas = gas;
+            Ok(())
+        }
+        None => Err(SyscallError::OutOfGas),
+    }
+}
+
+/// A position in the journal of state changes of a [`StubSyscallHandler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Checkpoint(usize);
+
+/// A reversible state change.
+#[derive(Debug, Clone)]
+enum JournalEntry {
+    StorageWrite {
+        contract_address: Felt,
+        key: (u32, Felt),
+        previous_value: Option<Felt>,
+    },
+    Event {
+        contract_address: Felt,
+        spied: bool,
+    },
+    L2ToL1Message {
+        contract_address: Felt,
+    },
+    Deploy {
+        contract_address: Felt,
+    },
+    ReplaceClass {
+        contract_address: Felt,
+        previous_class_hash: Option<Felt>,
+    },
+    #[cfg(feature = "with-cheatcode")]
+    PopLog {
+        contract_address: Felt,
+        event: StubEvent,
+    },
+    #[cfg(feature = "with-cheatcode")]
+    PopL2ToL1Message {
+        contract_address: Felt,
+        message: L2ToL1Message,
+    },
+    #[cfg(feature = "with-cheatcode")]
+    SpyEvents {
+        previous_spied_events: Option<VecDeque<(Felt, StubEvent)>>,
+    },
+    #[cfg(feature = "with-cheatcode")]
+    PopSpiedEvent {
+        from_address: Felt,
+        event: StubEvent,
+    },
+}
+
+/// A nested call in progress, with what's needed to return to its caller.
+#[derive(Debug, Clone)]
+struct CallFrame {
+    /// The caller address, contract address and entry point selector of the caller.
+    previous_info: (Felt, Felt, Felt),
+    checkpoint: Checkpoint,
+    /// The address of the contract being deployed, when running its constructor.
+    deployed_address: Option<Felt>,
 }
 
 impl Default for StubSyscallHandler {
@@ -59,10 +275,463 @@ impl Default for StubSyscallHandler {
                 entry_point_selector: 4.into(),
             },
             logs: HashMap::new(),
+            classes: HashMap::new(),
+            contracts: HashMap::new(),
+            syscall_costs: SyscallCosts::default(),
+            cheats: Cheats::default(),
+            journal: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl StubSyscallHandler {
+    /// Declare a class, making it available to deploy and library calls.
+    pub fn declare(&mut self, class_hash: Felt, executor: AotContractExecutor) {
+        self.classes.insert(class_hash, Arc::new(executor));
+    }
+
+    /// Mark the current state, so that every change made after it can be reverted.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.journal.len())
+    }
+
+    /// Revert every state change made since the checkpoint.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        while self.journal.len() > checkpoint.0 {
+            let Some(entry) = self.journal.pop() else {
+                break;
+            };
+
+            match entry {
+                JournalEntry::StorageWrite {
+                    contract_address,
+                    key,
+                    previous_value,
+                } => {
+                    let storage = self.storage.entry(contract_address).or_default();
+                    match previous_value {
+                        Some(value) => storage.insert(key, value),
+                        None => storage.remove(&key),
+                    };
+                }
+                JournalEntry::Event {
+                    contract_address,
+                    spied,
+                } => {
+                    self.events.pop();
+                    if let Some(logs) = self.logs.get_mut(&contract_address) {
+                        logs.events.pop_back();
+                    }
+                    if spied {
+                        if let Some(spied_events) = &mut self.cheats.spied_events {
+                            spied_events.pop_back();
+                        }
+                    }
+                }
+                JournalEntry::L2ToL1Message { contract_address } => {
+                    if let Some(logs) = self.logs.get_mut(&contract_address) {
+                        logs.l2_to_l1_messages.pop_back();
+                    }
+                }
+                JournalEntry::Deploy { contract_address } => {
+                    self.contracts.remove(&contract_address);
+                }
+                JournalEntry::ReplaceClass {
+                    contract_address,
+                    previous_class_hash,
+                } => match previous_class_hash {
+                    Some(class_hash) => {
+                        self.contracts.insert(contract_address, class_hash);
+                    }
+                    None => {
+                        self.contracts.remove(&contract_address);
+                    }
+                },
+                #[cfg(feature = "with-cheatcode")]
+                JournalEntry::PopLog {
+                    contract_address,
+                    event,
+                } => {
+                    self.logs
+                        .entry(contract_address)
+                        .or_default()
+                        .events
+                        .push_front(event);
+                }
+                #[cfg(feature = "with-cheatcode")]
+                JournalEntry::PopL2ToL1Message {
+                    contract_address,
+                    message,
+                } => {
+                    self.logs
+                        .entry(contract_address)
+                        .or_default()
+                        .l2_to_l1_messages
+                        .push_front(message);
+                }
+                #[cfg(feature = "with-cheatcode")]
+                JournalEntry::SpyEvents {
+                    previous_spied_events,
+                } => {
+                    self.cheats.spied_events = previous_spied_events;
+                }
+                #[cfg(feature = "with-cheatcode")]
+                JournalEntry::PopSpiedEvent {
+                    from_address,
+                    event,
+                } => {
+                    if let Some(spied_events) = &mut self.cheats.spied_events {
+                        spied_events.push_front((from_address, event));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Deploy a contract of a declared class, running its constructor (if any).
+    ///
+    /// Returns the address of the new contract and the constructor's return values.
+    pub fn deploy_contract(
+        &mut self,
+        class_hash: Felt,
+        contract_address_salt: Felt,
+        calldata: &[Felt],
+        deployer_address: Felt,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<(Felt, Vec<Felt>)> {
+        let start = self.begin_deploy(
+            class_hash,
+            contract_address_salt,
+            calldata,
+            deployer_address,
+        );
+        ResponseValue::from_result(self.run_nested_call(start, remaining_gas))
+    }
+
+    /// Call an entry point of a deployed contract, as if called by the current contract.
+    pub fn call_contract_entry_point(
+        &mut self,
+        address: Felt,
+        entry_point_selector: Felt,
+        calldata: &[Felt],
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<Vec<Felt>> {
+        let start = self.begin_call_contract(address, entry_point_selector, calldata);
+        ResponseValue::from_result(self.run_nested_call(start, remaining_gas))
+    }
+
+    /// Start the deployment of a contract. A contract without a constructor is deployed right
+    /// away.
+    fn begin_deploy(
+        &mut self,
+        class_hash: Felt,
+        contract_address_salt: Felt,
+        calldata: &[Felt],
+        deployer_address: Felt,
+    ) -> SyscallResult<NestedCallStart> {
+        if !self.classes.contains_key(&class_hash) {
+            return Err(SyscallError::ClassHashNotFound);
+        }
+
+        let contract_address = calculate_contract_address(
+            contract_address_salt,
+            class_hash,
+            calldata,
+            deployer_address,
+        );
+        if self.contracts.contains_key(&contract_address) {
+            return Err(SyscallError::ContractAddressUnavailable);
+        }
+
+        // Contracts without a constructor may only be deployed without calldata.
+        let constructor_selector = Felt::from(starknet_keccak(b"constructor"));
+        if !self.classes[&class_hash].has_entry_point(constructor_selector) {
+            if !calldata.is_empty() {
+                return Err(SyscallError::InvalidCalldataLength);
+            }
+
+            self.contracts.insert(contract_address, class_hash);
+            self.journal.push(JournalEntry::Deploy { contract_address });
+            return Ok(NestedCallStart::Finished(Ok(SyscallResponse::Deploy {
+                contract_address,
+                retdata: Vec::new(),
+            })));
+        }
+
+        // The deployment is made after the frame's checkpoint, so that a failing constructor
+        // reverts it along with its own changes.
+        let entry_point = self.begin_entry_point(
+            class_hash,
+            constructor_selector,
+            calldata,
+            deployer_address,
+            contract_address,
+            Some(contract_address),
+        )?;
+        self.contracts.insert(contract_address, class_hash);
+        self.journal.push(JournalEntry::Deploy { contract_address });
+
+        Ok(NestedCallStart::Run(entry_point))
+    }
+
+    /// Start a call to a deployed contract. Mocked calls finish right away.
+    fn begin_call_contract(
+        &mut self,
+        address: Felt,
+        entry_point_selector: Felt,
+        calldata: &[Felt],
+    ) -> SyscallResult<NestedCallStart> {
+        if let Some(retdata) = self
+            .cheats
+            .mocked_calls
+            .get(&(address, entry_point_selector))
+        {
+            return Ok(NestedCallStart::Finished(Ok(SyscallResponse::Felts(
+                retdata.clone(),
+            ))));
+        }
+
+        let class_hash = *self
+            .contracts
+            .get(&address)
+            .ok_or(SyscallError::ContractNotDeployed)?;
+        let caller_address = self.execution_info.contract_address;
+
+        self.begin_entry_point(
+            class_hash,
+            entry_point_selector,
+            calldata,
+            caller_address,
+            address,
+            None,
+        )
+        .map(NestedCallStart::Run)
+    }
+
+    /// Switch to the context of the given contract to run an entry point of a declared class,
+    /// until [`end_entry_point`](Self::end_entry_point) restores the current one.
+    fn begin_entry_point(
+        &mut self,
+        class_hash: Felt,
+        entry_point_selector: Felt,
+        calldata: &[Felt],
+        caller_address: Felt,
+        contract_address: Felt,
+        deployed_address: Option<Felt>,
+    ) -> SyscallResult<NestedEntryPoint> {
+        let executor = self
+            .classes
+            .get(&class_hash)
+            .cloned()
+            .ok_or(SyscallError::ClassHashNotFound)?;
+
+        let frame = CallFrame {
+            previous_info: (
+                self.execution_info.caller_address,
+                self.execution_info.contract_address,
+                self.execution_info.entry_point_selector,
+            ),
+            checkpoint: self.checkpoint(),
+            deployed_address,
+        };
+        self.frames.push(frame);
+        self.execution_info.caller_address = caller_address;
+        self.execution_info.contract_address = contract_address;
+        self.execution_info.entry_point_selector = entry_point_selector;
+
+        Ok(NestedEntryPoint {
+            executor,
+            selector: entry_point_selector,
+            calldata: calldata.to_vec(),
+        })
+    }
+
+    /// Finish the innermost entry point, restoring the execution info of its caller. Every state
+    /// change is reverted if the entry point failed.
+    fn end_entry_point(
+        &mut self,
+        result: Result<ContractExecutionResult, Error>,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<SyscallResponse> {
+        let frame = self
+            .frames
+            .pop()
+            .ok_or_else(|| SyscallError::Internal("no nested call in progress".to_string()))?;
+        (
+            self.execution_info.caller_address,
+            self.execution_info.contract_address,
+            self.execution_info.entry_point_selector,
+        ) = frame.previous_info;
+
+        let result = match result {
+            Ok(ContractExecutionResult {
+                remaining_gas: gas,
+                failure_flag,
+                return_values,
+                ..
+            }) => {
+                *remaining_gas = gas;
+                match failure_flag {
+                    false => Ok(return_values),
+                    true => Err(SyscallError::Revert(return_values)),
+                }
+            }
+            Err(Error::SelectorNotFound) => Err(SyscallError::EntryPointNotFound),
+            // Errors of the nested execution abort, keeping the reason of nested aborts.
+            Err(Error::SyscallHandlerAborted(reason)) => Err(SyscallError::Abort(reason)),
+            Err(Error::SyscallHandlerFailed(msg)) => Err(SyscallError::Internal(msg)),
+            Err(e @ (Error::Cancelled | Error::TimedOut)) => {
+                Err(SyscallError::Abort(e.to_string()))
+            }
+            Err(e) => Err(SyscallError::Internal(e.to_string())),
+        };
+
+        match (result, frame.deployed_address) {
+            (Ok(retdata), Some(contract_address)) => Ok(SyscallResponse::Deploy {
+                contract_address,
+                retdata,
+            }),
+            (Ok(retdata), None) => Ok(SyscallResponse::Felts(retdata)),
+            (Err(e), _) => {
+                self.rollback(frame.checkpoint);
+                Err(e)
+            }
+        }
+    }
+
+    /// Run a started nested call with this same handler.
+    fn run_nested_call(
+        &mut self,
+        start: SyscallResult<NestedCallStart>,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<SyscallResponse> {
+        match start? {
+            NestedCallStart::Finished(result) => result,
+            NestedCallStart::Run(entry_point) => {
+                let result = entry_point.run(
+                    &mut *self,
+                    remaining_gas.saturating_add(ENTRY_POINT_INITIAL_BUDGET),
+                );
+                self.end_entry_point(result, remaining_gas)
+            }
+        }
+    }
+
+    /// Deduct the gas cost of a `deploy`, `library_call` or `call_contract` syscall and start it.
+    fn begin_nested_syscall(
+        &mut self,
+        request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<NestedCallStart> {
+        match request {
+            SyscallRequest::Deploy {
+                class_hash,
+                contract_address_salt,
+                calldata,
+                deploy_from_zero,
+            } => {
+                deduct_gas(
+                    remaining_gas,
+                    self.syscall_costs.deploy
+                        + self.syscall_costs.calldata_element * calldata.len() as u64,
+                )?;
+                let deployer_address = match *deploy_from_zero {
+                    true => Felt::ZERO,
+                    false => self.execution_info.contract_address,
+                };
+
+                self.begin_deploy(
+                    *class_hash,
+                    *contract_address_salt,
+                    calldata,
+                    deployer_address,
+                )
+            }
+            SyscallRequest::LibraryCall {
+                class_hash,
+                function_selector,
+                calldata,
+            } => {
+                deduct_gas(
+                    remaining_gas,
+                    self.syscall_costs.library_call
+                        + self.syscall_costs.calldata_element * calldata.len() as u64,
+                )?;
+                let caller_address = self.execution_info.caller_address;
+                let contract_address = self.execution_info.contract_address;
+
+                self.begin_entry_point(
+                    *class_hash,
+                    *function_selector,
+                    calldata,
+                    caller_address,
+                    contract_address,
+                    None,
+                )
+                .map(NestedCallStart::Run)
+            }
+            SyscallRequest::CallContract {
+                address,
+                entry_point_selector,
+                calldata,
+            } => {
+                deduct_gas(
+                    remaining_gas,
+                    self.syscall_costs.call_contract
+                        + self.syscall_costs.calldata_element * calldata.len() as u64,
+                )?;
+
+                self.begin_call_contract(*address, *entry_point_selector, calldata)
+            }
+            _ => Err(SyscallError::Internal(format!(
+                "`{}` is not a nested call",
+                request.name()
+            ))),
+        }
+    }
+}
+
+impl StubSyscallHandler {
+    /// The execution info as seen by the current contract, with its cheats applied.
+    pub fn cheated_execution_info(&self) -> ExecutionInfoV2 {
+        let mut execution_info = self.execution_info.clone();
+        let contract_address = execution_info.contract_address;
+
+        if let Some(caller_address) = self.cheats.pranks.get(&contract_address) {
+            execution_info.caller_address = *caller_address;
+        }
+        if let Some(block_timestamp) = self.cheats.warps.get(&contract_address) {
+            execution_info.block_info.block_timestamp = *block_timestamp;
         }
+        if let Some(block_number) = self.cheats.rolls.get(&contract_address) {
+            execution_info.block_info.block_number = *block_number;
+        }
+
+        execution_info
     }
 }
 
+/// Compute the address of a contract the same way Starknet does.
+pub fn calculate_contract_address(
+    contract_address_salt: Felt,
+    class_hash: Felt,
+    constructor_calldata: &[Felt],
+    deployer_address: Felt,
+) -> Felt {
+    let hash = Pedersen::hash_array(&[
+        Felt::from_bytes_be_slice(b"STARKNET_CONTRACT_ADDRESS"),
+        deployer_address,
+        contract_address_salt,
+        class_hash,
+        Pedersen::hash_array(constructor_calldata),
+    ]);
+
+    // Addresses are in the range [0, 2**251 - 256).
+    let upper_bound = (BigUint::from(1u8) << 251u32) - BigUint::from(256u32);
+    Felt::from(hash.to_biguint() % upper_bound)
+}
+
 /// Event emitted by the emit_event syscall.
 #[derive(Debug, Clone)]
 pub struct StubEvent {
@@ -143,42 +812,6 @@ pub fn big4int_to_u256(b_int: BigInt<4>) -> U256 {
     U256 { lo, hi }
 }
 
-pub fn encode_str_as_felts(msg: &str) -> Vec<Felt> {
-    const CHUNK_SIZE: usize = 32;
-
-    let data = msg.as_bytes().chunks(CHUNK_SIZE - 1);
-    let mut encoding = vec![Felt::default(); data.len()];
-    for (i, data_chunk) in data.enumerate() {
-        let mut chunk = [0_u8; CHUNK_SIZE];
-        chunk[1..data_chunk.len() + 1].copy_from_slice(data_chunk);
-        encoding[i] = Felt::from_bytes_be(&chunk);
-    }
-    encoding
-}
-
-pub fn decode_felts_as_str(encoding: &[Felt]) -> String {
-    let bytes_err: Vec<_> = encoding
-        .iter()
-        .flat_map(|felt| felt.to_bytes_be()[1..32].to_vec())
-        .collect();
-
-    match String::from_utf8(bytes_err) {
-        Ok(s) => s.trim_matches('\0').to_owned(),
-        Err(_) => {
-            let err_msgs = encoding
-                .iter()
-                .map(
-                    |felt| match String::from_utf8(felt.to_bytes_be()[1..32].to_vec()) {
-                        Ok(s) => format!("{} ({})", s.trim_matches('\0'), felt),
-                        Err(_) => felt.to_string(),
-                    },
-                )
-                .join(", ");
-            format!("[{}]", err_msgs)
-        }
-    }
-}
-
 impl<Curve: SWCurveConfig> Secp256Point<Curve>
 where
     Curve::BaseField: PrimeField, // constraint for get_point_by_id
@@ -188,18 +821,13 @@ where
     // - Err if either x or y is outside of the modulus
     // - Ok(None) if (x,y) are within the modules but not on the curve
     // - Ok(Some(Point)) if (x,y) are on the curve
-    fn new(x: U256, y: U256) -> Result<Option<Self>, Vec<Felt>> {
+    fn new(x: U256, y: U256) -> SyscallResult<Option<Self>> {
         let x = u256_to_biguint(x);
         let y = u256_to_biguint(y);
         let modulos = Curve::BaseField::MODULUS.into();
 
         if x >= modulos || y >= modulos {
-            let error = Felt::from_hex(
-                "0x00000000000000000000000000000000496e76616c696420617267756d656e74",
-            ) // INVALID_ARGUMENT
-            .map_err(|err| encode_str_as_felts(&err.to_string()))?;
-
-            return Err(vec![error]);
+            return Err(SyscallError::InvalidArgument);
         }
 
         Ok(maybe_affine(x.into(), y.into()))
@@ -215,17 +843,12 @@ where
         Secp256Point(result.into())
     }
 
-    fn get_point_from_x(x: U256, y_parity: bool) -> Result<Option<Self>, Vec<Felt>> {
+    fn get_point_from_x(x: U256, y_parity: bool) -> SyscallResult<Option<Self>> {
         let modulos = Curve::BaseField::MODULUS.into();
         let x = u256_to_biguint(x);
 
         if x >= modulos {
-            let error = Felt::from_hex(
-                "0x00000000000000000000000000000000496e76616c696420617267756d656e74",
-            ) // INVALID_ARGUMENT
-            .map_err(|err| encode_str_as_felts(&err.to_string()))?;
-
-            return Err(vec![error]);
+            return Err(SyscallError::InvalidArgument);
         }
 
         let x = x.into();
@@ -271,6 +894,7 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<Felt> {
         tracing::debug!("called");
+        deduct_gas(remaining_gas, self.syscall_costs.get_block_hash)?;
         Ok(block_number.into())
     }
 
@@ -280,20 +904,22 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<crate::starknet::ExecutionInfo> {
         tracing::debug!("called");
+        deduct_gas(remaining_gas, self.syscall_costs.get_execution_info)?;
+        let execution_info = self.cheated_execution_info();
         Ok(ExecutionInfo {
-            block_info: self.execution_info.block_info,
+            block_info: execution_info.block_info,
             tx_info: TxInfo {
-                version: self.execution_info.tx_info.version,
-                account_contract_address: self.execution_info.tx_info.account_contract_address,
-                max_fee: self.execution_info.tx_info.max_fee,
-                signature: self.execution_info.tx_info.signature.clone(),
-                transaction_hash: self.execution_info.tx_info.transaction_hash,
-                chain_id: self.execution_info.tx_info.chain_id,
-                nonce: self.execution_info.tx_info.nonce,
+                version: execution_info.tx_info.version,
+                account_contract_address: execution_info.tx_info.account_contract_address,
+                max_fee: execution_info.tx_info.max_fee,
+                signature: execution_info.tx_info.signature,
+                transaction_hash: execution_info.tx_info.transaction_hash,
+                chain_id: execution_info.tx_info.chain_id,
+                nonce: execution_info.tx_info.nonce,
             },
-            caller_address: self.execution_info.caller_address,
-            contract_address: self.execution_info.contract_address,
-            entry_point_selector: self.execution_info.entry_point_selector,
+            caller_address: execution_info.caller_address,
+            contract_address: execution_info.contract_address,
+            entry_point_selector: execution_info.entry_point_selector,
         })
     }
 
@@ -303,7 +929,8 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<crate::starknet::ExecutionInfoV2> {
         tracing::debug!("called");
-        Ok(self.execution_info.clone())
+        deduct_gas(remaining_gas, self.syscall_costs.get_execution_info)?;
+        Ok(self.cheated_execution_info())
     }
 
     #[instrument(skip(self))]
@@ -316,7 +943,16 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<(Felt, Vec<Felt>)> {
         tracing::debug!("called");
-        todo!()
+        let start = self.begin_nested_syscall(
+            &SyscallRequest::Deploy {
+                class_hash,
+                contract_address_salt,
+                calldata: calldata.to_vec(),
+                deploy_from_zero,
+            },
+            remaining_gas,
+        );
+        ResponseValue::from_result(self.run_nested_call(start, remaining_gas))
     }
 
     #[instrument(skip(self))]
@@ -326,7 +962,17 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<()> {
         tracing::debug!("called");
-        tracing::warn!("unimplemented");
+        deduct_gas(remaining_gas, self.syscall_costs.replace_class)?;
+        if !self.classes.contains_key(&class_hash) {
+            return Err(SyscallError::ClassHashNotFound);
+        }
+
+        let contract_address = self.execution_info.contract_address;
+        let previous_class_hash = self.contracts.insert(contract_address, class_hash);
+        self.journal.push(JournalEntry::ReplaceClass {
+            contract_address,
+            previous_class_hash,
+        });
         Ok(())
     }
 
@@ -339,8 +985,15 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<Vec<Felt>> {
         tracing::debug!("called");
-        tracing::warn!("unimplemented");
-        Ok(vec![])
+        let start = self.begin_nested_syscall(
+            &SyscallRequest::LibraryCall {
+                class_hash,
+                function_selector,
+                calldata: calldata.to_vec(),
+            },
+            remaining_gas,
+        );
+        ResponseValue::from_result(self.run_nested_call(start, remaining_gas))
     }
 
     #[instrument(skip(self))]
@@ -352,22 +1005,32 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<Vec<Felt>> {
         tracing::debug!("called");
-        tracing::warn!("unimplemented");
-        Ok(vec![])
+        let start = self.begin_nested_syscall(
+            &SyscallRequest::CallContract {
+                address,
+                entry_point_selector,
+                calldata: calldata.to_vec(),
+            },
+            remaining_gas,
+        );
+        ResponseValue::from_result(self.run_nested_call(start, remaining_gas))
     }
 
     fn storage_read(
         &mut self,
         address_domain: u32,
         address: Felt,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<Felt> {
         tracing::debug!("called");
-        if let Some(value) = self.storage.get(&(address_domain, address)) {
-            Ok(*value)
-        } else {
-            Err(vec![Felt::from_bytes_be_slice(b"address not found")])
-        }
+        deduct_gas(remaining_gas, self.syscall_costs.storage_read)?;
+        // Unset storage slots are zero.
+        Ok(self
+            .storage
+            .get(&self.execution_info.contract_address)
+            .and_then(|storage| storage.get(&(address_domain, address)))
+            .copied()
+            .unwrap_or_default())
     }
 
     #[instrument(skip(self))]
@@ -379,7 +1042,19 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<()> {
         tracing::debug!("called");
-        self.storage.insert((address_domain, address), value);
+        deduct_gas(remaining_gas, self.syscall_costs.storage_write)?;
+        let contract_address = self.execution_info.contract_address;
+        let key = (address_domain, address);
+        let previous_value = self
+            .storage
+            .entry(contract_address)
+            .or_default()
+            .insert(key, value);
+        self.journal.push(JournalEntry::StorageWrite {
+            contract_address,
+            key,
+            previous_value,
+        });
         Ok(())
     }
 
@@ -391,10 +1066,33 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<()> {
         tracing::debug!("called");
-        tracing::warn!("unimplemented but stored");
-        self.events.push(StubEvent {
+        deduct_gas(
+            remaining_gas,
+            self.syscall_costs.emit_event
+                + self.syscall_costs.event_element * (keys.len() + data.len()) as u64,
+        )?;
+        let contract_address = self.execution_info.contract_address;
+        let event = StubEvent {
             keys: keys.to_vec(),
             data: data.to_vec(),
+        };
+
+        let spied = match &mut self.cheats.spied_events {
+            Some(spied_events) => {
+                spied_events.push_back((contract_address, event.clone()));
+                true
+            }
+            None => false,
+        };
+        self.logs
+            .entry(contract_address)
+            .or_default()
+            .events
+            .push_back(event.clone());
+        self.events.push(event);
+        self.journal.push(JournalEntry::Event {
+            contract_address,
+            spied,
         });
         Ok(())
     }
@@ -407,24 +1105,40 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         remaining_gas: &mut u64,
     ) -> crate::starknet::SyscallResult<()> {
         tracing::debug!("called");
-        tracing::warn!("unimplemented");
+        deduct_gas(
+            remaining_gas,
+            self.syscall_costs.send_message_to_l1
+                + self.syscall_costs.l2_to_l1_payload_element * payload.len() as u64,
+        )?;
+        let contract_address = self.execution_info.contract_address;
+        self.logs
+            .entry(contract_address)
+            .or_default()
+            .l2_to_l1_messages
+            .push_back((to_address, payload.to_vec()));
+        self.journal
+            .push(JournalEntry::L2ToL1Message { contract_address });
         Ok(())
     }
 
     #[instrument(skip(self))]
-    fn keccak(&mut self, input: &[u64], gas: &mut u64) -> SyscallResult<U256> {
+    fn keccak(&mut self, input: &[u64], remaining_gas: &mut u64) -> SyscallResult<U256> {
         const KECCAK_FULL_RATE_IN_WORDS: usize = 17;
+        deduct_gas(remaining_gas, self.syscall_costs.keccak)?;
 
         let length = input.len();
-        let (_n_rounds, remainder) = num_integer::div_rem(length, KECCAK_FULL_RATE_IN_WORDS);
+        let (n_rounds, remainder) = num_integer::div_rem(length, KECCAK_FULL_RATE_IN_WORDS);
 
         if remainder != 0 {
             // In VM this error is wrapped into `SyscallExecutionError::SyscallError`
-            return Err(vec![Felt::from_hex(
-                "0x000000000000000000000000496e76616c696420696e707574206c656e677468",
-            )
-            .unwrap()]);
+            return Err(SyscallError::Custom(vec![Felt::from_bytes_be_slice(
+                b"Invalid input length",
+            )]));
         }
+        deduct_gas(
+            remaining_gas,
+            self.syscall_costs.keccak_round * n_rounds as u64,
+        )?;
 
         let mut state = [0u64; 25];
         for chunk in input.chunks(KECCAK_FULL_RATE_IN_WORDS) {
@@ -445,8 +1159,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         x: U256,
         y: U256,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Option<Secp256k1Point>> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256k1_new)?;
         Secp256Point::new(x, y).map(|op| op.map(|p| p.into()))
     }
 
@@ -455,9 +1170,10 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         p0: Secp256k1Point,
         p1: Secp256k1Point,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Secp256k1Point> {
         tracing::debug!("called");
+        deduct_gas(remaining_gas, self.syscall_costs.secp256k1_add)?;
 
         Ok(Secp256Point::add(p0.into(), p1.into()).into())
     }
@@ -467,8 +1183,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         p: Secp256k1Point,
         m: U256,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Secp256k1Point> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256k1_mul)?;
         Ok(Secp256Point::mul(p.into(), m).into())
     }
 
@@ -477,8 +1194,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         x: U256,
         y_parity: bool,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Option<Secp256k1Point>> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256k1_get_point_from_x)?;
         Secp256Point::get_point_from_x(x, y_parity).map(|op| op.map(|p| p.into()))
     }
 
@@ -486,8 +1204,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
     fn secp256k1_get_xy(
         &mut self,
         p: Secp256k1Point,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<(U256, U256)> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256k1_get_xy)?;
         Ok((p.x, p.y))
     }
 
@@ -496,8 +1215,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         x: U256,
         y: U256,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Option<Secp256r1Point>> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256r1_new)?;
         Secp256Point::new(x, y).map(|op| op.map(|p| p.into()))
     }
 
@@ -506,8 +1226,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         p0: Secp256r1Point,
         p1: Secp256r1Point,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Secp256r1Point> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256r1_add)?;
         Ok(Secp256Point::add(p0.into(), p1.into()).into())
     }
 
@@ -516,8 +1237,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         p: Secp256r1Point,
         m: U256,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Secp256r1Point> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256r1_mul)?;
         Ok(Secp256Point::mul(p.into(), m).into())
     }
 
@@ -526,8 +1248,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
         &mut self,
         x: U256,
         y_parity: bool,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Option<Secp256r1Point>> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256r1_get_point_from_x)?;
         Secp256Point::get_point_from_x(x, y_parity).map(|op| op.map(|p| p.into()))
     }
 
@@ -535,8 +1258,9 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
     fn secp256r1_get_xy(
         &mut self,
         p: Secp256r1Point,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<(U256, U256)> {
+        deduct_gas(remaining_gas, self.syscall_costs.secp256r1_get_xy)?;
         Ok((p.x, p.y))
     }
 
@@ -551,93 +1275,179 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
             Err(_) => return Vec::new(),
         };
 
-        match selector {
-            "set_sequencer_address" => {
-                self.execution_info.block_info.sequencer_address = input[0];
-                vec![]
+        // Unknown and malformed cheatcodes (with the wrong number of arguments or out of range
+        // values) are ignored, returning an empty result.
+        let result = match (selector, input) {
+            ("set_sequencer_address", &[value]) => {
+                self.execution_info.block_info.sequencer_address = value;
+                Some(vec![])
             }
-            "set_caller_address" => {
-                self.execution_info.caller_address = input[0];
-                vec![]
+            ("set_caller_address", &[value]) => {
+                self.execution_info.caller_address = value;
+                Some(vec![])
             }
-            "set_contract_address" => {
-                self.execution_info.contract_address = input[0];
-                vec![]
+            ("set_contract_address", &[value]) => {
+                self.execution_info.contract_address = value;
+                Some(vec![])
             }
-            "set_account_contract_address" => {
-                self.execution_info.tx_info.account_contract_address = input[0];
-                vec![]
+            ("set_account_contract_address", &[value]) => {
+                self.execution_info.tx_info.account_contract_address = value;
+                Some(vec![])
             }
-            "set_transaction_hash" => {
-                self.execution_info.tx_info.transaction_hash = input[0];
-                vec![]
+            ("set_transaction_hash", &[value]) => {
+                self.execution_info.tx_info.transaction_hash = value;
+                Some(vec![])
             }
-            "set_nonce" => {
-                self.execution_info.tx_info.nonce = input[0];
-                vec![]
+            ("set_nonce", &[value]) => {
+                self.execution_info.tx_info.nonce = value;
+                Some(vec![])
             }
-            "set_version" => {
-                self.execution_info.tx_info.version = input[0];
-                vec![]
+            ("set_version", &[value]) => {
+                self.execution_info.tx_info.version = value;
+                Some(vec![])
             }
-            "set_chain_id" => {
-                self.execution_info.tx_info.chain_id = input[0];
-                vec![]
+            ("set_chain_id", &[value]) => {
+                self.execution_info.tx_info.chain_id = value;
+                Some(vec![])
             }
-            "set_max_fee" => {
-                let max_fee = input[0].to_biguint().try_into().unwrap();
+            ("set_max_fee", &[value]) => felt_to_int(value).map(|max_fee| {
                 self.execution_info.tx_info.max_fee = max_fee;
                 vec![]
-            }
-            "set_block_number" => {
-                let block_number = input[0].to_biguint().try_into().unwrap();
+            }),
+            ("set_block_number", &[value]) => felt_to_int(value).map(|block_number| {
                 self.execution_info.block_info.block_number = block_number;
                 vec![]
-            }
-            "set_block_timestamp" => {
-                let block_timestamp = input[0].to_biguint().try_into().unwrap();
+            }),
+            ("set_block_timestamp", &[value]) => felt_to_int(value).map(|block_timestamp| {
                 self.execution_info.block_info.block_timestamp = block_timestamp;
                 vec![]
+            }),
+            ("set_signature", signature) => {
+                self.execution_info.tx_info.signature = signature.to_vec();
+                Some(vec![])
             }
-            "set_signature" => {
-                self.execution_info.tx_info.signature = input.to_vec();
-                vec![]
+            ("pop_log", &[contract_address]) => Some(
+                self.logs
+                    .get_mut(&contract_address)
+                    .and_then(|logs| logs.events.pop_front())
+                    .map(|mut log| {
+                        self.journal.push(JournalEntry::PopLog {
+                            contract_address,
+                            event: log.clone(),
+                        });
+                        let mut serialized_log = Vec::new();
+                        serialized_log.push(log.keys.len().into());
+                        serialized_log.append(&mut log.keys);
+                        serialized_log.push(log.data.len().into());
+                        serialized_log.append(&mut log.data);
+                        serialized_log
+                    })
+                    .unwrap_or_default(),
+            ),
+            ("pop_l2_to_l1_message", &[contract_address]) => Some(
+                self.logs
+                    .get_mut(&contract_address)
+                    .and_then(|logs| logs.l2_to_l1_messages.pop_front())
+                    .map(|mut log| {
+                        self.journal.push(JournalEntry::PopL2ToL1Message {
+                            contract_address,
+                            message: log.clone(),
+                        });
+                        let mut serialized_log = Vec::new();
+                        serialized_log.push(log.0);
+                        serialized_log.push(log.1.len().into());
+                        serialized_log.append(&mut log.1);
+                        serialized_log
+                    })
+                    .unwrap_or_default(),
+            ),
+            ("start_prank", &[contract_address, caller_address]) => {
+                self.cheats.pranks.insert(contract_address, caller_address);
+                Some(vec![])
             }
-            "pop_log" => self
-                .logs
-                .get_mut(&input[0])
-                .and_then(|logs| logs.events.pop_front())
-                .map(|mut log| {
-                    let mut serialized_log = Vec::new();
-                    serialized_log.push(log.keys.len().into());
-                    serialized_log.append(&mut log.keys);
-                    serialized_log.push(log.data.len().into());
-                    serialized_log.append(&mut log.data);
-                    serialized_log
-                })
-                .unwrap_or_default(),
-            "pop_l2_to_l1_message" => self
-                .logs
-                .get_mut(&input[0])
-                .and_then(|logs| logs.l2_to_l1_messages.pop_front())
-                .map(|mut log| {
-                    let mut serialized_log = Vec::new();
-                    serialized_log.push(log.0);
-                    serialized_log.push(log.1.len().into());
-                    serialized_log.append(&mut log.1);
-                    serialized_log
+            ("stop_prank", [contract_address]) => {
+                self.cheats.pranks.remove(contract_address);
+                Some(vec![])
+            }
+            ("start_warp", &[contract_address, block_timestamp]) => felt_to_int(block_timestamp)
+                .map(|block_timestamp| {
+                    self.cheats.warps.insert(contract_address, block_timestamp);
+                    vec![]
+                }),
+            ("stop_warp", [contract_address]) => {
+                self.cheats.warps.remove(contract_address);
+                Some(vec![])
+            }
+            ("start_roll", &[contract_address, block_number]) => {
+                felt_to_int(block_number).map(|block_number| {
+                    self.cheats.rolls.insert(contract_address, block_number);
+                    vec![]
                 })
-                .unwrap_or_default(),
-            _ => vec![],
-        }
+            }
+            ("stop_roll", [contract_address]) => {
+                self.cheats.rolls.remove(contract_address);
+                Some(vec![])
+            }
+            // Input: contract address, selector and the serialized return values.
+            ("mock_call", &[contract_address, selector, retdata_len, ref retdata @ ..]) => {
+                felt_to_int::<usize>(retdata_len)
+                    .and_then(|retdata_len| retdata.get(..retdata_len))
+                    .map(|retdata| {
+                        self.cheats
+                            .mocked_calls
+                            .insert((contract_address, selector), retdata.to_vec());
+                        vec![]
+                    })
+            }
+            ("stop_mock_call", &[contract_address, selector]) => {
+                self.cheats
+                    .mocked_calls
+                    .remove(&(contract_address, selector));
+                Some(vec![])
+            }
+            ("spy_events", []) => {
+                let previous_spied_events = self.cheats.spied_events.replace(VecDeque::new());
+                self.journal.push(JournalEntry::SpyEvents {
+                    previous_spied_events,
+                });
+                Some(vec![])
+            }
+            ("pop_spied_event", []) => Some(
+                self.cheats
+                    .spied_events
+                    .as_mut()
+                    .and_then(VecDeque::pop_front)
+                    .map(|(from_address, mut event)| {
+                        self.journal.push(JournalEntry::PopSpiedEvent {
+                            from_address,
+                            event: event.clone(),
+                        });
+                        let mut serialized_event = vec![from_address];
+                        serialized_event.push(event.keys.len().into());
+                        serialized_event.append(&mut event.keys);
+                        serialized_event.push(event.data.len().into());
+                        serialized_event.append(&mut event.data);
+                        serialized_event
+                    })
+                    .unwrap_or_default(),
+            ),
+            _ => None,
+        };
+
+        result.unwrap_or_else(|| {
+            tracing::warn!("ignoring unknown or malformed cheatcode {selector:?} ({input:?})");
+            vec![]
+        })
     }
 
     fn sha256_process_block(
         &mut self,
         state: &mut [u32; 8],
         block: &[u32; 16],
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<()> {
+        deduct_gas(remaining_gas, self.syscall_costs.sha256_process_block)?;
+
         // reference impl
         // https://github.com/starkware-libs/cairo/blob/ba3f82b4a09972b6a24bf791e344cabce579bf69/crates/cairo-lang-runner/src/casm_run/mod.rs#L1292
         let data_as_bytes = sha2::digest::generic_array::GenericArray::from_exact_iter(
@@ -651,15 +1461,44 @@ impl StarknetSyscallHandler for &mut StubSyscallHandler {
     fn get_class_hash_at(
         &mut self,
         contract_address: Felt,
-        _remaining_gas: &mut u64,
+        remaining_gas: &mut u64,
     ) -> SyscallResult<Felt> {
-        Ok(contract_address)
+        deduct_gas(remaining_gas, self.syscall_costs.get_class_hash_at)?;
+        Ok(self
+            .contracts
+            .get(&contract_address)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    fn begin_nested_call(
+        &mut self,
+        request: &SyscallRequest,
+        remaining_gas: &mut u64,
+    ) -> Option<NestedCallStart> {
+        if !request.is_nested_call() {
+            return None;
+        }
+
+        Some(
+            self.begin_nested_syscall(request, remaining_gas)
+                .unwrap_or_else(|e| NestedCallStart::Finished(Err(e))),
+        )
+    }
+
+    fn end_nested_call(
+        &mut self,
+        result: Result<ContractExecutionResult, Error>,
+        remaining_gas: &mut u64,
+    ) -> SyscallResult<SyscallResponse> {
+        self.end_entry_point(result, remaining_gas)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{utils::test::load_starknet_contract, OptLevel};
 
     #[test]
     fn test_secp256k1_get_xy() {
@@ -679,7 +1518,9 @@ mod tests {
         let mut test_syscall_handler = &mut test_syscall_handler;
 
         assert_eq!(
-            test_syscall_handler.secp256k1_get_xy(p, &mut 10).unwrap(),
+            test_syscall_handler
+                .secp256k1_get_xy(p, &mut u64::MAX)
+                .unwrap(),
             (
                 U256 {
                     hi: 331229800296699308591929724809569456681,
@@ -708,7 +1549,9 @@ mod tests {
         };
 
         assert_eq!(
-            test_syscall_handler.secp256k1_new(x, y, &mut 10).unwrap(),
+            test_syscall_handler
+                .secp256k1_new(x, y, &mut u64::MAX)
+                .unwrap(),
             Some(Secp256k1Point {
                 x,
                 y,
@@ -729,7 +1572,7 @@ mod tests {
         let y = U256 { hi: 0, lo: 0 };
 
         assert!(test_syscall_handler
-            .secp256k1_new(x, y, &mut 10)
+            .secp256k1_new(x, y, &mut u64::MAX)
             .unwrap()
             .is_none());
     }
@@ -754,7 +1597,9 @@ mod tests {
         let p2 = p1;
 
         // 2 * P1
-        let p3 = test_syscall_handler.secp256k1_add(p1, p2, &mut 10).unwrap();
+        let p3 = test_syscall_handler
+            .secp256k1_add(p1, p2, &mut u64::MAX)
+            .unwrap();
 
         let p1_double = Secp256k1Point {
             x: U256 {
@@ -770,7 +1615,7 @@ mod tests {
         assert_eq!(p3, p1_double);
         assert_eq!(
             test_syscall_handler
-                .secp256k1_mul(p1, U256 { lo: 2, hi: 0 }, &mut 10)
+                .secp256k1_mul(p1, U256 { lo: 2, hi: 0 }, &mut u64::MAX)
                 .unwrap(),
             p1_double
         );
@@ -788,12 +1633,14 @@ mod tests {
             is_infinity: false,
         };
         assert_eq!(
-            test_syscall_handler.secp256k1_add(p1, p3, &mut 10).unwrap(),
+            test_syscall_handler
+                .secp256k1_add(p1, p3, &mut u64::MAX)
+                .unwrap(),
             three_p1
         );
         assert_eq!(
             test_syscall_handler
-                .secp256k1_mul(p1, U256 { lo: 3, hi: 0 }, &mut 10)
+                .secp256k1_mul(p1, U256 { lo: 3, hi: 0 }, &mut u64::MAX)
                 .unwrap(),
             three_p1
         );
@@ -812,7 +1659,7 @@ mod tests {
                         hi: 97179038819393695679,
                     },
                     false,
-                    &mut 10
+                    &mut u64::MAX
                 )
                 .unwrap()
                 .unwrap(),
@@ -843,7 +1690,7 @@ mod tests {
                         hi: 97179038819393695679,
                     },
                     true,
-                    &mut 10
+                    &mut u64::MAX
                 )
                 .unwrap()
                 .unwrap(),
@@ -867,7 +1714,7 @@ mod tests {
         let mut test_syscall_handler = &mut test_syscall_handler;
 
         assert!(test_syscall_handler
-            .secp256k1_get_point_from_x(U256 { hi: 0, lo: 0 }, true, &mut 10)
+            .secp256k1_get_point_from_x(U256 { hi: 0, lo: 0 }, true, &mut u64::MAX)
             .unwrap()
             .is_none());
     }
@@ -888,7 +1735,7 @@ mod tests {
 
         assert_eq!(
             test_syscall_handler
-                .secp256r1_new(x, y, &mut 10)
+                .secp256r1_new(x, y, &mut u64::MAX)
                 .unwrap()
                 .unwrap(),
             Secp256r1Point {
@@ -909,7 +1756,7 @@ mod tests {
 
         assert!(
             test_syscall_handler
-                .secp256r1_new(x, y, &mut 10)
+                .secp256r1_new(x, y, &mut u64::MAX)
                 .unwrap()
                 .unwrap()
                 .is_infinity
@@ -936,7 +1783,9 @@ mod tests {
         let p2 = p1;
 
         // 2 * P1
-        let p3 = test_syscall_handler.secp256r1_add(p1, p2, &mut 10).unwrap();
+        let p3 = test_syscall_handler
+            .secp256r1_add(p1, p2, &mut u64::MAX)
+            .unwrap();
 
         let p1_double = Secp256r1Point {
             x: U256 {
@@ -952,7 +1801,7 @@ mod tests {
         assert_eq!(p3, p1_double);
         assert_eq!(
             test_syscall_handler
-                .secp256r1_mul(p1, U256 { lo: 2, hi: 0 }, &mut 10)
+                .secp256r1_mul(p1, U256 { lo: 2, hi: 0 }, &mut u64::MAX)
                 .unwrap(),
             p1_double
         );
@@ -970,12 +1819,14 @@ mod tests {
             is_infinity: false,
         };
         assert_eq!(
-            test_syscall_handler.secp256r1_add(p1, p3, &mut 10).unwrap(),
+            test_syscall_handler
+                .secp256r1_add(p1, p3, &mut u64::MAX)
+                .unwrap(),
             three_p1
         );
         assert_eq!(
             test_syscall_handler
-                .secp256r1_mul(p1, U256 { lo: 3, hi: 0 }, &mut 10)
+                .secp256r1_mul(p1, U256 { lo: 3, hi: 0 }, &mut u64::MAX)
                 .unwrap(),
             three_p1
         );
@@ -998,7 +1849,7 @@ mod tests {
 
         assert_eq!(
             test_syscall_handler
-                .secp256r1_get_point_from_x(x, true, &mut 10)
+                .secp256r1_get_point_from_x(x, true, &mut u64::MAX)
                 .unwrap()
                 .unwrap(),
             Secp256r1Point {
@@ -1026,7 +1877,7 @@ mod tests {
 
         assert_eq!(
             test_syscall_handler
-                .secp256r1_get_point_from_x(x, false, &mut 10)
+                .secp256r1_get_point_from_x(x, false, &mut u64::MAX)
                 .unwrap()
                 .unwrap(),
             Secp256r1Point {
@@ -1045,7 +1896,7 @@ mod tests {
         let x = U256 { lo: 0, hi: 10 };
 
         assert!(test_syscall_handler
-            .secp256r1_get_point_from_x(x, true, &mut 10)
+            .secp256r1_get_point_from_x(x, true, &mut u64::MAX)
             .unwrap()
             .is_none());
     }
@@ -1068,7 +1919,9 @@ mod tests {
         let mut test_syscall_handler = &mut test_syscall_handler;
 
         assert_eq!(
-            test_syscall_handler.secp256r1_get_xy(p, &mut 10).unwrap(),
+            test_syscall_handler
+                .secp256r1_get_xy(p, &mut u64::MAX)
+                .unwrap(),
             (
                 U256 {
                     lo: 97179038819393695679,
@@ -1081,4 +1934,369 @@ mod tests {
             )
         )
     }
+
+    #[test]
+    fn test_deploy_and_call_contract() {
+        let (_, contract) = load_starknet_contract! {
+            #[starknet::interface]
+            trait ICaller<TContractState> {
+                fn get_caller(self: @TContractState) -> starknet::ContractAddress;
+            }
+
+            #[starknet::contract]
+            mod contract {
+                #[storage]
+                struct Storage {}
+
+                #[abi(embed_v0)]
+                impl ICallerImpl of super::ICaller<ContractState> {
+                    fn get_caller(self: @ContractState) -> starknet::ContractAddress {
+                        starknet::get_caller_address()
+                    }
+                }
+            }
+        };
+        let executor = AotContractExecutor::new(
+            &contract.extract_sierra_program().unwrap(),
+            &contract.entry_points_by_type,
+            OptLevel::Default,
+        )
+        .unwrap();
+
+        let mut test_syscall_handler = StubSyscallHandler::default();
+        test_syscall_handler.declare(Felt::from(1234), executor);
+        let mut test_syscall_handler = &mut test_syscall_handler;
+
+        let (contract_address, retdata) = test_syscall_handler
+            .deploy(Felt::from(1234), Felt::from(5), &[], false, &mut u64::MAX)
+            .unwrap();
+        assert!(retdata.is_empty());
+        assert_eq!(
+            contract_address,
+            calculate_contract_address(Felt::from(5), Felt::from(1234), &[], Felt::from(3))
+        );
+        assert_eq!(
+            test_syscall_handler
+                .get_class_hash_at(contract_address, &mut u64::MAX)
+                .unwrap(),
+            Felt::from(1234)
+        );
+
+        // Deploying twice to the same address fails.
+        assert!(test_syscall_handler
+            .deploy(Felt::from(1234), Felt::from(5), &[], false, &mut u64::MAX)
+            .is_err());
+
+        let selector = Felt::from(starknet_keccak(b"get_caller"));
+        assert_eq!(
+            test_syscall_handler
+                .call_contract(contract_address, selector, &[], &mut u64::MAX)
+                .unwrap(),
+            vec![Felt::from(3)]
+        );
+        assert_eq!(
+            test_syscall_handler.execution_info.contract_address,
+            Felt::from(3)
+        );
+    }
+
+    #[test]
+    fn test_failed_constructor_reverts_deployment() {
+        let (_, contract) = load_starknet_contract! {
+            #[starknet::contract]
+            mod contract {
+                #[storage]
+                struct Storage {
+                    value: felt252,
+                }
+
+                #[constructor]
+                fn constructor(ref self: ContractState, value: felt252) {
+                    self.value.write(value);
+                    if value == 0 {
+                        panic!("zero value");
+                    }
+                }
+            }
+        };
+        let executor = AotContractExecutor::new(
+            &contract.extract_sierra_program().unwrap(),
+            &contract.entry_points_by_type,
+            OptLevel::Default,
+        )
+        .unwrap();
+
+        let mut test_syscall_handler = StubSyscallHandler::default();
+        test_syscall_handler.declare(Felt::from(1234), executor);
+        let mut test_syscall_handler = &mut test_syscall_handler;
+
+        assert!(test_syscall_handler
+            .deploy(
+                Felt::from(1234),
+                Felt::from(5),
+                &[0.into()],
+                false,
+                &mut u64::MAX
+            )
+            .is_err());
+        let contract_address =
+            calculate_contract_address(Felt::from(5), Felt::from(1234), &[0.into()], Felt::from(3));
+        assert!(!test_syscall_handler
+            .contracts
+            .contains_key(&contract_address));
+        assert!(test_syscall_handler
+            .storage
+            .get(&contract_address)
+            .is_none_or(HashMap::is_empty));
+        assert!(test_syscall_handler.journal.is_empty());
+
+        // A successful constructor keeps the deployment.
+        let (contract_address, _) = test_syscall_handler
+            .deploy(
+                Felt::from(1234),
+                Felt::from(5),
+                &[1.into()],
+                false,
+                &mut u64::MAX,
+            )
+            .unwrap();
+        assert_eq!(
+            test_syscall_handler.contracts.get(&contract_address),
+            Some(&Felt::from(1234))
+        );
+    }
+
+    #[test]
+    fn test_storage_rollback() {
+        let mut test_syscall_handler = StubSyscallHandler::default();
+        let mut handler = &mut test_syscall_handler;
+
+        assert_eq!(
+            handler.storage_read(0, 1.into(), &mut u64::MAX).unwrap(),
+            Felt::ZERO
+        );
+        handler
+            .storage_write(0, 1.into(), 2.into(), &mut u64::MAX)
+            .unwrap();
+
+        let checkpoint = handler.checkpoint();
+        handler
+            .storage_write(0, 1.into(), 3.into(), &mut u64::MAX)
+            .unwrap();
+        handler
+            .storage_write(0, 4.into(), 5.into(), &mut u64::MAX)
+            .unwrap();
+        handler.emit_event(&[], &[], &mut u64::MAX).unwrap();
+
+        // Storage is not shared between contracts.
+        handler.execution_info.contract_address = 6.into();
+        assert_eq!(
+            handler.storage_read(0, 1.into(), &mut u64::MAX).unwrap(),
+            Felt::ZERO
+        );
+        handler.execution_info.contract_address = 3.into();
+
+        handler.rollback(checkpoint);
+        assert_eq!(
+            handler.storage_read(0, 1.into(), &mut u64::MAX).unwrap(),
+            Felt::from(2)
+        );
+        assert_eq!(
+            handler.storage_read(0, 4.into(), &mut u64::MAX).unwrap(),
+            Felt::ZERO
+        );
+        assert!(handler.events.is_empty());
+        assert!(handler.logs[&Felt::from(3)].events.is_empty());
+    }
+
+    #[cfg(feature = "with-cheatcode")]
+    #[test]
+    fn test_rollback_consumed_logs() {
+        let mut test_syscall_handler = StubSyscallHandler::default();
+        let mut handler = &mut test_syscall_handler;
+        let selector = |name: &str| Felt::from_bytes_be_slice(name.as_bytes());
+        let contract_address = handler.execution_info.contract_address;
+
+        handler.emit_event(&[1.into()], &[], &mut u64::MAX).unwrap();
+        let checkpoint = handler.checkpoint();
+        handler.emit_event(&[2.into()], &[], &mut u64::MAX).unwrap();
+        handler
+            .send_message_to_l1(4.into(), &[], &mut u64::MAX)
+            .unwrap();
+
+        // The logs are consumed oldest first, so this takes the event emitted before the
+        // checkpoint.
+        assert_eq!(
+            handler.cheatcode(selector("pop_log"), &[contract_address]),
+            [Felt::ONE, Felt::ONE, Felt::ZERO]
+        );
+        handler.cheatcode(selector("pop_l2_to_l1_message"), &[contract_address]);
+        handler.cheatcode(selector("spy_events"), &[]);
+
+        handler.rollback(checkpoint);
+        let logs = &handler.logs[&contract_address];
+        assert_eq!(logs.events.len(), 1);
+        assert_eq!(logs.events[0].keys, [Felt::ONE]);
+        assert!(logs.l2_to_l1_messages.is_empty());
+        assert!(handler.cheats.spied_events.is_none());
+    }
+
+    #[test]
+    fn test_scoped_cheats() {
+        let mut test_syscall_handler = StubSyscallHandler::default();
+        let mut handler = &mut test_syscall_handler;
+        let target = handler.execution_info.contract_address;
+
+        handler.cheats.pranks.insert(target, 10.into());
+        handler.cheats.warps.insert(target, 20);
+        handler.cheats.rolls.insert(99.into(), 30);
+
+        let execution_info = handler.get_execution_info_v2(&mut u64::MAX).unwrap();
+        assert_eq!(execution_info.caller_address, Felt::from(10));
+        assert_eq!(execution_info.block_info.block_timestamp, 20);
+        assert_eq!(execution_info.block_info.block_number, 0);
+
+        // Mocked calls don't need the contract to be deployed.
+        handler
+            .cheats
+            .mocked_calls
+            .insert((99.into(), 1.into()), vec![42.into()]);
+        assert_eq!(
+            handler.call_contract(99.into(), 1.into(), &[], &mut u64::MAX),
+            Ok(vec![42.into()])
+        );
+
+        handler.cheats.spied_events = Some(VecDeque::new());
+        handler
+            .emit_event(&[1.into()], &[2.into()], &mut u64::MAX)
+            .unwrap();
+        let (from_address, event) = handler
+            .cheats
+            .spied_events
+            .as_mut()
+            .and_then(VecDeque::pop_front)
+            .unwrap();
+        assert_eq!(from_address, target);
+        assert_eq!(event.keys, [Felt::from(1)]);
+        assert_eq!(handler.logs[&target].events.len(), 1);
+    }
+
+    #[cfg(feature = "with-cheatcode")]
+    #[test]
+    fn test_malformed_cheatcodes() {
+        let mut test_syscall_handler = StubSyscallHandler::default();
+        let mut handler = &mut test_syscall_handler;
+        let selector = |name: &str| Felt::from_bytes_be_slice(name.as_bytes());
+
+        assert!(handler
+            .cheatcode(selector("set_caller_address"), &[])
+            .is_empty());
+        assert!(handler
+            .cheatcode(selector("set_block_number"), &[Felt::MAX])
+            .is_empty());
+        assert!(handler
+            .cheatcode(selector("start_warp"), &[Felt::ONE])
+            .is_empty());
+        assert!(handler
+            .cheatcode(
+                selector("mock_call"),
+                &[1.into(), 2.into(), 3.into(), 4.into()]
+            )
+            .is_empty());
+        assert_eq!(handler.execution_info.block_info.block_number, 0);
+        assert!(handler.cheats.warps.is_empty());
+        assert!(handler.cheats.mocked_calls.is_empty());
+
+        // Well-formed cheatcodes still apply.
+        handler.cheatcode(selector("set_block_number"), &[5.into()]);
+        assert_eq!(handler.execution_info.block_info.block_number, 5);
+    }
+
+    #[test]
+    fn test_syscall_gas_costs() {
+        let mut test_syscall_handler = StubSyscallHandler::default();
+        let costs = test_syscall_handler.syscall_costs;
+        let mut handler = &mut test_syscall_handler;
+
+        let mut remaining_gas = costs.storage_write + 1;
+        handler
+            .storage_write(0, 1.into(), 2.into(), &mut remaining_gas)
+            .unwrap();
+        assert_eq!(remaining_gas, 1);
+
+        assert_eq!(
+            handler.storage_read(0, 1.into(), &mut remaining_gas),
+            Err(SyscallError::OutOfGas)
+        );
+
+        // Keccak charges for every round.
+        let mut remaining_gas = u64::MAX;
+        handler.keccak(&[0; 34], &mut remaining_gas).unwrap();
+        assert_eq!(
+            remaining_gas,
+            u64::MAX - costs.keccak - 2 * costs.keccak_round
+        );
+
+        // Nested calls refund the initial budget of the entry point they run, like the runner.
+        let (_, contract) = load_starknet_contract! {
+            #[starknet::contract]
+            mod contract {
+                #[storage]
+                struct Storage {}
+
+                #[constructor]
+                fn constructor(ref self: ContractState) {}
+
+                #[external(v0)]
+                fn get(self: @ContractState) -> felt252 {
+                    42
+                }
+            }
+        };
+        let executor = AotContractExecutor::new(
+            &contract.extract_sierra_program().unwrap(),
+            &contract.entry_points_by_type,
+            OptLevel::Default,
+        )
+        .unwrap();
+        let entry_point_gas = |selector: &[u8]| {
+            let result = executor
+                .run(
+                    Felt::from(starknet_keccak(selector)),
+                    &[],
+                    u64::MAX,
+                    None,
+                    &mut StubSyscallHandler::default(),
+                )
+                .unwrap();
+            u64::MAX - result.remaining_gas
+        };
+        let constructor_gas = entry_point_gas(b"constructor");
+        let get_gas = entry_point_gas(b"get");
+        handler.declare(Felt::from(1234), executor);
+
+        let initial_gas = 10_000_000;
+        let mut remaining_gas = initial_gas;
+        let (contract_address, _) = handler
+            .deploy(Felt::from(1234), Felt::ZERO, &[], false, &mut remaining_gas)
+            .unwrap();
+        assert_eq!(
+            remaining_gas,
+            initial_gas - costs.deploy + ENTRY_POINT_INITIAL_BUDGET - constructor_gas
+        );
+
+        let mut remaining_gas = initial_gas;
+        handler
+            .call_contract(
+                contract_address,
+                Felt::from(starknet_keccak(b"get")),
+                &[],
+                &mut remaining_gas,
+            )
+            .unwrap();
+        assert_eq!(
+            remaining_gas,
+            initial_gas - costs.call_contract + ENTRY_POINT_INITIAL_BUDGET - get_gas
+        );
+    }
 }
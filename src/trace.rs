@@ -0,0 +1,243 @@
+//! # Statement traces
+//!
+//! Programs compiled with the `with-trace` feature append every Sierra statement they execute to the
+//! trace of the runtime. Executions that request it through
+//! [`ExecutionOptions::with_trace`](crate::executor::ExecutionOptions::with_trace) collect those
+//! statements, in order, into a [`StatementTrace`] which is returned along with their results.
+//!
+//! Setting the `NATIVE_TRACE_VALUES` environment variable while compiling also records the felt and
+//! integer values produced by every statement, which is slower but shows where the values of two
+//! executions start to differ. Function calls don't have values of their own, the values they
+//! return are those of the callee's return statement.
+//!
+//! Programs compiled without the feature don't trace anything, so their traces are empty.
+//!
+//! A trace can be compared with a reference trace (another native execution, or the statements
+//! executed by the Cairo VM) with [`StatementTrace::find_divergence`].
+
+use cairo_lang_sierra::program::StatementIdx;
+use cairo_native_runtime::TraceSink;
+use num_bigint::BigInt;
+use starknet_types_core::felt::Felt;
+use std::ffi::c_void;
+
+/// The Sierra statements executed by a program, in order.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct StatementTrace {
+    pub entries: Vec<TraceEntry>,
+}
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct TraceEntry {
+    pub statement_idx: StatementIdx,
+    /// The felt and integer values produced by the statement, in order, with negative integers
+    /// represented as felts. Empty unless the values were traced.
+    pub values: Vec<Felt>,
+}
+
+/// The first point where a trace differs from its reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDivergence {
+    /// The position of the divergence in the trace.
+    pub position: usize,
+    /// The position of the divergence in the reference.
+    pub reference_position: usize,
+    /// The entry of the trace, if it didn't end before the reference.
+    pub entry: Option<TraceEntry>,
+    /// The entry of the reference, if it didn't end before the trace.
+    pub expected: Option<TraceEntry>,
+}
+
+impl StatementTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The executed statements, in order.
+    pub fn statements(&self) -> impl Iterator<Item = StatementIdx> + '_ {
+        self.entries.iter().map(|x| x.statement_idx)
+    }
+
+    /// Find the first entry where the trace diverges from the `reference`, either because a
+    /// different statement was executed (or one of them ended first) or because the same statement
+    /// took different values. Values are only compared when both entries have them.
+    ///
+    /// The reference may only contain some of the statements, like the Cairo VM which only sees
+    /// those compiled into CASM instructions. The statements that `is_observable` rejects are
+    /// skipped in both traces.
+    pub fn find_divergence(
+        &self,
+        reference: &[TraceEntry],
+        is_observable: impl Fn(StatementIdx) -> bool,
+    ) -> Option<TraceDivergence> {
+        let mut entries = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| is_observable(x.statement_idx));
+        let mut expected_entries = reference
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| is_observable(x.statement_idx));
+
+        loop {
+            match (entries.next(), expected_entries.next()) {
+                (None, None) => return None,
+                (Some((_, entry)), Some((_, expected)))
+                    if entry.statement_idx == expected.statement_idx
+                        && (entry.values.is_empty()
+                            || expected.values.is_empty()
+                            || entry.values == expected.values) => {}
+                (entry, expected) => {
+                    return Some(TraceDivergence {
+                        position: entry.map_or(self.entries.len(), |x| x.0),
+                        reference_position: expected.map_or(reference.len(), |x| x.0),
+                        entry: entry.map(|x| x.1.clone()),
+                        expected: expected.map(|x| x.1.clone()),
+                    })
+                }
+            }
+        }
+    }
+}
+
+extern "C" fn append_statement(
+    data: *mut c_void,
+    statement_idx: u64,
+    values: *const [u8; 32],
+    len: u64,
+) {
+    let trace = unsafe { &mut *data.cast::<StatementTrace>() };
+    let values = match len {
+        0 => &[][..],
+        len => unsafe { std::slice::from_raw_parts(values, len as usize) },
+    };
+
+    trace.entries.push(TraceEntry {
+        statement_idx: StatementIdx(statement_idx as usize),
+        values: values
+            .iter()
+            .map(|x| Felt::from(&BigInt::from_signed_bytes_le(x)))
+            .collect(),
+    });
+}
+
+/// The sink that appends the statements executed by an execution to `trace`, or discards them if
+/// `None`.
+///
+/// The trace must outlive the execution.
+pub(crate) fn sink(trace: Option<&mut StatementTrace>) -> TraceSink {
+    match trace {
+        Some(trace) => TraceSink {
+            data: (trace as *mut StatementTrace).cast(),
+            append: Some(append_statement),
+        },
+        None => TraceSink {
+            data: std::ptr::null_mut(),
+            append: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(statement_idx: usize, values: &[i64]) -> TraceEntry {
+        TraceEntry {
+            statement_idx: StatementIdx(statement_idx),
+            values: values.iter().map(|x| Felt::from(*x)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_append_statement() {
+        let mut values = [[0u8; 32]; 2];
+        values[0][0] = 42;
+        values[1] = [0xFF; 32];
+
+        let mut trace = StatementTrace::new();
+        append_statement(
+            (&mut trace as *mut StatementTrace).cast(),
+            3,
+            values.as_ptr(),
+            2,
+        );
+        append_statement(
+            (&mut trace as *mut StatementTrace).cast(),
+            4,
+            std::ptr::null(),
+            0,
+        );
+
+        assert_eq!(trace.entries, [entry(3, &[42, -1]), entry(4, &[])]);
+        assert_eq!(
+            trace.statements().collect::<Vec<_>>(),
+            [StatementIdx(3), StatementIdx(4)]
+        );
+    }
+
+    #[test]
+    fn test_find_divergence() {
+        let trace = StatementTrace {
+            entries: vec![entry(0, &[1]), entry(1, &[]), entry(2, &[3]), entry(5, &[])],
+        };
+
+        // Statement 1 is skipped, and values are only compared when both entries have them.
+        let is_observable = |x: StatementIdx| x.0 != 1;
+        let reference = [entry(0, &[]), entry(2, &[3]), entry(5, &[])];
+        assert_eq!(trace.find_divergence(&reference, is_observable), None);
+
+        let reference = [entry(0, &[1]), entry(2, &[4]), entry(5, &[])];
+        assert_eq!(
+            trace.find_divergence(&reference, is_observable),
+            Some(TraceDivergence {
+                position: 2,
+                reference_position: 1,
+                entry: Some(entry(2, &[3])),
+                expected: Some(entry(2, &[4])),
+            })
+        );
+
+        let reference = [entry(0, &[]), entry(2, &[]), entry(4, &[]), entry(5, &[])];
+        assert_eq!(
+            trace
+                .find_divergence(&reference, is_observable)
+                .map(|x| (x.position, x.reference_position)),
+            Some((3, 2))
+        );
+
+        let reference = [entry(0, &[])];
+        assert_eq!(
+            trace.find_divergence(&reference, is_observable),
+            Some(TraceDivergence {
+                position: 2,
+                reference_position: 1,
+                entry: Some(entry(2, &[3])),
+                expected: None,
+            })
+        );
+    }
+}
@@ -13,6 +13,7 @@ use cairo_lang_sierra::{
     ids::FunctionId,
     program::{GenFunction, Program, StatementIdx},
 };
+use itertools::Itertools;
 use melior::{
     ir::Module,
     pass::{self, PassManager},
@@ -263,6 +264,44 @@ pub fn felt252_short_str(value: &str) -> Felt {
     Felt::from_bytes_be_slice(&values)
 }
 
+/// Encode a string as felts of up to 31 bytes each.
+pub fn encode_str_as_felts(msg: &str) -> Vec<Felt> {
+    const CHUNK_SIZE: usize = 32;
+
+    let data = msg.as_bytes().chunks(CHUNK_SIZE - 1);
+    let mut encoding = vec![Felt::default(); data.len()];
+    for (i, data_chunk) in data.enumerate() {
+        let mut chunk = [0_u8; CHUNK_SIZE];
+        chunk[1..data_chunk.len() + 1].copy_from_slice(data_chunk);
+        encoding[i] = Felt::from_bytes_be(&chunk);
+    }
+    encoding
+}
+
+/// Decode felts made by [`encode_str_as_felts`], listing them if they aren't valid UTF-8.
+pub fn decode_felts_as_str(encoding: &[Felt]) -> String {
+    let bytes_err: Vec<_> = encoding
+        .iter()
+        .flat_map(|felt| felt.to_bytes_be()[1..32].to_vec())
+        .collect();
+
+    match String::from_utf8(bytes_err) {
+        Ok(s) => s.trim_matches('\0').to_owned(),
+        Err(_) => {
+            let err_msgs = encoding
+                .iter()
+                .map(
+                    |felt| match String::from_utf8(felt.to_bytes_be()[1..32].to_vec()) {
+                        Ok(s) => format!("{} ({})", s.trim_matches('\0'), felt),
+                        Err(_) => felt.to_string(),
+                    },
+                )
+                .join(", ");
+            format!("[{}]", err_msgs)
+        }
+    }
+}
+
 /// Creates the execution engine, with all symbols registered.
 pub fn create_engine(
     module: &Module,
@@ -281,9 +320,6 @@ pub fn create_engine(
         .unwrap()
         .register_impls(&engine);
 
-    #[cfg(feature = "with-mem-tracing")]
-    self::mem_tracing::register_bindings(&engine);
-
     engine
 }
 
@@ -404,6 +440,41 @@ pub fn register_runtime_symbols(engine: &ExecutionEngine) {
                 as *mut (),
         );
 
+        engine.register_symbol(
+            "cairo_native__set_runtime_state",
+            cairo_native_runtime::cairo_native__set_runtime_state as *mut (),
+        );
+
+        engine.register_symbol(
+            "cairo_native__record_event",
+            cairo_native_runtime::cairo_native__record_event as *mut (),
+        );
+
+        engine.register_symbol(
+            "cairo_native__trace_statement",
+            cairo_native_runtime::cairo_native__trace_statement as *mut (),
+        );
+
+        engine.register_symbol(
+            "cairo_native__function_hook",
+            cairo_native_runtime::cairo_native__function_hook as *mut (),
+        );
+
+        engine.register_symbol(
+            "cairo_native__breakpoint_hit",
+            cairo_native_runtime::cairo_native__breakpoint_hit as *mut (),
+        );
+
+        engine.register_symbol(
+            "cairo_native__realloc",
+            cairo_native_runtime::cairo_native__realloc as *mut (),
+        );
+
+        engine.register_symbol(
+            "cairo_native__free",
+            cairo_native_runtime::cairo_native__free as *mut (),
+        );
+
         #[cfg(feature = "with-cheatcode")]
         {
             engine.register_symbol(
@@ -530,6 +601,7 @@ pub mod test {
         program::Program,
         program::{FunctionSignature, GenFunction, StatementIdx},
     };
+    use cairo_lang_sierra_generator::statements_locations::StatementsLocations;
     use cairo_lang_starknet::{compile::compile_contract_in_prepared_db, starknet_plugin_suite};
     use cairo_lang_starknet_classes::contract_class::ContractClass;
     use pretty_assertions_sorted::assert_eq;
@@ -541,6 +613,14 @@ pub mod test {
             $crate::utils::test::load_cairo_str(stringify!($($program)+))
         };
     }
+    macro_rules! load_cairo_with_debug_info {
+        ( $( $program:tt )+ ) => {
+            $crate::utils::test::compile_program_with_debug_info(
+                stringify!($($program)+),
+                cairo_lang_compiler::db::RootDatabase::default(),
+            )
+        };
+    }
     macro_rules! load_starknet {
         ( $( $program:tt )+ ) => {
             $crate::utils::test::load_starknet_str(stringify!($($program)+))
@@ -552,6 +632,7 @@ pub mod test {
         };
     }
     pub(crate) use load_cairo;
+    pub(crate) use load_cairo_with_debug_info;
     pub(crate) use load_starknet;
     pub(crate) use load_starknet_contract;
 
@@ -655,7 +736,17 @@ pub mod test {
         (module_name.to_string(), contract)
     }
 
-    pub(crate) fn compile_program(program_str: &str, mut db: RootDatabase) -> (String, Program) {
+    pub(crate) fn compile_program(program_str: &str, db: RootDatabase) -> (String, Program) {
+        let (module_name, program, _, _) = compile_program_with_debug_info(program_str, db);
+        (module_name, program)
+    }
+
+    /// Compile a program, also returning the database and the statement locations it was compiled
+    /// with.
+    pub(crate) fn compile_program_with_debug_info(
+        program_str: &str,
+        mut db: RootDatabase,
+    ) -> (String, Program, RootDatabase, StatementsLocations) {
         let mut program_file = tempfile::Builder::new()
             .prefix("test_")
             .suffix(".cairo")
@@ -681,7 +772,12 @@ pub mod test {
 
         let module_name = program_file.path().with_extension("");
         let module_name = module_name.file_name().unwrap().to_str().unwrap();
-        (module_name.to_string(), sierra_program_with_dbg.program)
+        (
+            module_name.to_string(),
+            sierra_program_with_dbg.program,
+            db,
+            sierra_program_with_dbg.debug_info.statements_locations,
+        )
     }
 
     pub fn run_program(
@@ -1,131 +1,323 @@
-#![cfg(feature = "with-mem-tracing")]
+//! # Memory tracing
+//!
+//! With the `with-mem-tracing` feature, compiled programs allocate and free memory through the
+//! runtime (`cairo_native__realloc` and `cairo_native__free`), which reports it to the memory
+//! tracer installed for the execution, along with the dictionaries allocated by the runtime
+//! itself. The executors' own allocations for arguments and results are tracked too. Every
+//! execution, either JIT or AOT, returns a [`MemoryReport`] along with its results, listing the
+//! allocations it didn't free, which makes it possible to assert that running an entry point
+//! leaks nothing.
+//!
+//! Nested executions (started from a syscall handler) are tracked separately from their caller.
 
-use libc::{c_void, size_t};
-use melior::ExecutionEngine;
-use std::cell::UnsafeCell;
-
-thread_local! {
-    static MEM_TRACING: UnsafeCell<MemTracing> = const { UnsafeCell::new(MemTracing::new()) };
+/// The allocations made by an execution.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct MemoryReport {
+    /// The allocations that weren't freed, in address order.
+    pub outstanding: Vec<OutstandingAllocation>,
+    /// The maximum number of bytes allocated at the same time.
+    pub peak_bytes: usize,
+    /// The number of allocations, counting every `realloc` as a new one.
+    pub total_allocations: usize,
 }
 
-struct MemTracing {
-    finished: Vec<AllocTrace>,
-    pending: Vec<AllocTrace>,
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct OutstandingAllocation {
+    pub address: usize,
+    pub size: usize,
 }
 
-struct AllocTrace {
-    ptr: *mut c_void,
-    len: size_t,
+impl MemoryReport {
+    /// Whether every allocation was freed.
+    pub fn is_leak_free(&self) -> bool {
+        self.outstanding.is_empty()
+    }
+
+    /// The number of bytes that weren't freed.
+    pub fn outstanding_bytes(&self) -> usize {
+        self.outstanding.iter().map(|x| x.size).sum()
+    }
 }
 
-impl MemTracing {
-    pub const fn new() -> Self {
-        Self {
-            finished: Vec::new(),
-            pending: Vec::new(),
-        }
+#[cfg(feature = "with-mem-tracing")]
+pub(crate) use self::tracing_impl::*;
+
+#[cfg(feature = "with-mem-tracing")]
+mod tracing_impl {
+    use super::{MemoryReport, OutstandingAllocation};
+    use cairo_native_runtime::MemoryTracer;
+    use libc::{c_void, size_t};
+    use std::{cell::UnsafeCell, ptr::null_mut};
+
+    thread_local! {
+        static MEM_TRACING: UnsafeCell<MemTracing> = const { UnsafeCell::new(MemTracing::new()) };
     }
 
-    pub fn push(&mut self, trace: AllocTrace) {
-        match self.pending.binary_search_by_key(&trace.ptr, |x| x.ptr) {
-            Ok(_) => unreachable!(),
-            Err(pos) => self.pending.insert(pos, trace),
-        }
+    #[derive(Debug)]
+    struct MemTracing {
+        pending: Vec<AllocTrace>,
+        current_bytes: usize,
+        peak_bytes: usize,
+        total_allocations: usize,
     }
 
-    pub fn update(&mut self, ptr: *mut c_void, trace: AllocTrace) {
-        if let Ok(pos) = self.pending.binary_search_by_key(&ptr, |x| x.ptr) {
-            let trace = self.pending.remove(pos);
-            if trace.len == 0 {
-                self.finished.push(trace);
+    #[derive(Debug)]
+    struct AllocTrace {
+        ptr: *mut c_void,
+        len: size_t,
+    }
+
+    impl MemTracing {
+        pub const fn new() -> Self {
+            Self {
+                pending: Vec::new(),
+                current_bytes: 0,
+                peak_bytes: 0,
+                total_allocations: 0,
+            }
+        }
+
+        pub fn push(&mut self, trace: AllocTrace) {
+            if trace.ptr.is_null() {
                 return;
             }
-        };
 
-        self.push(trace);
-    }
+            self.total_allocations += 1;
+            self.current_bytes += trace.len;
+            self.peak_bytes = self.peak_bytes.max(self.current_bytes);
+
+            match self.pending.binary_search_by_key(&trace.ptr, |x| x.ptr) {
+                // The pointer was freed by code that isn't traced (like the runtime library).
+                Ok(pos) => {
+                    self.current_bytes -= self.pending[pos].len;
+                    self.pending[pos] = trace;
+                }
+                Err(pos) => self.pending.insert(pos, trace),
+            }
+        }
 
-    pub fn finish(&mut self, ptr: *mut c_void) {
-        if ptr.is_null() {
-            return;
+        pub fn update(&mut self, ptr: *mut c_void, trace: AllocTrace) {
+            self.finish(ptr);
+            self.push(trace);
         }
 
-        match self.pending.binary_search_by_key(&ptr, |x| x.ptr) {
-            Ok(pos) => {
+        pub fn finish(&mut self, ptr: *mut c_void) {
+            if ptr.is_null() {
+                return;
+            }
+
+            // Pointers allocated before the execution started (or by code that isn't traced) are
+            // ignored.
+            if let Ok(pos) = self.pending.binary_search_by_key(&ptr, |x| x.ptr) {
                 let trace = self.pending.remove(pos);
-                self.finished.push(trace);
+                self.current_bytes -= trace.len;
+            }
+        }
+
+        pub fn report(&self) -> MemoryReport {
+            MemoryReport {
+                outstanding: self
+                    .pending
+                    .iter()
+                    .map(|x| OutstandingAllocation {
+                        address: x.ptr as usize,
+                        size: x.len,
+                    })
+                    .collect(),
+                peak_bytes: self.peak_bytes,
+                total_allocations: self.total_allocations,
             }
-            Err(_) => unreachable!(),
         }
     }
-}
 
-impl AllocTrace {
-    pub fn new(ptr: *mut c_void, len: size_t) -> Self {
-        Self { ptr, len }
+    impl AllocTrace {
+        pub fn new(ptr: *mut c_void, len: size_t) -> Self {
+            Self { ptr, len }
+        }
     }
-}
 
-pub(crate) fn register_bindings(engine: &ExecutionEngine) {
-    unsafe {
-        engine.register_symbol(
-            "malloc",
-            _wrapped_malloc as *const fn(size_t) -> *mut c_void as *mut (),
-        );
-        engine.register_symbol(
-            "realloc",
-            _wrapped_realloc as *const fn(*mut c_void, size_t) -> *mut c_void as *mut (),
-        );
-        engine.register_symbol("free", _wrapped_free as *const fn(*mut c_void) as *mut ());
+    /// Tracks the allocations of an execution, from its start until [`finish`](Self::finish) is
+    /// called. The tracking state of the caller is restored even if the execution fails.
+    ///
+    /// The allocations made by the runtime are reported through the [`memory_tracer`] of the
+    /// execution's runtime state.
+    pub(crate) struct ExecutionTracker {
+        previous: Option<MemTracing>,
     }
-}
 
-pub fn report_stats() {
-    unsafe {
-        MEM_TRACING.with(|x| {
-            println!();
-            println!("[MemTracing] Stats:");
-            println!(
-                "[MemTracing]   Freed allocations: {}",
-                (*x.get()).finished.len()
-            );
-            println!("[MemTracing]   Pending allocations:");
-            for AllocTrace { ptr, len } in &(*x.get()).pending {
-                println!("[MemTracing]     - {ptr:?} ({len} bytes)");
+    impl ExecutionTracker {
+        pub fn start() -> Self {
+            Self {
+                previous: Some(replace_state(MemTracing::new())),
             }
+        }
 
-            assert!((*x.get()).pending.is_empty());
-            *x.get() = MemTracing::new();
+        pub fn finish(mut self) -> MemoryReport {
+            match self.previous.take() {
+                Some(state) => replace_state(state).report(),
+                None => MemoryReport::default(),
+            }
+        }
+    }
+
+    impl Drop for ExecutionTracker {
+        fn drop(&mut self) {
+            if let Some(state) = self.previous.take() {
+                replace_state(state);
+            }
+        }
+    }
+
+    /// The memory tracer that reports the allocations made by the runtime to the current
+    /// [`ExecutionTracker`].
+    pub(crate) fn memory_tracer() -> MemoryTracer {
+        MemoryTracer {
+            data: null_mut(),
+            trace: Some(trace_allocation),
+        }
+    }
+
+    fn replace_state(state: MemTracing) -> MemTracing {
+        MEM_TRACING.with(|x| unsafe { std::mem::replace(&mut *x.get(), state) })
+    }
+
+    extern "C" fn trace_allocation(
+        _data: *mut c_void,
+        old_ptr: *mut c_void,
+        new_ptr: *mut c_void,
+        len: u64,
+    ) {
+        tracing::trace!(
+            "[MemTracing] Runtime reallocated {old_ptr:?} into {new_ptr:?} with {len} bytes."
+        );
+        MEM_TRACING.with(|x| unsafe {
+            (*x.get()).update(old_ptr, AllocTrace::new(new_ptr, len as size_t))
         });
     }
-}
 
-pub(crate) unsafe extern "C" fn _wrapped_malloc(len: size_t) -> *mut c_void {
-    let ptr = libc::malloc(len);
+    pub(crate) unsafe extern "C" fn _wrapped_malloc(len: size_t) -> *mut c_void {
+        let ptr = libc::malloc(len);
 
-    println!("[MemTracing] Allocating ptr {ptr:?} with {len} bytes.");
-    MEM_TRACING.with(|x| (*x.get()).push(AllocTrace::new(ptr, len)));
+        tracing::trace!("[MemTracing] Allocating ptr {ptr:?} with {len} bytes.");
+        MEM_TRACING.with(|x| (*x.get()).push(AllocTrace::new(ptr, len)));
 
-    ptr
-}
+        ptr
+    }
 
-pub(crate) unsafe extern "C" fn _wrapped_realloc(ptr: *mut c_void, len: size_t) -> *mut c_void {
-    let new_ptr = libc::realloc(ptr, len);
+    pub(crate) unsafe extern "C" fn _wrapped_realloc(ptr: *mut c_void, len: size_t) -> *mut c_void {
+        let new_ptr = libc::realloc(ptr, len);
 
-    println!("[MemTracing] Reallocating {ptr:?} into {new_ptr:?} with {len} bytes.");
-    MEM_TRACING.with(|x| (*x.get()).update(ptr, AllocTrace::new(new_ptr, len)));
+        tracing::trace!("[MemTracing] Reallocating {ptr:?} into {new_ptr:?} with {len} bytes.");
+        MEM_TRACING.with(|x| (*x.get()).update(ptr, AllocTrace::new(new_ptr, len)));
 
-    new_ptr
-}
+        new_ptr
+    }
 
-pub(crate) unsafe extern "C" fn _wrapped_free(ptr: *mut c_void) {
-    if !ptr.is_null() {
-        // This print is placed before the actual call to log pointers before double free
-        // situations.
-        println!("[MemTracing] Freeing {ptr:?}.");
+    pub(crate) unsafe extern "C" fn _wrapped_free(ptr: *mut c_void) {
+        if !ptr.is_null() {
+            // This log is placed before the actual call to log pointers before double free
+            // situations.
+            tracing::trace!("[MemTracing] Freeing {ptr:?}.");
 
-        libc::free(ptr);
+            libc::free(ptr);
 
-        MEM_TRACING.with(|x| (*x.get()).finish(ptr));
+            MEM_TRACING.with(|x| (*x.get()).finish(ptr));
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_mem_tracing_report() {
+            let mut mem_tracing = MemTracing::new();
+            mem_tracing.push(AllocTrace::new(0x1000 as *mut c_void, 16));
+            mem_tracing.push(AllocTrace::new(0x2000 as *mut c_void, 32));
+            mem_tracing.update(
+                0x1000 as *mut c_void,
+                AllocTrace::new(0x3000 as *mut c_void, 64),
+            );
+            mem_tracing.finish(0x2000 as *mut c_void);
+            // Frees of untracked pointers are ignored.
+            mem_tracing.finish(0x4000 as *mut c_void);
+
+            let report = mem_tracing.report();
+            assert_eq!(
+                report.outstanding,
+                [OutstandingAllocation {
+                    address: 0x3000,
+                    size: 64
+                }]
+            );
+            assert_eq!(report.peak_bytes, 96);
+            assert_eq!(report.total_allocations, 3);
+            assert!(!report.is_leak_free());
+            assert_eq!(report.outstanding_bytes(), 64);
+        }
+
+        #[test]
+        fn test_mem_tracing_runtime_dict_leak() {
+            use cairo_native_runtime::{
+                cairo_native__dict_drop, cairo_native__dict_new, cairo_native__set_runtime_state,
+                FeltDict, RuntimeState,
+            };
+
+            let mut state = RuntimeState {
+                memory_tracer: memory_tracer(),
+                ..RuntimeState::EMPTY
+            };
+            let previous = cairo_native__set_runtime_state(&mut state);
+
+            // A dictionary that is freed within the execution isn't reported.
+            let tracker = ExecutionTracker::start();
+            unsafe {
+                let dict = cairo_native__dict_new(32, 16);
+                cairo_native__dict_drop(dict, None);
+            }
+            let report = tracker.finish();
+            assert!(report.is_leak_free());
+            assert_eq!(report.total_allocations, 1);
+
+            // A dictionary that is leaked is.
+            let tracker = ExecutionTracker::start();
+            let dict = unsafe { cairo_native__dict_new(32, 16) };
+            let report = tracker.finish();
+            assert_eq!(
+                report.outstanding,
+                [OutstandingAllocation {
+                    address: dict as usize,
+                    size: std::mem::size_of::<FeltDict>(),
+                }]
+            );
+
+            // Once the tracker is finished, the runtime doesn't report to it anymore.
+            unsafe { cairo_native__dict_drop(dict, None) };
+            assert!(MEM_TRACING.with(|x| unsafe { (*x.get()).pending.is_empty() }));
+
+            cairo_native__set_runtime_state(previous);
+        }
     }
 }
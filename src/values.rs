@@ -3,7 +3,7 @@
 //! A Rusty interface to provide parameters to cairo-native entry point calls.
 
 use crate::{
-    error::{panic::ToNativeAssertError, CompilerError, Error},
+    error::{panic::ToNativeAssertError, CompilerError, Error, ValueInvariantError},
     native_assert, native_panic,
     starknet::{Secp256k1Point, Secp256r1Point},
     types::TypeBuilder,
@@ -28,7 +28,7 @@ use num_traits::{Euclid, One};
 use starknet_types_core::felt::Felt;
 use std::{
     alloc::{alloc, dealloc, Layout},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ptr::{null_mut, NonNull},
     slice,
 };
@@ -558,11 +558,16 @@ impl Value {
     }
 
     /// From the given pointer acquired from the either the JIT / compiled program outputs, convert it to a [`Self`]
+    ///
+    /// When `strict` is set, the decoded data is checked against the type's invariants (felts
+    /// below the prime, enum tags and bounded ints in range, well-formed arrays and dictionaries)
+    /// instead of being silently masked into a valid value.
     pub(crate) fn from_ptr(
         ptr: NonNull<()>,
         type_id: &ConcreteTypeId,
         registry: &ProgramRegistry<CoreType, CoreLibfunc>,
         should_drop: bool,
+        strict: bool,
     ) -> Result<Self, Error> {
         let ty = registry.get_type(type_id)?;
 
@@ -582,12 +587,33 @@ impl Value {
                         .to_native_assert_error("tried to make a non-null ptr out of a null one")?
                         .cast::<u32>()
                         .as_ref();
-                    let (_, offset) = ptr_layout.extend(len_layout)?;
+                    let (ptr_layout, offset) = ptr_layout.extend(len_layout)?;
                     let end_offset_value = *NonNull::new(ptr.as_ptr().byte_add(offset))
                         .to_native_assert_error("tried to make a non-null ptr out of a null one")?
                         .cast::<u32>()
                         .as_ref();
 
+                    if strict {
+                        let (_, offset) = ptr_layout.extend(len_layout)?;
+                        let capacity_value = *NonNull::new(ptr.as_ptr().byte_add(offset))
+                            .to_native_assert_error(
+                                "tried to make a non-null ptr out of a null one",
+                            )?
+                            .cast::<u32>()
+                            .as_ref();
+
+                        if start_offset_value > end_offset_value
+                            || end_offset_value > capacity_value
+                        {
+                            return Err(ValueInvariantError::ArrayBounds {
+                                start: start_offset_value,
+                                end: end_offset_value,
+                                capacity: capacity_value,
+                            }
+                            .into());
+                        }
+                    }
+
                     // This pointer can be null if the array is empty.
                     let init_data_ptr = *ptr.cast::<*mut ()>().as_ref();
                     let data_ptr =
@@ -633,6 +659,7 @@ impl Value {
                             &info.ty,
                             registry,
                             should_drop,
+                            strict,
                         )?);
                     }
 
@@ -644,7 +671,7 @@ impl Value {
                 }
                 CoreTypeConcrete::Box(info) => {
                     let inner = *ptr.cast::<NonNull<()>>().as_ptr();
-                    let value = Self::from_ptr(inner, &info.ty, registry, should_drop)?;
+                    let value = Self::from_ptr(inner, &info.ty, registry, should_drop, strict)?;
 
                     if should_drop {
                         libc_free(inner.as_ptr().cast());
@@ -653,33 +680,25 @@ impl Value {
                     value
                 }
                 CoreTypeConcrete::EcPoint(_) => {
-                    let data = ptr.cast::<[[u8; 32]; 2]>().as_mut();
-
-                    data[0][31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-                    data[1][31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
+                    let data = ptr.cast::<[[u8; 32]; 2]>().as_ref();
 
-                    Self::EcPoint(Felt::from_bytes_le(&data[0]), Felt::from_bytes_le(&data[1]))
+                    Self::EcPoint(
+                        felt_from_bytes(data[0], strict)?,
+                        felt_from_bytes(data[1], strict)?,
+                    )
                 }
                 CoreTypeConcrete::EcState(_) => {
-                    let data = ptr.cast::<[[u8; 32]; 4]>().as_mut();
-
-                    data[0][31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-                    data[1][31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-                    data[2][31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-                    data[3][31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
+                    let data = ptr.cast::<[[u8; 32]; 4]>().as_ref();
 
                     Self::EcState(
-                        Felt::from_bytes_le(&data[0]),
-                        Felt::from_bytes_le(&data[1]),
-                        Felt::from_bytes_le(&data[2]),
-                        Felt::from_bytes_le(&data[3]),
+                        felt_from_bytes(data[0], strict)?,
+                        felt_from_bytes(data[1], strict)?,
+                        felt_from_bytes(data[2], strict)?,
+                        felt_from_bytes(data[3], strict)?,
                     )
                 }
                 CoreTypeConcrete::Felt252(_) => {
-                    let data = ptr.cast::<[u8; 32]>().as_mut();
-                    data[31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-                    let data = Felt::from_bytes_le_slice(data);
-                    Self::Felt252(data)
+                    Self::Felt252(felt_from_bytes(*ptr.cast::<[u8; 32]>().as_ref(), strict)?)
                 }
                 CoreTypeConcrete::Uint8(_) => Self::Uint8(*ptr.cast::<u8>().as_ref()),
                 CoreTypeConcrete::Uint16(_) => Self::Uint16(*ptr.cast::<u16>().as_ref()),
@@ -695,7 +714,7 @@ impl Value {
                 CoreTypeConcrete::Sint64(_) => Self::Sint64(*ptr.cast::<i64>().as_ref()),
                 CoreTypeConcrete::Sint128(_) => Self::Sint128(*ptr.cast::<i128>().as_ref()),
                 CoreTypeConcrete::NonZero(info) => {
-                    Self::from_ptr(ptr, &info.ty, registry, should_drop)?
+                    Self::from_ptr(ptr, &info.ty, registry, should_drop, strict)?
                 }
                 CoreTypeConcrete::Nullable(info) => {
                     let inner_ptr = *ptr.cast::<*mut ()>().as_ptr();
@@ -707,6 +726,7 @@ impl Value {
                             &info.ty,
                             registry,
                             should_drop,
+                            strict,
                         )?;
 
                         if should_drop {
@@ -746,6 +766,18 @@ impl Value {
                         & 1usize
                             .wrapping_shl(info.variants.len().next_power_of_two().trailing_zeros())
                             .wrapping_sub(1);
+                    if tag_value >= info.variants.len() {
+                        if strict {
+                            return Err(ValueInvariantError::EnumTag {
+                                type_id: type_id.clone(),
+                                tag: tag_value,
+                                num_variants: info.variants.len(),
+                            }
+                            .into());
+                        }
+
+                        native_panic!("enum tag {tag_value} is out of range for {type_id:?}");
+                    }
 
                     let payload_ty = registry.get_type(&info.variants[tag_value])?;
                     let payload_layout = payload_ty.layout(registry)?;
@@ -759,6 +791,7 @@ impl Value {
                         &info.variants[tag_value],
                         registry,
                         should_drop,
+                        strict,
                     )?;
 
                     Self::Enum {
@@ -788,6 +821,7 @@ impl Value {
                             member_ty,
                             registry,
                             should_drop,
+                            strict,
                         )?);
                     }
 
@@ -804,12 +838,13 @@ impl Value {
                         .cast::<FeltDict>()
                         .as_ref();
 
+                    if strict {
+                        validate_dict(dict)?;
+                    }
+
                     let mut output_map = HashMap::with_capacity(dict.mappings.len());
                     for (&key, &index) in dict.mappings.iter() {
-                        let mut key = key;
-                        key[31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-
-                        let key = Felt::from_bytes_le(&key);
+                        let key = felt_from_bytes(key, strict)?;
                         output_map.insert(
                             key,
                             Self::from_ptr(
@@ -824,6 +859,7 @@ impl Value {
                                 &info.ty,
                                 registry,
                                 should_drop,
+                                strict,
                             )?,
                         );
                     }
@@ -872,10 +908,7 @@ impl Value {
                     | StarkNetTypeConcrete::StorageBaseAddress(_)
                     | StarkNetTypeConcrete::StorageAddress(_) => {
                         // felt values
-                        let data = ptr.cast::<[u8; 32]>().as_mut();
-                        data[31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
-                        let data = Felt::from_bytes_le(data);
-                        Self::Felt252(data)
+                        Self::Felt252(felt_from_bytes(*ptr.cast::<[u8; 32]>().as_ref(), strict)?)
                     }
                     StarkNetTypeConcrete::System(_) => {
                         native_panic!("should be handled before")
@@ -896,7 +929,7 @@ impl Value {
                 },
                 CoreTypeConcrete::Span(_) => native_panic!("implement span from_ptr"),
                 CoreTypeConcrete::Snapshot(info) => {
-                    Self::from_ptr(ptr, &info.ty, registry, should_drop)?
+                    Self::from_ptr(ptr, &info.ty, registry, should_drop, strict)?
                 }
                 CoreTypeConcrete::Bytes31(_) => {
                     let data = *ptr.cast::<[u8; 31]>().as_ref();
@@ -916,6 +949,14 @@ impl Value {
                     data &= (BigInt::one() << info.range.offset_bit_width()) - BigInt::one();
                     data += &info.range.lower;
 
+                    if strict && data >= info.range.upper {
+                        return Err(ValueInvariantError::BoundedIntOutOfRange {
+                            value: Box::new(data),
+                            range: Box::new(info.range.clone()),
+                        }
+                        .into());
+                    }
+
                     Self::BoundedInt {
                         value: data.into(),
                         range: info.range.clone(),
@@ -935,6 +976,7 @@ impl Value {
                         &info.ty,
                         registry,
                         should_drop,
+                        strict,
                     )?;
 
                     let y = Self::from_ptr(
@@ -946,6 +988,7 @@ impl Value {
                         &info.ty,
                         registry,
                         should_drop,
+                        strict,
                     )?;
 
                     Self::IntRange {
@@ -958,6 +1001,49 @@ impl Value {
     }
 }
 
+/// Convert the in-memory representation of a felt into a [`Felt`].
+///
+/// The 4 most significant bits are always discarded since they're outside an i252. Values that are
+/// not below the prime are rejected in strict mode, and reduced by [`Felt`] otherwise.
+pub(crate) fn felt_from_bytes(mut data: [u8; 32], strict: bool) -> Result<Felt, Error> {
+    data[31] &= 0x0F; // Filter out first 4 bits (they're outside an i252).
+
+    if strict {
+        let value = BigUint::from_bytes_le(&data);
+        if value >= *PRIME {
+            return Err(ValueInvariantError::FeltOutOfRange(Box::new(value)).into());
+        }
+    }
+
+    Ok(Felt::from_bytes_le(&data))
+}
+
+/// Check that every entry of the dictionary points to a distinct, allocated element.
+fn validate_dict(dict: &FeltDict) -> Result<(), Error> {
+    if !dict.mappings.is_empty() && dict.elements.is_null() {
+        return Err(ValueInvariantError::DictNullElements {
+            len: dict.mappings.len(),
+        }
+        .into());
+    }
+
+    let mut seen_indices = HashSet::with_capacity(dict.mappings.len());
+    for &index in dict.mappings.values() {
+        if index >= dict.mappings.len() {
+            return Err(ValueInvariantError::DictIndexOutOfRange {
+                index,
+                len: dict.mappings.len(),
+            }
+            .into());
+        }
+        if !seen_indices.insert(index) {
+            return Err(ValueInvariantError::DictDuplicateIndex(index).into());
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1700,6 +1786,165 @@ mod test {
             _ => panic!("Unexpected error type: {:?}", result),
         }
     }
+
+    #[test]
+    fn test_felt_from_bytes_strict() {
+        let mut prime = PRIME.to_bytes_le();
+        prime.resize(32, 0);
+        let prime: [u8; 32] = prime.try_into().unwrap();
+
+        // Non-strict decoding reduces the value modulo the prime.
+        assert_eq!(felt_from_bytes(prime, false).unwrap(), Felt::ZERO);
+
+        // Strict decoding rejects it.
+        assert!(matches!(
+            felt_from_bytes(prime, true),
+            Err(Error::ValueInvariant(ValueInvariantError::FeltOutOfRange(
+                _
+            )))
+        ));
+
+        // The biggest valid felt is accepted in both modes.
+        let max = Felt::MAX.to_bytes_le();
+        assert_eq!(felt_from_bytes(max, true).unwrap(), Felt::MAX);
+    }
+
+    #[test]
+    fn test_from_ptr_strict_bounded_int() {
+        let program = ProgramParser::new()
+            .parse("type BoundedInt = BoundedInt<10, 12>;")
+            .unwrap();
+        let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(&program).unwrap();
+
+        // The offset 3 fits in the 2 bits of storage but is outside the range `[10, 12]`.
+        let mut data = 3u8;
+        let ptr = NonNull::from(&mut data).cast::<()>();
+
+        assert_eq!(
+            Value::from_ptr(
+                ptr,
+                &program.type_declarations[0].id,
+                &registry,
+                false,
+                false
+            )
+            .unwrap(),
+            Value::BoundedInt {
+                value: Felt::from(13),
+                range: Range {
+                    lower: BigInt::from(10),
+                    upper: BigInt::from(13),
+                },
+            }
+        );
+        assert!(matches!(
+            Value::from_ptr(
+                ptr,
+                &program.type_declarations[0].id,
+                &registry,
+                false,
+                true
+            ),
+            Err(Error::ValueInvariant(
+                ValueInvariantError::BoundedIntOutOfRange { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_from_ptr_strict_enum_tag() {
+        let program = ProgramParser::new()
+            .parse(
+                "type felt252 = felt252;
+                type MyEnum = Enum<ut@MyEnum, felt252, felt252, felt252>;",
+            )
+            .unwrap();
+        let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(&program).unwrap();
+
+        // The tag 3 fits in the 2 bits of the tag but there are only 3 variants.
+        let mut data = [0u128; 3];
+        data[0] = 3;
+        let ptr = NonNull::from(&mut data).cast::<()>();
+
+        // It can't be masked into a valid variant, so it's an error in both modes.
+        assert!(matches!(
+            Value::from_ptr(
+                ptr,
+                &program.type_declarations[1].id,
+                &registry,
+                false,
+                false
+            ),
+            Err(Error::NativeAssert(_))
+        ));
+        assert!(matches!(
+            Value::from_ptr(
+                ptr,
+                &program.type_declarations[1].id,
+                &registry,
+                false,
+                true
+            ),
+            Err(Error::ValueInvariant(ValueInvariantError::EnumTag {
+                tag: 3,
+                num_variants: 3,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_from_ptr_strict_dict() {
+        let program = ProgramParser::new()
+            .parse(
+                "type u32 = u32;
+                type MyDict = Felt252Dict<u32>;",
+            )
+            .unwrap();
+        let registry = ProgramRegistry::<CoreType, CoreLibfunc>::new(&program).unwrap();
+
+        // Two keys pointing to the same element.
+        let mut elements = [7u32, 8u32];
+        let mut dict = FeltDict {
+            mappings: HashMap::from([([1; 32], 0), ([2; 32], 0)]),
+            layout: Layout::new::<u32>(),
+            elements: elements.as_mut_ptr().cast(),
+            count: 0,
+        };
+        let mut dict_ptr = NonNull::from(&mut dict);
+        let ptr = NonNull::from(&mut dict_ptr).cast::<()>();
+
+        assert!(matches!(
+            Value::from_ptr(
+                ptr,
+                &program.type_declarations[1].id,
+                &registry,
+                false,
+                true
+            ),
+            Err(Error::ValueInvariant(
+                ValueInvariantError::DictDuplicateIndex(0)
+            ))
+        ));
+
+        // Entries without any element storage.
+        dict.elements = null_mut();
+        let mut dict_ptr = NonNull::from(&mut dict);
+        let ptr = NonNull::from(&mut dict_ptr).cast::<()>();
+
+        assert!(matches!(
+            Value::from_ptr(
+                ptr,
+                &program.type_declarations[1].id,
+                &registry,
+                false,
+                true
+            ),
+            Err(Error::ValueInvariant(
+                ValueInvariantError::DictNullElements { len: 2 }
+            ))
+        ));
+    }
 }
 
 mod range_serde {
@@ -1,5 +1,7 @@
 use cairo_native::{
-    execution_result::{BuiltinStats, ContractExecutionResult, ExecutionResult},
+    execution_result::{
+        BuiltinStats, ContractExecutionResult, ExecutionDiagnostics, ExecutionResult,
+    },
     Value,
 };
 use test_case::test_case;
@@ -90,6 +92,7 @@ fn test_cases(return_value: Value) {
         return_value,
         remaining_gas: None,
         builtin_stats: BuiltinStats::default(),
+        diagnostics: ExecutionDiagnostics::default(),
     })
     .unwrap();
 }
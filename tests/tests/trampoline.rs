@@ -2,7 +2,7 @@ use crate::common::load_cairo;
 use cairo_lang_sierra::program::Program;
 use cairo_native::{
     context::NativeContext,
-    execution_result::{BuiltinStats, ExecutionResult},
+    execution_result::{BuiltinStats, ExecutionDiagnostics, ExecutionResult},
     executor::JitNativeExecutor,
     utils::find_function_id,
     OptLevel, Value,
@@ -37,6 +37,7 @@ fn invoke0() {
                 debug_name: None,
             },
             builtin_stats: BuiltinStats::default(),
+            diagnostics: ExecutionDiagnostics::default(),
         },
     );
 }
@@ -61,6 +62,7 @@ fn invoke1_felt252() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -91,6 +93,7 @@ fn invoke1_u8() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -121,6 +124,7 @@ fn invoke1_u16() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -151,6 +155,7 @@ fn invoke1_u32() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -181,6 +186,7 @@ fn invoke1_u64() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -211,6 +217,7 @@ fn invoke1_u128() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -244,6 +251,7 @@ fn invoke1_tuple1_felt252() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -277,6 +285,7 @@ fn invoke1_tuple1_u64() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -316,6 +325,7 @@ fn invoke1_tuple5_u8_u16_u32_u64_u128() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -346,6 +356,7 @@ fn invoke1_array_felt252() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -387,6 +398,7 @@ fn invoke1_enum1_unit() {
             remaining_gas: None,
             return_value: x,
             builtin_stats: BuiltinStats::default(),
+            diagnostics: ExecutionDiagnostics::default(),
         },
     );
 }
@@ -419,6 +431,7 @@ fn invoke1_enum1_u64() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -457,6 +470,7 @@ fn invoke1_enum1_felt252() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -508,6 +522,7 @@ fn invoke1_enum2_u8_u16() {
                 remaining_gas: None,
                 return_value: x,
                 builtin_stats: BuiltinStats::default(),
+                diagnostics: ExecutionDiagnostics::default(),
             },
         );
     };
@@ -540,6 +555,7 @@ fn invoke1_box_felt252() {
             remaining_gas: None,
             return_value: Value::Felt252(42.into()),
             builtin_stats: BuiltinStats::default(),
+            diagnostics: ExecutionDiagnostics::default(),
         }
     );
 }
@@ -571,6 +587,7 @@ fn invoke1_nullable_felt252() {
                 debug_name: None
             },
             builtin_stats: BuiltinStats::default(),
+            diagnostics: ExecutionDiagnostics::default(),
         }
     );
     assert_eq!(
@@ -590,6 +607,7 @@ fn invoke1_nullable_felt252() {
                 debug_name: None
             },
             builtin_stats: BuiltinStats::default(),
+            diagnostics: ExecutionDiagnostics::default(),
         }
     );
 }
@@ -632,6 +650,7 @@ fn test_deserialize_param_bug() {
                 debug_name: None
             },
             builtin_stats: BuiltinStats::default(),
+            diagnostics: ExecutionDiagnostics::default(),
         },
     );
 }